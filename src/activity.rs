@@ -0,0 +1,91 @@
+use std::{path::PathBuf, sync::mpsc::Sender};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{limits::ResourceUsage, sync::PushOutcome};
+
+/// Something the watcher did, reported to its subscribers (e.g. dashboards).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Activity {
+    /// A filesystem event was received.
+    Event { description: String },
+    /// A commit was made, `latency_ms` after the oldest change it includes if known.
+    Commit {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        latency_ms: Option<u64>,
+    },
+    /// A push was attempted, `latency_ms` after the oldest commit it pushed if it succeeded.
+    Push {
+        outcome: PushOutcome,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        latency_ms: Option<u64>,
+    },
+    /// Auto-commits were paused.
+    Paused,
+    /// Auto-commits were resumed.
+    Resumed,
+    /// The directories which cannot be watched (e.g. unreadable) changed.
+    Unwatched { paths: Vec<PathBuf> },
+    /// The resources used by the watcher changed, reported periodically.
+    Usage {
+        #[serde(flatten)]
+        usage: ResourceUsage,
+    },
+}
+
+/// Names of the kinds of activity, as serialized.
+pub const ACTIVITY_KINDS: &[&str] = &[
+    "event",
+    "commit",
+    "push",
+    "paused",
+    "resumed",
+    "unwatched",
+    "usage",
+];
+
+impl Activity {
+    /// Name of the kind of activity, as serialized (e.g. `commit`).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Activity::Event { .. } => "event",
+            Activity::Commit { .. } => "commit",
+            Activity::Push { .. } => "push",
+            Activity::Paused => "paused",
+            Activity::Resumed => "resumed",
+            Activity::Unwatched { .. } => "unwatched",
+            Activity::Usage { .. } => "usage",
+        }
+    }
+}
+
+/// A request to change the behaviour of a running watcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Stop committing changes, they are snapshotted once resumed.
+    Pause,
+    /// Resume committing changes.
+    Resume,
+    /// Push as soon as possible.
+    PushNow,
+}
+
+/// Broadcasts activity to all subscribers.
+#[derive(Default)]
+pub struct ActivityBroadcaster(Vec<Sender<Activity>>);
+
+impl ActivityBroadcaster {
+    /// Add a subscriber.
+    pub fn subscribe(&mut self, subscriber: Sender<Activity>) {
+        self.0.push(subscriber);
+    }
+
+    /// Send the activity to all subscribers, subscribers which hung up are ignored.
+    pub fn broadcast(&self, activity: Activity) {
+        for subscriber in &self.0 {
+            let _ = subscriber.send(activity.clone());
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Window the editing activity is measured over.
+const ACTIVITY_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Delay which adapts to the editing activity: each commit made within the last
+/// `ACTIVITY_WINDOW` adds the configured delay to it, up to `max`,
+/// so heavy editing makes fewer commits while quiet periods keep the configured delay.
+#[derive(Debug)]
+pub struct AdaptiveDelay {
+    max: Duration,
+    /// When the commits of the last `ACTIVITY_WINDOW` were made.
+    recent: VecDeque<Instant>,
+}
+
+impl AdaptiveDelay {
+    pub fn new(max: Duration) -> Self {
+        Self {
+            max,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Record a commit.
+    pub fn on_commit(&mut self, now: Instant) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|commit| now.duration_since(*commit) > ACTIVITY_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+    }
+
+    /// The delay for a change, given the configured one and the current activity.
+    pub fn delay(&self, configured: Duration, now: Instant) -> Duration {
+        let commits = self
+            .recent
+            .iter()
+            .filter(|commit| now.duration_since(**commit) <= ACTIVITY_WINDOW)
+            .count();
+        let factor = u32::try_from(commits).unwrap_or(u32::MAX).saturating_add(1);
+        configured
+            .saturating_mul(factor)
+            .clamp(configured, self.max.max(configured))
+    }
+}
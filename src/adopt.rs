@@ -0,0 +1,148 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+/// Default size (in megabytes) above which the files of an adopted directory are ignored.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10;
+
+const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+/// Directories holding dependencies, build outputs or caches.
+const GENERATED_DIRECTORIES: &[&str] = &[
+    "node_modules",
+    "target",
+    "__pycache__",
+    ".venv",
+    "venv",
+    ".tox",
+    ".gradle",
+    ".cache",
+    ".pytest_cache",
+    ".mypy_cache",
+    ".Trash",
+    ".Trashes",
+];
+
+/// Files left behind by the operating systems and editors.
+const CLUTTER_FILES: &[&str] = &[
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+    "*.swp",
+    "*.swo",
+    "*~",
+    "*.tmp",
+    ".~lock.*#",
+];
+
+/// Extensions of compiled or generated files.
+const GENERATED_EXTENSIONS: &[&str] = &[
+    "o", "obj", "pyc", "pyo", "class", "exe", "dll", "so", "dylib", "log",
+];
+
+/// The ignore rules of a directory adopted by nabu, found by looking at its files.
+#[derive(Debug, Default)]
+pub struct Gitignore {
+    /// Names of the dependency, build output and cache directories found.
+    pub directories: BTreeSet<String>,
+    /// Patterns of the operating system and editor files found, see [`CLUTTER_FILES`].
+    pub clutter: BTreeSet<String>,
+    /// Extensions of the compiled or generated files found.
+    pub extensions: BTreeSet<String>,
+    /// Files larger than `max_file_size` megabytes, relative to the directory.
+    pub large_files: BTreeSet<PathBuf>,
+    max_file_size: u64,
+}
+
+impl Gitignore {
+    /// Look for the files of the directory which should not be committed,
+    /// ignoring the ones larger than `max_file_size` megabytes.
+    pub fn scan(root: &Path, max_file_size: u64) -> io::Result<Self> {
+        let clutter = clutter_patterns();
+        let mut gitignore = Self {
+            max_file_size,
+            ..Self::default()
+        };
+        let mut entries = WalkDir::new(root).into_iter();
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy();
+            if entry.file_type().is_dir() {
+                // Ignored directories are not looked into
+                if entry.depth() > 0 && name == ".git" {
+                    entries.skip_current_dir();
+                } else if entry.depth() > 0 && GENERATED_DIRECTORIES.contains(&name.as_ref()) {
+                    gitignore.directories.insert(name.into_owned());
+                    entries.skip_current_dir();
+                }
+                continue;
+            }
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if let Some(index) = clutter.matches(name.as_ref()).first() {
+                gitignore.clutter.insert(CLUTTER_FILES[*index].to_string());
+            } else if let Some(extension) = entry
+                .path()
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .filter(|extension| GENERATED_EXTENSIONS.contains(&extension.as_str()))
+            {
+                gitignore.extensions.insert(extension);
+            } else if entry.metadata()?.len() > max_file_size * BYTES_PER_MEGABYTE {
+                let path = entry.path().strip_prefix(root).unwrap().to_path_buf();
+                gitignore.large_files.insert(path);
+            }
+        }
+        Ok(gitignore)
+    }
+
+    /// The content of the `.gitignore` file, one commented section per kind of file.
+    pub fn render(&self) -> String {
+        let mut content = String::from("# Generated by `nabu adopt`\n");
+        let directories = self.directories.iter().map(|name| format!("{}/", name));
+        let clutter = self.clutter.iter().cloned();
+        let extensions = self
+            .extensions
+            .iter()
+            .map(|extension| format!("*.{}", extension));
+        let large_files = self
+            .large_files
+            .iter()
+            .map(|path| format!("/{}", path.to_string_lossy().replace('\\', "/")));
+        let large_files_comment = format!("Files larger than {} MB", self.max_file_size);
+        let sections: [(&str, Vec<String>); 4] = [
+            (
+                "Dependencies, build outputs and caches",
+                directories.collect(),
+            ),
+            ("Operating system and editor files", clutter.collect()),
+            ("Compiled and generated files", extensions.collect()),
+            (&large_files_comment, large_files.collect()),
+        ];
+        for (comment, patterns) in sections {
+            if patterns.is_empty() {
+                continue;
+            }
+            content.push_str(&format!("\n# {}\n", comment));
+            for pattern in patterns {
+                content.push_str(&pattern);
+                content.push('\n');
+            }
+        }
+        content
+    }
+}
+
+fn clutter_patterns() -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in CLUTTER_FILES {
+        builder.add(Glob::new(pattern).expect("the clutter patterns are valid"));
+    }
+    builder.build().expect("the clutter patterns are valid")
+}
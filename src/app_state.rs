@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Application state files rewritten on every interaction (e.g. the panes open in Obsidian),
+/// relative to the watched directory.
+pub const APP_STATE_FILES: &[&str] = &[
+    ".obsidian/workspace.json",
+    ".obsidian/workspace-mobile.json",
+    ".obsidian/workspace",
+];
+
+/// Minimum time between the commits of an application state file.
+const APP_STATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Whether the path, relative to the watched directory, is a known application state file.
+pub fn is_app_state(relative: &Path) -> bool {
+    APP_STATE_FILES
+        .iter()
+        .any(|file| relative == Path::new(file))
+}
+
+/// Commits each application state file at most once per hour,
+/// the changes made meanwhile are held back until then, only the last one per file.
+#[derive(Debug)]
+pub struct AppStateThrottle<E> {
+    /// When each file was last committed.
+    committed: HashMap<PathBuf, Instant>,
+    held: HashMap<PathBuf, E>,
+}
+
+impl<E> Default for AppStateThrottle<E> {
+    fn default() -> Self {
+        Self {
+            committed: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+}
+
+impl<E> AppStateThrottle<E> {
+    /// Hold the change back if the file was committed within the last hour,
+    /// otherwise return it to be committed.
+    pub fn hold(&mut self, path: &Path, change: E, now: Instant) -> Option<E> {
+        match self.committed.get(path) {
+            Some(committed) if now.duration_since(*committed) < APP_STATE_INTERVAL => {
+                self.held.insert(path.to_path_buf(), change);
+                None
+            }
+            _ => Some(change),
+        }
+    }
+
+    /// Record that the file was committed.
+    pub fn on_commit(&mut self, path: &Path, now: Instant) {
+        self.committed.insert(path.to_path_buf(), now);
+        self.held.remove(path);
+    }
+
+    /// Take the changes held back for an hour since their file was last committed.
+    pub fn due(&mut self, now: Instant) -> Vec<E> {
+        let due = self
+            .held
+            .keys()
+            .filter(|path| {
+                self.committed
+                    .get(*path)
+                    .is_none_or(|committed| now.duration_since(*committed) >= APP_STATE_INTERVAL)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        due.iter()
+            .filter_map(|path| self.held.remove(path))
+            .collect()
+    }
+}
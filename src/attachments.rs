@@ -0,0 +1,232 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// Name of the git filter driver replacing the large files with pointers in the commits.
+pub const FILTER_DRIVER: &str = "nabu-attachments";
+
+/// First line of the pointer files.
+const POINTER_HEADER: &str = "nabu-attachment v1";
+
+/// Larger files are never pointers, and are not read to find out.
+const MAX_POINTER_SIZE: u64 = 256;
+
+/// Delimit the lines nabu manages in `.git/info/attributes`.
+const ATTRIBUTES_BEGIN: &str = "# begin nabu [attachments]";
+const ATTRIBUTES_END: &str = "# end nabu [attachments]";
+
+const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+fn default_threshold() -> u64 {
+    5
+}
+
+fn default_patterns() -> Vec<String> {
+    vec![String::from("*")]
+}
+
+/// Keep the large files out of the history, declared as the `[attachments]` section:
+/// the commits hold pointers to their content, kept in a content-addressed store outside the repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttachmentsConfig {
+    /// The store directory, synced separately (e.g. `/mnt/nas/vault-attachments`).
+    pub store: PathBuf,
+
+    /// Files larger than this many megabytes are moved into the store.
+    #[serde(default = "default_threshold")]
+    pub threshold: u64,
+
+    /// The files considered, as `.gitattributes` patterns (e.g. `*.pdf`).
+    #[serde(default = "default_patterns")]
+    pub patterns: Vec<String>,
+}
+
+impl AttachmentsConfig {
+    /// Check that the store is absolute, and that the patterns can be written as attributes.
+    pub fn validate(&self) -> Result<()> {
+        if !self.store.is_absolute() {
+            return Err(eyre!(
+                "the attachments store {} must be an absolute path",
+                self.store.display()
+            ));
+        }
+        if let Some(pattern) = self
+            .patterns
+            .iter()
+            .find(|pattern| pattern.is_empty() || pattern.contains(char::is_whitespace))
+        {
+            return Err(eyre!(
+                "the attachments pattern \"{}\" must neither be empty nor contain spaces",
+                pattern
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Pointer to a file's content in the store, committed in its place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pointer {
+    /// The content's blob ID, naming it in the store.
+    pub oid: git2::Oid,
+    pub size: u64,
+}
+
+impl Pointer {
+    /// Parse the content of a pointer file, `None` if it is not one.
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        let content = std::str::from_utf8(content).ok()?;
+        let mut lines = content.lines();
+        if lines.next()? != POINTER_HEADER {
+            return None;
+        }
+        let oid = git2::Oid::from_str(lines.next()?.strip_prefix("oid ")?).ok()?;
+        let size = lines.next()?.strip_prefix("size ")?.parse().ok()?;
+        Some(Self { oid, size })
+    }
+
+    /// Read the file at `path` if it is a pointer.
+    pub fn read(path: &Path) -> io::Result<Option<Self>> {
+        if fs::symlink_metadata(path)?.len() > MAX_POINTER_SIZE {
+            return Ok(None);
+        }
+        Ok(Self::parse(&fs::read(path)?))
+    }
+
+    /// The content of the pointer file.
+    pub fn to_bytes(self) -> Vec<u8> {
+        format!("{}\noid {}\nsize {}\n", POINTER_HEADER, self.oid, self.size).into_bytes()
+    }
+}
+
+/// The content-addressed store, each content is kept at `<oid[..2]>/<oid[2..]>`.
+#[derive(Debug, Clone)]
+pub struct Store(PathBuf);
+
+impl Store {
+    pub fn new(path: &Path) -> Self {
+        Self(path.to_path_buf())
+    }
+
+    fn object_path(&self, oid: git2::Oid) -> PathBuf {
+        let oid = oid.to_string();
+        self.0.join(&oid[..2]).join(&oid[2..])
+    }
+
+    /// Add the content to the store, unless it already holds it, and return its pointer.
+    pub fn put(&self, content: &[u8]) -> io::Result<Pointer> {
+        let oid = git2::Oid::hash_object(git2::ObjectType::Blob, content)
+            .map_err(|err| io::Error::other(err.message()))?;
+        let path = self.object_path(oid);
+        if !path.exists() {
+            fs::create_dir_all(path.parent().unwrap())?;
+            // Renamed once complete, so that the store never holds a partial content
+            let temporary = path.with_extension("tmp");
+            fs::write(&temporary, content)?;
+            fs::rename(&temporary, &path)?;
+        }
+        Ok(Pointer {
+            oid,
+            size: content.len() as u64,
+        })
+    }
+
+    /// The content the pointer refers to, `None` if the store does not hold it (e.g. not synced yet).
+    pub fn get(&self, pointer: Pointer) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.object_path(pointer.oid)) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The clean filter: the pointer to the content once added to the store
+    /// if it is larger than `threshold` megabytes, the content itself otherwise.
+    pub fn clean(&self, content: Vec<u8>, threshold: u64) -> io::Result<Vec<u8>> {
+        if content.len() as u64 <= threshold * BYTES_PER_MEGABYTE {
+            return Ok(content);
+        }
+        Ok(self.put(&content)?.to_bytes())
+    }
+
+    /// The smudge filter: the content a pointer refers to, anything else (including the pointers
+    /// to the contents missing from the store) as is.
+    pub fn smudge(&self, content: Vec<u8>) -> io::Result<Vec<u8>> {
+        match Pointer::parse(&content) {
+            Some(pointer) => Ok(self.get(pointer)?.unwrap_or(content)),
+            None => Ok(content),
+        }
+    }
+}
+
+/// Configure the filter driver, running `executable` (nabu itself), in the repository with the given `.git` directory,
+/// for the files matching the patterns; without `config`, the files are no longer filtered.
+pub fn configure_filter(
+    git_dir: &Path,
+    executable: &Path,
+    config: Option<&AttachmentsConfig>,
+) -> Result<()> {
+    let attributes_path = git_dir.join("info").join("attributes");
+    let attributes = match fs::read_to_string(&attributes_path) {
+        Ok(attributes) => attributes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut lines = vec![];
+    let mut managed = false;
+    for line in attributes.lines() {
+        match line {
+            ATTRIBUTES_BEGIN => managed = true,
+            ATTRIBUTES_END => managed = false,
+            _ if !managed => lines.push(line.to_string()),
+            _ => {}
+        }
+    }
+    let config = match config {
+        Some(config) => config,
+        None if lines.len() == attributes.lines().count() => return Ok(()),
+        None => return write_attributes(&attributes_path, &lines),
+    };
+
+    let mut git_config = git2::Config::open(&git_dir.join("config"))?;
+    let executable = quote(&executable.to_string_lossy());
+    let store = quote(&config.store.to_string_lossy());
+    git_config.set_str(
+        &format!("filter.{}.clean", FILTER_DRIVER),
+        &format!(
+            "{} filter clean --store {} --threshold {}",
+            executable, store, config.threshold
+        ),
+    )?;
+    git_config.set_str(
+        &format!("filter.{}.smudge", FILTER_DRIVER),
+        &format!("{} filter smudge --store {}", executable, store),
+    )?;
+
+    lines.push(ATTRIBUTES_BEGIN.to_string());
+    for pattern in &config.patterns {
+        lines.push(format!("{} filter={}", pattern, FILTER_DRIVER));
+    }
+    lines.push(ATTRIBUTES_END.to_string());
+    write_attributes(&attributes_path, &lines)
+}
+
+fn write_attributes(path: &Path, lines: &[String]) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let attributes = lines
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    fs::write(path, attributes)?;
+    Ok(())
+}
+
+/// Quote an argument of the filter commands, which git runs through the shell.
+fn quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', r"'\''"))
+}
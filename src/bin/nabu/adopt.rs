@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    adopt::{Gitignore, DEFAULT_MAX_FILE_SIZE},
+    config::{Config, ConfigFormat},
+    git::{Repository, WatchedRepository},
+    sync::push,
+};
+
+use crate::{
+    auth::AuthArgs,
+    exit::{push_error, Failure},
+    init::InitFormat,
+};
+
+const GITIGNORE_FILE_NAME: &str = ".gitignore";
+
+/// `nabu`'s `adopt` command arguments structure.
+#[derive(Args)]
+pub(crate) struct AdoptArgs {
+    /// The directory to adopt.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Size, in megabytes, above which the files are ignored.
+    #[clap(long, default_value_t = DEFAULT_MAX_FILE_SIZE)]
+    max_file_size: u64,
+
+    /// URL of the remote, set as "origin".
+    #[clap(long)]
+    remote: Option<String>,
+
+    /// Push the initial commit to the remote.
+    #[clap(long, requires = "remote")]
+    push: bool,
+
+    /// Format of the configuration file.
+    #[clap(long, value_enum, default_value = "toml")]
+    format: InitFormat,
+
+    #[clap(flatten)]
+    auth: AuthArgs,
+}
+
+impl AdoptArgs {
+    /// Execute the `adopt` command.
+    pub fn run(self) -> Result<()> {
+        let directory = &self.directory;
+        if !directory.is_dir() {
+            return Err(eyre!("{} is not a directory", directory.display()));
+        }
+        if let Ok(repo) = WatchedRepository::new(directory) {
+            if repo.head()?.is_some() {
+                return Err(eyre!(
+                    "{} is already a repository, run `nabu init` to configure it instead",
+                    directory.display()
+                ));
+            }
+        }
+        // Checked first, nothing is written if pushing is not possible
+        let authentication_method = if self.push {
+            Some(
+                self.auth
+                    .get_authentication_method()?
+                    .ok_or_else(|| eyre!("no authentication method was provided"))?,
+            )
+        } else {
+            None
+        };
+
+        let gitignore_path = directory.join(GITIGNORE_FILE_NAME);
+        if gitignore_path.exists() {
+            log::info!("keeping the existing {}", gitignore_path.display());
+        } else {
+            let gitignore = Gitignore::scan(directory, self.max_file_size)?;
+            for path in &gitignore.large_files {
+                log::warn!(
+                    "ignoring {}, larger than {} MB",
+                    path.display(),
+                    self.max_file_size
+                );
+            }
+            std::fs::write(&gitignore_path, gitignore.render())?;
+            log::info!("ignore rules written to {}", gitignore_path.display());
+        }
+
+        let existing_config = ConfigFormat::ALL
+            .into_iter()
+            .map(|format| directory.join(format.file_name()))
+            .find(|path| path.exists());
+        let config = match existing_config {
+            Some(path) => {
+                log::info!("keeping the existing {}", path.display());
+                Config::from_path(&path).wrap_err(Failure::Config)?
+            }
+            None => {
+                let format = ConfigFormat::from(self.format);
+                let config = Config::default();
+                let path = directory.join(format.file_name());
+                std::fs::write(&path, format.serialize(&config)?)?;
+                log::info!("config file written to {}", path.display());
+                config
+            }
+        };
+
+        let repo = WatchedRepository::init(directory)?;
+        config.apply_tls().wrap_err(Failure::Config)?;
+        if config.secret_scan {
+            let secrets = repo.find_secrets(None)?;
+            for secret in &secrets {
+                log::warn!(
+                    "possible secret ({}) at {}:{}",
+                    secret.kind,
+                    secret.path.display(),
+                    secret.line
+                );
+            }
+            if !secrets.is_empty() {
+                return Err(eyre!(
+                    "not committing the possible secrets, remove or ignore them and run `nabu adopt` again"
+                ));
+            }
+        }
+        let message = format!(
+            "{} @ {}",
+            config.catalog().adopted_snapshot,
+            config.timezone.timestamp(&config.timestamp_format)
+        );
+        repo.initial_commit(&message)?;
+        log::info!("committed the files of {}", directory.display());
+
+        if let Some(remote) = &self.remote {
+            repo.set_origin(remote)?;
+            log::info!("origin set to {}", remote);
+        }
+        if let Some(authentication_method) = authentication_method {
+            let outcome = push(&repo, Some(&authentication_method), &config);
+            if let Some(err) = push_error(&outcome) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
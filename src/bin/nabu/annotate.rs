@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::git::{Repository, WatchedRepository};
+
+/// `nabu`'s `annotate` command arguments structure.
+#[derive(Args)]
+pub(crate) struct AnnotateArgs {
+    /// What you are working on, e.g. "drafting the chapter on rivers".
+    text: String,
+
+    /// The repository directory.
+    #[clap(short, long, parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Attach the text as a note to the last commit instead of committing it.
+    #[clap(long)]
+    note: bool,
+}
+
+impl AnnotateArgs {
+    /// Execute the `annotate` command, marking the history with the text.
+    pub fn run(self) -> Result<()> {
+        let text = self.text.trim();
+        if text.is_empty() {
+            return Err(eyre!("the annotation is empty"));
+        }
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let head = repo
+            .head()?
+            .ok_or_else(|| eyre!("there are no commits to annotate yet"))?;
+        if self.note {
+            repo.append_note(head, text)
+                .wrap_err("failed to write the note")?;
+            println!("Annotated {}", head);
+        } else {
+            let commit = repo
+                .commit_empty(text)
+                .wrap_err("failed to commit the annotation")?;
+            println!("Committed {}", commit);
+        }
+        Ok(())
+    }
+}
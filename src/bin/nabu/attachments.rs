@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use clap::{Args, Subcommand};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    attachments::{Pointer, Store},
+    config::Config,
+};
+use walkdir::WalkDir;
+
+use crate::exit::Failure;
+
+/// `nabu`'s `restore` command arguments structure.
+#[derive(Args)]
+pub(crate) struct RestoreArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+}
+
+impl RestoreArgs {
+    /// Execute the `restore` command, replacing the pointer files with their content from the `[attachments]` store.
+    pub fn run(self) -> Result<()> {
+        let config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        let attachments = config
+            .attachments
+            .ok_or_else(|| eyre!("no [attachments] section is configured"))
+            .wrap_err(Failure::Config)?;
+        let store = Store::new(&attachments.store);
+
+        let mut restored = 0;
+        let mut missing = vec![];
+        let entries = WalkDir::new(&self.directory)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git");
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let pointer = match Pointer::read(entry.path()) {
+                Ok(Some(pointer)) => pointer,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::warn!("cannot read {}: {}", entry.path().display(), err);
+                    continue;
+                }
+            };
+            match store.get(pointer)? {
+                Some(content) => {
+                    fs::write(entry.path(), content)?;
+                    restored += 1;
+                }
+                None => missing.push(entry.into_path()),
+            }
+        }
+
+        println!("{} files restored", restored);
+        if !missing.is_empty() {
+            println!(
+                "{} files are missing from {}, sync the store and restore again:",
+                missing.len(),
+                attachments.store.display()
+            );
+            for path in &missing {
+                println!("  {}", path.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `nabu`'s `filter` command arguments structure, the git filter driver of the `[attachments]`.
+#[derive(Args)]
+pub(crate) struct FilterArgs {
+    #[clap(subcommand)]
+    command: FilterCommand,
+}
+
+#[derive(Subcommand)]
+enum FilterCommand {
+    /// Replace the content read from the standard input with its pointer if it is large enough.
+    Clean {
+        /// The store directory.
+        #[clap(long, parse(from_os_str))]
+        store: PathBuf,
+
+        /// Contents larger than this many megabytes are moved into the store.
+        #[clap(long)]
+        threshold: u64,
+    },
+    /// Replace the pointer read from the standard input with its content.
+    Smudge {
+        /// The store directory.
+        #[clap(long, parse(from_os_str))]
+        store: PathBuf,
+    },
+}
+
+impl FilterArgs {
+    /// Execute the `filter` command, from the standard input to the standard output.
+    pub fn run(self) -> Result<()> {
+        let mut content = vec![];
+        io::stdin().lock().read_to_end(&mut content)?;
+        let content = match self.command {
+            FilterCommand::Clean { store, threshold } => {
+                Store::new(&store).clean(content, threshold)?
+            }
+            FilterCommand::Smudge { store } => Store::new(&store).smudge(content)?,
+        };
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&content)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
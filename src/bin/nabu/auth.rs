@@ -0,0 +1,176 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use clap::Args;
+use color_eyre::{eyre::eyre, Result};
+use nabu::{
+    fleet::AuthConfig,
+    git::{AuthenticationMethod, TOKEN_VARIABLES},
+    interpolate::expand,
+    ssh_key::SshKeyInfo,
+};
+
+/// The passphrases asked on the terminal, by key, so that each one is only asked once.
+static ASKED_PASSPHRASES: Mutex<BTreeMap<PathBuf, String>> = Mutex::new(BTreeMap::new());
+
+// Group names for the CLI.
+const AUTHENTICATION_METHOD_GROUP_NAME: &str = "authentication_method_group";
+const SSH_KEY_GROUP_NAME: &str = "ssh_key_group";
+
+/// Authentication arguments shared by the commands that talk to the remote.
+#[derive(Args, Clone)]
+pub(crate) struct AuthArgs {
+    /// Use the ssh-agent as authenticaton method.
+    #[clap(long, group(AUTHENTICATION_METHOD_GROUP_NAME))]
+    ssh_agent: bool,
+
+    /// Use the ssh-key as authentication method, repeat it to try several keys in order.
+    #[clap(
+        long,
+        parse(from_os_str),
+        multiple_occurrences(true),
+        groups(&[AUTHENTICATION_METHOD_GROUP_NAME, SSH_KEY_GROUP_NAME]),
+    )]
+    ssh_key: Vec<PathBuf>,
+
+    /// Provide a passphrase for the ssh-keys.
+    #[clap(long, requires(SSH_KEY_GROUP_NAME), default_value_t)]
+    ssh_passphrase: String,
+}
+
+impl AuthArgs {
+    /// Convert the authentication-related arguments into an `AuthenticationMethod` enumeration.
+    /// Returns `None` if no authentication method was provided.
+    pub fn get_authentication_method(&self) -> Result<Option<AuthenticationMethod>> {
+        if self.ssh_agent {
+            if env::var("SSH_AGENT_PID").is_err() && env::var("SSH_AUTH_SOCK").is_err() {
+                log::warn!("ssh-agent is not running.");
+            }
+            return Ok(Some(AuthenticationMethod::SshAgent));
+        }
+
+        if self.ssh_key.is_empty() {
+            // Pushing without flags works when a token for the remote is available
+            return Ok(has_token().then_some(AuthenticationMethod::Token));
+        }
+        let mut paths = vec![];
+        for path in &self.ssh_key {
+            // The path may be quoted, leaving `~` to us
+            let path = PathBuf::from(expand(&path.to_string_lossy())?);
+            if !path.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("provided key {} does not exist", path.display()),
+                )
+                .into());
+            }
+            paths.push(path);
+        }
+        let passphrase = if self.ssh_passphrase.is_empty() {
+            ask_passphrase(&paths)?
+        } else {
+            if let Some(key) = paths.iter().find(|path| is_security_key(path)) {
+                log::warn!(
+                    "The passphrase is not given to OpenSSH for the security key {}, it asks for it on the terminal, load the key into the ssh-agent instead.",
+                    key.display()
+                );
+            }
+            self.ssh_passphrase.clone()
+        };
+        Ok(Some(AuthenticationMethod::SshKey { paths, passphrase }))
+    }
+
+    /// The arguments, or the `[auth]` section of the configuration if no authentication flag was given.
+    pub fn or_config(&self, auth: Option<&AuthConfig>) -> Self {
+        match auth {
+            Some(auth) if !self.ssh_agent && self.ssh_key.is_empty() => Self::from(auth),
+            _ => self.clone(),
+        }
+    }
+}
+
+impl From<&AuthConfig> for AuthArgs {
+    /// The arguments set by a repository of the fleet configuration.
+    fn from(auth: &AuthConfig) -> Self {
+        Self {
+            ssh_agent: auth.ssh_agent,
+            ssh_key: auth.ssh_key.clone(),
+            ssh_passphrase: auth.ssh_passphrase.clone(),
+        }
+    }
+}
+
+/// Whether any of the token variables is set.
+fn has_token() -> bool {
+    TOKEN_VARIABLES
+        .iter()
+        .any(|(variable, _, _)| env::var_os(variable).is_some())
+}
+
+fn is_security_key(path: &Path) -> bool {
+    SshKeyInfo::read(path).is_ok_and(|info| info.security_key)
+}
+
+/// Ask for the passphrase of the first key protected by one, which libgit2 would fail to use without it,
+/// if someone is at the terminal. The security keys are left to OpenSSH, which asks on its own.
+fn ask_passphrase(paths: &[PathBuf]) -> Result<String> {
+    let key = paths
+        .iter()
+        .find(|path| SshKeyInfo::read(path).is_ok_and(|info| info.encrypted && !info.security_key));
+    let key = match key {
+        Some(key) if io::stdin().is_terminal() => key,
+        _ => return Ok(String::new()),
+    };
+    let mut asked = ASKED_PASSPHRASES
+        .lock()
+        .map_err(|_| eyre!("the passphrases were poisoned"))?;
+    if let Some(passphrase) = asked.get(key) {
+        return Ok(passphrase.clone());
+    }
+    eprint!("Enter the passphrase for {}: ", key.display());
+    io::stderr().flush()?;
+    let passphrase = read_hidden_line()?;
+    asked.insert(key.clone(), passphrase.clone());
+    Ok(passphrase)
+}
+
+/// Read a line from the terminal without echoing it.
+#[cfg(unix)]
+fn read_hidden_line() -> io::Result<String> {
+    let fd = libc::STDIN_FILENO;
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let echoing = termios;
+    termios.c_lflag &= !libc::ECHO;
+    termios.c_lflag |= libc::ECHONL;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let line = read_line();
+    // Echo again even if reading failed
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &echoing) };
+    line
+}
+
+#[cfg(not(unix))]
+fn read_hidden_line() -> io::Result<String> {
+    read_line()
+}
+
+fn read_line() -> io::Result<String> {
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the standard input was closed",
+        ));
+    }
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
@@ -0,0 +1,526 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    activity::Activity,
+    config::Config,
+    fs::{Discovery, IgnoreRules},
+    git::{AuthenticationMethod, WatchedRepository},
+    latency::format_ms,
+    overrides::Overrides,
+};
+use serde::Serialize;
+
+use crate::{
+    exit::Failure,
+    output::{print_json, OutputFormat},
+    signal::Signals,
+    watch::WatchCommand,
+};
+
+/// Interval between the checks of whether the writes were all committed (and pushed).
+const SETTLE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// First line of the files written, followed by the index of the write.
+const WRITE_HEADER: &str = "nabu bench write ";
+
+/// `nabu`'s `bench` command arguments structure.
+#[derive(Args)]
+pub(crate) struct BenchArgs {
+    /// The directory whose configuration is benchmarked.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Files written to, in turn.
+    #[clap(long, default_value_t = 20)]
+    files: usize,
+
+    /// Writes made in total.
+    #[clap(long, default_value_t = 200)]
+    writes: usize,
+
+    /// Writes per second.
+    #[clap(long, default_value_t = 20.0)]
+    rate: f64,
+
+    /// Bytes per write.
+    #[clap(long, default_value_t = 1024)]
+    size: usize,
+
+    /// Push to a local bare repository as configured, after each commit if no push is configured.
+    #[clap(long)]
+    push: bool,
+
+    /// Seconds to wait for the writes to be committed (and pushed) once they are all made.
+    #[clap(long, default_value_t = 60)]
+    timeout: u64,
+
+    /// Keep the temporary repository, printing where it is.
+    #[clap(long)]
+    keep: bool,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+/// A write of the workload, along with how long it took to be committed and pushed.
+struct Write {
+    path: PathBuf,
+    index: usize,
+    at: Instant,
+    committed: Option<Duration>,
+    pushed: Option<Duration>,
+}
+
+/// The writes of the workload, shared by the writer and the observer of the watcher.
+#[derive(Default)]
+struct Writes {
+    writes: Vec<Write>,
+    commits: usize,
+    pushes: usize,
+    /// When the last write was committed or pushed.
+    settled_at: Option<Instant>,
+}
+
+impl Writes {
+    /// Whether every write was committed, and pushed if `push` is set.
+    fn settled(&self, push: bool) -> bool {
+        self.writes
+            .iter()
+            .all(|write| write.committed.is_some() && (!push || write.pushed.is_some()))
+    }
+}
+
+/// Summary of the latencies of the writes.
+#[derive(Debug, Serialize)]
+struct LatencySummary {
+    mean_ms: u64,
+    p50_ms: u64,
+    p90_ms: u64,
+    p99_ms: u64,
+    max_ms: u64,
+}
+
+impl LatencySummary {
+    fn new(mut latencies: Vec<u64>) -> Option<Self> {
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let percentile = |percentile: usize| {
+            let rank = (latencies.len() * percentile).div_ceil(100).max(1);
+            latencies[rank - 1]
+        };
+        Some(Self {
+            mean_ms: latencies.iter().sum::<u64>() / latencies.len() as u64,
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p99_ms: percentile(99),
+            max_ms: latencies[latencies.len() - 1],
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "mean {}, p50 {}, p90 {}, p99 {}, max {}",
+            format_ms(self.mean_ms),
+            format_ms(self.p50_ms),
+            format_ms(self.p90_ms),
+            format_ms(self.p99_ms),
+            format_ms(self.max_ms)
+        )
+    }
+}
+
+/// The results of the benchmark.
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    writes: usize,
+    files: usize,
+    delay: u64,
+    /// From the first write until the last one was committed (or pushed).
+    duration_ms: u64,
+    commits: usize,
+    pushes: usize,
+    committed_writes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pushed_writes: Option<usize>,
+    /// Writes committed per second.
+    throughput: f64,
+    /// From a write to the commit including it, or a later write to the same file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    write_to_commit: Option<LatencySummary>,
+    /// From a write to the push sending it, or a later write to the same file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    write_to_push: Option<LatencySummary>,
+}
+
+impl BenchArgs {
+    /// Execute the `bench` command, making synthetic writes in a temporary repository watched with the configuration
+    /// of the directory, and measuring how long they take to be committed (and pushed).
+    pub fn run(self, signals: Signals) -> Result<()> {
+        if self.files == 0 || self.writes == 0 {
+            return Err(eyre!("--files and --writes must be at least 1"));
+        }
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(eyre!("--rate must be positive"));
+        }
+        let config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        let root = std::env::temp_dir().join(format!("nabu-bench-{}", std::process::id()));
+        let result = self.bench(&root, config, signals);
+        if self.keep {
+            eprintln!("The benchmark repository is kept in {}", root.display());
+        } else if let Err(err) = fs::remove_dir_all(&root) {
+            log::warn!("Failed to remove {}: {}", root.display(), err);
+        }
+        let report = result?;
+
+        if self.output == OutputFormat::Json {
+            return print_json(&report);
+        }
+        println!(
+            "{} writes to {} files with delay = {}s, over {}",
+            report.writes,
+            report.files,
+            report.delay,
+            format_ms(report.duration_ms)
+        );
+        println!(
+            "{} commits, {:.1} writes committed per second",
+            report.commits, report.throughput
+        );
+        if let Some(write_to_commit) = &report.write_to_commit {
+            println!("Write to commit: {}", write_to_commit.describe());
+        }
+        if let Some(pushed_writes) = report.pushed_writes {
+            println!("{} pushes", report.pushes);
+            if let Some(write_to_push) = &report.write_to_push {
+                println!("Write to push: {}", write_to_push.describe());
+            }
+            if pushed_writes < report.writes {
+                println!(
+                    "{} writes were not pushed within the timeout",
+                    report.writes - pushed_writes
+                );
+            }
+        }
+        if report.committed_writes < report.writes {
+            println!(
+                "{} writes were not committed within the timeout",
+                report.writes - report.committed_writes
+            );
+        }
+        Ok(())
+    }
+
+    fn bench(&self, root: &Path, config: Config, signals: Signals) -> Result<BenchReport> {
+        let workdir = root.join("repository");
+        let remote = root.join("remote.git");
+        fs::create_dir_all(&workdir)?;
+        let workdir = workdir.canonicalize()?;
+        init_repository(&workdir, self.push.then_some(remote.as_path()))
+            .wrap_err("failed to create the benchmark repository")?;
+        let config = isolate(config, self.push);
+        let delay = config.delay;
+
+        let discovery = Discovery::new(
+            workdir.clone(),
+            IgnoreRules::new(&config.ignore)?,
+            config.follow_symlinks,
+        )
+        .with_overrides(Overrides::new(&config.directories)?);
+        let repo = WatchedRepository::discover(&workdir)?
+            .with_scope(&workdir)
+            .with_unicode_normalization(config.unicode_normalization)
+            .with_track_new_files(config.track_new_files);
+        // The local remote needs no credentials, any method lets the watcher push
+        let authentication_method = self.push.then_some(AuthenticationMethod::Token);
+        let mut command = WatchCommand::new(
+            repo,
+            signals.clone(),
+            discovery,
+            config,
+            self.timeout,
+            authentication_method,
+        )?;
+
+        let writes = Arc::new(Mutex::new(Writes::default()));
+        let observer = observe(
+            command.subscribe(),
+            workdir.clone(),
+            self.push.then(|| remote.clone()),
+            writes.clone(),
+        );
+        let writer = self.spawn_writer(workdir, writes.clone(), signals);
+        command.run()?;
+        let started = writer
+            .join()
+            .map_err(|_| eyre!("the benchmark writer panicked"))??;
+        observer
+            .join()
+            .map_err(|_| eyre!("the benchmark observer panicked"))??;
+
+        let writes = writes.lock().unwrap();
+        let committed: Vec<_> = writes
+            .writes
+            .iter()
+            .filter_map(|write| write.committed)
+            .map(|latency| latency.as_millis() as u64)
+            .collect();
+        let pushed: Vec<_> = writes
+            .writes
+            .iter()
+            .filter_map(|write| write.pushed)
+            .map(|latency| latency.as_millis() as u64)
+            .collect();
+        let duration = writes
+            .settled_at
+            .map_or(Duration::ZERO, |settled_at| settled_at - started);
+        Ok(BenchReport {
+            writes: self.writes,
+            files: self.files,
+            delay,
+            duration_ms: duration.as_millis() as u64,
+            commits: writes.commits,
+            pushes: writes.pushes,
+            committed_writes: committed.len(),
+            pushed_writes: self.push.then_some(pushed.len()),
+            throughput: committed.len() as f64 / duration.as_secs_f64().max(f64::EPSILON),
+            write_to_commit: LatencySummary::new(committed),
+            write_to_push: LatencySummary::new(pushed),
+        })
+    }
+
+    /// Make the writes at the given rate, then wait for them to be committed (and pushed) before stopping the watcher.
+    /// Returns when the first write was made.
+    fn spawn_writer(
+        &self,
+        workdir: PathBuf,
+        writes: Arc<Mutex<Writes>>,
+        signals: Signals,
+    ) -> JoinHandle<Result<Instant>> {
+        let (files, total, size, push) = (self.files, self.writes, self.size, self.push);
+        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        let timeout = Duration::from_secs(self.timeout);
+        thread::spawn(move || {
+            // Stop the watcher whatever happens, the benchmark is over
+            let result = (|| {
+                // Let the watcher start watching
+                thread::sleep(Duration::from_secs(1));
+                let started = Instant::now();
+                for index in 0..total {
+                    if !signals.is_running() {
+                        return Ok(started);
+                    }
+                    let path = PathBuf::from(format!("bench-{}.txt", index % files));
+                    fs::write(workdir.join(&path), content(index, size))?;
+                    writes.lock().unwrap().writes.push(Write {
+                        path,
+                        index,
+                        at: Instant::now(),
+                        committed: None,
+                        pushed: None,
+                    });
+                    if let Some(wait) = (started + interval * (index as u32 + 1))
+                        .checked_duration_since(Instant::now())
+                    {
+                        thread::sleep(wait);
+                    }
+                }
+                let deadline = Instant::now() + timeout;
+                while signals.is_running()
+                    && Instant::now() < deadline
+                    && !writes.lock().unwrap().settled(push)
+                {
+                    thread::sleep(SETTLE_CHECK_INTERVAL);
+                }
+                Ok(started)
+            })();
+            signals.request_termination();
+            result
+        })
+    }
+}
+
+/// Create the repository with an initial commit, along with the bare repository it pushes to if given.
+fn init_repository(workdir: &Path, remote: Option<&Path>) -> Result<(), git2::Error> {
+    let repo = git2::Repository::init(workdir)?;
+    let mut config = repo.config()?;
+    config.set_str("user.name", "nabu bench")?;
+    config.set_str("user.email", "bench@nabu.invalid")?;
+    let signature = repo.signature()?;
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "nabu bench",
+        &tree,
+        &[],
+    )?;
+    if let Some(remote) = remote {
+        git2::Repository::init_bare(remote)?;
+        repo.remote("origin", &remote.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+/// Keep the benchmark to its repository: only the settings of how the changes are committed carry over,
+/// the others (e.g. hooks, scripts, mirrors, notifications or rolling branches) are left to their defaults.
+/// No push unless benchmarked, then after each commit unless configured otherwise.
+fn isolate(config: Config, push: bool) -> Config {
+    let mut isolated = Config {
+        delay: config.delay,
+        adaptive_delay: config.adaptive_delay,
+        ignore: config.ignore,
+        directories: config.directories,
+        commit_strategy: config.commit_strategy,
+        amend_window: config.amend_window,
+        commit_batch_interval: config.commit_batch_interval,
+        session_gap: config.session_gap,
+        group_by_folder: config.group_by_folder,
+        suppress_grace_period: config.suppress_grace_period,
+        storm_threshold: config.storm_threshold,
+        ignore_chmod: config.ignore_chmod,
+        follow_symlinks: config.follow_symlinks,
+        unicode_normalization: config.unicode_normalization,
+        track_new_files: config.track_new_files,
+        force_include: config.force_include,
+        author_time_from_mtime: config.author_time_from_mtime,
+        git_backend: config.git_backend,
+        secret_scan: config.secret_scan,
+        allow_conflict_markers: config.allow_conflict_markers,
+        commit_message: config.commit_message,
+        redact_paths: config.redact_paths,
+        commit_notes: config.commit_notes,
+        session_trailer: config.session_trailer,
+        timezone: config.timezone,
+        timestamp_format: config.timestamp_format,
+        locale: config.locale,
+        conventions: config.conventions,
+        limits: config.limits,
+        pack_threads: config.pack_threads,
+        pack_compression: config.pack_compression,
+        ..Config::default()
+    };
+    if push {
+        isolated.push_schedule = config.push_schedule;
+        isolated.push_every_n_commits = config.push_every_n_commits;
+        isolated.push_every_n_megabytes = config.push_every_n_megabytes;
+        if isolated.push_schedule.is_none()
+            && isolated.push_every_n_commits.is_none()
+            && isolated.push_every_n_megabytes.is_none()
+        {
+            isolated.push_every_n_commits = Some(1);
+        }
+    }
+    isolated
+}
+
+/// Content of the write, its index followed by filler up to `size` bytes.
+fn content(index: usize, size: usize) -> String {
+    let mut content = format!("{}{}\n", WRITE_HEADER, index);
+    while content.len() < size {
+        content.push_str("nabu bench filler\n");
+    }
+    content
+}
+
+/// Settle the writes committed or pushed as the watcher reports them,
+/// by reading the files from `HEAD` or the remote's branch.
+fn observe(
+    activity: Receiver<Activity>,
+    workdir: PathBuf,
+    remote: Option<PathBuf>,
+    writes: Arc<Mutex<Writes>>,
+) -> JoinHandle<Result<()>> {
+    thread::spawn(move || {
+        let repo = git2::Repository::open(&workdir)?;
+        for activity in activity {
+            let now = Instant::now();
+            match activity {
+                Activity::Commit { .. } => {
+                    let tree = repo.head()?.peel_to_tree()?;
+                    let mut writes = writes.lock().unwrap();
+                    writes.commits += 1;
+                    settle(&repo, &tree, &mut writes, now, |write| &mut write.committed);
+                }
+                Activity::Push { outcome, .. } if outcome.is_pushed() => {
+                    let remote = match &remote {
+                        Some(remote) => git2::Repository::open_bare(remote)?,
+                        None => continue,
+                    };
+                    let branch = repo
+                        .head()?
+                        .shorthand()
+                        .map(str::to_string)
+                        .ok_or_else(|| eyre!("the benchmark repository has no branch"))?;
+                    let tree = remote
+                        .find_reference(&format!("refs/heads/{}", branch))?
+                        .peel_to_tree()?;
+                    let mut writes = writes.lock().unwrap();
+                    writes.pushes += 1;
+                    settle(&remote, &tree, &mut writes, now, |write| &mut write.pushed);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Settle the writes found in the tree, or overwritten by a later write found in it.
+fn settle<F>(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    writes: &mut Writes,
+    now: Instant,
+    latency: F,
+) where
+    F: Fn(&mut Write) -> &mut Option<Duration>,
+{
+    let mut settled = false;
+    for write in &mut writes.writes {
+        if latency(write).is_some() {
+            continue;
+        }
+        let found = tree
+            .get_path(&write.path)
+            .ok()
+            .and_then(|entry| repo.find_blob(entry.id()).ok())
+            .and_then(|blob| written_index(blob.content()));
+        if found.is_some_and(|found| found >= write.index) {
+            *latency(write) = Some(now - write.at);
+            settled = true;
+        }
+    }
+    if settled {
+        writes.settled_at = Some(now);
+    }
+}
+
+/// Index of the write a file's content comes from.
+fn written_index(content: &[u8]) -> Option<usize> {
+    let line = content.split(|byte| *byte == b'\n').next()?;
+    std::str::from_utf8(line)
+        .ok()?
+        .strip_prefix(WRITE_HEADER)?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::Result;
+use nabu::{
+    git::{CommitSummary, WatchedRepository},
+    notes::CommitMetadata,
+};
+use serde::Serialize;
+
+use crate::output::{print_json, OutputFormat};
+
+/// Default number of commits listed.
+const DEFAULT_LOG_LIMIT: usize = 20;
+
+/// `nabu`'s `log` command arguments structure.
+#[derive(Args)]
+pub(crate) struct LogArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Number of commits to list.
+    #[clap(short = 'n', long, default_value_t = DEFAULT_LOG_LIMIT)]
+    limit: usize,
+
+    /// Only list the commits made on this device, as noted with `commit_notes`.
+    #[clap(long)]
+    device: Option<String>,
+
+    /// Only list the commits triggered by this event (e.g. `written` or `snapshot`),
+    /// as noted with `commit_notes`.
+    #[clap(long)]
+    event: Option<String>,
+
+    /// Group the commits by the watcher session which made them,
+    /// as recorded with `session_trailer` or `commit_notes`.
+    #[clap(long)]
+    sessions: bool,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+/// The commits made by a watcher session, newest first.
+#[derive(Serialize)]
+struct Session<'a> {
+    /// `None` for the commits recording no session.
+    session: Option<&'a str>,
+    commits: Vec<&'a CommitSummary>,
+}
+
+impl LogArgs {
+    /// Execute the `log` command.
+    pub fn run(self) -> Result<()> {
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let commits = repo.log_matching(self.limit, |commit| {
+            let noted = |filter: &Option<String>, value: fn(&CommitMetadata) -> &str| {
+                filter.as_ref().is_none_or(|filter| {
+                    commit
+                        .metadata
+                        .as_ref()
+                        .is_some_and(|metadata| value(metadata) == filter)
+                })
+            };
+            noted(&self.device, |metadata| &metadata.device)
+                && noted(&self.event, |metadata| &metadata.event)
+        })?;
+        if self.sessions {
+            return self.print_sessions(&commits);
+        }
+        if self.output == OutputFormat::Json {
+            return print_json(&commits);
+        }
+
+        for commit in &commits {
+            println!("{}", describe(commit));
+        }
+        Ok(())
+    }
+
+    /// Print the commits grouped by session, the sessions ordered by their latest commit.
+    fn print_sessions(&self, commits: &[CommitSummary]) -> Result<()> {
+        let mut sessions: Vec<Session> = vec![];
+        for commit in commits {
+            let session = commit.session.as_deref();
+            match sessions.iter_mut().find(|group| group.session == session) {
+                Some(group) => group.commits.push(commit),
+                None => sessions.push(Session {
+                    session,
+                    commits: vec![commit],
+                }),
+            }
+        }
+        if self.output == OutputFormat::Json {
+            return print_json(&sessions);
+        }
+
+        for (index, group) in sessions.iter().enumerate() {
+            if index > 0 {
+                println!();
+            }
+            let first = group.commits.last().unwrap();
+            let last = group.commits.first().unwrap();
+            println!(
+                "{} ({} commits, {} to {})",
+                group.session.map_or_else(
+                    || String::from("No session"),
+                    |session| format!("Session {}", session)
+                ),
+                group.commits.len(),
+                first.time,
+                last.time
+            );
+            for commit in &group.commits {
+                println!("  {}", describe(commit));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A line describing the commit, along with its noted event and device.
+fn describe(commit: &CommitSummary) -> String {
+    match &commit.metadata {
+        Some(metadata) => format!(
+            "{} {} {} ({} on {})",
+            &commit.id[..7],
+            commit.time,
+            commit.summary,
+            metadata.event,
+            metadata.device
+        ),
+        None => format!("{} {} {}", &commit.id[..7], commit.time, commit.summary),
+    }
+}
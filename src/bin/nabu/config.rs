@@ -0,0 +1,81 @@
+use std::{ffi::OsString, fs, path::PathBuf};
+
+use clap::{Args, Subcommand};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    config::{find_config_path, Config, ConfigFormat},
+    migration::{migrate, CONFIG_VERSION},
+};
+
+use crate::exit::Failure;
+
+/// `nabu`'s `config` command arguments structure.
+#[derive(Args)]
+pub(crate) struct ConfigArgs {
+    #[clap(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Upgrade the configuration file to the current schema version, in place.
+    Migrate(MigrateArgs),
+}
+
+#[derive(Args)]
+struct MigrateArgs {
+    /// The directory whose configuration file is upgraded.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+}
+
+impl ConfigArgs {
+    /// Execute the `config` command.
+    pub fn run(self) -> Result<()> {
+        match self.command {
+            ConfigCommand::Migrate(migrate) => migrate.run(),
+        }
+    }
+}
+
+impl MigrateArgs {
+    /// Upgrade the configuration file, keeping the previous one next to it as `<name>.bak`.
+    fn run(self) -> Result<()> {
+        let path = self
+            .config
+            .or_else(|| find_config_path(&self.directory))
+            .ok_or_else(|| eyre!("no configuration file found"))?;
+        let format = ConfigFormat::from_path(&path);
+        let mut config = format
+            .parse_value(&fs::read(&path)?)
+            .wrap_err(Failure::Config)?;
+        let version = migrate(&mut config).wrap_err(Failure::Config)?;
+        if version == CONFIG_VERSION {
+            log::info!("{} is up to date", path.display());
+            return Ok(());
+        }
+        let config = Config::from_value(config).wrap_err(Failure::Config)?;
+        config.validate().wrap_err(Failure::Config)?;
+
+        let mut backup = OsString::from(path.as_os_str());
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        fs::copy(&path, &backup)?;
+        fs::write(&path, format.serialize(&config)?)?;
+        log::info!(
+            "{} upgraded from version {} to version {}, the previous file was kept as {}",
+            path.display(),
+            version,
+            CONFIG_VERSION,
+            backup.display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::signal::Signals;
+
+/// How often the answer is polled, to notice `nabu` being stopped meanwhile.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Asks for confirmation before each commit, in attended mode.
+pub(crate) struct Confirmation {
+    answers: Receiver<String>,
+    timeout: Option<Duration>,
+    signals: Signals,
+}
+
+impl Confirmation {
+    /// Read the answers from the standard input,
+    /// accepting the commit if there is no answer within `timeout`.
+    /// Once the push on exit is skipped (i.e. on the second Ctrl-C) the commits are declined.
+    pub fn new(timeout: Option<Duration>, signals: Signals) -> Self {
+        let (answer_snd, answer_rcv) = channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let answer = match line {
+                    Ok(answer) => answer,
+                    Err(_) => return,
+                };
+                if answer_snd.send(answer).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            answers: answer_rcv,
+            timeout,
+            signals,
+        }
+    }
+
+    /// Show the prospective commit and wait for the answer.
+    /// Returns whether the commit was accepted.
+    pub fn confirm<P>(&self, paths: &[P], message: &str) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        // Discard the answers given before the question
+        while self.answers.try_recv().is_ok() {}
+
+        println!("About to commit \"{}\":", message);
+        for path in paths {
+            println!("    {}", path.as_ref().display());
+        }
+        match self.timeout {
+            Some(timeout) => print!("Commit? [Y/n] (accepting in {}s) ", timeout.as_secs()),
+            None => print!("Commit? [Y/n] "),
+        }
+        let _ = io::stdout().flush();
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            match self.answers.recv_timeout(POLL_INTERVAL) {
+                Ok(answer) => {
+                    return matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes")
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    println!();
+                    log::warn!("The standard input was closed, declining the commit.");
+                    return false;
+                }
+                Err(RecvTimeoutError::Timeout) if self.signals.skip_push() => {
+                    println!();
+                    return false;
+                }
+                Err(RecvTimeoutError::Timeout)
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) =>
+                {
+                    println!();
+                    log::info!("No answer, accepting the commit.");
+                    return true;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    cell::RefCell,
+    panic::{self, PanicHookInfo},
+    process,
+    sync::Once,
+    time::Duration,
+};
+
+use color_eyre::Result;
+use nabu::{
+    config::Config,
+    git::{Repository, WatchedRepository},
+    notes::with_trailers,
+    state::{state_path, Crash, State},
+};
+
+use crate::report::flush_error_reports;
+
+thread_local! {
+    /// The watcher running on this thread, snapshotted if it panics.
+    static WATCHED: RefCell<Option<Watched>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// What the panic hook needs to snapshot the changes of a watcher.
+struct Watched {
+    /// The repository, set up as the watcher's one.
+    repo: WatchedRepository,
+    config: Config,
+}
+
+/// Snapshots the watcher's changes if its thread panics, for as long as it is held.
+pub(crate) struct CrashGuard(());
+
+impl Drop for CrashGuard {
+    fn drop(&mut self) {
+        WATCHED.with(|watched| watched.borrow_mut().take());
+    }
+}
+
+/// Make a best-effort snapshot of the changes of the watcher running on this thread if it panics,
+/// recording the panic in the state file (for `nabu doctor`) before aborting.
+///
+/// The hook runs after the ones installed before it (e.g. printing the panic, or reporting it),
+/// so it is installed once the watcher is set up.
+pub(crate) fn guard(repo: WatchedRepository, config: &Config) -> CrashGuard {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            let watched = WATCHED.with(|watched| watched.try_borrow_mut().ok()?.take());
+            // Panics of the other threads are handled by whoever joins them
+            if let Some(watched) = watched {
+                watched.crash(info);
+            }
+        }));
+    });
+    WATCHED.with(|watched| {
+        *watched.borrow_mut() = Some(Watched {
+            repo,
+            config: config.clone(),
+        })
+    });
+    CrashGuard(())
+}
+
+impl Watched {
+    /// Snapshot the changes, record the panic and abort,
+    /// unwinding would leave the state file to the watcher's threads.
+    fn crash(self, info: &PanicHookInfo) -> ! {
+        log::error!(
+            "nabu crashed, attempting a snapshot of the changes of {}",
+            self.repo.workdir().display()
+        );
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown panic"));
+        let location = info.location().map(ToString::to_string);
+        let mut crash = Crash::new(message, location);
+        match self.snapshot() {
+            Ok(snapshot) => crash.snapshot = snapshot.map(|id| id.to_string()),
+            Err(err) => log::error!("Failed to snapshot the changes: {}", err),
+        }
+        let path = state_path(self.repo.git_dir());
+        let mut state = State::read(&path).unwrap_or_default();
+        state.last_crash = Some(crash);
+        if let Err(err) = state.write(&path) {
+            log::error!("Failed to record the crash in {}: {}", path.display(), err);
+        }
+        flush_error_reports();
+        process::abort()
+    }
+
+    /// Stage and commit all changes, unless git is changing them or they may hold secrets.
+    /// Returns the snapshot's ID, `None` if there was nothing to commit.
+    fn snapshot(&self) -> Result<Option<git2::Oid>> {
+        let repo = &self.repo;
+        if !repo.has_changes()? {
+            return Ok(None);
+        }
+        if let Some(operation) = repo.git_operation(Duration::ZERO, None) {
+            log::warn!("Skipping the crash snapshot: {}.", operation);
+            return Ok(None);
+        }
+        if self.config.secret_scan && !repo.find_secrets(None)?.is_empty() {
+            log::warn!("Skipping the crash snapshot, the changes may hold secrets.");
+            return Ok(None);
+        }
+        let message = format!(
+            "{} @ {}",
+            self.config.catalog().crash_snapshot,
+            self.config
+                .timezone
+                .timestamp(&self.config.timestamp_format)
+        );
+        repo.stage_all()?;
+        repo.commit(&with_trailers(&message, None))?;
+        log::info!("commit with message: {}", message);
+        Ok(repo.head()?)
+    }
+}
@@ -0,0 +1,291 @@
+use std::{fs, path::PathBuf, thread};
+
+use chrono::DateTime;
+use clap::{Args, Subcommand};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use crossbeam_channel::{select, unbounded};
+use nabu::{
+    activity::{Activity, ACTIVITY_KINDS},
+    config::{find_config_path, Config, ConfigFormat},
+    control::{control_socket_path, TailedActivity},
+    git::WatchedRepository,
+    runtime::{parse_setting, runtime_settings_path, RuntimeSettings},
+    state::{state_path, State},
+    sync::PushOutcome,
+};
+
+use crate::{exit::Failure, output::OutputFormat, signal::Signals};
+
+/// `nabu`'s `ctl` command arguments structure.
+#[derive(Args)]
+pub(crate) struct CtlArgs {
+    #[clap(subcommand)]
+    command: CtlCommand,
+}
+
+#[derive(Subcommand)]
+enum CtlCommand {
+    /// Change a setting of the running watcher (e.g. `nabu ctl set delay 10`).
+    Set(SetArgs),
+    /// Stream the running watcher's activity (e.g. `nabu ctl tail --kind commit --kind push`).
+    Tail(TailArgs),
+}
+
+#[derive(Args)]
+struct SetArgs {
+    /// The top-level configuration key (e.g. `delay` or `push_every_n_commits`).
+    key: String,
+
+    /// The value, as written in TOML (e.g. `10`, `true` or `["a", "b"]`).
+    value: String,
+
+    /// The watched directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Also write the setting to the configuration file, instead of only until the watcher stops.
+    #[clap(long)]
+    persist: bool,
+}
+
+#[derive(Args)]
+struct TailArgs {
+    /// The watched directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Only show this kind of activity (e.g. `event`, `commit` or `push`), can be repeated.
+    #[clap(long = "kind")]
+    kinds: Vec<String>,
+
+    /// Output format, JSON prints an object per line.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+impl CtlArgs {
+    /// Execute the `ctl` command.
+    pub fn run(self, signals: Signals) -> Result<()> {
+        match self.command {
+            CtlCommand::Set(set) => set.run(),
+            CtlCommand::Tail(tail) => tail.run(signals),
+        }
+    }
+}
+
+impl SetArgs {
+    /// Apply the setting to the running watcher, reloading its configuration,
+    /// and to the configuration file with `--persist`.
+    fn run(self) -> Result<()> {
+        if self.key.contains('.') {
+            return Err(eyre!(
+                "only top-level keys can be set, edit the configuration file for {}",
+                self.key
+            ));
+        }
+        let value = parse_setting(&self.value);
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let watcher = State::read(&state_path(repo.git_dir())).filter(|state| !state.stopped());
+        if watcher.is_none() && !self.persist {
+            return Err(eyre!(
+                "no watcher is running for {}, use --persist to change the configuration file",
+                self.directory.display()
+            ));
+        }
+
+        // Reject the unknown keys and invalid values before touching anything
+        let mut settings = RuntimeSettings::read(&runtime_settings_path(repo.git_dir()))?;
+        settings.set(&self.key, value.clone());
+        let config = Config::load(self.config.as_deref(), &self.directory)
+            .and_then(|config| settings.apply(config))
+            .wrap_err(Failure::Config)?;
+        config.validate().wrap_err(Failure::Config)?;
+
+        if self.persist {
+            let path = self
+                .config
+                .clone()
+                .or_else(|| find_config_path(&self.directory))
+                .ok_or_else(|| eyre!("no configuration file found, create one with `nabu init`"))?;
+            let format = ConfigFormat::from_path(&path);
+            let mut file = format
+                .parse_value(&fs::read(&path)?)
+                .wrap_err(Failure::Config)?;
+            file.as_object_mut()
+                .ok_or_else(|| eyre!("{} is not a table", path.display()))?
+                .insert(self.key.clone(), value);
+            fs::write(&path, format.serialize_value(&file)?)?;
+            println!("{} = {} written to {}", self.key, self.value, path.display());
+        }
+
+        if let Some(watcher) = watcher {
+            // Also overrides the value previously set for the key at runtime, if any
+            settings.write(&runtime_settings_path(repo.git_dir()))?;
+            reload(watcher.pid)?;
+            println!(
+                "{} = {} applied to the watcher (pid {})",
+                self.key, self.value, watcher.pid
+            );
+        }
+        Ok(())
+    }
+}
+
+impl TailArgs {
+    /// Print the watcher's activity as it happens, until the watcher stops or `nabu` is interrupted.
+    #[cfg(unix)]
+    fn run(self, signals: Signals) -> Result<()> {
+        if let Some(kind) = self
+            .kinds
+            .iter()
+            .find(|kind| !ACTIVITY_KINDS.contains(&kind.as_str()))
+        {
+            return Err(eyre!(
+                "invalid activity kind {:?}, expected one of {}",
+                kind,
+                ACTIVITY_KINDS.join(", ")
+            ));
+        }
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let path = control_socket_path(repo.git_dir());
+        let lines = nabu::control::tail(&path).map_err(|err| {
+            eyre!(
+                "no watcher is running for {} ({}: {})",
+                self.directory.display(),
+                path.display(),
+                err
+            )
+        })?;
+        // Read apart, the socket gives no way to wait on the signals as well
+        let (line_snd, line_rcv) = unbounded();
+        thread::spawn(move || {
+            for line in lines {
+                if line_snd.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let wakeups = signals.wakeups();
+        while signals.is_running() {
+            select! {
+                recv(line_rcv) -> line => match line {
+                    Ok(line) => self.print(&line?),
+                    Err(_) => {
+                        eprintln!("The watcher stopped.");
+                        break;
+                    }
+                },
+                recv(wakeups) -> _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn run(self, _signals: Signals) -> Result<()> {
+        Err(eyre!(
+            "the watcher has no control socket on this platform, see its log instead"
+        ))
+    }
+
+    /// Print the activity sent by the watcher, unless filtered out.
+    #[cfg(unix)]
+    fn print(&self, line: &str) {
+        let tailed = match serde_json::from_str::<TailedActivity>(line) {
+            Ok(tailed) => tailed,
+            // Sent by a newer watcher
+            Err(err) => {
+                log::debug!("unknown activity {}: {}", line, err);
+                return;
+            }
+        };
+        let kind = tailed.activity.kind();
+        if !self.kinds.is_empty() && !self.kinds.iter().any(|shown| shown == kind) {
+            return;
+        }
+        match self.output {
+            OutputFormat::Json => println!("{}", line),
+            OutputFormat::Text => {
+                let time = DateTime::parse_from_rfc3339(&tailed.time)
+                    .map(|time| time.format("%H:%M:%S").to_string())
+                    .unwrap_or(tailed.time);
+                println!("{} {:<9} {}", time, kind, describe(&tailed.activity));
+            }
+        }
+    }
+}
+
+/// Describe the activity for humans, without its kind.
+#[cfg(unix)]
+fn describe(activity: &Activity) -> String {
+    match activity {
+        Activity::Event { description } => description.clone(),
+        Activity::Commit {
+            message,
+            latency_ms,
+        } => {
+            let summary = message.lines().next().unwrap_or_default();
+            match latency_ms {
+                Some(latency_ms) => format!("{} ({} ms after the change)", summary, latency_ms),
+                None => summary.to_string(),
+            }
+        }
+        Activity::Push { outcome, .. } => match outcome {
+            PushOutcome::Pushed => String::from("pushed"),
+            PushOutcome::Paused { conflicts } => format!(
+                "paused, rebasing conflicts on {}",
+                conflicts
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            PushOutcome::Failed { error, .. } => format!("failed: {}", error),
+            PushOutcome::Bundled { path, error } => {
+                format!("bundled into {}: {}", path.display(), error)
+            }
+        },
+        Activity::Paused | Activity::Resumed => String::new(),
+        Activity::Unwatched { paths } if paths.is_empty() => {
+            String::from("every directory is watched")
+        }
+        Activity::Unwatched { paths } => format!(
+            "{} directories cannot be watched: {}",
+            paths.len(),
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Activity::Usage { usage } => usage.describe(),
+    }
+}
+
+/// Request the watcher to reload its configuration.
+#[cfg(unix)]
+fn reload(pid: u32) -> Result<()> {
+    // SAFETY: sending a signal touches no memory of this process
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGHUP) } != 0 {
+        return Err(eyre!(
+            "failed to signal the watcher (pid {}): {}",
+            pid,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn reload(_pid: u32) -> Result<()> {
+    Err(eyre!(
+        "the watcher cannot reload its configuration on this platform, restart it to apply the setting"
+    ))
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    config::Config,
+    doctor::{diagnose, Diagnostic, Status},
+};
+
+use crate::{
+    auth::AuthArgs,
+    exit::Failure,
+    output::{print_json, OutputFormat},
+};
+
+/// `nabu`'s `doctor` command arguments structure.
+#[derive(Args)]
+pub(crate) struct DoctorArgs {
+    /// The directory to diagnose.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+
+    #[clap(flatten)]
+    auth: AuthArgs,
+}
+
+impl DoctorArgs {
+    /// Execute the `doctor` command, failing if any of the checks failed.
+    pub fn run(self) -> Result<()> {
+        let config = Config::load(self.config.as_deref(), &self.directory).ok();
+        if let Some(config) = &config {
+            config.apply_tls().wrap_err(Failure::Config)?;
+        }
+        let auth = config.and_then(|config| config.auth);
+        let authentication_method = self
+            .auth
+            .or_config(auth.as_ref())
+            .get_authentication_method()?;
+        let diagnostics = diagnose(
+            &self.directory,
+            self.config.as_deref(),
+            authentication_method.as_ref(),
+        );
+
+        let failures = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.status == Status::Fail)
+            .count();
+        if self.output == OutputFormat::Json {
+            print_json(&diagnostics)?;
+        } else {
+            print_diagnostics(&diagnostics);
+        }
+
+        if failures > 0 {
+            return Err(eyre!("{} checks failed", failures));
+        }
+        Ok(())
+    }
+}
+
+/// Print the diagnostics as text.
+fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        let status = match diagnostic.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        };
+        println!(
+            "[{:<4}] {}: {}",
+            status, diagnostic.check, diagnostic.message
+        );
+        if let Some(fix) = &diagnostic.fix {
+            println!("       fix: {}", fix);
+        }
+    }
+}
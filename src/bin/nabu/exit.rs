@@ -0,0 +1,92 @@
+use std::fmt::{self, Display};
+
+use color_eyre::{eyre::eyre, Report};
+use nabu::sync::PushOutcome;
+
+/// Failures with a dedicated exit code, following `sysexits.h`,
+/// so that wrapper scripts and service managers can react to them.
+/// They are attached to the errors as context (e.g. `.wrap_err(Failure::Config)`),
+/// other errors exit with 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Failure {
+    /// The configuration cannot be read or is invalid.
+    Config,
+    /// The remote rejected the credentials.
+    Authentication,
+    /// The push failed, or syncing paused on conflicts.
+    Push,
+    /// The directory is not inside a git repository.
+    RepositoryNotFound,
+    /// Another process holds a lock on the repository (e.g. `.git/index.lock`).
+    LockHeld,
+    /// The repository is in a state nabu cannot watch (e.g. in the middle of a rebase).
+    UnsupportedState,
+}
+
+impl Failure {
+    /// The process exit code.
+    pub fn code(self) -> i32 {
+        match self {
+            Failure::Config => 78,
+            Failure::Authentication => 77,
+            Failure::Push => 69,
+            Failure::RepositoryNotFound => 66,
+            Failure::LockHeld => 75,
+            Failure::UnsupportedState => 65,
+        }
+    }
+
+    /// The failure the error stands for, from its context or from the underlying git error.
+    pub fn of(report: &Report) -> Option<Self> {
+        if let Some(failure) = report.downcast_ref::<Failure>() {
+            return Some(*failure);
+        }
+        let err = report.downcast_ref::<git2::Error>()?;
+        match err.code() {
+            git2::ErrorCode::Auth => Some(Failure::Authentication),
+            git2::ErrorCode::Locked => Some(Failure::LockHeld),
+            git2::ErrorCode::NotFound if err.class() == git2::ErrorClass::Repository => {
+                Some(Failure::RepositoryNotFound)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Failure::Config => "invalid configuration",
+            Failure::Authentication => "authentication failed",
+            Failure::Push => "push failed",
+            Failure::RepositoryNotFound => "repository not found",
+            Failure::LockHeld => "the repository is locked",
+            Failure::UnsupportedState => "unsupported repository state",
+        };
+        f.write_str(description)
+    }
+}
+
+/// The error of an unsuccessful push, along with its failure.
+pub(crate) fn push_error(outcome: &PushOutcome) -> Option<Report> {
+    let (error, failure) = match outcome {
+        PushOutcome::Pushed | PushOutcome::Bundled { .. } => return None,
+        PushOutcome::Paused { conflicts } => (
+            eyre!("sync paused due to conflicts on {:?}", conflicts),
+            Failure::Push,
+        ),
+        PushOutcome::Failed {
+            error,
+            authentication,
+            ..
+        } => {
+            let failure = if *authentication {
+                Failure::Authentication
+            } else {
+                Failure::Push
+            };
+            (eyre!("{}", error), failure)
+        }
+    };
+    Some(error.wrap_err(failure))
+}
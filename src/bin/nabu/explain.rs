@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{eyre::WrapErr, Result};
+use nabu::{
+    config::{find_config_path, Config},
+    explain::{explain, Explanation, Verdict},
+    fs::ForceInclude,
+    git::WatchedRepository,
+};
+
+use crate::{
+    exit::Failure,
+    output::{print_json, OutputFormat},
+};
+
+/// `nabu`'s `explain` command arguments structure.
+#[derive(Args)]
+pub(crate) struct ExplainArgs {
+    /// The file to explain.
+    #[clap(parse(from_os_str))]
+    path: PathBuf,
+
+    /// The watched directory.
+    #[clap(short, long, parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+impl ExplainArgs {
+    /// Execute the `explain` command.
+    pub fn run(self) -> Result<()> {
+        let config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        // The same repository the watcher commits to
+        let mut repo = WatchedRepository::discover(&self.directory)?
+            .with_scope(&self.directory)
+            .with_unicode_normalization(config.unicode_normalization)
+            .with_track_new_files(config.track_new_files)
+            .with_force_include(ForceInclude::new(&config.force_include)?);
+        if !config.commit_config_secrets {
+            if let Some(config_path) = self
+                .config
+                .clone()
+                .or_else(|| find_config_path(&self.directory))
+            {
+                repo = repo.with_guarded_file(&config_path);
+            }
+        }
+        let explanation = explain(&self.directory, &repo, &config, &self.path)?;
+        if self.output == OutputFormat::Json {
+            print_json(&explanation)?;
+        } else {
+            print_explanation(&explanation);
+        }
+        Ok(())
+    }
+}
+
+/// Print the explanation as text.
+fn print_explanation(explanation: &Explanation) {
+    if explanation.committed {
+        println!("{} is committed", explanation.path.display());
+    } else {
+        println!("{} is not committed", explanation.path.display());
+    }
+    for finding in &explanation.findings {
+        let verdict = match finding.verdict {
+            Verdict::Skip => "skip",
+            Verdict::Note => "note",
+        };
+        println!("[{:<4}] {}: {}", verdict, finding.check, finding.message);
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use chrono::{Duration, Local, NaiveDate};
+use clap::{Args, ValueEnum};
+use color_eyre::Result;
+use nabu::{export::Report, git::WatchedRepository, journal::read_journal};
+
+use crate::output::print_json;
+
+/// Number of days exported by default, i.e. the past week.
+const DEFAULT_EXPORT_DAYS: i64 = 7;
+
+/// Format of the `export` output.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    /// Markdown, to paste into notes.
+    Markdown,
+    /// JSON, for scripts.
+    Json,
+}
+
+/// `nabu`'s `export` command arguments structure.
+#[derive(Args)]
+pub(crate) struct ExportArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// First day exported (YYYY-MM-DD), defaults to a week ago.
+    #[clap(long)]
+    since: Option<NaiveDate>,
+
+    /// Last day exported (YYYY-MM-DD), defaults to today.
+    #[clap(long)]
+    until: Option<NaiveDate>,
+
+    /// Export every commit, not just the ones made by nabu.
+    #[clap(long)]
+    all: bool,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "markdown")]
+    output: ExportFormat,
+}
+
+impl ExportArgs {
+    /// Execute the `export` command.
+    pub fn run(self) -> Result<()> {
+        let today = Local::now().date().naive_local();
+        let until = self.until.unwrap_or(today);
+        let since = self
+            .since
+            .unwrap_or_else(|| until - Duration::days(DEFAULT_EXPORT_DAYS - 1));
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let mut report = repo.report(Some(since), Some(until), self.all)?;
+        if let Some(journal) = read_journal(repo.git_dir()) {
+            let mut watcher = journal.days(Some(since), Some(until))?;
+            for day in &mut report.days {
+                day.watcher = watcher.remove(&day.date);
+            }
+        }
+        match self.output {
+            ExportFormat::Markdown => print!("{}", markdown(&report)),
+            ExportFormat::Json => print_json(&report)?,
+        }
+        Ok(())
+    }
+}
+
+/// Render the report as Markdown, one section per day.
+fn markdown(report: &Report) -> String {
+    let mut markdown = format!(
+        "# Activity from {} to {}\n\n",
+        report.since.as_deref().unwrap_or("the start"),
+        report.until.as_deref().unwrap_or("today")
+    );
+    markdown.push_str(&format!(
+        "{} commits, {} files changed, {} words added, {} removed.\n",
+        report.commits, report.files_changed, report.words_added, report.words_removed
+    ));
+    for day in &report.days {
+        markdown.push_str(&format!(
+            "\n## {}\n\n{} commits, {} words added, {} removed.\n\n",
+            day.date, day.commits, day.words_added, day.words_removed
+        ));
+        if let Some(watcher) = &day.watcher {
+            markdown.push_str(&format!(
+                "{} events, {} pushes, {} failed pushes.\n\n",
+                watcher.events, watcher.pushes, watcher.failed_pushes
+            ));
+        }
+        for file in &day.files {
+            markdown.push_str(&format!(
+                "- `{}` ({} edits)\n",
+                file.path.display(),
+                file.edits
+            ));
+        }
+    }
+    markdown
+}
@@ -0,0 +1,75 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use clap::Args;
+use color_eyre::{eyre::WrapErr, Result};
+use nabu::git::WatchedRepository;
+
+use crate::output::{print_json, OutputFormat};
+
+/// `nabu`'s `history` command arguments structure.
+#[derive(Args)]
+pub(crate) struct HistoryArgs {
+    /// The file, relative to the current directory.
+    #[clap(parse(from_os_str))]
+    file: PathBuf,
+
+    /// A directory of the repository.
+    #[clap(short = 'C', long, parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// List every commit changing the file, not just the ones made by nabu.
+    #[clap(long)]
+    all: bool,
+
+    /// Print the content of the file at this commit instead of the timeline.
+    #[clap(long, value_name = "COMMIT")]
+    at: Option<String>,
+
+    /// Write the content printed by `--at` to this file instead.
+    #[clap(long, parse(from_os_str), requires = "at")]
+    export: Option<PathBuf>,
+
+    /// Output format of the timeline.
+    #[clap(long, value_enum, default_value = "text", conflicts_with = "at")]
+    output: OutputFormat,
+}
+
+impl HistoryArgs {
+    /// Execute the `history` command.
+    pub fn run(self) -> Result<()> {
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let path = repo.resolve_path(&self.file)?;
+
+        if let Some(revision) = &self.at {
+            let content = repo.content_at(revision, &path)?;
+            return match &self.export {
+                Some(export) => std::fs::write(export, content)
+                    .wrap_err_with(|| format!("failed to write {}", export.display())),
+                None => Ok(io::stdout().write_all(&content)?),
+            };
+        }
+
+        let versions = repo.history(&path, self.all)?;
+        if self.output == OutputFormat::Json {
+            return print_json(&versions);
+        }
+
+        if versions.is_empty() {
+            println!("No commits changed {}", path.display());
+        }
+        for version in &versions {
+            println!(
+                "{} {} {:>6} {:>6}  {}",
+                &version.commit.id[..7],
+                version.commit.time,
+                format!("+{}", version.insertions),
+                format!("-{}", version.deletions),
+                version.commit.summary
+            );
+        }
+        Ok(())
+    }
+}
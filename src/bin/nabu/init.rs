@@ -1,27 +1,49 @@
 use std::env::current_dir;
 
-use clap::Args;
-use nabu::config::{global_config_path, Config};
+use clap::{Args, ValueEnum};
+use color_eyre::Result;
+use nabu::config::{global_config_path, Config, ConfigFormat};
+
+/// Format of the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum InitFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl From<InitFormat> for ConfigFormat {
+    fn from(format: InitFormat) -> Self {
+        match format {
+            InitFormat::Toml => ConfigFormat::Toml,
+            InitFormat::Yaml => ConfigFormat::Yaml,
+            InitFormat::Json => ConfigFormat::Json,
+        }
+    }
+}
 
 #[derive(Args)]
 pub(crate) struct InitArgs {
     /// Global configuration file
     #[clap(long)]
     global: bool,
+
+    /// Format of the configuration file.
+    #[clap(long, value_enum, default_value = "toml")]
+    format: InitFormat,
 }
 
 impl InitArgs {
-    pub fn run(self) {
-        let config = Config::default();
-        let config_toml = toml::to_string_pretty(&config).unwrap();
+    pub fn run(self) -> Result<()> {
+        let format = ConfigFormat::from(self.format);
+        let config = format.serialize(&Config::default())?;
         let path = if self.global {
-            global_config_path()
+            global_config_path(format)
         } else {
-            let mut local_config_path = current_dir().unwrap();
-            local_config_path.push("nabu.toml");
-            local_config_path
+            current_dir()?.join(format.file_name())
         };
-        std::fs::write(&path, config_toml).unwrap();
+        std::fs::write(&path, config)?;
         log::info!("config file written to {}", path.display());
+        Ok(())
     }
 }
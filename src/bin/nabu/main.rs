@@ -1,34 +1,213 @@
+mod adopt;
+mod annotate;
+mod attachments;
+mod auth;
+mod bench;
+mod commit_log;
+mod config;
+mod confirm;
+mod crash;
+mod ctl;
+mod doctor;
+mod exit;
+mod explain;
+mod export;
+mod history;
 mod init;
+mod merge_devices;
+mod observe;
+mod output;
+mod pid1;
+#[cfg(unix)]
+mod privileges;
+mod prompt;
+mod push;
+mod report;
+mod service;
+mod setup;
+mod signal;
+mod size;
+mod stats;
+mod status;
+mod suggest_ignores;
+mod tag;
+#[cfg(feature = "tui")]
+mod tui;
+mod verify;
 mod watch;
+#[cfg(feature = "web")]
+mod web;
+mod writers;
 
+use adopt::AdoptArgs;
+use annotate::AnnotateArgs;
+use attachments::{FilterArgs, RestoreArgs};
+use bench::BenchArgs;
+use commit_log::LogArgs;
+use config::ConfigArgs;
+use ctl::CtlArgs;
+use doctor::DoctorArgs;
+use exit::Failure;
+use explain::ExplainArgs;
+use export::ExportArgs;
+#[cfg(feature = "tui")]
+use flexi_logger::FileSpec;
 use flexi_logger::Logger;
+use history::HistoryArgs;
 use init::InitArgs;
-
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use merge_devices::MergeDevicesArgs;
+use observe::ObserveArgs;
+use pid1::DEFAULT_SHUTDOWN_TIMEOUT;
+use prompt::PromptArgs;
+use push::{PushArgs, SyncArgs};
+use service::ServiceArgs;
+use signal::Signals;
+use size::SizeArgs;
+use stats::StatsArgs;
+use status::StatusArgs;
+use suggest_ignores::SuggestIgnoresArgs;
+use tag::TagArgs;
+use verify::VerifyArgs;
 
 use watch::WatchArgs;
+use writers::WritersArgs;
+
+use std::{io::ErrorKind, time::Duration};
 
 use clap::{Parser, Subcommand};
-use color_eyre::Result;
+use color_eyre::{eyre::eyre, Result};
+use nabu::{
+    git::CREDENTIALS_LOG_TARGET,
+    plugin::{plugin_command, PLUGIN_PREFIX},
+};
 
 #[derive(Parser)]
 struct Cli {
     #[clap(subcommand)]
     commands: Commands,
-    /// Print debug information.
-    #[clap(long)]
+    /// Print debug information, same as `-v`.
+    #[clap(long, global = true)]
     debug: bool,
+    /// Only print warnings and errors, `-qq` for errors only.
+    #[clap(short, long, parse(from_occurrences), global = true)]
+    quiet: u8,
+    /// Print more information, `-vv` to trace.
+    #[clap(
+        short,
+        long,
+        parse(from_occurrences),
+        global = true,
+        conflicts_with = "quiet"
+    )]
+    verbose: u8,
+    /// Module log filters (e.g. `warn,nabu::git=debug`), replacing the level set by the other flags.
+    #[clap(long, global = true)]
+    log_filter: Option<String>,
+    /// Log each credential attempt made to the remote, to diagnose authentication failures.
+    #[clap(long, global = true)]
+    debug_auth: bool,
+    /// Run as the init process of a container: watch from a child process,
+    /// forwarding it the signals and reaping the processes left over by the hooks.
+    #[clap(long, global = true)]
+    pid1: bool,
+    /// Seconds given to the exit snapshot and push with `--pid1`, once asked to stop,
+    /// before the watcher is killed.
+    #[clap(long, global = true, requires = "pid1", default_value_t = DEFAULT_SHUTDOWN_TIMEOUT)]
+    shutdown_timeout: u64,
+}
+
+impl Cli {
+    /// The log specification, from the `--log-filter` or the verbosity flags.
+    fn log_spec(&self) -> String {
+        let spec = self.level_spec();
+        if self.debug_auth {
+            return format!("{},{}=debug", spec, CREDENTIALS_LOG_TARGET);
+        }
+        spec
+    }
+
+    /// The log specification, without the credential attempts.
+    fn level_spec(&self) -> String {
+        if let Some(log_filter) = &self.log_filter {
+            return log_filter.clone();
+        }
+        let verbose = self.verbose.max(u8::from(self.debug));
+        let level = match (self.quiet, verbose) {
+            (0, 0) => "info",
+            (0, 1) => "debug",
+            (0, _) => "trace",
+            (1, _) => "warn",
+            _ => "error",
+        };
+        level.to_string()
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Initialize a `nabu.toml` configuration file.
+    /// Diagnose common configuration problems.
+    Doctor(DoctorArgs),
+    /// Explain whether the watcher commits a file, and which rules or settings decide it.
+    Explain(ExplainArgs),
+    /// Initialize a `nabu.toml` (or `nabu.yaml`, `nabu.json`) configuration file.
     Init(InitArgs),
+    /// Turn an existing directory into a repository, with a `.gitignore` and a configuration file.
+    Adopt(AdoptArgs),
+    /// Manage the configuration file.
+    Config(ConfigArgs),
     /// Watch over a given directory
-    Watch(WatchArgs),
+    Watch(Box<WatchArgs>),
+    /// Print the events the watcher would receive, and what it would do with them,
+    /// without any git operation (e.g. to try the delays and ignore rules on a precious repository).
+    Observe(ObserveArgs),
+    /// Control the running watcher.
+    Ctl(CtlArgs),
+    /// Show the repository status.
+    Status(StatusArgs),
+    /// Print a compact status segment of the watcher for shell prompts, read from its state file.
+    Prompt(PromptArgs),
+    /// List the latest commits.
+    Log(LogArgs),
+    /// Show the commits changing a file, or its content at one of them.
+    History(HistoryArgs),
+    /// Push the current branch to the remote.
+    Push(PushArgs),
+    /// Fetch, rebase onto the remote and push.
+    Sync(SyncArgs),
+    /// Merge the references the devices push to (`push_mode = "device"`) into the current branch and push it.
+    MergeDevices(MergeDevicesArgs),
+    /// Check that the remote contains `HEAD`, optionally checking the files of a fresh clone.
+    Verify(VerifyArgs),
+    /// Summarize the activity recorded by nabu's commits.
+    Stats(StatsArgs),
+    /// Report the size of the working tree and of `.git`, their growth since the last check,
+    /// and the largest files committed by nabu.
+    Size(SizeArgs),
+    /// Export a Markdown or JSON report of the activity recorded by nabu's commits, day by day.
+    Export(ExportArgs),
+    /// Tag the last commit of each day, and `HEAD` as today's snapshot.
+    Tag(TagArgs),
+    /// Mark the history with what you are working on, as an empty commit or a note on the last commit.
+    Annotate(AnnotateArgs),
+    /// Suggest `.gitignore` patterns for the files the watcher keeps holding back for secrets,
+    /// committing while large, or committing over and over.
+    SuggestIgnores(SuggestIgnoresArgs),
+    /// List the files changing more than `changes_per_hour` times within an hour, see `[learning]`.
+    Writers(WritersArgs),
+    /// Replace the pointer files with their content from the `[attachments]` store.
+    Restore(RestoreArgs),
+    /// The git filter driver keeping the large files in the `[attachments]` store, run by git.
+    #[clap(hide = true)]
+    Filter(FilterArgs),
+    /// Measure how long synthetic writes take to be committed and pushed, in a temporary repository
+    /// watched with the configuration of a directory.
+    #[clap(hide = true)]
+    Bench(BenchArgs),
+    /// Run `nabu watch` as a service (e.g. a Windows service).
+    Service(ServiceArgs),
+    /// Run the `nabu-<name>` plugin executable, e.g. `nabu slack` runs `nabu-slack`.
+    #[clap(external_subcommand)]
+    External(Vec<String>),
 }
 
 fn main() -> Result<()> {
@@ -36,23 +215,92 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    let watching = Arc::new(AtomicBool::new(true));
-    let r = watching.clone();
+    let logger = Logger::try_with_str(cli.log_spec())?.use_utc();
+    // Services have no console, so their logs go to a file
+    let logger = match &cli.commands {
+        Commands::Service(args) => match args.log_file() {
+            Some(log_file) => logger.log_to_file(log_file),
+            None => logger,
+        },
+        _ => logger,
+    };
+    // The dashboard owns the terminal, so the logs go to a file instead
+    #[cfg(feature = "tui")]
+    let logger = match &cli.commands {
+        Commands::Watch(args) if args.tui => logger.log_to_file(
+            FileSpec::default()
+                .directory(std::env::temp_dir())
+                .basename("nabu"),
+        ),
+        _ => logger,
+    };
+    let _logger = logger.start()?;
 
-    ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
-    })
-    .expect("Error setting Ctrl-C handler");
+    if cli.pid1 && !pid1::is_child() {
+        return pid1::run(Duration::from_secs(cli.shutdown_timeout));
+    }
+    let signals = Signals::install()?;
+    #[cfg(feature = "rustls")]
+    nabu::transport::register()?;
 
-    let level = if cli.debug { "debug" } else { "info" };
-    Logger::try_with_str(level)?.use_utc().start()?;
+    let result = match cli.commands {
+        Commands::Watch(args) => args.run(signals),
+        Commands::Observe(observe) => observe.run(signals),
+        Commands::Init(init) => init.run(),
+        Commands::Adopt(adopt) => adopt.run(),
+        Commands::Config(config) => config.run(),
+        Commands::Ctl(ctl) => ctl.run(signals),
+        Commands::Doctor(doctor) => doctor.run(),
+        Commands::Explain(explain) => explain.run(),
+        Commands::Status(status) => status.run(),
+        Commands::Prompt(prompt) => prompt.run(),
+        Commands::Log(log) => log.run(),
+        Commands::History(history) => history.run(),
+        Commands::Push(push) => push.run(),
+        Commands::Sync(sync) => sync.run(),
+        Commands::MergeDevices(merge_devices) => merge_devices.run(),
+        Commands::Verify(verify) => verify.run(),
+        Commands::Stats(stats) => stats.run(),
+        Commands::Size(size) => size.run(),
+        Commands::Export(export) => export.run(),
+        Commands::Tag(tag) => tag.run(),
+        Commands::Annotate(annotate) => annotate.run(),
+        Commands::SuggestIgnores(suggest_ignores) => suggest_ignores.run(),
+        Commands::Writers(writers) => writers.run(),
+        Commands::Restore(restore) => restore.run(),
+        Commands::Filter(filter) => filter.run(),
+        Commands::Bench(bench) => bench.run(signals),
+        Commands::Service(service) => service.run(signals),
+        Commands::External(args) => run_plugin(&args),
+    };
 
-    match cli.commands {
-        Commands::Watch(args) => {
-            args.run(watching)?;
+    // The failures scripts can react to get their own exit code, see `Failure`
+    if let Err(err) = &result {
+        if let Some(failure) = Failure::of(err) {
+            eprintln!("Error: {:?}", err);
+            std::process::exit(failure.code());
         }
-        Commands::Init(init) => init.run(),
     }
+    result
+}
 
+/// Run a plugin executable with the arguments following its name, exiting with its exit code.
+fn run_plugin(args: &[String]) -> Result<()> {
+    let (name, args) = args.split_first().expect("the subcommand name is present");
+    let status = plugin_command(name)
+        .args(args)
+        .status()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => eyre!(
+                "unknown command {:?}, no {}{} executable in the PATH",
+                name,
+                PLUGIN_PREFIX,
+                name
+            ),
+            _ => eyre!("failed to run {}{}: {}", PLUGIN_PREFIX, name, err),
+        })?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
     Ok(())
 }
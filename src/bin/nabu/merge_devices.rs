@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    config::Config,
+    git::{MergeOutcome, PushMode, Repository, WatchedRepository},
+    sync::push,
+};
+
+use crate::{
+    auth::AuthArgs,
+    exit::{push_error, Failure},
+};
+
+/// `nabu`'s `merge-devices` command arguments structure.
+#[derive(Args)]
+pub(crate) struct MergeDevicesArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Merge the devices locally, without pushing the branch.
+    #[clap(long)]
+    no_push: bool,
+
+    #[clap(flatten)]
+    auth: AuthArgs,
+}
+
+impl MergeDevicesArgs {
+    /// Execute the `merge-devices` command, merging the references the devices push to
+    /// in the `device` push mode into the current branch, and pushing it.
+    pub fn run(self) -> Result<()> {
+        let mut config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        config.apply_tls().wrap_err(Failure::Config)?;
+        let authentication_method = self
+            .auth
+            .get_authentication_method()?
+            .ok_or_else(|| eyre!("no authentication method was provided"))?;
+
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let rolling_branch = config
+            .rolling_branch
+            .is_some()
+            .then(|| repo.branch())
+            .flatten();
+        let devices = repo.fetch_devices(&authentication_method, rolling_branch.as_deref())?;
+        if devices.is_empty() {
+            println!("No device pushed to the remote.");
+            return Ok(());
+        }
+
+        let mut merged = false;
+        let mut conflicted = vec![];
+        for (device, commit) in devices {
+            match repo.merge_device(&device, commit)? {
+                MergeOutcome::UpToDate => println!("{}: already merged", device),
+                MergeOutcome::FastForwarded => {
+                    println!("{}: fast-forwarded", device);
+                    merged = true;
+                }
+                MergeOutcome::Merged => {
+                    println!("{}: merged", device);
+                    merged = true;
+                }
+                MergeOutcome::Conflicted(paths) => {
+                    println!("{}: conflicts on {:?}, skipped", device, paths);
+                    conflicted.push(device);
+                }
+                MergeOutcome::Blocked(paths) => {
+                    return Err(eyre!(
+                        "merging {} would overwrite the local modifications to {:?}, commit or revert them first",
+                        device,
+                        paths
+                    ));
+                }
+            }
+        }
+
+        if merged && !self.no_push {
+            // The merged branch goes to the shared branch, not to this device's reference
+            config.push_mode = PushMode::Branch;
+            config.push_refspecs.clear();
+            // Rebasing would flatten the merges
+            config.sync = false;
+            config.sync_interval = None;
+            if let Some(err) = push_error(&push(&repo, Some(&authentication_method), &config)) {
+                return Err(err);
+            }
+        }
+
+        if !conflicted.is_empty() {
+            let tracking = match &rolling_branch {
+                Some(branch) => format!("origin/nabu/{}/{}", branch, conflicted[0]),
+                None => format!("origin/nabu/{}", conflicted[0]),
+            };
+            return Err(eyre!(
+                "the devices {} conflict with the branch, merge them manually (e.g. `git merge {}`)",
+                conflicted.join(", "),
+                tracking
+            ));
+        }
+        Ok(())
+    }
+}
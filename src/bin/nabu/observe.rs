@@ -0,0 +1,240 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError},
+    time::Duration,
+};
+
+use chrono::Local;
+use clap::Args;
+use color_eyre::{eyre::WrapErr, Result};
+use nabu::{
+    activity::Activity, app_state::is_app_state, config::Config, fs::Discovery, journal::Journal,
+    notes::session_id, overrides::Overrides,
+};
+use notify::DebouncedEvent;
+use serde::Serialize;
+
+use crate::{
+    exit::Failure,
+    output::OutputFormat,
+    signal::Signals,
+    watch::{describe_event, discovery, Watchers},
+};
+
+/// Interval between the checks for termination and for the newly discovered directories.
+const TICK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// `nabu`'s `observe` command arguments structure.
+#[derive(Args)]
+pub(crate) struct ObserveArgs {
+    /// The directory to observe.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Seconds to wait after the last change to a file before reporting it.
+    #[clap(long)]
+    delay: Option<u64>,
+
+    /// Glob patterns of the directories to ignore (e.g. `build` or `docs/build`).
+    #[clap(long)]
+    ignore: Vec<String>,
+
+    /// Record the events in the SQLite journal at this path, requires the `journal` feature.
+    #[clap(long, parse(from_os_str))]
+    journal: Option<PathBuf>,
+
+    /// Output format, JSON prints an object per line.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+/// An event, along with what the watcher would do with it.
+#[derive(Serialize)]
+struct Observation {
+    /// When the event was received (RFC 3339).
+    time: String,
+    description: String,
+    /// The file, relative to the observed directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    /// Whether the watcher would commit the change.
+    committed: bool,
+    /// What the watcher would do with the change.
+    verdict: String,
+}
+
+impl ObserveArgs {
+    /// Execute the `observe` command, reporting the events the watcher would receive
+    /// with the configured delays and ignore rules, without any git operation, until terminated.
+    pub fn run(self, signals: Signals) -> Result<()> {
+        let mut config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        if let Some(delay) = self.delay {
+            config.delay = delay;
+        }
+        if !self.ignore.is_empty() {
+            config.ignore = self.ignore.clone();
+        }
+        config.validate().wrap_err(Failure::Config)?;
+        let discovery = discovery(&self.directory, &config)?;
+        let overrides = Overrides::new(&config.directories)?;
+        let journal = self
+            .journal
+            .as_deref()
+            .map(Journal::open)
+            .transpose()
+            .wrap_err("failed to open the journal")?;
+        let session = session_id();
+        let observer = Observer {
+            root: discovery.root().to_path_buf(),
+            discovery: &discovery,
+            config: &config,
+            overrides: &overrides,
+        };
+
+        let (event_snd, event_rcv) = channel();
+        let mut watchers = Watchers::new(event_snd, config.limits.max_watches);
+        let mut discovered = Some(discovery.spawn());
+        let mut watched = 0;
+        log::info!(
+            "Observing {}, nothing is committed nor pushed.",
+            observer.root.display()
+        );
+        while signals.is_running() {
+            if let Some(directories) = &discovered {
+                let (count, done) = watch_discovered(&observer, &mut watchers, directories);
+                watched += count;
+                if done {
+                    log::info!("Watching {} directories.", watched);
+                    discovered = None;
+                }
+            }
+            let event = match event_rcv.recv_timeout(TICK_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let observation = match observer.observe(&event) {
+                Some(observation) => observation,
+                None => continue,
+            };
+            match self.output {
+                OutputFormat::Text => println!(
+                    "{}  {}  ({})",
+                    Local::now().format("%H:%M:%S"),
+                    observation.description,
+                    observation.verdict
+                ),
+                OutputFormat::Json => println!("{}", serde_json::to_string(&observation)?),
+            }
+            if let Some(journal) = &journal {
+                let activity = Activity::Event {
+                    description: observation.description,
+                };
+                if let Err(err) = journal.record(&session, &activity) {
+                    log::warn!("failed to record the event in the journal: {}", err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Watch the directories discovered so far,
+/// returns how many along with whether every directory was discovered.
+fn watch_discovered(
+    observer: &Observer,
+    watchers: &mut Watchers,
+    directories: &Receiver<PathBuf>,
+) -> (usize, bool) {
+    let mut watched = 0;
+    loop {
+        match directories.try_recv() {
+            Ok(directory) => {
+                let delay = observer.delay(&directory);
+                watchers.watch(directory, delay);
+                watched += 1;
+            }
+            Err(TryRecvError::Empty) => return (watched, false),
+            Err(TryRecvError::Disconnected) => return (watched, true),
+        }
+    }
+}
+
+/// Tells what the watcher would do with the events, from the configuration alone.
+struct Observer<'a> {
+    root: PathBuf,
+    discovery: &'a Discovery,
+    config: &'a Config,
+    overrides: &'a Overrides,
+}
+
+impl Observer<'_> {
+    /// The delay of the directory, see `[dir]`.
+    fn delay(&self, directory: &Path) -> u64 {
+        let relative = directory.strip_prefix(&self.root).unwrap_or(directory);
+        self.overrides
+            .settings(relative)
+            .delay
+            .unwrap_or(self.config.delay)
+    }
+
+    /// Describe the event along with what the watcher would do with it, `None` for the notices
+    /// announcing the events.
+    fn observe(&self, event: &DebouncedEvent) -> Option<Observation> {
+        let description = describe_event(event)?;
+        let path = match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::Remove(path)
+            | DebouncedEvent::Rename(_, path) => path,
+            DebouncedEvent::Rescan => {
+                return Some(self.observation(description, None, false, "events were missed"))
+            }
+            _ => return Some(self.observation(description, None, false, "watcher error")),
+        };
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let settings = self.overrides.settings(relative);
+        let (committed, verdict) = if self.discovery.is_git_internal(path) {
+            (false, String::from("git's own metadata, never committed"))
+        } else if matches!(event, DebouncedEvent::Chmod(_))
+            && self.config.ignore_chmod == Some(true)
+        {
+            (false, String::from("chmod, ignored, see ignore_chmod"))
+        } else if !settings.watch {
+            (false, String::from("not watched, see [dir]"))
+        } else if path.is_dir() && matches!(event, DebouncedEvent::Create(_)) {
+            (false, String::from("directory, its files are committed"))
+        } else if self.config.throttle_app_state && is_app_state(relative) {
+            (
+                true,
+                String::from("application state, committed at most once per hour"),
+            )
+        } else {
+            let delay = settings.delay.unwrap_or(self.config.delay);
+            (true, format!("committed after {}s", delay))
+        };
+        Some(self.observation(description, Some(relative), committed, &verdict))
+    }
+
+    fn observation(
+        &self,
+        description: String,
+        path: Option<&Path>,
+        committed: bool,
+        verdict: &str,
+    ) -> Observation {
+        Observation {
+            time: Local::now().to_rfc3339(),
+            description,
+            path: path.map(Path::to_path_buf),
+            committed,
+            verdict: verdict.to_string(),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use clap::ValueEnum;
+use color_eyre::Result;
+use serde::Serialize;
+
+/// Format of the command output.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    /// Human readable text.
+    Text,
+    /// JSON, for scripts and editor integrations.
+    Json,
+}
+
+/// Print a value as pretty JSON to the standard output.
+pub(crate) fn print_json<T>(value: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use color_eyre::{eyre::eyre, Result};
+
+/// Seconds the watcher has to take the exit snapshot and push, once asked to stop,
+/// before it is killed (Docker kills the container after 10 seconds by default).
+pub(crate) const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 8;
+
+/// Set on the watcher started by the init process, so it does not start another one.
+const CHILD_ENV_VAR: &str = "NABU_PID1_CHILD";
+
+/// Whether this process is the watcher started by the init process.
+pub(crate) fn is_child() -> bool {
+    std::env::var_os(CHILD_ENV_VAR).is_some()
+}
+
+/// Run as the init process of a container: start `nabu` again, with the same arguments,
+/// forward it the signals and reap every process reparented to this one (e.g. left over by hook commands).
+/// Once asked to stop, the watcher is killed if it is still running after `shutdown_timeout`.
+/// Exits with the exit code of the watcher.
+#[cfg(unix)]
+pub(crate) fn run(shutdown_timeout: Duration) -> Result<()> {
+    use std::{os::unix::process::CommandExt, process::Command, time::Instant};
+
+    use crossbeam_channel::{unbounded, RecvTimeoutError};
+    use signal_hook::consts::{SIGCHLD, SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2};
+
+    // Registered before starting the watcher, so that its exit is not missed
+    let mut incoming = signal_hook::iterator::Signals::new([
+        SIGCHLD, SIGINT, SIGTERM, SIGHUP, SIGQUIT, SIGUSR1, SIGUSR2,
+    ])?;
+    // The orphaned processes are reparented to this one even when it is not PID 1 (e.g. under a shell)
+    #[cfg(target_os = "linux")]
+    // SAFETY: only sets a flag of this process
+    unsafe {
+        libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1)
+    };
+    let (signal_snd, signal_rcv) = unbounded();
+    std::thread::spawn(move || {
+        for signal in incoming.forever() {
+            if signal_snd.send(signal).is_err() {
+                break;
+            }
+        }
+    });
+
+    // In its own process group, so that a Ctrl-C in the terminal reaches it once, forwarded,
+    // rather than twice (which skips the exit push)
+    let watcher = Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .env(CHILD_ENV_VAR, "1")
+        .process_group(0)
+        .spawn()?;
+    let watcher = watcher.id() as libc::pid_t;
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let signal = match deadline {
+            Some(deadline) => signal_rcv.recv_deadline(deadline),
+            None => signal_rcv
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match signal {
+            Ok(SIGCHLD) => {
+                if let Some(code) = reap(watcher) {
+                    std::process::exit(code);
+                }
+            }
+            Ok(signal) => {
+                // SAFETY: the watcher is only reaped right before exiting, its PID cannot be reused
+                unsafe { libc::kill(watcher, signal) };
+                if (signal == SIGINT || signal == SIGTERM) && deadline.is_none() {
+                    deadline = Some(Instant::now() + shutdown_timeout);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                log::error!(
+                    "Killing the watcher, it did not stop within {} seconds.",
+                    shutdown_timeout.as_secs()
+                );
+                // SAFETY: as above
+                unsafe { libc::kill(watcher, libc::SIGKILL) };
+                deadline = None;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(eyre!("stopped receiving the signals"));
+            }
+        }
+    }
+}
+
+/// Run as the init process of a container, only supported on Unix.
+#[cfg(not(unix))]
+pub(crate) fn run(_shutdown_timeout: Duration) -> Result<()> {
+    Err(eyre!("--pid1 is only supported on Unix"))
+}
+
+/// Reap the exited processes, returning the exit code of the watcher if it is one of them
+/// (128 plus the signal number if it was killed, as shells report it).
+#[cfg(unix)]
+fn reap(watcher: libc::pid_t) -> Option<i32> {
+    let mut code = None;
+    loop {
+        let mut status = 0;
+        // SAFETY: `status` is a valid pointer for the duration of the call
+        let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+        if pid <= 0 {
+            return code;
+        }
+        if pid == watcher {
+            code = Some(if libc::WIFEXITED(status) {
+                libc::WEXITSTATUS(status)
+            } else {
+                128 + libc::WTERMSIG(status)
+            });
+        }
+    }
+}
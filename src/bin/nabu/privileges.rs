@@ -0,0 +1,230 @@
+use std::{
+    ffi::CStr,
+    io,
+    os::unix::{fs::MetadataExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::Duration,
+};
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use crossbeam_channel::RecvTimeoutError;
+use nabu::fleet::RepositoryConfig;
+
+use crate::signal::Signals;
+
+/// Environment variable holding, as JSON, the repository of the fleet a worker process watches.
+const FLEET_REPOSITORY_ENV_VAR: &str = "NABU_FLEET_REPOSITORY";
+
+/// Interval between the checks of the workers, unless a signal arrives.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Most supplementary groups looked up for the owner of a repository.
+const MAX_GROUPS: usize = 256;
+
+/// The user a repository is watched as, the owner of its directory.
+struct Owner {
+    name: String,
+    home: PathBuf,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    groups: Vec<libc::gid_t>,
+}
+
+impl Owner {
+    /// The owner of the directory, along with their groups.
+    fn of(directory: &Path) -> Result<Self> {
+        let uid = directory.metadata()?.uid();
+        // SAFETY: plain data, filled in by `getpwuid_r`
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0; 16 * 1024];
+        let mut entry = std::ptr::null_mut();
+        // SAFETY: the buffer outlives the use of the strings `passwd` points into
+        unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut passwd,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut entry,
+            )
+        };
+        if entry.is_null() {
+            return Err(eyre!(
+                "no user with uid {}, the owner of {}",
+                uid,
+                directory.display()
+            ));
+        }
+        // SAFETY: set by `getpwuid_r` to NUL-terminated strings in the buffer
+        let (name, home) = unsafe {
+            (
+                CStr::from_ptr(passwd.pw_name),
+                CStr::from_ptr(passwd.pw_dir),
+            )
+        };
+        let gid = passwd.pw_gid;
+        Ok(Self {
+            groups: groups(name, gid),
+            name: name.to_string_lossy().into_owned(),
+            home: PathBuf::from(home.to_string_lossy().into_owned()),
+            uid,
+            gid,
+        })
+    }
+}
+
+/// The groups of the user, their primary group only if they cannot be looked up.
+#[cfg(target_os = "linux")]
+fn groups(name: &CStr, gid: libc::gid_t) -> Vec<libc::gid_t> {
+    let mut groups = vec![0; MAX_GROUPS];
+    let mut count = MAX_GROUPS as libc::c_int;
+    // SAFETY: `count` is the capacity of `groups`, updated to the number of groups written
+    let found = unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+    if found < 0 {
+        log::warn!(
+            "{} belongs to more than {} groups, keeping only their primary group",
+            name.to_string_lossy(),
+            MAX_GROUPS
+        );
+        return vec![gid];
+    }
+    groups.truncate(count as usize);
+    groups
+}
+
+/// The groups of the user, only their primary group outside of Linux.
+#[cfg(not(target_os = "linux"))]
+fn groups(_name: &CStr, gid: libc::gid_t) -> Vec<libc::gid_t> {
+    vec![gid]
+}
+
+/// Whether nabu runs as root, and can run the workers as the owners of the repositories.
+pub(crate) fn is_root() -> bool {
+    // SAFETY: always successful
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// The repository of the fleet this process watches, when it is a worker started by [`watch_as_owners`].
+pub(crate) fn worker_repository() -> Result<Option<RepositoryConfig>> {
+    match std::env::var(FLEET_REPOSITORY_ENV_VAR) {
+        Ok(repository) => Ok(Some(serde_json::from_str(&repository)?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Watch each repository from a worker process running as the owner of its directory,
+/// with the same arguments, forwarding them the termination and reload requests.
+/// Fails with the first worker failing, once the other workers are done.
+pub(crate) fn watch_as_owners(repositories: Vec<RepositoryConfig>, signals: Signals) -> Result<()> {
+    let mut workers = Vec::with_capacity(repositories.len());
+    for repository in repositories {
+        match start_worker(&repository) {
+            Ok(worker) => workers.push((repository.path, worker)),
+            Err(err) => {
+                // Not left running without anyone to stop them
+                for (_, worker) in &mut workers {
+                    signal(worker, libc::SIGTERM);
+                    let _ = worker.wait();
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    let wakeups = signals.wakeups();
+    // Termination requests forwarded so far, the second one skips the push on exit
+    let mut forwarded = 0;
+    let mut result = Ok(());
+    while !workers.is_empty() {
+        if let Err(RecvTimeoutError::Disconnected) = wakeups.recv_timeout(POLL_INTERVAL) {
+            return Err(eyre!("stopped receiving the signals"));
+        }
+        let requests = if signals.skip_push() {
+            2
+        } else {
+            usize::from(!signals.is_running())
+        };
+        let reload = signals.take_reload();
+        for (_, worker) in &workers {
+            for _ in forwarded..requests {
+                signal(worker, libc::SIGTERM);
+            }
+            if reload {
+                signal(worker, libc::SIGHUP);
+            }
+        }
+        forwarded = forwarded.max(requests);
+        workers.retain_mut(|(path, worker)| {
+            let status = match worker.try_wait() {
+                Ok(None) => return true,
+                Ok(Some(status)) if status.success() => return false,
+                Ok(Some(status)) => eyre!("the worker exited with {}", status),
+                Err(err) => eyre!("cannot wait for the worker: {}", err),
+            };
+            log::error!("Stopped watching {}: {}", path.display(), status);
+            if result.is_ok() {
+                result = Err(status);
+            }
+            false
+        });
+    }
+    result
+}
+
+/// Start the worker watching the repository as the owner of its directory.
+fn start_worker(repository: &RepositoryConfig) -> Result<Child> {
+    let owner = Owner::of(&repository.path)?;
+    if owner.uid == 0 {
+        log::warn!(
+            "{} is owned by root, it is watched as root",
+            repository.path.display()
+        );
+    } else {
+        log::info!("watching {} as {}", repository.path.display(), owner.name);
+    }
+    spawn_worker(repository, &owner).wrap_err_with(|| {
+        format!(
+            "cannot start the worker watching {} as {}",
+            repository.path.display(),
+            owner.name
+        )
+    })
+}
+
+/// Start `nabu` again, with the same arguments, as the owner, to watch the repository.
+fn spawn_worker(repository: &RepositoryConfig, owner: &Owner) -> Result<Child> {
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(FLEET_REPOSITORY_ENV_VAR, serde_json::to_string(repository)?)
+        .env("HOME", &owner.home)
+        .env("USER", &owner.name)
+        .env("LOGNAME", &owner.name)
+        // Out of the terminal's process group, Ctrl-C would otherwise reach them twice
+        .process_group(0);
+    let (uid, gid, groups) = (owner.uid, owner.gid, owner.groups.clone());
+    // SAFETY: only async-signal-safe system calls are made between the fork and the exec
+    unsafe {
+        command.pre_exec(move || {
+            // The groups first, they can no longer be changed once the user is
+            if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0
+                || libc::setgid(gid) != 0
+                || libc::setuid(uid) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        })
+    };
+    Ok(command.spawn()?)
+}
+
+/// Send the signal to the worker, unless it already exited.
+fn signal(worker: &Child, signal: libc::c_int) {
+    // SAFETY: the worker is only reaped by `try_wait`, after which it is no longer signaled
+    unsafe { libc::kill(worker.id() as libc::pid_t, signal) };
+}
@@ -0,0 +1,107 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use color_eyre::Result;
+use nabu::{
+    state::{state_path, State, WatcherState},
+    sync::PushOutcome,
+};
+use serde::Serialize;
+
+use crate::output::{print_json, OutputFormat};
+
+/// `nabu`'s `prompt` command arguments structure.
+#[derive(Args)]
+pub(crate) struct PromptArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+/// The watcher's health, as shown in a shell prompt.
+#[derive(Serialize)]
+struct Segment {
+    /// `watching`, `paused`, `stopped` or `crashed`.
+    state: &'static str,
+    /// Number of local commits missing from the upstream.
+    unpushed: usize,
+    /// What went wrong with the last push, if anything.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+impl PromptArgs {
+    /// Execute the `prompt` command, reading nothing but the state file so that it stays fast.
+    /// Outside of a watched repository it prints nothing, leaving the prompt as it is.
+    pub fn run(self) -> Result<()> {
+        let state = match find_git_dir(&self.directory)
+            .and_then(|git_dir| State::read(&state_path(&git_dir)))
+        {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        let segment = segment(&state);
+        if self.output == OutputFormat::Json {
+            return print_json(&segment);
+        }
+
+        let mut parts = vec![String::from("nabu"), segment.state.to_string()];
+        if segment.unpushed > 0 {
+            parts.push(format!("↑{}", segment.unpushed));
+        }
+        if let Some(error) = segment.error {
+            parts.push(error.to_string());
+        }
+        println!("{}", parts.join(" "));
+        Ok(())
+    }
+}
+
+/// Summarize the state into a prompt segment.
+fn segment(state: &State) -> Segment {
+    let state_name = match state.state {
+        _ if state.crashed() => "crashed",
+        WatcherState::Running => "watching",
+        WatcherState::Paused => "paused",
+        WatcherState::Stopped => "stopped",
+    };
+    let error = state
+        .last_push
+        .as_ref()
+        .and_then(|last_push| match &last_push.outcome {
+            PushOutcome::Pushed => None,
+            PushOutcome::Paused { .. } => Some("conflicts"),
+            PushOutcome::Failed { .. } => Some("push failed"),
+            PushOutcome::Bundled { .. } => Some("offline"),
+        });
+    Segment {
+        state: state_name,
+        unpushed: state.unpushed_commits.unwrap_or(0),
+        error,
+    }
+}
+
+/// The `.git` directory of the repository holding `directory`, found without libgit2,
+/// whose initialization alone takes longer than a prompt can wait.
+fn find_git_dir(directory: &Path) -> Option<PathBuf> {
+    let directory = directory.canonicalize().ok()?;
+    for ancestor in directory.ancestors() {
+        let dot_git = ancestor.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        // Worktrees and submodules have a `.git` file pointing to their directory
+        if let Ok(content) = fs::read_to_string(&dot_git) {
+            let git_dir = content.strip_prefix("gitdir:")?.trim();
+            return Some(ancestor.join(git_dir));
+        }
+    }
+    None
+}
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    config::Config,
+    git::{ConflictStrategy, WatchedRepository},
+    sync::push,
+};
+
+use crate::{
+    auth::AuthArgs,
+    exit::{push_error, Failure},
+    output::{print_json, OutputFormat},
+};
+
+/// `nabu`'s `push` command arguments structure.
+#[derive(Args)]
+pub(crate) struct PushArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+
+    #[clap(flatten)]
+    auth: AuthArgs,
+}
+
+/// `nabu`'s `sync` command arguments structure.
+#[derive(Args)]
+pub(crate) struct SyncArgs {
+    #[clap(flatten)]
+    push: PushArgs,
+
+    /// Keep both versions of conflicting Markdown files instead of pausing the sync.
+    #[clap(long)]
+    conflict_copies: bool,
+}
+
+impl SyncArgs {
+    /// Execute the `sync` command.
+    pub fn run(self) -> Result<()> {
+        let conflict_copies = self.conflict_copies;
+        self.push.run_with(|config| {
+            config.sync = true;
+            if conflict_copies {
+                config.on_conflict = ConflictStrategy::Copy;
+            }
+        })
+    }
+}
+
+impl PushArgs {
+    /// Execute the `push` command.
+    pub fn run(self) -> Result<()> {
+        self.run_with(|_| {})
+    }
+
+    /// Push after applying the command specific changes to the configuration.
+    fn run_with<F>(self, configure: F) -> Result<()>
+    where
+        F: FnOnce(&mut Config),
+    {
+        let mut config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        configure(&mut config);
+        config.apply_tls().wrap_err(Failure::Config)?;
+        let authentication_method = self
+            .auth
+            .or_config(config.auth.as_ref())
+            .get_authentication_method()?;
+        // Uploads to S3 authenticate through the environment
+        if authentication_method.is_none() && config.remote.s3.is_none() {
+            return Err(eyre!("no authentication method was provided"));
+        }
+
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let outcome = push(&repo, authentication_method.as_ref(), &config);
+        if self.output == OutputFormat::Json {
+            print_json(&outcome)?;
+        }
+
+        match push_error(&outcome) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
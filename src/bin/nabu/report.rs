@@ -0,0 +1,85 @@
+#[cfg(feature = "error-reports")]
+use std::time::Duration;
+use std::{sync::mpsc::Receiver, thread::JoinHandle};
+
+use color_eyre::Result;
+use nabu::activity::Activity;
+#[cfg(feature = "error-reports")]
+use {color_eyre::eyre::WrapErr, nabu::sync::PushOutcome, std::thread};
+
+/// Consecutive push failures after which they are reported.
+#[cfg(feature = "error-reports")]
+const REPORTED_PUSH_FAILURES: usize = 3;
+
+/// How long the pending reports may take to be sent before the process aborts.
+#[cfg(feature = "error-reports")]
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Report panics, and pushes failing `REPORTED_PUSH_FAILURES` times in a row,
+/// to the Sentry (or GlitchTip) project of the DSN.
+/// The reports stop once the watcher hangs up.
+#[cfg(feature = "error-reports")]
+pub(crate) fn spawn_error_reporter(
+    dsn: Option<&str>,
+    activity: Receiver<Activity>,
+) -> Result<Option<JoinHandle<()>>> {
+    let dsn = match dsn {
+        Some(dsn) => dsn
+            .parse::<sentry::types::Dsn>()
+            .wrap_err("invalid error_reports_dsn")?,
+        None => return Ok(None),
+    };
+    let guard = sentry::init(sentry::ClientOptions {
+        dsn: Some(dsn),
+        release: sentry::release_name!(),
+        ..Default::default()
+    });
+    Ok(Some(thread::spawn(move || {
+        let mut failures = 0;
+        for activity in activity {
+            let error = match activity {
+                Activity::Push {
+                    outcome: PushOutcome::Failed { error, .. },
+                    ..
+                } => error,
+                Activity::Push { .. } => {
+                    failures = 0;
+                    continue;
+                }
+                _ => continue,
+            };
+            failures += 1;
+            // Report once per streak of failures
+            if failures == REPORTED_PUSH_FAILURES {
+                sentry::capture_message(
+                    &format!("push failed {} times in a row: {}", failures, error),
+                    sentry::Level::Error,
+                );
+            }
+        }
+        drop(guard);
+    })))
+}
+
+/// Send the pending reports (e.g. of a panic) before the process aborts.
+#[cfg(feature = "error-reports")]
+pub(crate) fn flush_error_reports() {
+    if let Some(client) = sentry::Hub::current().client() {
+        client.flush(Some(FLUSH_TIMEOUT));
+    }
+}
+
+/// Without the `error-reports` feature there is nothing to report to.
+#[cfg(not(feature = "error-reports"))]
+pub(crate) fn spawn_error_reporter(
+    dsn: Option<&str>,
+    _activity: Receiver<Activity>,
+) -> Result<Option<JoinHandle<()>>> {
+    if dsn.is_some() {
+        log::warn!("error_reports_dsn is set but nabu was built without the error-reports feature, errors are not reported.");
+    }
+    Ok(None)
+}
+
+#[cfg(not(feature = "error-reports"))]
+pub(crate) fn flush_error_reports() {}
@@ -0,0 +1,313 @@
+use std::{env::current_dir, ffi::OsString, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use flexi_logger::FileSpec;
+
+use crate::{signal::Signals, watch::WatchArgs, Cli};
+
+/// Name the service is registered under, unless `--name` is set.
+const DEFAULT_SERVICE_NAME: &str = "nabu";
+
+/// `nabu`'s `service` command arguments structure.
+#[derive(Args)]
+pub(crate) struct ServiceArgs {
+    #[clap(subcommand)]
+    command: ServiceCommand,
+}
+
+#[derive(Subcommand)]
+enum ServiceCommand {
+    /// Register `nabu watch` as a service started with the machine, and start it.
+    Install(InstallArgs),
+    /// Stop and remove the service.
+    Uninstall(UninstallArgs),
+    /// Watch as the service, started by the service manager.
+    #[clap(hide = true)]
+    Run(Box<RunArgs>),
+}
+
+#[derive(Args)]
+struct InstallArgs {
+    /// Register a Windows service, the only service manager supported for now.
+    #[clap(long)]
+    windows: bool,
+
+    /// Name of the service.
+    #[clap(long, default_value = DEFAULT_SERVICE_NAME)]
+    name: String,
+
+    /// Account the service runs as (e.g. `.\me`), the local system account if not set.
+    #[clap(long)]
+    account: Option<String>,
+
+    /// Password of the account.
+    #[clap(long, requires = "account")]
+    password: Option<String>,
+
+    /// Arguments of the `watch` command run by the service (e.g. `-- C:\notes --push-on-exit`).
+    #[clap(last = true, required = true)]
+    watch: Vec<OsString>,
+}
+
+#[derive(Args)]
+struct UninstallArgs {
+    /// Remove a Windows service, the only service manager supported for now.
+    #[clap(long)]
+    windows: bool,
+
+    /// Name of the service.
+    #[clap(long, default_value = DEFAULT_SERVICE_NAME)]
+    name: String,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Name the service was registered under.
+    #[clap(long)]
+    name: String,
+
+    /// Directory the relative paths of the `watch` arguments are resolved from,
+    /// services start in the system directory.
+    #[clap(long, parse(from_os_str))]
+    working_directory: PathBuf,
+
+    #[clap(flatten)]
+    watch: WatchArgs,
+}
+
+impl ServiceArgs {
+    /// Execute the `service` command.
+    pub fn run(self, signals: Signals) -> Result<()> {
+        match self.command {
+            ServiceCommand::Install(install) => install.run(),
+            ServiceCommand::Uninstall(uninstall) => uninstall.run(),
+            ServiceCommand::Run(run) => run.run(signals),
+        }
+    }
+
+    /// The file the service logs to, as it has no console.
+    /// `None` unless running as the service.
+    pub fn log_file(&self) -> Option<FileSpec> {
+        match &self.command {
+            ServiceCommand::Run(run) => Some(
+                FileSpec::default()
+                    .directory(std::env::temp_dir())
+                    .basename(&run.name),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl InstallArgs {
+    fn run(self) -> Result<()> {
+        check_service_manager(self.windows)?;
+        let mut launch_arguments: Vec<OsString> = vec![
+            "service".into(),
+            "run".into(),
+            "--name".into(),
+            self.name.clone().into(),
+            "--working-directory".into(),
+            current_dir()?.into(),
+        ];
+        launch_arguments.extend(self.watch);
+        // Checked now, the service manager would only report the service failing to start
+        Cli::try_parse_from(
+            std::iter::once(OsString::from("nabu")).chain(launch_arguments.clone()),
+        )
+        .wrap_err("invalid watch arguments")?;
+        windows::install(&self.name, launch_arguments, self.account, self.password)?;
+        log::info!("service {} installed and started", self.name);
+        Ok(())
+    }
+}
+
+impl UninstallArgs {
+    fn run(self) -> Result<()> {
+        check_service_manager(self.windows)?;
+        windows::uninstall(&self.name)?;
+        log::info!("service {} removed", self.name);
+        Ok(())
+    }
+}
+
+impl RunArgs {
+    fn run(self, signals: Signals) -> Result<()> {
+        std::env::set_current_dir(&self.working_directory)?;
+        windows::run(self.name, self.watch, signals)
+    }
+}
+
+/// Fail unless a supported service manager was chosen.
+fn check_service_manager(windows: bool) -> Result<()> {
+    if !windows {
+        return Err(eyre!(
+            "no service manager chosen, only Windows services (`--windows`) are supported"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{ffi::OsString, sync::Mutex, time::Duration};
+
+    use color_eyre::Result;
+    use windows_service::{
+        define_windows_service,
+        service::{
+            ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+            ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
+            ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    use crate::{exit::Failure, signal::Signals, watch::WatchArgs};
+
+    /// The service `service_main` runs, set before handing the process over to the service manager.
+    static SERVICE: Mutex<Option<(String, WatchArgs, Signals)>> = Mutex::new(None);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Register the service, started automatically with the machine, and start it.
+    pub(super) fn install(
+        name: &str,
+        launch_arguments: Vec<OsString>,
+        account: Option<String>,
+        password: Option<String>,
+    ) -> Result<()> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )?;
+        let info = ServiceInfo {
+            name: name.into(),
+            display_name: format!("nabu ({})", name).into(),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            launch_arguments,
+            dependencies: vec![],
+            account_name: account.map(OsString::from),
+            account_password: password.map(OsString::from),
+        };
+        let service =
+            manager.create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)?;
+        service.set_description("Keeps the work committed and the repository up-to-date")?;
+        service.start::<OsString>(&[])?;
+        Ok(())
+    }
+
+    /// Stop the service, if running, and remove it.
+    pub(super) fn uninstall(name: &str) -> Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+        let service = manager.open_service(
+            name,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )?;
+        // Only marked for deletion, it is removed once stopped
+        service.delete()?;
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop()?;
+        }
+        Ok(())
+    }
+
+    /// Hand the process over to the service manager, which calls `service_main`.
+    pub(super) fn run(name: String, watch: WatchArgs, signals: Signals) -> Result<()> {
+        *SERVICE.lock().unwrap() = Some((name.clone(), watch, signals));
+        service_dispatcher::start(&name, ffi_service_main)?;
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let (name, watch, signals) = SERVICE
+            .lock()
+            .unwrap()
+            .take()
+            .expect("the service is set before starting the dispatcher");
+        if let Err(err) = run_service(&name, watch, signals) {
+            log::error!("{:?}", err);
+        }
+    }
+
+    fn run_service(name: &str, watch: WatchArgs, signals: Signals) -> Result<()> {
+        let handler = signals.clone();
+        let status_handle =
+            service_control_handler::register(name, move |control| match control {
+                // Same as Ctrl-C, watching stops after the exit snapshot and push
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    handler.request_termination();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            })?;
+        status_handle
+            .set_service_status(status(ServiceState::Running, ServiceExitCode::Win32(0)))?;
+        let result = watch.run(signals);
+        let exit_code = match &result {
+            Ok(()) => ServiceExitCode::Win32(0),
+            Err(err) => {
+                ServiceExitCode::ServiceSpecific(Failure::of(err).map_or(1, Failure::code) as u32)
+            }
+        };
+        status_handle.set_service_status(status(ServiceState::Stopped, exit_code))?;
+        result
+    }
+
+    fn status(current_state: ServiceState, exit_code: ServiceExitCode) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state,
+            controls_accepted: match current_state {
+                ServiceState::Running => {
+                    ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+                }
+                _ => ServiceControlAccept::empty(),
+            },
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod windows {
+    use std::ffi::OsString;
+
+    use color_eyre::{eyre::eyre, Report, Result};
+
+    use crate::{signal::Signals, watch::WatchArgs};
+
+    fn unsupported() -> Report {
+        eyre!("Windows services are only available on Windows")
+    }
+
+    pub(super) fn install(
+        _name: &str,
+        _launch_arguments: Vec<OsString>,
+        _account: Option<String>,
+        _password: Option<String>,
+    ) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn uninstall(_name: &str) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn run(_name: String, _watch: WatchArgs, _signals: Signals) -> Result<()> {
+        Err(unsupported())
+    }
+}
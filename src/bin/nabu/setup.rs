@@ -0,0 +1,134 @@
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use nabu::{
+    config::ConfigFormat,
+    doctor::{check_authentication, Status},
+    fleet::AuthConfig,
+    git::{Repository, WatchedRepository, TOKEN_VARIABLES},
+    migration::CONFIG_VERSION,
+};
+use serde_json::json;
+
+use crate::auth::AuthArgs;
+
+/// SSH keys proposed by the setup, in order, relative to `~/.ssh`.
+const DEFAULT_SSH_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Guide the first run of `nabu watch` with pushes enabled, but neither an authentication method
+/// nor a configuration file: propose the authentication methods fitting the remote,
+/// test the accepted one and write it to the `[auth]` section of a new `nabu.toml`.
+pub(crate) fn guided_setup(directory: &Path) -> Result<()> {
+    println!("Pushing is enabled, but no authentication method was given.");
+    let repo = WatchedRepository::discover(directory)?;
+    let url = match repo.origin_url()? {
+        Some(url) => url,
+        None => {
+            println!("There is no origin remote, add one with `git remote add origin <url>`.");
+            return Ok(());
+        }
+    };
+
+    if url.starts_with("https://") || url.starts_with("http://") {
+        // Tokens are read from the environment, never from the configuration file
+        let (variable, _, _) = TOKEN_VARIABLES
+            .iter()
+            .find(|(_, host, _)| url.contains(host))
+            .unwrap_or(&TOKEN_VARIABLES[0]);
+        println!(
+            "{} is an HTTPS remote, export an access token as {} (e.g. in your shell profile) and start nabu again.",
+            url, variable
+        );
+        return Ok(());
+    }
+    // e.g. `ssh://git@host/repo.git` or `git@host:repo.git`
+    let is_ssh = url.starts_with("ssh://")
+        || url
+            .split_once(':')
+            .is_some_and(|(host, _)| !host.contains('/'));
+    if !is_ssh {
+        println!(
+            "{} is neither an SSH nor an HTTPS remote, declare `--ssh-agent` or `--ssh-key <path>`.",
+            url
+        );
+        return Ok(());
+    }
+
+    println!("{} is an SSH remote.", url);
+    for (description, auth) in ssh_candidates() {
+        if !ask(&format!("Authenticate with {}? [Y/n] ", description))? {
+            continue;
+        }
+        let authentication_method = AuthArgs::from(&auth)
+            .get_authentication_method()?
+            .ok_or_else(|| eyre!("no authentication method was provided"))?;
+        let authentication = check_authentication(Some(&authentication_method));
+        if authentication.status == Status::Fail {
+            println!("{}", authentication.message);
+            continue;
+        }
+        if let Err(err) = repo.check_remote(Some(&authentication_method)) {
+            println!("The remote cannot be reached this way: {}", err.message());
+            continue;
+        }
+        println!("The remote is reachable.");
+
+        let path = directory.join(ConfigFormat::Toml.file_name());
+        let config = json!({ "version": CONFIG_VERSION, "auth": auth });
+        std::fs::write(&path, ConfigFormat::Toml.serialize_value(&config)?)?;
+        println!("Wrote the authentication method to {}.", path.display());
+        return Ok(());
+    }
+
+    println!("No authentication method was chosen, declare `--ssh-agent` or `--ssh-key <path>`.");
+    Ok(())
+}
+
+/// The SSH authentication methods available, along with their description.
+fn ssh_candidates() -> Vec<(String, AuthConfig)> {
+    let mut candidates = vec![];
+    if env::var_os("SSH_AUTH_SOCK").is_some() {
+        candidates.push((
+            String::from("the ssh-agent"),
+            AuthConfig {
+                ssh_agent: true,
+                ..AuthConfig::default()
+            },
+        ));
+    }
+    let ssh_dir = env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh"));
+    for key in DEFAULT_SSH_KEYS {
+        let path = match &ssh_dir {
+            Some(ssh_dir) => ssh_dir.join(key),
+            None => break,
+        };
+        if path.exists() {
+            candidates.push((
+                format!("the SSH key {}", path.display()),
+                AuthConfig {
+                    ssh_key: vec![path],
+                    ..AuthConfig::default()
+                },
+            ));
+        }
+    }
+    candidates
+}
+
+/// Ask a yes or no question, yes being the default.
+fn ask(question: &str) -> Result<bool> {
+    print!("{}", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Err(eyre!("the standard input was closed"));
+    }
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
@@ -0,0 +1,134 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use color_eyre::Result;
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+
+/// Termination requests after which the push on exit is skipped.
+const SKIP_PUSH_REQUESTS: usize = 2;
+
+/// Termination requests after which `nabu` exits right away.
+const ABORT_REQUESTS: usize = 3;
+
+/// Exit code when aborting, as if killed by `SIGINT`.
+const ABORT_EXIT_CODE: i32 = 130;
+
+/// Tracks the signals sent to `nabu`.
+///
+/// `SIGINT` (Ctrl-C) and `SIGTERM` request termination, each one escalating the shutdown:
+/// the first stops watching gracefully, the second skips the push on exit
+/// and the third exits immediately.
+/// `SIGHUP` requests the configuration to be reloaded.
+#[derive(Clone)]
+pub(crate) struct Signals {
+    running: Arc<AtomicBool>,
+    requests: Arc<AtomicUsize>,
+    /// Reloads requested so far.
+    reloads: Arc<AtomicUsize>,
+    /// Reloads already taken, see [`Signals::worker`].
+    reloads_taken: Arc<AtomicUsize>,
+    /// Woken up on each signal, so the watch loops do not wait for their next tick.
+    wakeups: Arc<Mutex<Vec<Sender<()>>>>,
+}
+
+impl Signals {
+    /// Handle the signals.
+    pub fn install() -> Result<Self> {
+        let signals = Self {
+            running: Arc::new(AtomicBool::new(true)),
+            requests: Arc::new(AtomicUsize::new(0)),
+            reloads: Arc::new(AtomicUsize::new(0)),
+            reloads_taken: Arc::new(AtomicUsize::new(0)),
+            wakeups: Arc::new(Mutex::new(Vec::new())),
+        };
+        let handler = signals.clone();
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+            let mut incoming = signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+            std::thread::spawn(move || {
+                for signal in incoming.forever() {
+                    match signal {
+                        SIGHUP => handler.request_reload(),
+                        _ => handler.request_termination(),
+                    }
+                }
+            });
+        }
+        #[cfg(not(unix))]
+        ctrlc::set_handler(move || handler.request_termination())?;
+        Ok(signals)
+    }
+
+    /// Flag which is cleared once `nabu` should stop watching.
+    #[cfg(any(feature = "tui", feature = "web"))]
+    pub fn running(&self) -> Arc<AtomicBool> {
+        self.running.clone()
+    }
+
+    /// The signals as seen by a worker watching another directory,
+    /// which takes the reload requests independently of the others.
+    pub fn worker(&self) -> Self {
+        Self {
+            reloads_taken: Arc::new(AtomicUsize::new(self.reloads.load(Ordering::SeqCst))),
+            ..self.clone()
+        }
+    }
+
+    /// Receives a message once a signal arrives.
+    pub fn wakeups(&self) -> Receiver<()> {
+        // A pending wakeup is enough, the requests themselves are tracked by the counters
+        let (wakeup_snd, wakeup_rcv) = bounded(1);
+        self.wakeups.lock().unwrap().push(wakeup_snd);
+        wakeup_rcv
+    }
+
+    /// Whether `nabu` should keep watching.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Whether the push on exit should be skipped.
+    pub fn skip_push(&self) -> bool {
+        self.requests.load(Ordering::SeqCst) >= SKIP_PUSH_REQUESTS
+    }
+
+    /// Whether the configuration should be reloaded, clearing the request.
+    pub fn take_reload(&self) -> bool {
+        let reloads = self.reloads.load(Ordering::SeqCst);
+        self.reloads_taken.swap(reloads, Ordering::SeqCst) != reloads
+    }
+
+    /// Wake up the watch loops, forgetting the ones which stopped.
+    fn wake_up(&self) {
+        self.wakeups
+            .lock()
+            .unwrap()
+            .retain(|wakeup| !matches!(wakeup.try_send(()), Err(TrySendError::Disconnected(_))));
+    }
+
+    /// Request termination, as `SIGINT` and `SIGTERM` do.
+    pub fn request_termination(&self) {
+        let requests = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        self.running.store(false, Ordering::SeqCst);
+        self.wake_up();
+        if requests >= ABORT_REQUESTS {
+            log::error!("Aborting, the exit snapshot and push may not have completed.");
+            std::process::exit(ABORT_EXIT_CODE);
+        } else if requests >= SKIP_PUSH_REQUESTS {
+            log::warn!("Skipping the push on exit, press Ctrl-C again to abort.");
+        } else {
+            log::info!("Shutting down, press Ctrl-C again to skip the push.");
+        }
+    }
+
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn request_reload(&self) {
+        log::info!("Reload requested.");
+        self.reloads.fetch_add(1, Ordering::SeqCst);
+        self.wake_up();
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{eyre::WrapErr, Result};
+use nabu::{
+    config::Config,
+    git::WatchedRepository,
+    size::{format_bytes, Growth, LargeBlob, SizeCheck},
+};
+use serde::Serialize;
+
+use crate::{
+    exit::Failure,
+    output::{print_json, OutputFormat},
+};
+
+/// Default number of blobs listed.
+const DEFAULT_TOP_BLOBS: usize = 10;
+
+/// `nabu`'s `size` command arguments structure.
+#[derive(Args)]
+pub(crate) struct SizeArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Number of largest blobs introduced by nabu's commits to list.
+    #[clap(long, default_value_t = DEFAULT_TOP_BLOBS)]
+    top: usize,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+/// The sizes of the repository, as reported by `nabu size`.
+#[derive(Serialize)]
+struct SizeReport {
+    #[serde(flatten)]
+    check: SizeCheck,
+    /// The growth since the last check, `None` on the first one.
+    growth: Option<Growth>,
+    /// Whether the `.git` directory grew faster than `growth_warning_megabytes_per_day`.
+    growth_exceeded: bool,
+    largest_blobs: Vec<LargeBlob>,
+}
+
+impl SizeArgs {
+    /// Execute the `size` command, keeping the sizes as the last check.
+    pub fn run(self) -> Result<()> {
+        let config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let check = SizeCheck::measure(repo.workdir(), repo.git_dir())
+            .wrap_err("failed to measure the repository")?;
+        let growth =
+            SizeCheck::read(repo.git_dir()).and_then(|previous| check.growth_since(&previous));
+        if let Err(err) = check.write(repo.git_dir()) {
+            log::warn!("failed to keep the size check: {}", err);
+        }
+        let exceeded_threshold = config.growth_warning_megabytes_per_day.filter(|threshold| {
+            growth
+                .as_ref()
+                .is_some_and(|growth| growth.exceeds(*threshold))
+        });
+        let report = SizeReport {
+            check,
+            growth,
+            growth_exceeded: exceeded_threshold.is_some(),
+            largest_blobs: repo.largest_blobs(self.top)?,
+        };
+        match self.output {
+            OutputFormat::Text => print_text(&report),
+            OutputFormat::Json => print_json(&report)?,
+        }
+        if let Some(threshold) = exceeded_threshold {
+            log::warn!(
+                "The .git directory grows faster than {} MB per day.",
+                threshold
+            );
+        }
+        Ok(())
+    }
+}
+
+fn print_text(report: &SizeReport) {
+    println!("Working tree  {}", format_bytes(report.check.worktree));
+    println!(".git          {}", format_bytes(report.check.git));
+
+    if let Some(growth) = &report.growth {
+        println!("\nSince the last check ({}):", growth.since);
+        println!("  working tree  {}", format_change(growth.worktree));
+        match growth.git_megabytes_per_day() {
+            Some(rate) => println!(
+                "  .git          {} ({:.1} MB per day)",
+                format_change(growth.git),
+                rate
+            ),
+            None => println!("  .git          {}", format_change(growth.git)),
+        }
+    }
+
+    if !report.largest_blobs.is_empty() {
+        println!("\nLargest blobs introduced by nabu's commits:");
+        for blob in &report.largest_blobs {
+            println!(
+                "  {:>9}  {}  {:.7}  {}",
+                format_bytes(blob.size),
+                blob.path.display(),
+                blob.commit,
+                blob.time
+            );
+        }
+    }
+}
+
+/// Format a change in bytes, e.g. `+1.2 MB`.
+fn format_change(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_bytes(bytes.unsigned_abs()))
+}
@@ -0,0 +1,193 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, ValueEnum};
+use color_eyre::Result;
+use nabu::{
+    git::WatchedRepository,
+    journal::read_journal,
+    latency::{format_ms, LatencyHistogram, LATENCY_BUCKETS},
+    state::{state_path, State},
+    stats::Stats,
+};
+
+use crate::output::print_json;
+
+/// Default number of files listed in the text output.
+const DEFAULT_TOP_FILES: usize = 10;
+
+/// Format of the `stats` output.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum StatsFormat {
+    /// Human readable text.
+    Text,
+    /// JSON, with every table.
+    Json,
+    /// CSV, with the table selected by `--table`.
+    Csv,
+}
+
+/// Table exported as CSV.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum StatsTable {
+    /// Commits and Markdown words added per day.
+    Day,
+    /// Edits per file.
+    File,
+    /// Commits per hour of the day.
+    Hour,
+    /// Commit and push latencies per bucket.
+    Latency,
+}
+
+/// `nabu`'s `stats` command arguments structure.
+#[derive(Args)]
+pub(crate) struct StatsArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Only count commits made on or after this date (YYYY-MM-DD).
+    #[clap(long)]
+    since: Option<NaiveDate>,
+
+    /// Count every commit, not just the ones made by nabu.
+    #[clap(long)]
+    all: bool,
+
+    /// Number of most edited files shown in the text output.
+    #[clap(long, default_value_t = DEFAULT_TOP_FILES)]
+    top: usize,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: StatsFormat,
+
+    /// Table exported with `--output csv`.
+    #[clap(long, value_enum, default_value = "day")]
+    table: StatsTable,
+}
+
+impl StatsArgs {
+    /// Execute the `stats` command.
+    pub fn run(self) -> Result<()> {
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let mut stats = repo.stats(self.since, self.all)?;
+        // The journal keeps the latencies of every session, even the ones which crashed
+        stats.latency = match read_journal(repo.git_dir()) {
+            Some(journal) => Some(journal.latencies()?),
+            None => State::read(&state_path(repo.git_dir())).map(|state| state.latency),
+        };
+        match self.output {
+            StatsFormat::Text => print_text(&stats, self.top),
+            StatsFormat::Json => print_json(&stats)?,
+            StatsFormat::Csv => print_csv(&stats, self.table),
+        }
+        Ok(())
+    }
+}
+
+fn print_text(stats: &Stats, top: usize) {
+    println!("{} commits over {} days", stats.commits, stats.days.len());
+
+    println!("\nPer day:");
+    for day in &stats.days {
+        println!(
+            "  {}  {:>5} commits  {:>6} words",
+            day.date, day.commits, day.words_added
+        );
+    }
+
+    println!("\nMost edited files:");
+    for file in stats.files.iter().take(top) {
+        println!("  {:>5}  {}", file.edits, file.path.display());
+    }
+
+    println!("\nBusiest hours:");
+    let mut hours = stats
+        .hours
+        .iter()
+        .filter(|hour| hour.commits > 0)
+        .collect::<Vec<_>>();
+    hours.sort_by(|a, b| b.commits.cmp(&a.commits).then(a.hour.cmp(&b.hour)));
+    for hour in hours.into_iter().take(3) {
+        println!("  {:02}:00  {:>5} commits", hour.hour, hour.commits);
+    }
+
+    if let Some(latency) = &stats.latency {
+        println!("\nLatency:");
+        print_latency("event to commit", &latency.event_to_commit);
+        print_latency("commit to push", &latency.commit_to_push);
+    }
+}
+
+fn print_latency(name: &str, histogram: &LatencyHistogram) {
+    let (mean, p50, p90) = match (
+        histogram.mean_ms(),
+        histogram.percentile_ms(50),
+        histogram.percentile_ms(90),
+    ) {
+        (Some(mean), Some(p50), Some(p90)) => (mean, p50, p90),
+        _ => {
+            println!("  {:<16} no samples", name);
+            return;
+        }
+    };
+    println!(
+        "  {:<16} {:>5} samples  mean {}  p50 <= {}  p90 <= {}  max {}",
+        name,
+        histogram.count,
+        format_ms(mean),
+        format_ms(p50),
+        format_ms(p90),
+        format_ms(histogram.max_ms)
+    );
+}
+
+fn print_csv(stats: &Stats, table: StatsTable) {
+    match table {
+        StatsTable::Day => {
+            println!("date,commits,words_added");
+            for day in &stats.days {
+                println!("{},{},{}", day.date, day.commits, day.words_added);
+            }
+        }
+        StatsTable::File => {
+            println!("path,edits");
+            for file in &stats.files {
+                println!("{},{}", csv_field(&file.path.to_string_lossy()), file.edits);
+            }
+        }
+        StatsTable::Hour => {
+            println!("hour,commits");
+            for hour in &stats.hours {
+                println!("{},{}", hour.hour, hour.commits);
+            }
+        }
+        StatsTable::Latency => {
+            println!("latency,le_seconds,count");
+            if let Some(latency) = &stats.latency {
+                for (name, histogram) in [
+                    ("event_to_commit", &latency.event_to_commit),
+                    ("commit_to_push", &latency.commit_to_push),
+                ] {
+                    for (bucket, count) in histogram.buckets.iter().enumerate() {
+                        let bound = LATENCY_BUCKETS
+                            .get(bucket)
+                            .map_or_else(|| String::from("inf"), u64::to_string);
+                        println!("{},{},{}", name, bound, count);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Quote a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
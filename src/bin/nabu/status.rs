@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::Result;
+use nabu::{
+    config::Config,
+    git::{RepositoryStatus, WatchedRepository},
+    journal::{read_journal, JournalStatus},
+    limits::ResourceUsage,
+    locale::Locale,
+    message::render,
+    state::{state_path, State},
+    sync::PushOutcome,
+};
+use serde::Serialize;
+
+use crate::output::{print_json, OutputFormat};
+
+/// `nabu`'s `status` command arguments structure.
+#[derive(Args)]
+pub(crate) struct StatusArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file, setting the `locale`.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+/// The repository status, along with what the running watcher reports.
+#[derive(Serialize)]
+struct Status {
+    #[serde(flatten)]
+    repository: RepositoryStatus,
+    /// Directories the running watcher cannot watch.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unwatched: Vec<PathBuf>,
+    /// The resources the running watcher uses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<ResourceUsage>,
+    /// The error of the push the remote rejected for good, stopping the running watcher's pushes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    push_rejected: Option<String>,
+    /// The last commit and push, from the watcher's journal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    journal: Option<JournalStatus>,
+}
+
+impl StatusArgs {
+    /// Execute the `status` command.
+    pub fn run(self) -> Result<()> {
+        let repo = WatchedRepository::discover(&self.directory)?.with_scope(&self.directory);
+        let state = State::read(&state_path(repo.git_dir())).filter(|state| !state.stopped());
+        let push_rejected = state
+            .as_ref()
+            .and_then(|state| state.last_push.as_ref())
+            .and_then(|last_push| match &last_push.outcome {
+                PushOutcome::Failed {
+                    error,
+                    permanent: true,
+                    ..
+                } => Some(error.clone()),
+                _ => None,
+            });
+        let journal = read_journal(repo.git_dir())
+            .map(|journal| journal.status())
+            .transpose()?;
+        let usage = state.as_ref().and_then(|state| state.usage.clone());
+        let status = Status {
+            repository: repo.status()?,
+            unwatched: state.map(|state| state.unwatched).unwrap_or_default(),
+            usage,
+            push_rejected,
+            journal,
+        };
+        if self.output == OutputFormat::Json {
+            return print_json(&status);
+        }
+
+        // The output is still printed with a broken configuration, in the environment's language
+        let catalog = Config::load(self.config.as_deref(), &self.directory)
+            .map_or_else(|_| Locale::from_env().catalog(), |config| config.catalog());
+        let Status {
+            repository: status,
+            unwatched,
+            usage,
+            push_rejected,
+            journal,
+        } = status;
+        match (&status.branch, &status.head) {
+            (Some(branch), _) => println!("{}", render(catalog.on_branch, &[("branch", branch)])),
+            (None, Some(head)) => println!("{}", render(catalog.detached_head, &[("head", head)])),
+            (None, None) => println!("{}", catalog.no_commits),
+        }
+        if let (Some(upstream), Some(ahead), Some(behind)) =
+            (&status.upstream, status.ahead, status.behind)
+        {
+            let (ahead, behind) = (ahead.to_string(), behind.to_string());
+            println!(
+                "{}",
+                render(
+                    catalog.ahead_behind,
+                    &[
+                        ("ahead", &ahead),
+                        ("behind", &behind),
+                        ("upstream", upstream)
+                    ]
+                )
+            );
+        }
+        if status.changes.is_empty() {
+            println!("{}", catalog.no_changes);
+        } else {
+            let count = status.changes.len().to_string();
+            println!("{}", render(catalog.changes, &[("count", &count)]));
+            for path in &status.changes {
+                println!("  {}", path.display());
+            }
+        }
+        if !unwatched.is_empty() {
+            let count = unwatched.len().to_string();
+            println!("{}", render(catalog.unwatched, &[("count", &count)]));
+            for path in &unwatched {
+                println!("  {}", path.display());
+            }
+        }
+        if let Some(usage) = usage {
+            println!("{}", render(catalog.usage, &[("usage", &usage.describe())]));
+        }
+        if let Some(journal) = journal {
+            if let Some(commit) = &journal.last_commit {
+                let message = commit.summary.lines().next().unwrap_or_default();
+                println!(
+                    "{}",
+                    render(
+                        catalog.last_commit,
+                        &[("time", &commit.time), ("message", message)]
+                    )
+                );
+            }
+            if let Some(push) = &journal.last_push {
+                println!(
+                    "{}",
+                    render(
+                        catalog.last_push,
+                        &[("time", &push.time), ("outcome", &push.summary)]
+                    )
+                );
+            }
+            if journal.failed_pushes > 0 {
+                let count = journal.failed_pushes.to_string();
+                println!("{}", render(catalog.failed_pushes, &[("count", &count)]));
+                if let Some(error) = journal.last_push.and_then(|push| push.error) {
+                    println!("  {}", error);
+                }
+            }
+        }
+        if let Some(error) = push_rejected {
+            println!("{}", render(catalog.push_rejected, &[("error", &error)]));
+            println!("  {}", catalog.push_rejected_hint);
+        }
+        Ok(())
+    }
+}
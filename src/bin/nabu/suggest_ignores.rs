@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{eyre::WrapErr, Result};
+use nabu::{
+    git::{Repository, WatchedRepository},
+    suggestions::{append_to_gitignore, observations_path, Observations},
+};
+
+use crate::output::{print_json, OutputFormat};
+
+/// `nabu`'s `suggest-ignores` command arguments structure.
+#[derive(Args)]
+pub(crate) struct SuggestIgnoresArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Append the suggested patterns to `.gitignore`.
+    #[clap(long)]
+    write: bool,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+impl SuggestIgnoresArgs {
+    /// Execute the `suggest-ignores` command, suggesting to ignore the files the watcher
+    /// kept holding back for secrets, committing while large, or committing over and over.
+    pub fn run(self) -> Result<()> {
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let path = observations_path(repo.git_dir());
+        let mut observations = Observations::read(&path);
+        let mut suggestions = observations.suggestions();
+        // Ignored since, by hand or by an earlier run, or removed altogether
+        for suggestion in &suggestions {
+            let file = repo.workdir().join(&suggestion.path);
+            if !file.exists() || repo.ignore_rule(&file)?.is_some() {
+                observations.forget(&suggestion.path);
+            }
+        }
+        suggestions.retain(|suggestion| observations.paths.contains_key(&suggestion.path));
+
+        if self.output == OutputFormat::Json {
+            print_json(&suggestions)?;
+        } else if suggestions.is_empty() {
+            println!("Nothing to ignore.");
+        } else {
+            for suggestion in &suggestions {
+                println!(
+                    "{}  ({})",
+                    suggestion.pattern,
+                    suggestion.reasons.join(", ")
+                );
+            }
+        }
+
+        if self.write && !suggestions.is_empty() {
+            let patterns: Vec<_> = suggestions
+                .iter()
+                .map(|suggestion| suggestion.pattern.clone())
+                .collect();
+            append_to_gitignore(repo.workdir(), &patterns)
+                .wrap_err("failed to update the .gitignore")?;
+            for suggestion in &suggestions {
+                observations.forget(&suggestion.path);
+                if repo.is_tracked(repo.workdir().join(&suggestion.path))? {
+                    eprintln!(
+                        "{} is still tracked, stop tracking it with `git rm --cached {}`.",
+                        suggestion.path.display(),
+                        suggestion.path.display()
+                    );
+                }
+            }
+            if self.output == OutputFormat::Text {
+                println!("Appended the patterns to .gitignore");
+            }
+        }
+        observations
+            .write(&path)
+            .wrap_err("failed to update the observations")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use nabu::{
+    config::Config,
+    git::{Repository, WatchedRepository},
+};
+
+use crate::exit::Failure;
+
+/// `nabu`'s `tag` command arguments structure.
+#[derive(Args)]
+pub(crate) struct TagArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Move today's tag if it already exists.
+    #[clap(long)]
+    force: bool,
+}
+
+impl TagArgs {
+    /// Execute the `tag` command, tagging `HEAD` as today's snapshot along with the untagged past days.
+    pub fn run(self) -> Result<()> {
+        let timezone = Config::load(self.config.as_deref(), &self.directory)
+            .wrap_err(Failure::Config)?
+            .timezone;
+        let repo = WatchedRepository::discover(&self.directory)?;
+        for tag in repo.tag_past_days(timezone)? {
+            println!("{}", tag);
+        }
+        let today = timezone.today();
+        match repo.tag_day(today, self.force) {
+            Ok(tag) => println!("{}", tag),
+            Err(err) if err.code() == git2::ErrorCode::Exists => {
+                return Err(eyre!(
+                    "today's tag already exists, declare --force to move it"
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use color_eyre::Result;
+use crossbeam_channel::Sender;
+use nabu::{
+    activity::{Activity, Control},
+    git::{RepositoryStatus, WatchedRepository},
+    sync::PushOutcome,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    widgets::{Block, Borders, List, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+/// How often the dashboard polls for key presses.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the repository status (ahead/behind) is refreshed.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+/// How many entries each pane keeps.
+const HISTORY_LENGTH: usize = 100;
+
+/// Start the dashboard in a separate thread.
+///
+/// The dashboard stops once `running` is cleared,
+/// pressing `q` (or Ctrl-C) clears it and stops the watcher.
+pub(crate) fn spawn(
+    directory: PathBuf,
+    activity: Receiver<Activity>,
+    control: Sender<Control>,
+    running: Arc<AtomicBool>,
+) -> JoinHandle<Result<()>> {
+    thread::spawn(move || {
+        let mut terminal = ratatui::init();
+        let mut dashboard = Dashboard::new(directory, activity, control, running);
+        let result = dashboard.run(&mut terminal);
+        ratatui::restore();
+        result
+    })
+}
+
+struct Dashboard {
+    directory: PathBuf,
+    activity: Receiver<Activity>,
+    control: Sender<Control>,
+    running: Arc<AtomicBool>,
+    paused: bool,
+    events: VecDeque<String>,
+    commits: VecDeque<String>,
+    last_push: Option<String>,
+    status: Option<RepositoryStatus>,
+    last_status: Option<Instant>,
+}
+
+impl Dashboard {
+    fn new(
+        directory: PathBuf,
+        activity: Receiver<Activity>,
+        control: Sender<Control>,
+        running: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            directory,
+            activity,
+            control,
+            running,
+            paused: false,
+            events: VecDeque::new(),
+            commits: VecDeque::new(),
+            last_push: None,
+            status: None,
+            last_status: None,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while self.running.load(Ordering::SeqCst) {
+            self.receive_activity();
+            self.refresh_status();
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(POLL_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key.code, key.modifiers);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        let control = match code {
+            KeyCode::Char('q') => None,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => None,
+            KeyCode::Char('p') if self.paused => Some(Control::Resume),
+            KeyCode::Char('p') => Some(Control::Pause),
+            KeyCode::Char('s') => Some(Control::PushNow),
+            _ => return,
+        };
+        match control {
+            // The watcher is gone, so there is nothing left to control
+            Some(control) if self.control.send(control).is_err() => {
+                self.running.store(false, Ordering::SeqCst)
+            }
+            Some(_) => {}
+            None => self.running.store(false, Ordering::SeqCst),
+        }
+    }
+
+    fn receive_activity(&mut self) {
+        while let Ok(activity) = self.activity.try_recv() {
+            let time = Local::now().format("%H:%M:%S");
+            match activity {
+                Activity::Event { description } => {
+                    push_bounded(&mut self.events, format!("{} {}", time, description))
+                }
+                Activity::Commit { message, .. } => {
+                    push_bounded(&mut self.commits, format!("{} {}", time, message));
+                    // A commit changes the ahead count, refresh it
+                    self.last_status = None;
+                }
+                Activity::Push { outcome, .. } => {
+                    let outcome = match outcome {
+                        PushOutcome::Pushed => String::from("pushed"),
+                        PushOutcome::Paused { conflicts } => {
+                            format!("paused, {} conflicted files", conflicts.len())
+                        }
+                        PushOutcome::Failed { error, .. } => format!("failed: {}", error),
+                        PushOutcome::Bundled { path, .. } => {
+                            format!("bundled into {}", path.display())
+                        }
+                    };
+                    self.last_push = Some(format!("{} {}", time, outcome));
+                    self.last_status = None;
+                }
+                Activity::Paused => self.paused = true,
+                Activity::Resumed => self.paused = false,
+                Activity::Unwatched { paths } => push_bounded(
+                    &mut self.events,
+                    format!("{} {} directories cannot be watched", time, paths.len()),
+                ),
+                Activity::Usage { .. } => {}
+            }
+        }
+    }
+
+    fn refresh_status(&mut self) {
+        if self
+            .last_status
+            .is_some_and(|last_status| last_status.elapsed() < STATUS_INTERVAL)
+        {
+            return;
+        }
+        self.status = WatchedRepository::discover(&self.directory)
+            .and_then(|repo| repo.with_scope(&self.directory).status())
+            .ok();
+        self.last_status = Some(Instant::now());
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, events, commits, help] = Layout::vertical([
+            Constraint::Length(5),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let state = if self.paused { "paused" } else { "watching" };
+        let repository = match &self.status {
+            Some(status) => format!(
+                "branch {}, {} ahead, {} behind, {} uncommitted changes",
+                status.branch.as_deref().unwrap_or("(detached)"),
+                count(status.ahead),
+                count(status.behind),
+                status.changes.len()
+            ),
+            None => String::from("repository status unavailable"),
+        };
+        let last_push = self.last_push.as_deref().unwrap_or("never");
+        let summary = Paragraph::new(format!(
+            "{}: {}\n{}\nlast push: {}",
+            state,
+            self.directory.display(),
+            repository,
+            last_push
+        ))
+        .block(Block::default().borders(Borders::ALL).title(" nabu "));
+        frame.render_widget(summary, header);
+
+        frame.render_widget(pane(" events ", &self.events), events);
+        frame.render_widget(pane(" commits ", &self.commits), commits);
+        frame.render_widget(
+            Paragraph::new("p: pause/resume  s: push now  q: quit"),
+            help,
+        );
+    }
+}
+
+/// A bordered list with the most recent entries first.
+fn pane<'a>(title: &'a str, entries: &'a VecDeque<String>) -> List<'a> {
+    List::new(entries.iter().rev().map(String::as_str))
+        .block(Block::default().borders(Borders::ALL).title(title))
+}
+
+fn count(count: Option<usize>) -> String {
+    count.map_or_else(|| String::from("?"), |count| count.to_string())
+}
+
+fn push_bounded(entries: &mut VecDeque<String>, entry: String) {
+    if entries.len() == HISTORY_LENGTH {
+        entries.pop_front();
+    }
+    entries.push_back(entry);
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{eyre::eyre, Result};
+use nabu::git::{CloneCheck, Repository, WatchedRepository};
+use serde::Serialize;
+
+use crate::{
+    auth::AuthArgs,
+    output::{print_json, OutputFormat},
+};
+
+/// `nabu`'s `verify` command arguments structure.
+#[derive(Args)]
+pub(crate) struct VerifyArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Also fetch the remote into a fresh repository and check that it holds every file of `HEAD`.
+    #[clap(long)]
+    clone: bool,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+
+    #[clap(flatten)]
+    auth: AuthArgs,
+}
+
+/// What the remote holds of the local `HEAD`.
+#[derive(Serialize)]
+struct Verification {
+    head: String,
+    /// The remote reference containing `HEAD`, if any.
+    reference: Option<String>,
+    /// The check of the fresh clone, with `--clone`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clone: Option<CloneCheck>,
+}
+
+impl VerifyArgs {
+    /// Execute the `verify` command, checking that the remote contains `HEAD`, without pushing anything.
+    pub fn run(self) -> Result<()> {
+        let authentication_method = self
+            .auth
+            .get_authentication_method()?
+            .ok_or_else(|| eyre!("no authentication method was provided"))?;
+
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let head = repo.head()?.ok_or_else(|| eyre!("there are no commits"))?;
+        repo.fetch(&authentication_method, false)?;
+        let reference = repo.remote_containing(head, &authentication_method)?;
+        let clone = match (&reference, self.clone) {
+            (Some(reference), true) => {
+                Some(repo.check_clone(reference, head, &authentication_method)?)
+            }
+            _ => None,
+        };
+        let verification = Verification {
+            head: head.to_string(),
+            reference,
+            clone,
+        };
+        if self.output == OutputFormat::Json {
+            print_json(&verification)?;
+        }
+
+        let reference = match &verification.reference {
+            Some(reference) => reference,
+            None => {
+                return Err(eyre!(
+                    "the remote does not contain HEAD ({}), push it",
+                    verification.head
+                ))
+            }
+        };
+        if self.output == OutputFormat::Text {
+            println!("{} contains HEAD ({})", reference, verification.head);
+        }
+        if let Some(check) = &verification.clone {
+            if !check.mismatched.is_empty() {
+                return Err(eyre!(
+                    "the fresh clone misses or corrupts {} of {} files: {:?}",
+                    check.mismatched.len(),
+                    check.files,
+                    check.mismatched
+                ));
+            }
+            if self.output == OutputFormat::Text {
+                println!("A fresh clone holds the {} files of HEAD", check.files);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,68 +1,189 @@
+use crate::{
+    auth::AuthArgs,
+    confirm::Confirmation,
+    crash,
+    exit::{push_error, Failure},
+    report::spawn_error_reporter,
+    setup::guided_setup,
+    signal::Signals,
+};
+
 use nabu::{
-    config::{global_config_path, Config, DEFAULT_DELAY},
-    fs::list_subdirs,
-    git::{AuthenticationMethod, DummyRepository, Repository, WatchedRepository},
+    activity::{Activity, ActivityBroadcaster, Control},
+    adaptive::AdaptiveDelay,
+    app_state::{is_app_state, AppStateThrottle},
+    attachments::{configure_filter, AttachmentsConfig},
+    config::{find_config_path, Config, DEFAULT_PULL_INTERVAL},
+    control::control_socket_path,
+    conventions::Conventions,
+    doctor::{check_authentication, Status},
+    fleet::{FleetConfig, RepositoryConfig, FLEET_CONFIG_PATH},
+    fs::{
+        list_subdirs, quarantine, reports_spurious_chmod, write_atomically, write_json, Discovery,
+        ForceInclude, IgnoreRules, MissingRoot, WATCHLIST_CACHE_FILE_NAME,
+    },
+    git::{
+        has_conflict_markers, redact_url, token_for_url, AuthenticationMethod, ConflictStrategy,
+        DryRun, DryRunRepository, FastForwardOutcome, Repository, WatchedRepository,
+    },
+    git_env::open_repository,
+    journal::{journal_path, read_journal, Journal},
+    latency::elapsed_ms,
+    limits::{git_cache_memory, ResourceUsage},
+    message::{render, PathRedaction},
+    mirror::Mirrors,
+    notes::{device_name, session_id, with_trailers, CommitMetadata},
+    notification::{Notifier, PushAlerts},
+    overrides::Overrides,
+    plugin::Plugin,
+    precondition::check_push_preconditions,
+    quiet::{active_quiet_hours, QuietMode},
+    runtime::{runtime_settings_path, RuntimeSettings},
+    schedule::{next_occurrence, parse_cron, parse_deadline, parse_duration},
+    script::{Script, ScriptEvent},
+    shutdown::UnfinishedShutdown,
+    size::{format_bytes, Growth, SizeCheck},
+    state::{state_path, State, WatcherState},
+    storm::StormDetector,
+    strategy::{commit_strategy, group_by_folder, CommitStrategy, CommitStrategyKind, Decision},
+    suggestions::{append_to_gitignore, ignore_pattern, observations_path, Observations},
+    suppress::{suppress_path, Suppressions},
+    sync::{push, PushOutcome},
+    writers::{writers_path, WriterProfile},
 };
 
 use std::{
-    collections::HashSet,
-    env,
-    ffi::OsStr,
-    io,
-    path::PathBuf,
+    collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
+    io::{self, BufRead, IsTerminal},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, channel, Receiver, Sender, TryRecvError},
         Arc, Mutex,
     },
-    thread,
-    time::Duration,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
 };
 
-use clap::Args;
-use color_eyre::Result;
-use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use chrono::{DateTime, Local, NaiveDate};
+use clap::{ArgGroup, Args};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use crossbeam_channel::{bounded, never, select, tick, unbounded, RecvTimeoutError, TrySendError};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
 
-macro_rules! handle_event {
-    ($path:ident, $message:literal) => {{
-        let commit_message = format!($message, $path.to_str().unwrap(), chrono::Utc::now());
-        ::log::info!("commit with message: {}", commit_message);
-        ($path, commit_message)
-    }};
-}
+/// Longest sleep before checking the `--for` and `--until` deadline against the clock,
+/// which keeps going while the machine is suspended.
+const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of discovered directories watched per tick of the watch loop.
+const WATCH_BATCH_SIZE: usize = 500;
+
+/// Interval between the ticks of the watch loop, which run the periodic tasks
+/// (e.g. snapshots, fetches or scheduled pushes) that are due.
+const TICK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time without renames after which the renames received are committed,
+/// the ones moving the files of a directory together,
+/// and within which a removed file created elsewhere with the same content is paired as a rename.
+const RENAME_WINDOW: Duration = Duration::from_secs(1);
 
 // Group names for the CLI.
-const AUTHENTICATION_METHOD_GROUP_NAME: &str = "authentication_method_group";
-const SSH_KEY_GROUP_NAME: &str = "ssh_key_group";
 const PUSH_GROUP_NAME: &str = "push_group";
 
 /// Default timeout in seconds for pushing.
 const DEFAULT_PUSH_TIMEOUT: u64 = 5;
 
+/// Interval in seconds before retrying a push skipped due to its preconditions.
+const PUSH_RETRY_INTERVAL: u64 = 60;
+
+/// Bytes in a megabyte, as counted by `push_every_n_megabytes`.
+const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+/// Interval in seconds between comparisons of the branch with its upstream.
+const UPSTREAM_CHECK_INTERVAL: u64 = 60;
+
+/// Interval between the looks at the last size check, which is renewed once it is a day old.
+const SIZE_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Interval between the compactions of the journal, see `journal_retention_days`.
+const JOURNAL_COMPACTION_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// Interval between the writes of the observations and of the high-frequency writers profile,
+/// see `nabu suggest-ignores` and `[learning]`.
+const JSON_WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of watched directories sampled on each watchdog check.
+const WATCHDOG_SAMPLE_SIZE: usize = 64;
+
+/// Time in seconds, on top of the watcher delay, for the event of a change to arrive.
+const WATCHDOG_GRACE_PERIOD: u64 = 5;
+
+/// Interval between the attempts to watch the directories which could not be watched
+/// (e.g. unreadable ones), in case their permissions changed.
+const UNWATCHED_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Interval between the checks of the directories polled rather than watched, see `max_watches`.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval between the reports of the resources used, when they changed.
+const USAGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time without commits after which the next commit starts a new burst,
+/// which a shared vault pulls the remote before, see `Config::shared_vault`.
+const BURST_GAP: Duration = Duration::from_secs(300);
+
 /// `nabu`'s `watch` command arguments structure.
-#[derive(Args)]
+#[derive(Args, Clone)]
+#[clap(group(ArgGroup::new(PUSH_GROUP_NAME).multiple(true)))]
 pub(crate) struct WatchArgs {
     /// The directory to watch over.
-    #[clap(parse(from_os_str))]
-    directory: PathBuf,
+    #[clap(parse(from_os_str), required_unless_present("all"))]
+    directory: Option<PathBuf>,
+
+    /// Other directories to watch, each one on its own worker,
+    /// so that a failing repository (e.g. with an unreachable remote) does not hold up the others.
+    #[clap(parse(from_os_str), conflicts_with("confirm"))]
+    directories: Vec<PathBuf>,
+
+    /// Watch the repositories of the fleet configuration file
+    /// (`/etc/nabu/config.toml`, or the one given with `--config`), each one on its own worker.
+    #[clap(long, conflicts_with_all(&["directory", "directories", "confirm"]))]
+    all: bool,
+
+    /// URL "origin" is set to before watching, from the fleet configuration.
+    #[clap(skip)]
+    remote: Option<String>,
 
     /// Whether to watch sub-directories.
     #[clap(short, long)]
     recursive: bool,
 
-    /// Watch over directory and print commands not performing them.
+    /// Print what would be staged, committed and pushed, without doing it.
     #[clap(long)]
     dry_run: bool,
 
-    /// Watcher event delay.
+    /// Commit, but print what would be pushed without pushing.
+    #[clap(long, conflicts_with("dry-run"))]
+    dry_run_push: bool,
+
+    /// Seconds to wait after the last change to a file before committing it.
     #[clap(long)]
     delay: Option<u64>,
 
-    /// List of directories to ignore.
+    /// Glob patterns of the directories to ignore (e.g. `build` or `docs/build`).
     #[clap(long)]
     ignore: Vec<String>,
 
-    /// Path to the configuration file.
+    /// Watch the directories read from the standard input, one per line (e.g. the output of `fd` or `find`),
+    /// instead of walking the directory.
+    #[clap(long, conflicts_with_all(&["directories", "all", "confirm"]))]
+    stdin: bool,
+
+    /// Path to the configuration file, the fleet configuration file with `--all`.
     #[clap(short, long, parse(from_os_str))]
     config: Option<PathBuf>,
 
@@ -71,124 +192,621 @@ pub(crate) struct WatchArgs {
     #[clap(long, group(PUSH_GROUP_NAME))]
     push_on_exit: bool,
 
+    /// Fetch and rebase the local commits onto the remote before pushing.
+    /// If not set, the value will be read from the config.
+    #[clap(long)]
+    sync: bool,
+
+    /// Sync with the remote every this many minutes,
+    /// committing the pending changes, rebasing them onto the remote and pushing them.
+    /// If not set, the value will be read from the config.
+    #[clap(long, group(PUSH_GROUP_NAME))]
+    sync_interval: Option<u64>,
+
+    /// Only fast-forward the branch to the remote, committing nothing.
+    /// If not set, the value will be read from the config.
+    #[clap(long, conflicts_with_all(&["sync", PUSH_GROUP_NAME]))]
+    pull_only: bool,
+
+    /// Cron expression for scheduled pushes (e.g. "0 */2 * * *").
+    /// If not set, the value will be read from the config.
+    #[clap(long, group(PUSH_GROUP_NAME))]
+    push_schedule: Option<String>,
+
+    /// Push once this many commits are waiting to be pushed.
+    /// If not set, the value will be read from the config.
+    #[clap(long, group(PUSH_GROUP_NAME))]
+    push_every_n_commits: Option<usize>,
+
+    /// Push once the commits waiting to be pushed add this many megabytes of file contents.
+    /// If not set, the value will be read from the config.
+    #[clap(long, group(PUSH_GROUP_NAME))]
+    push_every_n_megabytes: Option<u64>,
+
+    /// When syncing, keep both versions of conflicting Markdown files
+    /// instead of pausing the sync.
+    #[clap(long)]
+    conflict_copies: bool,
+
+    /// Set when the repository cannot be pushed, see `check_repository_state`.
+    #[clap(skip)]
+    pushes_disabled: bool,
+
     /// Push timeout (in seconds).
     #[clap(long, requires(PUSH_GROUP_NAME), default_value_t = DEFAULT_PUSH_TIMEOUT)]
     push_timeout: u64,
 
-    /// Use the ssh-agent as authenticaton method.
-    #[clap(
-        long,
-        group(AUTHENTICATION_METHOD_GROUP_NAME),
-        requires(PUSH_GROUP_NAME)
-    )]
-    ssh_agent: bool,
+    /// Skip checking the authentication method and the remote before watching.
+    #[clap(long)]
+    skip_preflight: bool,
+
+    /// Commit the changes made since the last run, push if enabled and exit,
+    /// instead of watching (e.g. to run from cron).
+    #[clap(long)]
+    once: bool,
 
-    /// Use the ssh-key as authentication method.
+    /// Stop watching after this long (e.g. `3h` or `1h30m`), committing and pushing as on exit.
     #[clap(
-        long,
-        parse(from_os_str),
-        requires(PUSH_GROUP_NAME),
-        groups(&[AUTHENTICATION_METHOD_GROUP_NAME, SSH_KEY_GROUP_NAME]),
+        long = "for",
+        value_name = "DURATION",
+        parse(try_from_str = parse_duration),
+        conflicts_with_all(&["until", "once"])
     )]
-    ssh_key: Option<PathBuf>,
+    for_duration: Option<Duration>,
+
+    /// Stop watching at this local time (`HH:MM`, or `YYYY-MM-DD HH:MM`), committing and pushing as on exit.
+    #[clap(long, value_name = "TIME", parse(try_from_str = parse_deadline), conflicts_with("once"))]
+    until: Option<DateTime<Local>>,
+
+    /// Ask for confirmation before each commit.
+    #[clap(long)]
+    confirm: bool,
+
+    /// Accept the commit if there is no answer within this many seconds.
+    #[clap(long, requires("confirm"))]
+    confirm_timeout: Option<u64>,
+
+    /// Show a dashboard with the watcher's activity.
+    #[cfg(feature = "tui")]
+    #[clap(long, conflicts_with_all(&["confirm", "once", "directories", "all"]))]
+    pub tui: bool,
 
-    /// Provide a passphrase for the ssh-key.
-    #[clap(long, requires(SSH_KEY_GROUP_NAME), default_value_t)]
-    ssh_passphrase: String,
+    /// Serve a web dashboard on this address (e.g. `8080`, on the loopback interface, or `0.0.0.0:8080`).
+    #[cfg(feature = "web")]
+    #[clap(long, value_name = "ADDRESS", conflicts_with_all(&["once", "directories", "all"]))]
+    web: Option<String>,
+
+    #[clap(flatten)]
+    auth: AuthArgs,
 }
 
 impl WatchArgs {
     /// Execute the `watch` command from the provided arguments.
-    pub fn run(mut self, watching: Arc<AtomicBool>) -> Result<()> {
-        self.update_from_config();
-        let watched_directories = self.list_watched_directories();
-        let delay = self.delay.unwrap_or(DEFAULT_DELAY);
+    pub fn run(self, signals: Signals) -> Result<()> {
+        if let Some(deadline) = self.deadline() {
+            stop_at(deadline, &signals);
+        }
+        if self.all {
+            self.watch_fleet(signals)
+        } else if self.directories.is_empty() {
+            if self.needs_setup()? {
+                guided_setup(self.directory())?;
+            }
+            self.watch(signals)
+        } else {
+            self.watch_all(signals)
+        }
+    }
+
+    /// When to stop watching, see `--for` and `--until`.
+    fn deadline(&self) -> Option<DateTime<Local>> {
+        let duration = self
+            .for_duration
+            .and_then(|duration| chrono::Duration::from_std(duration).ok());
+        self.until
+            .or_else(|| duration.and_then(|duration| Local::now().checked_add_signed(duration)))
+    }
+
+    /// Whether to guide the setup, see [`guided_setup`]: pushes are enabled on the command line
+    /// but there is neither an authentication method nor a configuration file, and someone is at the terminal.
+    fn needs_setup(&self) -> Result<bool> {
+        if self.config.is_some()
+            || self.dry_run
+            || !io::stdin().is_terminal()
+            || find_config_path(self.directory()).is_some()
+        {
+            return Ok(false);
+        }
+        let config = self.resolve_config().wrap_err(Failure::Config)?;
+        Ok(config.push_enabled()
+            && config.remote.s3.is_none()
+            && self.auth.get_authentication_method()?.is_none())
+    }
+
+    /// Watch every directory on its own worker, see [`WatchArgs::watch_workers`].
+    fn watch_all(self, signals: Signals) -> Result<()> {
+        let directories =
+            std::iter::once(self.directory().to_path_buf()).chain(self.directories.clone());
+        let workers = directories
+            .map(|directory| Self {
+                directory: Some(directory),
+                directories: Vec::new(),
+                ..self.clone()
+            })
+            .collect();
+        watch_workers(workers, signals)
+    }
+
+    /// Watch every repository of the fleet configuration on its own worker,
+    /// with the settings of the fleet configuration on top of the arguments.
+    fn watch_fleet(self, signals: Signals) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(repository) = crate::privileges::worker_repository()? {
+            return self.fleet_worker(repository).watch(signals);
+        }
+        let path = self
+            .config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(FLEET_CONFIG_PATH));
+        let fleet = FleetConfig::from_path(&path).wrap_err(Failure::Config)?;
+        fleet.validate().wrap_err(Failure::Config)?;
+        #[cfg(unix)]
+        if fleet.drop_privileges {
+            if crate::privileges::is_root() {
+                return crate::privileges::watch_as_owners(fleet.repositories, signals);
+            }
+            log::warn!("Not running as root, the privileges are already dropped.");
+        }
+        let workers = fleet
+            .repositories
+            .into_iter()
+            .map(|repository| self.fleet_worker(repository))
+            .collect();
+        watch_workers(workers, signals)
+    }
+
+    /// The arguments watching a repository of the fleet,
+    /// with its settings on top of the arguments.
+    fn fleet_worker(&self, repository: RepositoryConfig) -> Self {
+        Self {
+            directory: Some(repository.path),
+            all: false,
+            remote: repository.remote,
+            config: repository.config,
+            push_schedule: repository.schedule.or_else(|| self.push_schedule.clone()),
+            auth: repository
+                .auth
+                .as_ref()
+                .map_or_else(|| self.auth.clone(), AuthArgs::from),
+            ..self.clone()
+        }
+    }
+
+    /// The watched directory, set unless watching the fleet.
+    fn directory(&self) -> &Path {
+        self.directory
+            .as_deref()
+            .expect("the directory is set unless watching the fleet")
+    }
+
+    /// Watch the directory.
+    fn watch(mut self, signals: Signals) -> Result<()> {
+        let mut config = self.resolve_config().wrap_err(Failure::Config)?;
+        config.validate().wrap_err(Failure::Config)?;
+        let discovery = discovery(self.directory(), &config)?;
+        let discovery = if self.stdin {
+            discovery.with_directories(read_directories(io::stdin().lock())?)
+        } else {
+            discovery
+        };
+        let authentication_method = self
+            .auth
+            .or_config(config.auth.as_ref())
+            .get_authentication_method()?;
+        let directory = self.directory().canonicalize()?;
+        let repo = WatchedRepository::discover(&directory)?;
+        self.set_remote(&repo)?;
+        self.check_repository_state(&repo, &mut config)?;
+        log_summary(
+            &directory,
+            &repo,
+            &discovery,
+            &config,
+            authentication_method.as_ref(),
+        );
+        if !self.dry_run && !self.dry_run_push {
+            configure_attachments(&repo, &directory, config.attachments.as_ref())?;
+        }
+        let repo = self.set_up_repository(repo, &directory, &config)?;
+        if !config.commit_config_secrets {
+            self.warn_config_secrets(&repo);
+        }
         if self.dry_run {
-            WatchCommand::new(
-                DummyRepository,
-                watching,
-                watched_directories,
-                delay,
-                self.push_on_exit,
+            let command = WatchCommand::new(
+                DryRunRepository::new(repo, DryRun::Everything),
+                signals.clone(),
+                discovery,
+                config,
                 self.push_timeout,
-                self.get_authentication_method()?,
-            )
-            .run();
+                authentication_method,
+            )?;
+            return self.execute(command, signals);
+        }
+
+        let discovery = discovery.with_cache(repo.git_dir().join(WATCHLIST_CACHE_FILE_NAME));
+        // Dry runs stay on libgit2
+        #[cfg(feature = "gitoxide")]
+        if config.git_backend == nabu::git::GitBackend::Gitoxide && !self.dry_run_push {
+            let command = WatchCommand::new(
+                nabu::gitoxide::GitoxideRepository::new(repo)?,
+                signals.clone(),
+                discovery,
+                config,
+                self.push_timeout,
+                authentication_method,
+            )?;
+            return self.execute(command, signals);
+        }
+        if self.dry_run_push {
+            let command = WatchCommand::new(
+                DryRunRepository::new(repo, DryRun::Push),
+                signals.clone(),
+                discovery,
+                config,
+                self.push_timeout,
+                authentication_method,
+            )?;
+            self.execute(command, signals)
         } else {
-            let directory = self.directory.clone().canonicalize()?;
-            log::info!("{}", directory.display());
-            let repo = WatchedRepository::new(directory)?;
-            WatchCommand::new(
+            let command = WatchCommand::new(
                 repo,
-                watching,
-                watched_directories,
-                delay,
-                self.push_on_exit,
+                signals.clone(),
+                discovery,
+                config,
                 self.push_timeout,
-                self.get_authentication_method()?,
+                authentication_method,
+            )?;
+            self.execute(command, signals)
+        }
+    }
+
+    /// Check and run the watcher, along with the dashboard if requested.
+    fn execute<R>(&self, command: WatchCommand<R>, signals: Signals) -> Result<()>
+    where
+        R: Repository + 'static,
+    {
+        if !self.skip_preflight {
+            command.preflight()?;
+        }
+        let args = self.clone();
+        let watched = WatchedRepository::discover(self.directory())?;
+        let runtime_settings = runtime_settings_path(watched.git_dir());
+        // The settings changed with `nabu ctl set` only last as long as the watcher they were given to
+        let _ = std::fs::remove_file(&runtime_settings);
+        let mut command = command.with_config_reloader(move || {
+            RuntimeSettings::read(&runtime_settings)?.apply(args.resolve_config()?)
+        });
+        if !self.dry_run {
+            command = command
+                .with_observations(watched.git_dir(), watched.workdir())
+                .with_writers(watched.git_dir(), watched.workdir());
+        }
+        if self.confirm {
+            let timeout = self.confirm_timeout.map(Duration::from_secs);
+            command = command.with_confirmation(Confirmation::new(timeout, signals));
+        }
+        let error_reports_dsn = command.config.error_reports_dsn.clone();
+        let error_reporter =
+            spawn_error_reporter(error_reports_dsn.as_deref(), command.subscribe())
+                .wrap_err(Failure::Config)?;
+        let plugin_activity = command.subscribe();
+        let plugins = spawn_plugins(
+            &command.config,
+            command.discovery.root(),
+            &suppress_path(watched.git_dir()),
+            plugin_activity,
+        );
+        let state_writer = if self.dry_run {
+            None
+        } else {
+            Some(spawn_state_writer(self.directory(), command.subscribe())?)
+        };
+        let journal_writer = if self.dry_run || !command.config.journal {
+            None
+        } else {
+            Some(spawn_journal_writer(
+                self.directory(),
+                command.session.clone(),
+                command.config.journal_retention_days,
+                command.subscribe(),
+            )?)
+        };
+        // Tailing the watcher is no reason not to start it
+        #[cfg(unix)]
+        let control_socket = if self.dry_run {
+            None
+        } else {
+            let path = control_socket_path(watched.git_dir());
+            nabu::control::serve(&path, command.subscribe())
+                .map_err(|err| {
+                    log::warn!(
+                        "Failed to listen on the control socket {}, `nabu ctl tail` is unavailable: {}",
+                        path.display(),
+                        err
+                    )
+                })
+                .ok()
+        };
+        #[cfg(feature = "tui")]
+        let dashboard = self.tui.then(|| {
+            crate::tui::spawn(
+                self.directory().to_path_buf(),
+                command.subscribe(),
+                command.controller(),
+                command.signals.running(),
             )
-            .run();
+        });
+        #[cfg(feature = "web")]
+        let web_dashboard = self
+            .web
+            .as_deref()
+            .map(|address| {
+                crate::web::spawn(
+                    address,
+                    self.directory().canonicalize()?,
+                    command.subscribe(),
+                    command.controller(),
+                    command.signals.running(),
+                )
+            })
+            .transpose()?;
+        let crash_guard = if self.dry_run {
+            None
+        } else {
+            // The snapshot must keep out what the watcher keeps out (e.g. the configuration's secrets)
+            let directory = self.directory().canonicalize()?;
+            let repo = WatchedRepository::discover(&directory)?;
+            let repo = self.set_up_repository(repo, &directory, &command.config)?;
+            Some(crash::guard(repo, &command.config))
+        };
+        let exit_push = if self.once {
+            Ok(command.run_once())
+        } else {
+            command.run()
+        };
+        drop(crash_guard);
+        #[cfg(feature = "tui")]
+        if let Some(dashboard) = dashboard {
+            dashboard
+                .join()
+                .map_err(|_| eyre!("the dashboard panicked"))??;
+        }
+        #[cfg(feature = "web")]
+        if let Some(web_dashboard) = web_dashboard {
+            web_dashboard
+                .join()
+                .map_err(|_| eyre!("the web dashboard panicked"))??;
+        }
+        if let Some(state_writer) = state_writer {
+            state_writer
+                .join()
+                .map_err(|_| eyre!("the state writer panicked"))?;
+        }
+        if let Some(journal_writer) = journal_writer {
+            journal_writer
+                .join()
+                .map_err(|_| eyre!("the journal writer panicked"))?;
+        }
+        #[cfg(unix)]
+        if let Some(control_socket) = control_socket {
+            control_socket
+                .join()
+                .map_err(|_| eyre!("the control socket panicked"))?;
+        }
+        if let Some(plugins) = plugins {
+            plugins.join().map_err(|_| eyre!("the plugins panicked"))?;
+        }
+        if let Some(error_reporter) = error_reporter {
+            error_reporter
+                .join()
+                .map_err(|_| eyre!("the error reporter panicked"))?;
+        }
+        match exit_push?.as_ref().and_then(push_error) {
+            Some(err) => Err(err),
+            None => Ok(()),
         }
-        Ok(())
     }
 
-    /// Update the arguments with values from the configuration file.
-    /// Only replaces missing values.
-    pub fn update_from_config(&mut self) {
-        let config = if let Some(path) = &self.config {
-            Config::from_path(path).unwrap()
+    /// Point "origin" to the remote of the fleet configuration, when it differs.
+    fn set_remote(&self, repo: &WatchedRepository) -> Result<()> {
+        let remote = match &self.remote {
+            Some(remote) => remote,
+            None => return Ok(()),
+        };
+        if repo.origin_url()?.as_deref() == Some(remote.as_str()) {
+            return Ok(());
+        }
+        if self.dry_run {
+            log::info!("Would set origin to {}", remote);
         } else {
-            let mut local_config_path = self.directory.clone();
-            local_config_path.push("nabu.toml");
+            repo.set_origin(remote)?;
+            log::info!("origin set to {}", remote);
+        }
+        Ok(())
+    }
+
+    /// Refuse to watch the repository in a state nabu does not support,
+    /// disabling the pushes when it can still commit.
+    fn check_repository_state(
+        &mut self,
+        repo: &WatchedRepository,
+        config: &mut Config,
+    ) -> Result<()> {
+        for state in repo.unsupported_states() {
+            if !state.degraded || config.pull_only {
+                return Err(eyre!("{} ({})", state.problem, state.fix))
+                    .wrap_err(Failure::UnsupportedState);
+            }
+            log::warn!(
+                "Pushing and syncing are disabled: {} ({}).",
+                state.problem,
+                state.fix
+            );
+            self.pushes_disabled = true;
+        }
+        if self.pushes_disabled {
+            config.disable_pushes();
+        }
+        Ok(())
+    }
+
+    /// Set the repository up for watching `directory`, as the watcher (and its crash snapshot) sees it,
+    /// keeping the active configuration file out of the commits while it holds secrets.
+    fn set_up_repository(
+        &self,
+        repo: WatchedRepository,
+        directory: &Path,
+        config: &Config,
+    ) -> Result<WatchedRepository> {
+        let repo = repo
+            .with_scope(directory)
+            .with_unicode_normalization(config.unicode_normalization)
+            .with_track_new_files(config.track_new_files)
+            .with_force_include(ForceInclude::new(&config.force_include)?);
+        Ok(match self.config_path() {
+            Some(config_path) if !config.commit_config_secrets => {
+                repo.with_guarded_file(&config_path)
+            }
+            _ => repo,
+        })
+    }
+
+    /// The active configuration file, if any.
+    fn config_path(&self) -> Option<PathBuf> {
+        self.config
+            .clone()
+            .or_else(|| find_config_path(self.directory()))
+    }
 
-            Config::from_path(&local_config_path)
-                .or_else(|_| Config::from_path(global_config_path()))
-                .unwrap_or_default()
+    /// Warn about the secrets the guarded configuration file already holds.
+    fn warn_config_secrets(&self, repo: &WatchedRepository) {
+        let config_path = match self.config_path() {
+            Some(config_path) => config_path,
+            None => return,
         };
+        let secrets = repo.guarded_secrets();
+        for secret in &secrets {
+            log::warn!(
+                "{} holds a possible {} on line {}, it is kept out of the commits \
+                (set `commit_config_secrets = true` to commit it anyway)",
+                secret.path.display(),
+                secret.kind,
+                secret.line
+            );
+        }
+        // Only guarded files inside the working directory can hold secrets
+        let tracked = || {
+            config_path
+                .canonicalize()
+                .is_ok_and(|config_path| matches!(repo.is_tracked(config_path), Ok(true)))
+        };
+        if let Some(secret) = secrets.first().filter(|_| tracked()) {
+            log::warn!(
+                "{} is already tracked, its history may hold the secrets",
+                secret.path.display()
+            );
+        }
+    }
 
-        if self.delay.is_none() {
-            self.delay = Some(config.delay);
+    /// Read the configuration file and override it with the provided arguments.
+    pub fn resolve_config(&self) -> Result<Config> {
+        let mut config = Config::load(self.config.as_deref(), self.directory())?;
+
+        if let Some(delay) = self.delay {
+            config.delay = delay;
         }
 
-        if self.ignore.is_empty() {
-            self.ignore = config.ignore.clone();
+        if !self.ignore.is_empty() {
+            config.ignore = self.ignore.clone();
         }
 
-        if !self.push_on_exit {
-            self.push_on_exit |= config.push_on_exit;
+        config.push_on_exit |= self.push_on_exit;
+        config.sync |= self.sync;
+        config.pull_only |= self.pull_only;
+        if self.sync_interval.is_some() {
+            config.sync_interval = self.sync_interval;
+        }
+        if self.push_schedule.is_some() {
+            config.push_schedule = self.push_schedule.clone();
+        }
+        if self.push_every_n_commits.is_some() {
+            config.push_every_n_commits = self.push_every_n_commits;
+        }
+        if self.push_every_n_megabytes.is_some() {
+            config.push_every_n_megabytes = self.push_every_n_megabytes;
+        }
+        if self.conflict_copies {
+            config.on_conflict = ConflictStrategy::Copy;
+        }
+        if self.pushes_disabled {
+            config.disable_pushes();
         }
-    }
 
-    /// List all watched directories.
-    pub fn list_watched_directories(&self) -> Vec<PathBuf> {
-        let ignored_set = self
-            .ignore
-            .iter()
-            .map(OsStr::new)
-            .collect::<HashSet<&OsStr>>();
+        Ok(config)
+    }
+}
 
-        list_subdirs(&self.directory, ignored_set)
+/// The discovery of the directories to watch under `directory`, as configured.
+/// Fails if the directory is inside the repository's git directory, whose changes committing would loop.
+pub(crate) fn discovery(directory: &Path, config: &Config) -> Result<Discovery> {
+    let discovery = Discovery::new(
+        directory.canonicalize()?,
+        IgnoreRules::new(&config.ignore)?,
+        config.follow_symlinks,
+    )
+    .with_overrides(Overrides::new(&config.directories)?)
+    .with_force_include(ForceInclude::new(&config.force_include)?);
+    if let Some(git_dir) = discovery.inside_git_dir() {
+        return Err(eyre!(
+            "{} is inside the git directory {}, watch the repository's working directory instead",
+            discovery.root().display(),
+            git_dir.display()
+        ))
+        .wrap_err(Failure::Config);
     }
+    Ok(discovery)
+}
 
-    /// Convert the authentication-related arguments into an `AuthenticationMethod` enumeration.
-    pub fn get_authentication_method(&self) -> Result<AuthenticationMethod> {
-        if self.ssh_agent {
-            if env::var("SSH_AGENT_PID").is_err() && env::var("SSH_AUTH_SOCK").is_err() {
-                log::warn!("ssh-agent is not running.");
-            }
-            return Ok(AuthenticationMethod::SshAgent);
+/// Run every worker on its own thread, until they all stop,
+/// so that a failing repository (e.g. with an unreachable remote) does not hold up the others.
+/// Fails with the first error, once the other workers are done.
+fn watch_workers(workers: Vec<WatchArgs>, signals: Signals) -> Result<()> {
+    let workers = workers
+        .into_iter()
+        .map(|args| {
+            let directory = args.directory().to_path_buf();
+            let signals = signals.worker();
+            thread::Builder::new()
+                .name(directory.display().to_string())
+                .spawn(move || {
+                    let result = args.watch(signals);
+                    if let Err(err) = &result {
+                        log::error!("Stopped watching {}: {}", directory.display(), err);
+                    }
+                    result
+                })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let mut result = Ok(());
+    for worker in workers {
+        let worker_result = worker
+            .join()
+            .unwrap_or_else(|_| Err(eyre!("a worker panicked")));
+        if result.is_ok() {
+            result = worker_result;
         }
-
-        let path = self.ssh_key.clone().unwrap();
-        return if path.exists() {
-            Ok(AuthenticationMethod::SshKey {
-                path,
-                passphrase: self.ssh_passphrase.clone(),
-            })
-        } else {
-            Err(io::Error::new(io::ErrorKind::NotFound, "provided key does not exist").into())
-        };
     }
+    result
 }
 
 /// `nabu`'s `watch` command inner logic.
@@ -197,12 +815,209 @@ where
     R: Repository,
 {
     repo: R,
-    running: Arc<AtomicBool>,
-    watchlist: Vec<PathBuf>,
-    delay: u64,
-    push_on_exit: bool,
+    signals: Signals,
+    discovery: Discovery,
+    config: Config,
+    /// Per-directory overrides of the configuration.
+    overrides: Overrides,
+    /// The repository's commit message conventions, followed if `[conventions]` is set.
+    conventions: Conventions,
+    /// The external directories mirrored into the watched one.
+    mirrors: Mirrors,
     push_timeout: u64,
-    authentication_method: AuthenticationMethod,
+    authentication_method: Option<AuthenticationMethod>,
+    push_schedule: Option<cron::Schedule>,
+    next_push: Option<DateTime<Local>>,
+    maintenance_schedule: Option<cron::Schedule>,
+    next_maintenance: Option<DateTime<Local>>,
+    unpushed_commits: usize,
+    retry_push_at: Option<Instant>,
+    /// The error of the push the remote rejected for good, no push is attempted until the configuration is reloaded.
+    push_rejected: Option<String>,
+    deferred_since: Option<Instant>,
+    last_snapshot: Instant,
+    last_reconciliation: Instant,
+    /// Identifier of this session in the commit notes.
+    session: String,
+    /// When the last event was received from the file watchers, compared to the modification times.
+    last_event_received: SystemTime,
+    last_watchdog_check: Instant,
+    last_unwatched_retry: Instant,
+    /// Whether the delays of the watched directories should be updated, after reloading the configuration.
+    update_delays: bool,
+    /// The directories which cannot be watched, as last broadcast.
+    unwatched: Vec<PathBuf>,
+    /// When the polled directories were last checked for changes.
+    last_poll: SystemTime,
+    /// Events dropped since the watcher started, the pending events being over `max_pending_events`.
+    dropped_events: usize,
+    /// Events dropped whose changes are not committed yet, once the pending events are processed.
+    uncommitted_drops: usize,
+    /// The resources used, as last broadcast.
+    usage: ResourceUsage,
+    last_usage_report: Instant,
+    last_fetch: Instant,
+    last_sync: Instant,
+    last_upstream_check: Instant,
+    last_size_check: Instant,
+    /// The growth of `.git` measured in the background since the last size check, see `run_size_check`.
+    size_check: Option<Receiver<Option<Growth>>>,
+    /// Whether the unpushed commits and the divergence from the upstream were warned about,
+    /// to warn again only once they were solved.
+    warned_unpushed: bool,
+    warned_diverged: bool,
+    /// Whether the local modifications blocking the pull-only mode's updates were warned about.
+    warned_blocked: bool,
+    notifier: Notifier,
+    /// Notifies the failing pushes, once per streak and error rather than on every attempt.
+    push_alerts: PushAlerts,
+    /// The user script customizing the commits, see `Script`.
+    script: Option<Script>,
+    storm: StormDetector,
+    /// Lengthens the delay while editing heavily, if enabled.
+    adaptive_delay: Option<AdaptiveDelay>,
+    /// Events held back by the adaptive delay, by path, along with the last change to the path.
+    held_events: HashMap<PathBuf, (DebouncedEvent, Instant)>,
+    /// Decides whether each change is committed, amended or left to a snapshot.
+    strategy: Box<dyn CommitStrategy>,
+    /// The commit made for the last event, which the commit strategy may amend.
+    last_event_commit: Option<git2::Oid>,
+    /// The path last committed for an event, the hash of its content and when,
+    /// to collapse the bursts of events an editor emits for a single save.
+    last_committed: Option<(PathBuf, Option<git2::Oid>, Instant)>,
+    /// Day the daily snapshot tags were last created on.
+    tagged_day: Option<NaiveDate>,
+    /// The rolling branch the commits were last moved onto, or failed to.
+    rolled_branch: Option<String>,
+    /// When each path changed, until the change is committed, for the latency of the commits.
+    changed_at: HashMap<PathBuf, Instant>,
+    /// The paths changed since the last snapshot, committed on their own within `shutdown_budget`.
+    uncommitted: HashSet<PathBuf>,
+    /// When the oldest commit not pushed yet was made, for the latency of the pushes.
+    oldest_unpushed: Option<Instant>,
+    /// Holds back the changes to the application state files committed within the hour.
+    app_state: AppStateThrottle<DebouncedEvent>,
+    /// Files renamed, from and to, held back until the renames settle
+    /// so that moving a directory file by file is committed once.
+    renames: Vec<(PathBuf, PathBuf)>,
+    /// Tracked files removed, along with their blob in `HEAD`, held back along with the renames
+    /// in case the file is created elsewhere (e.g. moved across watched directories).
+    removals: Vec<(PathBuf, git2::Oid)>,
+    /// When the last file was renamed or removed.
+    renamed_at: Option<Instant>,
+    ignore_chmod: bool,
+    paused: bool,
+    /// The repository's `.git` directory, which vanishes along with the watched directory
+    /// when it is the drive's mount point.
+    git_dir: Option<PathBuf>,
+    /// The repository's working directory, which the paths of the changes are relative to,
+    /// above the watched directory when only part of the repository is watched.
+    workdir: PathBuf,
+    /// Whether the watched directory vanished (e.g. its drive was unmounted), see `missing_root`.
+    root_missing: bool,
+    activity: ActivityBroadcaster,
+    #[cfg_attr(not(any(feature = "tui", feature = "web")), allow(dead_code))]
+    control_snd: crossbeam_channel::Sender<Control>,
+    control_rcv: crossbeam_channel::Receiver<Control>,
+    reload_config: Option<Box<dyn Fn() -> Result<Config>>>,
+    confirmation: Option<Confirmation>,
+    /// What was observed of each file, for `nabu suggest-ignores`, not recorded in dry-run mode.
+    observed: Option<ObservedFiles>,
+    /// How often each file changes, for `nabu writers` and `[learning]`, not recorded in dry-run mode.
+    writers: Option<LearnedWriters>,
+    /// The paths the hooks declared they change, whose events are dropped for a while.
+    suppressions: Suppressions,
+}
+
+/// The observations of the watcher, along with where they are written.
+struct ObservedFiles {
+    observations: Observations,
+    file: JsonFile,
+    /// The working directory, which the observed paths are relative to.
+    workdir: PathBuf,
+}
+
+/// The changes of each file over the learning window, along with where they are written.
+struct LearnedWriters {
+    profile: WriterProfile,
+    file: JsonFile,
+    /// The working directory, which the recorded paths are relative to.
+    workdir: PathBuf,
+    /// Changes to the demoted writers held back until their snapshot, see `demote`.
+    held: BTreeSet<PathBuf>,
+    snapshotted_at: Instant,
+}
+
+/// A JSON file of the watcher (e.g. the observations) rewritten at most once per [`JSON_WRITE_INTERVAL`],
+/// in the background rather than on the event loop.
+struct JsonFile {
+    path: PathBuf,
+    /// Whether the value changed since it was last written.
+    dirty: bool,
+    written_at: Instant,
+    /// The write running in the background, if any.
+    writing: Option<JoinHandle<()>>,
+}
+
+impl JsonFile {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            dirty: false,
+            written_at: Instant::now(),
+            writing: None,
+        }
+    }
+
+    /// Whether the value changed and was last written long enough ago,
+    /// and the last write is over rather than racing it on the temporary file.
+    fn is_due(&self) -> bool {
+        self.dirty
+            && self.written_at.elapsed() >= JSON_WRITE_INTERVAL
+            && self
+                .writing
+                .as_ref()
+                .is_none_or(|writing| writing.is_finished())
+    }
+
+    /// Write the value in a separate thread, `what` it holds is logged if the write fails.
+    fn write_in_background<T>(&mut self, value: &T, what: &'static str)
+    where
+        T: Serialize,
+    {
+        let contents = match serde_json::to_vec_pretty(value) {
+            Ok(contents) => contents,
+            Err(err) => {
+                log::warn!("Failed to record {}: {}", what, err);
+                return;
+            }
+        };
+        let path = self.path.clone();
+        self.writing = Some(thread::spawn(move || {
+            if let Err(err) = write_atomically(&path, &contents) {
+                log::warn!("Failed to record {}: {}", what, err);
+            }
+        }));
+        self.dirty = false;
+        self.written_at = Instant::now();
+    }
+
+    /// Write the value right away if it changed, once the write in the background is over, e.g. on exit.
+    fn write_now<T>(&mut self, value: &T, what: &'static str)
+    where
+        T: Serialize,
+    {
+        if let Some(writing) = self.writing.take() {
+            let _ = writing.join();
+        }
+        if !self.dirty {
+            return;
+        }
+        if let Err(err) = write_json(&self.path, value) {
+            log::warn!("Failed to record {}: {}", what, err);
+        }
+        self.dirty = false;
+    }
 }
 
 impl<R> WatchCommand<R>
@@ -212,114 +1027,3348 @@ where
     /// Construct a new `WatchCommand`.
     pub fn new(
         repo: R,
-        running: Arc<AtomicBool>,
-        watchlist: Vec<PathBuf>,
-        delay: u64,
-        push_on_exit: bool,
+        signals: Signals,
+        discovery: Discovery,
+        config: Config,
         push_timeout: u64,
-        authentication_method: AuthenticationMethod,
-    ) -> Self {
-        Self {
+        authentication_method: Option<AuthenticationMethod>,
+    ) -> Result<Self> {
+        config.validate()?;
+        config.limits.apply_to_libgit2()?;
+        config.apply_tls().wrap_err(Failure::Config)?;
+        let push_schedule = config
+            .push_schedule
+            .as_deref()
+            .map(parse_cron)
+            .transpose()?;
+        let next_push = push_schedule.as_ref().and_then(next_occurrence);
+        let maintenance_schedule = config
+            .maintenance_schedule
+            .as_deref()
+            .map(parse_cron)
+            .transpose()?;
+        let next_maintenance = maintenance_schedule.as_ref().and_then(next_occurrence);
+        let ignore_chmod = config.ignore_chmod.unwrap_or_else(|| {
+            let spurious = reports_spurious_chmod(discovery.root());
+            if spurious {
+                log::info!("The filesystem reports spurious chmod events, ignoring them.");
+            }
+            spurious
+        });
+        let overrides = Overrides::new(&config.directories)?;
+        let mirrors = Mirrors::new(discovery.root(), &config.mirrors);
+        let (control_snd, control_rcv) = unbounded();
+        let strategy = commit_strategy(&config);
+        let storm = StormDetector::new(config.storm_threshold);
+        let adaptive_delay = adaptive_delay(&config);
+        let notifier = notifier(&config, discovery.root());
+        let push_alerts = PushAlerts::new(config.push_failure_escalation);
+        let script = load_script(&config, discovery.root())?;
+        let opened = open_repository(discovery.root(), true).ok();
+        let git_dir = opened.as_ref().map(|(repo, _)| repo.path().to_path_buf());
+        let workdir = opened
+            .as_ref()
+            .and_then(|(repo, _)| repo.workdir())
+            .unwrap_or(discovery.root())
+            .to_path_buf();
+        let suppressions = Suppressions::new(git_dir.as_deref().map(suppress_path));
+        let conventions = read_conventions(discovery.root());
+        Ok(Self {
             repo,
-            running,
-            watchlist,
-            delay,
-            push_on_exit,
+            signals,
+            discovery,
+            config,
+            overrides,
+            conventions,
+            mirrors,
             push_timeout,
             authentication_method,
-        }
+            push_schedule,
+            next_push,
+            maintenance_schedule,
+            next_maintenance,
+            unpushed_commits: 0,
+            retry_push_at: None,
+            push_rejected: None,
+            deferred_since: None,
+            last_snapshot: Instant::now(),
+            last_reconciliation: Instant::now(),
+            session: session_id(),
+            last_event_received: SystemTime::now(),
+            last_watchdog_check: Instant::now(),
+            last_unwatched_retry: Instant::now(),
+            update_delays: false,
+            unwatched: vec![],
+            last_poll: SystemTime::now(),
+            dropped_events: 0,
+            uncommitted_drops: 0,
+            usage: ResourceUsage::default(),
+            last_usage_report: Instant::now(),
+            last_fetch: Instant::now(),
+            last_sync: Instant::now(),
+            last_upstream_check: Instant::now(),
+            last_size_check: Instant::now(),
+            size_check: None,
+            warned_unpushed: false,
+            warned_diverged: false,
+            warned_blocked: false,
+            notifier,
+            push_alerts,
+            script,
+            storm,
+            adaptive_delay,
+            held_events: HashMap::new(),
+            strategy,
+            last_event_commit: None,
+            last_committed: None,
+            tagged_day: None,
+            rolled_branch: None,
+            changed_at: HashMap::new(),
+            uncommitted: HashSet::new(),
+            oldest_unpushed: None,
+            app_state: AppStateThrottle::default(),
+            renames: Vec::new(),
+            removals: Vec::new(),
+            renamed_at: None,
+            ignore_chmod,
+            paused: false,
+            git_dir,
+            workdir,
+            root_missing: false,
+            activity: ActivityBroadcaster::default(),
+            control_snd,
+            control_rcv,
+            reload_config: None,
+            confirmation: None,
+            observed: None,
+            writers: None,
+            suppressions,
+        })
     }
 
-    /// Execute the `watch` command.
-    pub fn run(self) {
-        let (event_snd, event_rcv): (Sender<DebouncedEvent>, Receiver<DebouncedEvent>) = channel();
-        let mut watcher = watcher(event_snd, Duration::from_secs(self.delay)).unwrap();
+    /// Ask for confirmation before each commit.
+    pub fn with_confirmation(mut self, confirmation: Confirmation) -> Self {
+        self.confirmation = Some(confirmation);
+        self
+    }
 
-        for dir in &self.watchlist {
-            log::info!("adding {} to watcher", dir.display());
-            watcher.watch(dir, RecursiveMode::NonRecursive).unwrap();
-        }
+    /// Record what is observed of the files, in the repository with the given `.git` and working directories.
+    pub fn with_observations(mut self, git_dir: &Path, workdir: &Path) -> Self {
+        let path = observations_path(git_dir);
+        self.observed = Some(ObservedFiles {
+            observations: Observations::read(&path),
+            file: JsonFile::new(path),
+            workdir: workdir.to_path_buf(),
+        });
+        self
+    }
 
-        log::debug!("watching over {:?}", &self.watchlist);
+    /// Learn how often the files change, in the repository with the given `.git` and working directories.
+    pub fn with_writers(mut self, git_dir: &Path, workdir: &Path) -> Self {
+        let path = writers_path(git_dir);
+        self.writers = Some(LearnedWriters {
+            profile: WriterProfile::read(&path),
+            file: JsonFile::new(path),
+            workdir: workdir.to_path_buf(),
+            held: BTreeSet::new(),
+            snapshotted_at: Instant::now(),
+        });
+        self
+    }
 
-        while self.running.load(Ordering::SeqCst) {
-            match event_rcv.recv_timeout(Duration::from_millis(500)) {
-                Ok(event) => {
-                    log::debug!("event received: {:?}", &event);
-                    self.handle_event(&event, &self.repo)
+    /// Record an observation of the file with `record`, which returns whether the file just became a suggestion,
+    /// appending it to `.gitignore` right away if `auto_ignore` is set.
+    fn observe<F>(&mut self, path: &Path, record: F)
+    where
+        F: FnOnce(&mut Observations, &Path) -> bool,
+    {
+        let observed = match &mut self.observed {
+            Some(observed) => observed,
+            None => return,
+        };
+        let relative = path.strip_prefix(&observed.workdir).unwrap_or(path);
+        if record(&mut observed.observations, relative) {
+            let pattern = ignore_pattern(relative);
+            if !self.config.auto_ignore {
+                log::info!(
+                    "{} keeps tripping the guards, consider ignoring it, see `nabu suggest-ignores`.",
+                    relative.display()
+                );
+            } else if let Err(err) =
+                append_to_gitignore(&observed.workdir, std::slice::from_ref(&pattern))
+            {
+                log::warn!("Failed to add {} to .gitignore: {}", pattern, err);
+            } else {
+                log::info!("Added {} to .gitignore, see `auto_ignore`.", pattern);
+                if self
+                    .repo
+                    .is_tracked(observed.workdir.join(relative))
+                    .unwrap_or(false)
+                {
+                    log::info!(
+                        "{} is still tracked, stop tracking it with `git rm --cached {}`.",
+                        relative.display(),
+                        relative.display()
+                    );
                 }
-                Err(RecvTimeoutError::Disconnected) => log::error!("sender disconnected"),
-                _ => {}
+                observed.observations.forget(relative);
             }
         }
+        observed.file.dirty = true;
+    }
 
-        log::info!("Termination signal received, attempting to save changes.");
-
-        self.repo.stage_all().unwrap();
-        log::info!("Staged changes.");
-        self.repo
-            .commit(&format!("nabu exited snapshot @ {}", chrono::Utc::now()))
-            .unwrap();
-
-        log::info!("Commited changes.");
-
-        if self.push_on_exit {
-            let (sig_snd, sig_rcv) = channel();
-            let repo = Arc::new(Mutex::new(self.repo));
-            thread::spawn(move || {
-                let r = repo.try_lock().unwrap();
-                match r.push(self.authentication_method) {
-                    Ok(()) => {
-                        log::info!("Successfully pushed to remote.");
-                    }
-                    Err(err) => {
-                        log::warn!("{}", err.message());
-                    }
-                }
-                sig_snd.send(()).unwrap();
-            });
-            if let Err(_) = sig_rcv.recv_timeout(Duration::from_secs(self.push_timeout)) {
-                log::warn!("Timeout while pushing, cleaning up now.");
+    /// Write the observations once a minute, in the background.
+    fn run_observations(&mut self) {
+        if let Some(observed) = &mut self.observed {
+            if observed.file.is_due() {
+                observed
+                    .file
+                    .write_in_background(&observed.observations, "the observations of the files");
             }
         }
     }
 
-    /// Handle an event sent by the watcher.
-    fn handle_event(&self, event: &DebouncedEvent, repo: &R)
+    /// The path relative to the watched directory.
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(self.discovery.root()).unwrap_or(path)
+    }
+
+    /// The path as written in the commit messages, redacted if `redact_paths` is set.
+    fn message_path(&self, path: &Path) -> String {
+        match self.config.redact_paths {
+            PathRedaction::None => path.to_string_lossy().into_owned(),
+            redaction => redaction.apply(self.relative(path)),
+        }
+    }
+
+    /// Watcher delay (in seconds) for the directory, taking the overrides into account.
+    fn delay(&self, dir: &Path) -> u64 {
+        self.overrides
+            .settings(self.relative(dir))
+            .delay
+            .unwrap_or(self.config.delay)
+    }
+
+    /// The current time, as configured for the commit messages.
+    fn timestamp(&self) -> String {
+        self.config
+            .timezone
+            .timestamp(&self.config.timestamp_format)
+    }
+
+    /// Whether the commit was accepted, always true unless in attended mode.
+    fn confirm<P>(&self, paths: &[P], message: &str) -> bool
     where
-        R: Repository,
+        P: AsRef<Path>,
     {
-        log::debug!("received event: {:?}", event);
-        // TODO: better commit messages (e.g. short title, descriptive body)
-        // TODO: configurable commit messages
-        let (path, message) = match event {
-            DebouncedEvent::Create(path) => {
-                if path.is_dir() {
-                    return;
-                }
-                handle_event!(path, "created file {} @ {}")
-            }
-            DebouncedEvent::Write(path) => handle_event!(path, "written file {} @ {}"),
-            DebouncedEvent::Chmod(path) => handle_event!(path, "chmod file {} @ {}"),
-            DebouncedEvent::Remove(path) => handle_event!(path, "deleted file {} @ {}"),
-            DebouncedEvent::Rename(old, new) => (
-                new,
-                format!(
-                    "renamed file {} to {} @ {}",
-                    old.to_str().unwrap(),
-                    new.to_str().unwrap(),
-                    chrono::Utc::now()
-                ),
-            ),
-            // TODO: handle these two later
-            DebouncedEvent::Rescan => todo!(),
-            DebouncedEvent::Error(_, _) => todo!(),
-            DebouncedEvent::NoticeRemove(_) | DebouncedEvent::NoticeWrite(_) => {
-                return;
+        match &self.confirmation {
+            Some(confirmation) if !confirmation.confirm(paths, message) => {
+                log::info!("Commit declined, the changes are left uncommitted.");
+                false
             }
-        };
+            _ => true,
+        }
+    }
+
+    /// Reload the configuration with `reload_config` when requested (i.e. on `SIGHUP`).
+    pub fn with_config_reloader<F>(mut self, reload_config: F) -> Self
+    where
+        F: Fn() -> Result<Config> + 'static,
+    {
+        self.reload_config = Some(Box::new(reload_config));
+        self
+    }
+
+    /// Subscribe to the watcher's activity.
+    pub fn subscribe(&mut self) -> Receiver<Activity> {
+        let (activity_snd, activity_rcv) = channel();
+        self.activity.subscribe(activity_snd);
+        activity_rcv
+    }
+
+    /// Get a handle to control the watcher while it runs.
+    #[cfg_attr(not(any(feature = "tui", feature = "web")), allow(dead_code))]
+    pub fn controller(&self) -> crossbeam_channel::Sender<Control> {
+        self.control_snd.clone()
+    }
 
-        repo.stage(path).unwrap();
-        repo.commit(&message).unwrap();
+    /// Check that pushing can work before starting to watch.
+    /// Fails if the authentication method is unusable,
+    /// an unreachable remote is only warned about as the network may come back later.
+    pub fn preflight(&self) -> Result<()> {
+        if !self.config.push_enabled() {
+            return Ok(());
+        }
+        if let Some(s3) = &self.config.remote.s3 {
+            log::info!("Pushes upload the repository to {}.", s3.location());
+            return Ok(());
+        }
+        if self.authentication_method.is_none() {
+            return Err(eyre!(
+                "pushing is enabled but no authentication method was provided"
+            ))
+            .wrap_err(Failure::Authentication);
+        }
+        let authentication = check_authentication(self.authentication_method.as_ref());
+        match authentication.status {
+            Status::Ok => {}
+            Status::Warn => log::warn!("{}", authentication.message),
+            Status::Fail => {
+                return Err(eyre!(
+                    "pushing is enabled but {} ({})",
+                    authentication.message,
+                    authentication.fix.unwrap_or_default()
+                ))
+                .wrap_err(Failure::Authentication);
+            }
+        }
+        if let Err(err) = self.repo.check_remote(self.authentication_method.as_ref()) {
+            log::error!(
+                "The remote is not reachable, pushes will fail until it is: {}",
+                err.message()
+            );
+        }
+        Ok(())
     }
+
+    /// Execute the `watch` command.
+    /// Returns the outcome of the push on exit, if attempted,
+    /// or an error if the watched directory vanished with `missing_root = "exit"`.
+    ///
+    /// The watch loop waits on the filesystem events, the control requests, the signals
+    /// and the ticks running the periodic tasks, handling each as soon as it arrives.
+    pub fn run(mut self) -> Result<Option<PushOutcome>> {
+        if self.config.pull_only {
+            self.run_pull_only();
+            return Ok(None);
+        }
+        let (event_snd, event_rcv): (Sender<DebouncedEvent>, Receiver<DebouncedEvent>) = channel();
+        let mut watchers = Watchers::new(event_snd, self.config.limits.max_watches);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let mut events = forward_events(
+            event_rcv,
+            self.config.limits.max_pending_events,
+            dropped.clone(),
+        );
+        let controls = self.control_rcv.clone();
+        let wakeups = self.signals.wakeups();
+        let ticks = tick(TICK_INTERVAL);
+
+        // Watch the root and the directories found on the previous run right away,
+        // the rest are added as they are discovered
+        let started = Instant::now();
+        let root = self.discovery.root().to_path_buf();
+        let cached = self.discovery.cached();
+        let cached_directories = cached.len();
+        for dir in std::iter::once(root).chain(cached) {
+            let delay = self.delay(&dir);
+            watchers.watch(dir, delay);
+        }
+        log::debug!(
+            "watched the root and the {} cached directories in {}ms",
+            cached_directories,
+            elapsed_ms(started)
+        );
+        for event in self.sync_mirrors() {
+            self.process_event(event);
+        }
+        for source in self.mirrors.sources() {
+            let dirs = list_subdirs(
+                source,
+                IgnoreRules::new::<&str>(&[]).expect("no patterns cannot be invalid"),
+                Overrides::default(),
+                self.config.follow_symlinks,
+            );
+            for dir in dirs {
+                watchers.watch(dir, self.config.delay);
+            }
+        }
+        self.run_rolling_branch();
+        self.finish_previous_shutdown();
+        let mut discovered = self.discovery.spawn();
+        // Paths written or removed whose debounced event has not been received yet
+        let mut in_flight = HashSet::new();
+
+        let mut discovering = true;
+        while self.signals.is_running() {
+            select! {
+                recv(events) -> event => match event {
+                    Ok(event) => {
+                        self.last_event_received = SystemTime::now();
+                        self.receive_event(event, &mut in_flight);
+                    }
+                    Err(_) => {
+                        log::error!("sender disconnected");
+                        events = never();
+                    }
+                },
+                recv(controls) -> control => {
+                    if let Ok(control) = control {
+                        self.handle_control(control);
+                    }
+                }
+                // Termination is checked by the loop itself
+                recv(wakeups) -> _ => self.run_reload(),
+                recv(ticks) -> _ => if self.check_root(&mut watchers, &mut discovered, &mut discovering) {
+                    if discovering {
+                        discovering = self.watch_discovered(&mut watchers, &discovered);
+                    } else {
+                        // The directories being discovered are not watched yet
+                        self.run_watchdog(&mut watchers);
+                        self.run_unwatched(&mut watchers);
+                    }
+                    self.run_polled(&mut watchers);
+                    self.run_dropped_events(&events, &dropped);
+                    self.report_usage(&watchers, &events);
+                    if std::mem::take(&mut self.update_delays)
+                        && watchers.set_delays(|dir| self.delay(dir))
+                    {
+                        // The debounced events were dropped along with the watchers
+                        self.reconcile();
+                    }
+                    self.run_rolling_branch();
+                    self.run_held_events();
+                    self.run_renames();
+                    self.run_app_state();
+                    self.run_writers();
+                    self.run_observations();
+                    self.run_deferred_snapshot();
+                    self.run_strategy_snapshot();
+                    self.run_storm_snapshot();
+                    self.run_heartbeat_snapshot();
+                    self.run_reconciliation();
+                    self.run_daily_tags();
+                    self.run_fetch();
+                    self.run_periodic_sync();
+                    self.run_upstream_check();
+                    self.run_size_check();
+                    self.run_scheduled_maintenance();
+                    self.run_scheduled_push();
+                }
+            }
+            if self.root_missing && self.config.missing_root == MissingRoot::Exit {
+                return Err(eyre!(
+                    "the watched directory {} vanished",
+                    self.discovery.root().display()
+                ))
+                .wrap_err(Failure::RepositoryNotFound);
+            }
+        }
+
+        self.write_learned();
+        if self.root_missing {
+            // Nothing can be saved nor pushed from the vanished directory
+            log::info!("Termination signal received, exiting.");
+            return Ok(None);
+        }
+        log::info!("Termination signal received, attempting to save changes.");
+
+        let deadline = self
+            .config
+            .shutdown_budget
+            .map(|budget| Instant::now() + Duration::from_secs(budget));
+        self.flush_events(&events, &mut in_flight, deadline);
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.snapshot(self.config.catalog().exit_snapshot);
+                let push = self.config.push_on_exit;
+                return Ok(self.push_and_maintain(push));
+            }
+        };
+
+        // The maintenance is left to a run without a budget
+        let uncommitted = self.budgeted_snapshot(deadline);
+        let git_dir = self.git_dir.clone();
+        let mut outcome = None;
+        let mut push = false;
+        if self.config.push_on_exit {
+            if Instant::now() < deadline {
+                outcome = self.push_on_exit(Some(deadline), false);
+            }
+            push =
+                Instant::now() >= deadline && !outcome.as_ref().is_some_and(PushOutcome::is_pushed);
+        }
+        let unfinished = UnfinishedShutdown::new(uncommitted, push);
+        if unfinished.is_unfinished() {
+            log::warn!(
+                "Leaving {} changes{} to the next run, within the shutdown budget.",
+                unfinished.uncommitted.len(),
+                if push { " and the push" } else { "" }
+            );
+            if let Some(git_dir) = git_dir {
+                if let Err(err) = unfinished.write(&git_dir) {
+                    log::error!("failed to record the unfinished shutdown: {}", err);
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Snapshot the changes seen by the watcher, rather than staging the whole tree,
+    /// as long as there is time left before `deadline` (see `shutdown_budget`).
+    /// Returns the changes left uncommitted, relative to the working directory.
+    fn budgeted_snapshot(&mut self, deadline: Instant) -> Vec<PathBuf> {
+        let workdir = self.workdir.clone();
+        let relative = |paths: Vec<&PathBuf>| {
+            let mut paths = paths
+                .into_iter()
+                .filter_map(|path| path.strip_prefix(&workdir).ok())
+                .map(Path::to_path_buf)
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+        // The files of the new directories are not watched, they are left to the next run
+        let (directories, files): (Vec<_>, Vec<_>) =
+            self.uncommitted.iter().partition(|path| path.is_dir());
+        let directories = relative(directories);
+        let files = relative(
+            files
+                .into_iter()
+                // Neither committed already, nor removed before being tracked
+                .filter(|path| !matches!(self.repo.is_committed(path), Ok(true)))
+                .filter(|path| path.exists() || matches!(self.repo.is_tracked(path), Ok(true)))
+                .collect(),
+        );
+        if files.is_empty() || Instant::now() >= deadline {
+            return [directories, files].concat();
+        }
+        self.commit_snapshot(self.config.catalog().exit_snapshot, Some(&files), false);
+        // Whatever was not committed (e.g. holding secrets) is left to the next run
+        let files = files
+            .into_iter()
+            .filter(|path| !matches!(self.repo.is_committed(workdir.join(path)), Ok(true)))
+            .collect::<Vec<_>>();
+        [directories, files].concat()
+    }
+
+    /// Finish what the previous run left once out of `shutdown_budget`:
+    /// commit the changes it left and push if it could not.
+    fn finish_previous_shutdown(&mut self) {
+        let unfinished = match self.git_dir.as_deref().and_then(UnfinishedShutdown::take) {
+            Some(unfinished) => unfinished,
+            None => return,
+        };
+        log::info!(
+            "Finishing what the shutdown of the previous run left at {}.",
+            unfinished.time
+        );
+        self.reconcile();
+        if unfinished.push {
+            self.try_push("Pushing what the previous run could not.");
+        }
+    }
+
+    /// Whether the watched directory, along with its repository, is there.
+    /// Once it vanishes (e.g. its drive is unmounted) it is no longer watched and, with `missing_root = "wait"`,
+    /// the watcher pauses until it is back, then watches it again and commits the changes made in the meantime.
+    fn check_root(
+        &mut self,
+        watchers: &mut Watchers,
+        discovered: &mut Receiver<PathBuf>,
+        discovering: &mut bool,
+    ) -> bool {
+        let present = self.root_present();
+        if present != self.root_missing {
+            return present;
+        }
+        let root = self.discovery.root().to_path_buf();
+        if !present {
+            self.root_missing = true;
+            watchers.unwatch_tree(&root);
+            // Left to the reconciliation once the directory is back
+            self.held_events.clear();
+            self.renames.clear();
+            self.removals.clear();
+            self.renamed_at = None;
+            self.changed_at.clear();
+            let message = match self.config.missing_root {
+                MissingRoot::Wait => format!(
+                    "{} vanished (e.g. its drive was unmounted), pausing until it is back",
+                    root.display()
+                ),
+                MissingRoot::Exit => format!(
+                    "{} vanished (e.g. its drive was unmounted), exiting",
+                    root.display()
+                ),
+            };
+            self.notifier.warn("root-missing", &message);
+            if !self.paused && self.config.missing_root == MissingRoot::Wait {
+                self.activity.broadcast(Activity::Paused);
+            }
+            return false;
+        }
+
+        self.root_missing = false;
+        self.notifier.notify(
+            log::Level::Info,
+            "root-back",
+            &format!("{} is back, watching it again", root.display()),
+        );
+        for dir in std::iter::once(root).chain(self.discovery.cached()) {
+            let delay = self.delay(&dir);
+            watchers.watch(dir, delay);
+        }
+        *discovered = self.discovery.spawn();
+        *discovering = true;
+        if self.paused {
+            self.deferred_since.get_or_insert_with(Instant::now);
+        } else {
+            self.activity.broadcast(Activity::Resumed);
+            self.reconcile();
+        }
+        true
+    }
+
+    /// Whether the watched directory and the repository's `.git` directory exist.
+    fn root_present(&self) -> bool {
+        self.discovery.root().is_dir() && self.git_dir.as_deref().is_none_or(Path::is_dir)
+    }
+
+    /// Pull from the remote every `fetch_interval` minutes until terminated,
+    /// the working directory is not watched and nothing is committed.
+    fn run_pull_only(&mut self) {
+        let interval =
+            Duration::from_secs(self.config.fetch_interval.unwrap_or(DEFAULT_PULL_INTERVAL) * 60);
+        log::info!(
+            "Pull-only mode, fast-forwarding to the remote every {} minutes.",
+            interval.as_secs() / 60
+        );
+        let wakeups = self.signals.wakeups();
+        let ticks = tick(TICK_INTERVAL);
+        self.pull();
+        while self.signals.is_running() {
+            select! {
+                recv(wakeups) -> _ => self.run_reload(),
+                recv(ticks) -> _ => {
+                    if self.last_fetch.elapsed() >= interval {
+                        self.pull();
+                    }
+                }
+            }
+        }
+        log::info!("Termination signal received, exiting.");
+    }
+
+    /// Fetch and fast-forward the branch to its upstream,
+    /// warning once when it diverged or local modifications block the update.
+    fn pull(&mut self) {
+        self.last_fetch = Instant::now();
+        let authentication_method = match &self.authentication_method {
+            Some(authentication_method) => authentication_method,
+            None => {
+                log::warn!("Pull requested but no authentication method was provided.");
+                return;
+            }
+        };
+        log::debug!("fetching from the remote");
+        if let Err(err) = self.repo.fetch(authentication_method, true) {
+            log::warn!("failed to fetch: {}", err.message());
+            return;
+        }
+        let outcome = match self.repo.fast_forward_to_upstream() {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                log::warn!("failed to fast-forward: {}", err.message());
+                return;
+            }
+        };
+        log::debug!("fast-forward outcome: {:?}", outcome);
+        if let FastForwardOutcome::FastForwarded = outcome {
+            log::info!("Fast-forwarded to the remote.");
+        }
+        let diverged = matches!(outcome, FastForwardOutcome::Diverged);
+        if diverged && !self.warned_diverged {
+            self.notifier.warn(
+                "diverged",
+                "the branch has local commits, it cannot be fast-forwarded to the remote",
+            );
+        }
+        self.warned_diverged = diverged;
+        let blocked = match &outcome {
+            FastForwardOutcome::Blocked(paths) => Some(paths),
+            _ => None,
+        };
+        if let Some(paths) = blocked.filter(|_| !self.warned_blocked) {
+            let paths = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+            self.notifier.warn(
+                "blocked",
+                &format!(
+                    "local modifications block the update from the remote: {}",
+                    paths.join(", ")
+                ),
+            );
+        }
+        self.warned_blocked = blocked.is_some();
+    }
+
+    /// Run a single cycle and exit: commit the changes made since the last run,
+    /// tag the past days and push (syncing first if configured to) if any kind of push is enabled.
+    /// Returns the outcome of the push, if attempted.
+    pub fn run_once(mut self) -> Option<PushOutcome> {
+        if self.config.pull_only {
+            self.pull();
+            return None;
+        }
+        self.sync_mirrors();
+        // Which finishes what the previous shutdown left, if anything
+        if let Some(git_dir) = &self.git_dir {
+            UnfinishedShutdown::take(git_dir);
+        }
+        self.run_rolling_branch();
+        self.catch_up_snapshot(self.config.catalog().catch_up_snapshot);
+        self.run_daily_tags();
+        let push = self.config.push_enabled();
+        self.push_and_maintain(push)
+    }
+
+    /// Push before exiting if `push`, then run the repository maintenance if `maintenance_on_exit`,
+    /// which does not delay the push, nor depends on it.
+    fn push_and_maintain(self, push: bool) -> Option<PushOutcome> {
+        let maintenance = self.config.maintenance_on_exit;
+        if push && self.may_push_on_exit() {
+            return self.push_on_exit(None, maintenance);
+        }
+        if maintenance {
+            self.run_maintenance();
+        }
+        None
+    }
+
+    /// Whether pushing before exiting is possible, logging why it is skipped otherwise.
+    fn may_push_on_exit(&self) -> bool {
+        if self.signals.skip_push() {
+            log::warn!("Push on exit skipped.");
+            return false;
+        }
+        if self.authentication_method.is_none() && self.config.remote.s3.is_none() {
+            log::warn!("Push requested but no authentication method was provided.");
+            return false;
+        }
+        if let Some(skip_reason) = check_push_preconditions(&self.config) {
+            log::warn!("Skipping push on exit ({}).", skip_reason);
+            return false;
+        }
+        true
+    }
+
+    /// Push before exiting, giving up after the push timeout, once the push is skipped,
+    /// or at the `deadline` of the shutdown budget, if any.
+    /// The repository maintenance then runs if `maintenance`, until done or skipped as well.
+    fn push_on_exit(self, deadline: Option<Instant>, maintenance: bool) -> Option<PushOutcome> {
+        if !self.may_push_on_exit() {
+            return None;
+        }
+        let authentication_method = self.authentication_method;
+        let config = self.config.clone();
+        let (sig_snd, sig_rcv) = channel();
+        let repo = Arc::new(Mutex::new(self.repo));
+        thread::spawn(move || {
+            let r = repo.try_lock().unwrap();
+            let outcome = push(&*r, authentication_method.as_ref(), &config);
+            let _ = sig_snd.send(outcome);
+            // Dropping the sender once done tells that the maintenance is over
+            if maintenance {
+                run_maintenance(&*r);
+            }
+        });
+        let timeout = Instant::now() + Duration::from_secs(self.push_timeout);
+        let deadline = deadline.map_or(timeout, |deadline| deadline.min(timeout));
+        loop {
+            match sig_rcv.recv_timeout(Duration::from_millis(100)) {
+                Ok(outcome) => {
+                    if let Some(script) = &self.script {
+                        script.on_push_result(&outcome);
+                    }
+                    let latency_ms = outcome
+                        .is_pushed()
+                        .then_some(self.oldest_unpushed)
+                        .flatten()
+                        .map(elapsed_ms);
+                    self.activity.broadcast(Activity::Push {
+                        outcome: outcome.clone(),
+                        latency_ms,
+                    });
+                    if maintenance {
+                        wait_for_maintenance(&self.signals, &sig_rcv);
+                    }
+                    return Some(outcome);
+                }
+                Err(_) if self.signals.skip_push() => {
+                    log::warn!("Push on exit skipped, cleaning up now.");
+                    return None;
+                }
+                Err(_) if Instant::now() >= deadline => {
+                    log::warn!("Timeout while pushing, cleaning up now.");
+                    return Some(PushOutcome::Failed {
+                        error: String::from("timed out"),
+                        authentication: false,
+                        permanent: false,
+                    });
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Watch a batch of the discovered directories, so that events keep being processed
+    /// while a large tree is registered.
+    /// Returns whether directories are still being discovered.
+    fn watch_discovered(&self, watchers: &mut Watchers, discovered: &Receiver<PathBuf>) -> bool {
+        for _ in 0..WATCH_BATCH_SIZE {
+            match discovered.try_recv() {
+                Ok(dir) => {
+                    let delay = self.delay(&dir);
+                    watchers.watch(dir, delay);
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => {
+                    log::info!(
+                        "Watching {} directories, {} polled, {} skipped.",
+                        watchers.watchlist.len(),
+                        watchers.polled.len(),
+                        watchers.skipped.len()
+                    );
+                    return false;
+                }
+            }
+        }
+        log::info!(
+            "Watching {} directories so far, {} polled, {} skipped.",
+            watchers.watchlist.len(),
+            watchers.polled.len(),
+            watchers.skipped.len()
+        );
+        true
+    }
+
+    /// Mirror the changes made to the external directories while not watching.
+    /// Returns the events of the paths mirroring the changed and removed files.
+    fn sync_mirrors(&self) -> Vec<DebouncedEvent> {
+        match self.mirrors.sync_all() {
+            Ok(mirrored) => {
+                if !mirrored.is_empty() {
+                    log::info!("Mirrored {} changes.", mirrored.len());
+                }
+                mirrored
+            }
+            Err(err) => {
+                log::warn!("failed to mirror the external directories: {}", err);
+                vec![]
+            }
+        }
+    }
+
+    /// Receive an event from the watcher, mirroring the changes to the external directories.
+    fn receive_event(&mut self, event: DebouncedEvent, in_flight: &mut HashSet<PathBuf>) {
+        // The events of the directory vanishing, which the next tick notices
+        if self.root_missing || !self.root_present() {
+            log::debug!(
+                "the watched directory vanished, dropping event: {:?}",
+                &event
+            );
+            return;
+        }
+        // Committing git's own metadata would only make more of it
+        if changed_paths(&event)
+            .iter()
+            .any(|path| self.discovery.is_git_internal(path))
+        {
+            log::debug!(
+                "the event is inside a git directory, dropping it: {:?}",
+                &event
+            );
+            return;
+        }
+        if self.is_suppressed(&event) {
+            log::debug!(
+                "a hook declared the change, dropping the event: {:?}",
+                &event
+            );
+            return;
+        }
+        if let Some(event) = self.mirrors.mirror_event(event) {
+            track_in_flight(in_flight, &event);
+            let now = Instant::now();
+            for path in changed_paths(&event) {
+                self.changed_at.entry(path.clone()).or_insert(now);
+                if self.config.shutdown_budget.is_some() {
+                    self.uncommitted.insert(path.clone());
+                }
+            }
+            self.process_event(event);
+        }
+    }
+
+    /// Whether the event changes a path a hook listed in the suppression file,
+    /// within `suppress_grace_period` seconds on top of the delay.
+    fn is_suppressed(&mut self, event: &DebouncedEvent) -> bool {
+        let paths = changed_paths(event);
+        let delay = paths
+            .first()
+            .map_or(self.config.delay, |path| self.delay(path));
+        let duration = Duration::from_secs(delay + self.config.suppress_grace_period);
+        if let Err(err) = self.suppressions.refresh(self.discovery.root(), duration) {
+            log::warn!("failed to read the paths the hooks change: {}", err);
+        }
+        paths
+            .iter()
+            .any(|path| self.suppressions.is_suppressed(path))
+    }
+
+    /// Act on an event sent by the watcher, unless paused or in quiet hours.
+    fn process_event(&mut self, event: DebouncedEvent) {
+        log::debug!("event received: {:?}", &event);
+        if let Some(description) = describe_event(&event) {
+            self.activity.broadcast(Activity::Event { description });
+        }
+        if self.paused {
+            log::debug!("paused, deferring event");
+            self.deferred_since.get_or_insert_with(Instant::now);
+        } else if let Some(quiet_hours) = active_quiet_hours(&self.config.quiet_hours, Local::now())
+        {
+            log::debug!("quiet hours ({:?}), deferring event", quiet_hours.mode);
+            self.deferred_since.get_or_insert_with(Instant::now);
+        } else if let DebouncedEvent::Rescan = event {
+            log::warn!("The watcher may have missed events, reconciling.");
+            self.reconcile();
+        } else if self.storm.on_event(Instant::now()) {
+            log::debug!("event storm, deferring event");
+        } else if let Some(event) = self.hold_event(event) {
+            self.commit_event(event);
+        }
+    }
+
+    /// Commit the change of the event, if it should be.
+    /// The renames and removals of files are held back, see [`WatchCommand::run_renames`].
+    fn commit_event(&mut self, event: DebouncedEvent) {
+        match &event {
+            DebouncedEvent::Rename(from, path) if !path.is_dir() => {
+                self.renames.push((from.clone(), path.clone()));
+                self.renamed_at = Some(Instant::now());
+            }
+            // The directory the held renames moved the files out of, committed along with them
+            DebouncedEvent::Remove(path)
+                if self.renames.iter().any(|(from, _)| from.starts_with(path)) => {}
+            DebouncedEvent::Remove(path) => match self.repo.committed_blob(path) {
+                Ok(Some(blob)) => {
+                    self.removals.push((path.clone(), blob));
+                    self.renamed_at = Some(Instant::now());
+                }
+                _ => self.commit_change(event),
+            },
+            DebouncedEvent::Create(path) if !self.removals.is_empty() && !path.is_dir() => {
+                match self.paired_removal(path) {
+                    Some(from) => {
+                        log::debug!(
+                            "{} was created with the content of the removed {}, renaming it",
+                            path.display(),
+                            from.display()
+                        );
+                        self.renames.push((from, path.clone()));
+                        self.renamed_at = Some(Instant::now());
+                    }
+                    None => self.commit_change(event),
+                }
+            }
+            _ => self.commit_change(event),
+        }
+    }
+
+    /// Take the held removal of a file with the same content as the created one, if any.
+    fn paired_removal(&mut self, path: &Path) -> Option<PathBuf> {
+        let content = content_hash(path)?;
+        let position = self
+            .removals
+            .iter()
+            .position(|(_, blob)| *blob == content)?;
+        Some(self.removals.remove(position).0)
+    }
+
+    /// Commit the renames once they settle, the files moved along with their directory
+    /// (e.g. the events of a renamed directory on some platforms) as a single directory rename,
+    /// and the removals no creation was paired with.
+    fn run_renames(&mut self) {
+        if self
+            .renamed_at
+            .is_none_or(|renamed_at| renamed_at.elapsed() < RENAME_WINDOW)
+        {
+            return;
+        }
+        self.renamed_at = None;
+        // Not created elsewhere, actually removed
+        for (path, _) in std::mem::take(&mut self.removals) {
+            self.commit_change(DebouncedEvent::Remove(path));
+        }
+        let mut directories = HashSet::new();
+        for (from, path) in std::mem::take(&mut self.renames) {
+            match renamed_directory(&from, &path) {
+                Some(directory) => {
+                    if directories.insert(directory.clone()) {
+                        let (from, path) = directory;
+                        self.commit_change(DebouncedEvent::Rename(from, path));
+                    }
+                }
+                None => self.commit_change(DebouncedEvent::Rename(from, path)),
+            }
+        }
+    }
+
+    /// Commit the change of the event, a directory renamed along with its files included.
+    fn commit_change(&mut self, event: DebouncedEvent) {
+        if self.learn(&event) {
+            return;
+        }
+        let app_state = self.app_state_path(&event);
+        let event = match &app_state {
+            Some(path) => match self.app_state.hold(path, event, Instant::now()) {
+                Some(event) => event,
+                None => {
+                    log::debug!(
+                        "{} was committed within the hour, holding it back",
+                        path.display()
+                    );
+                    return;
+                }
+            },
+            None => event,
+        };
+        if self.config.shared_vault && self.starts_burst() {
+            self.pull_before_burst();
+        }
+        let committed = match &event {
+            DebouncedEvent::Rename(from, path) if path.is_dir() => {
+                self.commit_directory_rename(from, path)
+            }
+            _ => self.handle_event(&event),
+        };
+        if committed {
+            if let Some(path) = &app_state {
+                self.app_state.on_commit(path, Instant::now());
+            }
+            if let Some(adaptive_delay) = &mut self.adaptive_delay {
+                adaptive_delay.on_commit(Instant::now());
+            }
+            self.unpushed_commits += 1;
+            self.run_threshold_push();
+        }
+        // The notices only announce the change, it is committed on the event following them
+        if !matches!(
+            event,
+            DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_)
+        ) {
+            for path in changed_paths(&event) {
+                self.changed_at.remove(path);
+            }
+        }
+    }
+
+    /// Whether the next commit starts a burst: nothing was committed nor fetched for `BURST_GAP`.
+    fn starts_burst(&self) -> bool {
+        self.last_fetch.elapsed() >= BURST_GAP
+            && self
+                .last_committed
+                .as_ref()
+                .is_none_or(|(_, _, committed_at)| committed_at.elapsed() >= BURST_GAP)
+    }
+
+    /// Fetch and fast-forward to the remote before a burst of commits in a shared vault,
+    /// so that they build on the teammates' latest changes. The local commits are rebased when pushing.
+    fn pull_before_burst(&mut self) {
+        self.last_fetch = Instant::now();
+        let authentication_method = match &self.authentication_method {
+            Some(authentication_method) => authentication_method.clone(),
+            None => return,
+        };
+        log::debug!("fetching from the remote before committing");
+        if let Err(err) = self.repo.fetch(&authentication_method, false) {
+            log::warn!("failed to fetch: {}", err.message());
+            return;
+        }
+        match self.repo.fast_forward_to_upstream() {
+            Ok(FastForwardOutcome::FastForwarded) => {
+                log::info!("Fast-forwarded to the remote before committing.")
+            }
+            Ok(FastForwardOutcome::Blocked(paths)) => {
+                let paths = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                self.notifier.warn(
+                    "blocked",
+                    &format!(
+                        "teammates changed files you are editing, they are merged when pushing: {}",
+                        paths.join(", ")
+                    ),
+                );
+            }
+            Ok(outcome) => log::debug!("fast-forward outcome: {:?}", outcome),
+            Err(err) => log::warn!("failed to fast-forward: {}", err.message()),
+        }
+    }
+
+    /// The path of the event if it is an application state file, committed at most once per hour
+    /// unless disabled by `throttle_app_state`.
+    fn app_state_path(&self, event: &DebouncedEvent) -> Option<PathBuf> {
+        let path = changed_file(event)?;
+        (self.config.throttle_app_state && is_app_state(self.relative(path))).then(|| path.clone())
+    }
+
+    /// Record the change of the file in the writers profile, reporting it once it changes more than
+    /// `changes_per_hour` times within an hour. Returns whether the change is held back
+    /// because the file is a high-frequency writer and `demote` is set.
+    fn learn(&mut self, event: &DebouncedEvent) -> bool {
+        let (learning, writers) = match (&self.config.learning, &mut self.writers) {
+            (Some(learning), Some(writers)) => (learning, writers),
+            _ => return false,
+        };
+        let path = match changed_file(event) {
+            Some(path) if !path.is_dir() => path,
+            _ => return false,
+        };
+        let relative = path.strip_prefix(&writers.workdir).unwrap_or(path);
+        writers.file.dirty = true;
+        if writers.profile.record(relative, Local::now(), learning) {
+            log::info!(
+                "{} changed more than {} times within the hour, see `nabu writers`.",
+                relative.display(),
+                learning.changes_per_hour
+            );
+        }
+        if !learning.demote || !writers.profile.is_writer(relative, learning) {
+            return false;
+        }
+        log::debug!(
+            "{} is a high-frequency writer, holding it back until the next snapshot",
+            path.display()
+        );
+        writers.held.insert(relative.to_path_buf());
+        true
+    }
+
+    /// Write the writers profile once a minute, and snapshot the changes to the demoted writers
+    /// every `commit_batch_interval` minutes.
+    fn run_writers(&mut self) {
+        let writers = match &mut self.writers {
+            Some(writers) => writers,
+            None => return,
+        };
+        if writers.file.is_due() {
+            if let Some(learning) = &self.config.learning {
+                writers.profile.prune(Local::now(), learning.window);
+            }
+            writers
+                .file
+                .write_in_background(&writers.profile, "how often the files change");
+        }
+        // Released once the pause or the quiet hours end
+        if writers.held.is_empty()
+            || writers.snapshotted_at.elapsed()
+                < Duration::from_secs(self.config.commit_batch_interval * 60)
+            || self.paused
+            || active_quiet_hours(&self.config.quiet_hours, Local::now()).is_some()
+        {
+            return;
+        }
+        writers.snapshotted_at = Instant::now();
+        let held = std::mem::take(&mut writers.held)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let message = render(
+            self.config.catalog().writers_snapshot,
+            &[("count", &held.len().to_string())],
+        );
+        if self.commit_snapshot(&message, Some(&held), false) {
+            self.unpushed_commits += 1;
+            self.run_threshold_push();
+        }
+    }
+
+    /// Write the observations and the writers profile, e.g. on exit.
+    fn write_learned(&mut self) {
+        if let Some(observed) = &mut self.observed {
+            observed
+                .file
+                .write_now(&observed.observations, "the observations of the files");
+        }
+        if let Some(writers) = &mut self.writers {
+            writers
+                .file
+                .write_now(&writers.profile, "how often the files change");
+        }
+    }
+
+    /// Commit the changes to the application state files held back, once their hour passed.
+    fn run_app_state(&mut self) {
+        // Released once the pause or the quiet hours end
+        if self.paused || active_quiet_hours(&self.config.quiet_hours, Local::now()).is_some() {
+            return;
+        }
+        for event in self.app_state.due(Instant::now()) {
+            self.commit_change(event);
+        }
+    }
+
+    /// Commit the rename of a directory along with every file it holds, as a single commit.
+    /// Returns whether the commit was made.
+    fn commit_directory_rename(&mut self, from: &Path, path: &Path) -> bool {
+        let settings = self.overrides.settings(self.relative(path));
+        if !settings.watch {
+            log::debug!("{} is not watched, skipping the event", path.display());
+            return false;
+        }
+        let delay = settings.delay.unwrap_or(self.config.delay);
+        if self.comes_from_git(delay, None)
+            || self.holds_secrets(None)
+            || self.holds_conflict_markers(None)
+        {
+            return false;
+        }
+        let template = settings
+            .commit_message
+            .or_else(|| self.config.commit_message.clone())
+            .unwrap_or_else(|| {
+                self.config
+                    .catalog()
+                    .rename_directory_commit_message
+                    .to_string()
+            });
+        let mut message = render(
+            &template,
+            &[
+                ("action", self.config.catalog().renamed),
+                ("path", &self.message_path(path)),
+                ("from", &self.message_path(from)),
+                ("time", &self.timestamp()),
+            ],
+        );
+        if let Some(script) = &self.script {
+            let event = ScriptEvent {
+                action: "renamed",
+                path: self.relative(path),
+                from: Some(self.relative(from)),
+                message: &message,
+            };
+            if let Some(script_message) = script.commit_message(&event) {
+                message = script_message;
+            }
+        }
+        let paths = [from, path];
+        if !self.confirm(&paths, &message) {
+            return false;
+        }
+        let result = paths
+            .iter()
+            .try_for_each(|path| self.repo.stage_directory(path))
+            .and_then(|_| self.repo.commit(&self.full_message(&message)));
+        if let Err(err) = result {
+            // The change is left to the next snapshot
+            log::error!("failed to commit {}: {}", path.display(), err.message());
+            return false;
+        }
+        log::info!("commit with message: {}", message);
+        self.note_commit("renamed", Some(path));
+        let mut changed_at = None;
+        self.changed_at.retain(|changed, at| {
+            let renamed = changed.starts_with(from) || changed.starts_with(path);
+            if renamed {
+                changed_at =
+                    Some(changed_at.map_or(*at, |changed_at: Instant| changed_at.min(*at)));
+            }
+            !renamed
+        });
+        self.broadcast_commit(message, changed_at);
+        true
+    }
+
+    /// Hold the event back while the adaptive delay is longer than the watcher's,
+    /// a later event to the same path replaces it.
+    /// Returns the event if it is not held.
+    fn hold_event(&mut self, event: DebouncedEvent) -> Option<DebouncedEvent> {
+        let adaptive_delay = match &self.adaptive_delay {
+            Some(adaptive_delay) => adaptive_delay,
+            None => return Some(event),
+        };
+        let path = match &event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::Remove(path)
+            | DebouncedEvent::Rename(_, path) => path.clone(),
+            _ => return Some(event),
+        };
+        let now = Instant::now();
+        let delay = Duration::from_secs(self.delay(path.parent().unwrap_or(&path)));
+        if adaptive_delay.delay(delay, now) <= delay && !self.held_events.contains_key(&path) {
+            return Some(event);
+        }
+        // The watcher sends the event its delay after the last change
+        let last_change = now.checked_sub(delay).unwrap_or(now);
+        log::debug!("holding {} back while editing heavily", path.display());
+        let event = match self.held_events.remove(&path) {
+            // The file is still new
+            Some((created @ DebouncedEvent::Create(_), _))
+                if matches!(event, DebouncedEvent::Write(_) | DebouncedEvent::Chmod(_)) =>
+            {
+                created
+            }
+            _ => event,
+        };
+        self.held_events.insert(path, (event, last_change));
+        None
+    }
+
+    /// Commit the events held back by the adaptive delay, once their path was left alone long enough.
+    fn run_held_events(&mut self) {
+        // Released once the pause or the quiet hours end
+        if self.held_events.is_empty()
+            || self.paused
+            || active_quiet_hours(&self.config.quiet_hours, Local::now()).is_some()
+        {
+            return;
+        }
+        let now = Instant::now();
+        let due = self
+            .held_events
+            .iter()
+            .filter(|(path, (_, last_change))| {
+                let delay = Duration::from_secs(self.delay(path.parent().unwrap_or(path)));
+                let held_for = match &self.adaptive_delay {
+                    // The delay may have shortened since the event was held
+                    Some(adaptive_delay) => adaptive_delay.delay(delay, now),
+                    None => delay,
+                };
+                now.duration_since(*last_change) >= held_for
+            })
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        for path in due {
+            if let Some((event, _)) = self.held_events.remove(&path) {
+                self.commit_event(event);
+            }
+        }
+    }
+
+    /// Wait for the debounced events still in flight and process them,
+    /// so the changes made right before shutting down get their own commits.
+    /// Gives up after the watcher delay, or once the push on exit is skipped.
+    fn flush_events(
+        &mut self,
+        event_rcv: &crossbeam_channel::Receiver<DebouncedEvent>,
+        in_flight: &mut HashSet<PathBuf>,
+        budget: Option<Instant>,
+    ) {
+        if in_flight.is_empty() {
+            return;
+        }
+        log::info!("Waiting for {} pending events.", in_flight.len());
+        let deadline = Instant::now() + Duration::from_secs(self.config.delay + 1);
+        let deadline = budget.map_or(deadline, |budget| budget.min(deadline));
+        while !in_flight.is_empty() && Instant::now() < deadline && !self.signals.skip_push() {
+            match event_rcv.recv_timeout(Duration::from_millis(100)) {
+                Ok(event) => self.receive_event(event, in_flight),
+                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+
+    /// Reload the configuration if requested.
+    fn run_reload(&mut self) {
+        if !self.signals.take_reload() {
+            return;
+        }
+        let reload_config = match &self.reload_config {
+            Some(reload_config) => reload_config,
+            None => return,
+        };
+        match reload_config().and_then(|config| self.apply_config(config)) {
+            Ok(()) => log::info!("Configuration reloaded."),
+            Err(err) => log::error!(
+                "Failed to reload the configuration, keeping the previous one: {}",
+                err
+            ),
+        }
+    }
+
+    /// Replace the configuration, settings which are only read at startup are kept.
+    fn apply_config(&mut self, config: Config) -> Result<()> {
+        config.validate()?;
+        let push_schedule = config
+            .push_schedule
+            .as_deref()
+            .map(parse_cron)
+            .transpose()?;
+        if config.ignore != self.config.ignore
+            || config.force_include != self.config.force_include
+            || config.follow_symlinks != self.config.follow_symlinks
+            || config.unicode_normalization != self.config.unicode_normalization
+            || config.mirrors != self.config.mirrors
+            || config.attachments != self.config.attachments
+            || config.limits.max_watches != self.config.limits.max_watches
+            || config.limits.max_pending_events != self.config.limits.max_pending_events
+        {
+            log::warn!(
+                "Changes to ignore, force_include, follow_symlinks, unicode_normalization, mirrors, attachments, max_watches and max_pending_events take effect after a restart."
+            );
+        }
+        config.limits.apply_to_libgit2()?;
+        config.apply_tls().wrap_err(Failure::Config)?;
+        // Which directories are watched is only resolved at startup, their delay is updated on the next tick
+        self.overrides = Overrides::new(&config.directories)?;
+        // The commitlint configuration may have been pulled since
+        self.conventions = read_conventions(self.discovery.root());
+        self.update_delays = true;
+        self.next_push = push_schedule.as_ref().and_then(next_occurrence);
+        self.push_schedule = push_schedule;
+        let maintenance_schedule = config
+            .maintenance_schedule
+            .as_deref()
+            .map(parse_cron)
+            .transpose()?;
+        self.next_maintenance = maintenance_schedule.as_ref().and_then(next_occurrence);
+        self.maintenance_schedule = maintenance_schedule;
+        if config.commit_strategy() != self.config.commit_strategy()
+            || config.amend_window != self.config.amend_window
+            || config.commit_batch_interval != self.config.commit_batch_interval
+            || config.session_gap != self.config.session_gap
+        {
+            self.strategy = commit_strategy(&config);
+            // Snapshot the changes deferred by the previous strategy
+            self.deferred_since.get_or_insert_with(Instant::now);
+        }
+        if config.adaptive_delay != self.config.adaptive_delay {
+            // The held events are released on the next tick
+            self.adaptive_delay = adaptive_delay(&config);
+        }
+        if config.storm_threshold != self.config.storm_threshold {
+            self.storm = StormDetector::new(config.storm_threshold);
+            // Snapshot the changes of an ongoing storm
+            self.deferred_since.get_or_insert_with(Instant::now);
+        }
+        if let Some(ignore_chmod) = config.ignore_chmod {
+            self.ignore_chmod = ignore_chmod;
+        }
+        if config.notify_command != self.config.notify_command
+            || config.notify_cooldown != self.config.notify_cooldown
+            || config.notify_payload != self.config.notify_payload
+            || config.notify != self.config.notify
+        {
+            self.notifier = notifier(&config, self.discovery.root());
+        }
+        self.push_alerts
+            .set_escalation(config.push_failure_escalation);
+        // e.g. the push mode was changed to push elsewhere
+        self.push_rejected = None;
+        // Reloading the configuration reloads the script as well
+        self.script = load_script(&config, self.discovery.root())?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Apply a control request.
+    fn handle_control(&mut self, control: Control) {
+        match control {
+            Control::Pause if !self.paused => {
+                log::info!("Pausing auto-commits.");
+                self.paused = true;
+                self.activity.broadcast(Activity::Paused);
+            }
+            Control::Resume if self.paused => {
+                log::info!("Resuming auto-commits.");
+                self.paused = false;
+                self.activity.broadcast(Activity::Resumed);
+            }
+            Control::PushNow => {
+                self.retry_push_at = None;
+                self.push_rejected = None;
+                self.try_push("Push requested.");
+            }
+            _ => {}
+        }
+    }
+
+    /// Snapshot the changes deferred while paused or during quiet hours,
+    /// once they end or, in batch mode, once the batch interval elapses.
+    fn run_deferred_snapshot(&mut self) {
+        let pending_since = match self.deferred_since {
+            Some(pending_since) if !self.paused => pending_since,
+            _ => return,
+        };
+        let message = match active_quiet_hours(&self.config.quiet_hours, Local::now()) {
+            None => self.config.catalog().deferred_snapshot,
+            Some(quiet_hours)
+                if quiet_hours.mode == QuietMode::Batch
+                    && pending_since.elapsed()
+                        >= Duration::from_secs(quiet_hours.batch_interval * 60) =>
+            {
+                self.config.catalog().quiet_hours_snapshot
+            }
+            Some(_) => return,
+        };
+        self.deferred_since = None;
+        if self.snapshot(message) {
+            self.unpushed_commits += 1;
+            self.run_threshold_push();
+        }
+    }
+
+    /// Snapshot the changes made during an event storm, once it settles.
+    fn run_storm_snapshot(&mut self) {
+        let events = match self.storm.settle(Instant::now()) {
+            Some(events) => events,
+            None => return,
+        };
+        log::info!("The event storm settled after {} events.", events);
+        if self.paused || active_quiet_hours(&self.config.quiet_hours, Local::now()).is_some() {
+            self.deferred_since.get_or_insert_with(Instant::now);
+            return;
+        }
+        let message = render(
+            self.config.catalog().bulk_snapshot,
+            &[("count", &events.to_string())],
+        );
+        if self.snapshot(&message) {
+            self.unpushed_commits += 1;
+            self.run_threshold_push();
+        }
+    }
+
+    /// Snapshot the changes deferred by the commit strategy, once due.
+    fn run_strategy_snapshot(&mut self) {
+        // The changes are snapshotted by the deferred snapshot meanwhile
+        if self.paused || active_quiet_hours(&self.config.quiet_hours, Local::now()).is_some() {
+            return;
+        }
+        let message = match self.strategy.snapshot_due(Instant::now()) {
+            Some(message) => message,
+            None => return,
+        };
+        let commits = match self.config.group_by_folder {
+            Some(depth) => self.grouped_snapshot(&message, depth),
+            None => usize::from(self.snapshot(&message)),
+        };
+        if commits > 0 {
+            self.unpushed_commits += commits;
+            self.run_threshold_push();
+        }
+    }
+
+    /// Snapshot the changes every `snapshot_interval` hours, even if no events fired.
+    fn run_heartbeat_snapshot(&mut self) {
+        let interval = match self.config.snapshot_interval {
+            Some(interval) => Duration::from_secs(interval * 60 * 60),
+            None => return,
+        };
+        if self.last_snapshot.elapsed() < interval {
+            return;
+        }
+        self.last_snapshot = Instant::now();
+        if self.snapshot(self.config.catalog().heartbeat_snapshot) {
+            self.unpushed_commits += 1;
+            self.run_threshold_push();
+        }
+    }
+
+    /// Run the repository maintenance if it is due.
+    fn run_scheduled_maintenance(&mut self) {
+        let due = matches!(self.next_maintenance, Some(next) if next <= Local::now());
+        if !due {
+            return;
+        }
+        self.next_maintenance = self.maintenance_schedule.as_ref().and_then(next_occurrence);
+        self.run_maintenance();
+    }
+
+    fn run_maintenance(&self) {
+        run_maintenance(&self.repo);
+    }
+
+    /// Fetch and prune every `fetch_interval` minutes.
+    fn run_fetch(&mut self) {
+        let (interval, authentication_method) =
+            match (self.config.fetch_interval, &self.authentication_method) {
+                (Some(interval), Some(authentication_method)) => {
+                    (Duration::from_secs(interval * 60), authentication_method)
+                }
+                _ => return,
+            };
+        if self.last_fetch.elapsed() < interval {
+            return;
+        }
+        self.last_fetch = Instant::now();
+        log::debug!("fetching from the remote");
+        if let Err(err) = self.repo.fetch(authentication_method, true) {
+            log::warn!("failed to fetch: {}", err.message());
+        }
+    }
+
+    /// Sync with the remote every `sync_interval` minutes, so that the devices converge:
+    /// the pending changes are committed, then the branch is fast-forwarded to the remote
+    /// if it has no commits of its own, otherwise they are rebased onto it and pushed.
+    fn run_periodic_sync(&mut self) {
+        let interval = match self.config.sync_interval {
+            Some(interval) => Duration::from_secs(interval * 60),
+            None => return,
+        };
+        if self.paused || self.last_sync.elapsed() < interval {
+            return;
+        }
+        self.last_sync = Instant::now();
+        let authentication_method = match &self.authentication_method {
+            Some(authentication_method) => authentication_method.clone(),
+            None => return,
+        };
+        // Rebasing needs the changes to be committed
+        if self.snapshot(self.config.catalog().sync_snapshot) {
+            self.unpushed_commits += 1;
+        }
+        log::debug!("fetching from the remote");
+        if let Err(err) = self.repo.fetch(&authentication_method, false) {
+            log::warn!("failed to fetch: {}", err.message());
+            return;
+        }
+        match self.repo.ahead_behind() {
+            Ok(Some((0, 0))) | Ok(None) => log::debug!("already in sync with the remote"),
+            Ok(Some((0, _))) => match self.repo.fast_forward_to_upstream() {
+                Ok(FastForwardOutcome::FastForwarded) => {
+                    log::info!("Fast-forwarded to the remote.")
+                }
+                Ok(FastForwardOutcome::Blocked(paths)) => log::warn!(
+                    "Local modifications block the update from the remote: {:?}",
+                    paths
+                ),
+                Ok(outcome) => log::debug!("fast-forward outcome: {:?}", outcome),
+                Err(err) => log::warn!("failed to fast-forward: {}", err.message()),
+            },
+            Ok(Some(_)) => {
+                self.try_push("Syncing with the remote.");
+            }
+            Err(err) => log::warn!("failed to compare with the upstream: {}", err.message()),
+        }
+    }
+
+    /// Compare the branch with its upstream every `UPSTREAM_CHECK_INTERVAL` seconds,
+    /// warning when too many commits are unpushed or when the remote diverged.
+    fn run_upstream_check(&mut self) {
+        if self.last_upstream_check.elapsed() < Duration::from_secs(UPSTREAM_CHECK_INTERVAL) {
+            return;
+        }
+        self.last_upstream_check = Instant::now();
+        let (ahead, behind) = match self.repo.ahead_behind() {
+            Ok(Some(ahead_behind)) => ahead_behind,
+            Ok(None) => return,
+            Err(err) => {
+                log::warn!("failed to compare with the upstream: {}", err.message());
+                return;
+            }
+        };
+        log::debug!("{} commits ahead and {} behind the upstream", ahead, behind);
+        let unpushed = matches!(
+            self.config.unpushed_warning_threshold,
+            Some(threshold) if ahead >= threshold
+        );
+        if unpushed && !self.warned_unpushed {
+            self.notifier.warn(
+                "unpushed",
+                &format!("{} commits are waiting to be pushed", ahead),
+            );
+        }
+        self.warned_unpushed = unpushed;
+        let diverged = ahead > 0 && behind > 0;
+        if diverged && !self.warned_diverged {
+            self.notifier.warn(
+                "diverged",
+                &format!(
+                    "the remote diverged: {} local and {} remote commits are not on the other side",
+                    ahead, behind
+                ),
+            );
+        }
+        self.warned_diverged = diverged;
+    }
+
+    /// Measure the repository in the background once the last size check (of the watcher or `nabu size`)
+    /// is a day old, warning when `.git` grew faster than `growth_warning_megabytes_per_day` since then.
+    fn run_size_check(&mut self) {
+        let threshold = match self.config.growth_warning_megabytes_per_day {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        if let Some(size_check) = &self.size_check {
+            match size_check.try_recv() {
+                Ok(growth) => {
+                    self.size_check = None;
+                    if let Some(growth) = growth.filter(|growth| growth.exceeds(threshold)) {
+                        self.notifier.warn(
+                            "growth",
+                            &format!(
+                                "the .git directory grew by {} in {:.1} days, faster than {} MB per day, see `nabu size`",
+                                format_bytes(growth.git.unsigned_abs()),
+                                growth.days,
+                                threshold
+                            ),
+                        );
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => self.size_check = None,
+            }
+        }
+        if self.last_size_check.elapsed() < SIZE_CHECK_INTERVAL {
+            return;
+        }
+        self.last_size_check = Instant::now();
+        let root = self.discovery.root().to_path_buf();
+        let (growth_snd, growth_rcv) = channel();
+        thread::spawn(move || {
+            let _ = growth_snd.send(measure_growth(&root));
+        });
+        self.size_check = Some(growth_rcv);
+    }
+
+    /// Move the commits onto the rolling branch of the current period, once per period.
+    fn run_rolling_branch(&mut self) {
+        let period = match self.config.rolling_branch {
+            Some(period) => period,
+            None => return,
+        };
+        let name = period.branch_name(
+            &self.config.rolling_branch_prefix,
+            self.config.timezone.today(),
+        );
+        if self.rolled_branch.as_ref() == Some(&name) {
+            return;
+        }
+        if self.repo.branch().as_ref() != Some(&name) && !self.push_before_rolling(&name) {
+            return;
+        }
+        let commit = match self.repo.head() {
+            Ok(head) => head.map(|head| head.to_string()).unwrap_or_default(),
+            Err(err) => {
+                log::warn!("failed to find HEAD: {}", err.message());
+                return;
+            }
+        };
+        let message = render(
+            self.config.catalog().rolling_branch_snapshot,
+            &[("branch", &name), ("commit", &commit)],
+        );
+        match self
+            .repo
+            .switch_to_rolling_branch(&name, &self.full_message(&message))
+        {
+            Ok(true) => {
+                // The previous branch's commits are not amended anymore
+                self.last_event_commit = None;
+                self.notifier.notify(
+                    log::Level::Info,
+                    "rolling-branch",
+                    &format!("Moved the commits onto the branch {}.", name),
+                );
+            }
+            Ok(false) => {}
+            Err(err) => log::warn!("failed to move onto the branch {}: {}", name, err.message()),
+        }
+        self.rolled_branch = Some(name);
+    }
+
+    /// Push the current branch's commits before moving onto the rolling branch,
+    /// only the new branch is pushed afterwards. A failed push is retried later, see `PUSH_RETRY_INTERVAL`.
+    /// Returns whether the current branch has no unpushed commits, or the watcher does not push.
+    fn push_before_rolling(&mut self, name: &str) -> bool {
+        if !self.config.pushes()
+            || (self.authentication_method.is_none() && self.config.remote.s3.is_none())
+        {
+            return true;
+        }
+        let pushed = match self.repo.head() {
+            Ok(Some(head)) => {
+                self.unpushed_commits == 0 && self.repo.is_pushed(head).unwrap_or(false)
+            }
+            Ok(None) => true,
+            Err(err) => {
+                log::warn!("failed to find HEAD: {}", err.message());
+                false
+            }
+        };
+        if pushed
+            || self.try_push(&format!(
+                "Pushing the commits before moving onto the branch {}.",
+                name
+            ))
+        {
+            return true;
+        }
+        if self.retry_push_at.is_none() {
+            log::warn!(
+                "Not moving onto the branch {} until the commits of the current one are pushed, retrying in {}s.",
+                name,
+                PUSH_RETRY_INTERVAL
+            );
+            self.retry_push_at = Some(Instant::now() + Duration::from_secs(PUSH_RETRY_INTERVAL));
+        }
+        false
+    }
+
+    /// Tag the last commit of the previous days, once a day.
+    fn run_daily_tags(&mut self) {
+        if !self.config.daily_tags {
+            return;
+        }
+        let today = self.config.timezone.today();
+        if self.tagged_day == Some(today) {
+            return;
+        }
+        self.tagged_day = Some(today);
+        match self.repo.tag_past_days(self.config.timezone) {
+            Ok(tags) if tags.is_empty() => {}
+            Ok(tags) => self.notifier.notify(
+                log::Level::Info,
+                "daily-snapshot",
+                &format!("Tagged the daily snapshots {}.", tags.join(", ")),
+            ),
+            Err(err) => log::warn!("failed to tag the daily snapshots: {}", err.message()),
+        }
+    }
+
+    /// Reconcile every `reconcile_interval` minutes.
+    fn run_reconciliation(&mut self) {
+        let interval = match self.config.reconcile_interval {
+            Some(interval) => Duration::from_secs(interval * 60),
+            None => return,
+        };
+        if self.last_reconciliation.elapsed() >= interval {
+            self.reconcile();
+        }
+    }
+
+    /// Every `watchdog_interval` seconds, check a sample of the watched directories
+    /// for changes which should have been reported by now, restarting the file watchers if so
+    /// (e.g. FSEvents streams can die silently) and committing the missed changes.
+    fn run_watchdog(&mut self, watchers: &mut Watchers) {
+        let interval = match self.config.watchdog_interval {
+            0 => return,
+            interval => Duration::from_secs(interval),
+        };
+        if self.last_watchdog_check.elapsed() < interval {
+            return;
+        }
+        self.last_watchdog_check = Instant::now();
+        // The events of more recent changes may still be debounced
+        let settled =
+            SystemTime::now() - Duration::from_secs(watchers.max_delay() + WATCHDOG_GRACE_PERIOD);
+        let missed =
+            match watchers.sample_modified(self.last_event_received, settled, &self.discovery) {
+                Some(missed) => missed,
+                None => return,
+            };
+        log::warn!(
+            "The file watchers missed the changes to {}, restarting them.",
+            missed.display()
+        );
+        watchers.restart();
+        self.last_event_received = SystemTime::now();
+        self.reconcile();
+    }
+
+    /// Report the directories which cannot be watched when they change, and every
+    /// [`UNWATCHED_RETRY_INTERVAL`] try to watch them again, along with their subdirectories,
+    /// committing the changes made in the ones which can now be watched.
+    fn run_unwatched(&mut self, watchers: &mut Watchers) {
+        if self.last_unwatched_retry.elapsed() >= UNWATCHED_RETRY_INTERVAL {
+            self.last_unwatched_retry = Instant::now();
+            let watched = watchers.retry_skipped();
+            if !watched.is_empty() {
+                log::info!(
+                    "Watching {} directories which could not be watched before.",
+                    watched.len()
+                );
+                for dir in watched {
+                    for subdir in self.discovery.subdirs(&dir) {
+                        let delay = self.delay(&subdir);
+                        watchers.watch(subdir, delay);
+                    }
+                }
+                self.reconcile();
+            }
+        }
+
+        let unwatched = watchers.skipped_paths();
+        if unwatched != self.unwatched {
+            self.unwatched = unwatched.clone();
+            self.activity
+                .broadcast(Activity::Unwatched { paths: unwatched });
+        }
+    }
+
+    /// Every [`POLL_INTERVAL`], check the directories polled rather than watched (past `max_watches`
+    /// or the OS's limit) for changes, committing them and polling the subdirectories created since.
+    fn run_polled(&mut self, watchers: &mut Watchers) {
+        if watchers.polled.is_empty()
+            || self
+                .last_poll
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed < POLL_INTERVAL)
+        {
+            return;
+        }
+        let now = SystemTime::now();
+        let modified = watchers.polled_modified(self.last_poll, now, &self.discovery);
+        self.last_poll = now;
+        if modified.is_empty() {
+            return;
+        }
+        log::debug!("polled directories changed: {:?}", modified);
+        // Their changes are not missed by the file watchers
+        self.last_event_received = now;
+        for dir in modified {
+            for subdir in self.discovery.subdirs(&dir) {
+                let delay = self.delay(&subdir);
+                watchers.watch(subdir, delay);
+            }
+        }
+        self.reconcile();
+    }
+
+    /// Once events were dropped, the pending events being over `max_pending_events`,
+    /// commit their changes together as soon as the pending events are processed.
+    fn run_dropped_events(
+        &mut self,
+        events: &crossbeam_channel::Receiver<DebouncedEvent>,
+        dropped: &AtomicUsize,
+    ) {
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            if self.uncommitted_drops == 0 {
+                self.notifier.warn(
+                    "events-dropped",
+                    "Too many pending events, dropping the new ones until they are processed, their changes are committed together then.",
+                );
+            }
+            self.dropped_events += dropped;
+            self.uncommitted_drops += dropped;
+        } else if self.uncommitted_drops > 0 && events.is_empty() {
+            log::info!(
+                "Caught up with the pending events, committing the changes of the {} dropped.",
+                std::mem::take(&mut self.uncommitted_drops)
+            );
+            self.reconcile();
+        }
+    }
+
+    /// Every [`USAGE_INTERVAL`], report the resources used if they changed, for `nabu status`.
+    fn report_usage(
+        &mut self,
+        watchers: &Watchers,
+        events: &crossbeam_channel::Receiver<DebouncedEvent>,
+    ) {
+        if self.last_usage_report.elapsed() < USAGE_INTERVAL {
+            return;
+        }
+        self.last_usage_report = Instant::now();
+        let git_cache = git_cache_memory();
+        let usage = ResourceUsage {
+            watches: watchers.watchlist.len(),
+            polled: watchers.polled.len(),
+            pending_events: events.len(),
+            dropped_events: self.dropped_events,
+            git_cache: git_cache.map(|(used, _)| used),
+            git_cache_limit: git_cache.map(|(_, limit)| limit),
+        };
+        if usage != self.usage {
+            self.usage = usage.clone();
+            self.activity.broadcast(Activity::Usage { usage });
+        }
+    }
+
+    /// Commit the changes the watcher missed, as found by the repository status.
+    fn reconcile(&mut self) {
+        self.last_reconciliation = Instant::now();
+        let changed_paths = match self.repo.changed_paths() {
+            Ok(changed_paths) => changed_paths,
+            Err(err) => {
+                log::warn!("failed to check for changes: {}", err.message());
+                return;
+            }
+        };
+        if changed_paths.is_empty() {
+            return;
+        }
+        log::debug!("reconciling {:?}", changed_paths);
+        let message = render(
+            self.config.catalog().reconciliation_snapshot,
+            &[("count", &changed_paths.len().to_string())],
+        );
+        if self.catch_up_snapshot(&message) {
+            self.unpushed_commits += 1;
+            self.run_threshold_push();
+        }
+    }
+
+    /// Stage and commit all changes, skipping the commit if there are none.
+    /// Returns whether the commit was made.
+    fn snapshot(&mut self, message: &str) -> bool {
+        self.commit_snapshot(message, None, false)
+    }
+
+    /// Snapshot the changes with a commit per folder `depth` levels deep, see `group_by_folder`.
+    /// Returns the number of commits made.
+    fn grouped_snapshot(&mut self, message: &str, depth: usize) -> usize {
+        let paths = match self.repo.changed_paths() {
+            Ok(paths) => paths,
+            Err(err) => {
+                log::warn!("failed to list the changes: {}", err.message());
+                return usize::from(self.snapshot(message));
+            }
+        };
+        let mut commits = 0;
+        for (folder, paths) in group_by_folder(paths, depth) {
+            let catalog = self.config.catalog();
+            let count = paths.len().to_string();
+            let message = if folder.as_os_str().is_empty() {
+                render(
+                    catalog.top_level_group,
+                    &[("message", message), ("count", &count)],
+                )
+            } else {
+                render(
+                    catalog.folder_group,
+                    &[
+                        ("message", message),
+                        ("count", &count),
+                        ("folder", &folder.display().to_string()),
+                    ],
+                )
+            };
+            if self.commit_snapshot(&message, Some(&paths), false) {
+                commits += 1;
+            }
+        }
+        commits
+    }
+
+    /// Snapshot the changes made while they were not watched, see [`WatchCommand::snapshot`],
+    /// authored when the files were last modified if `author_time_from_mtime` is set.
+    fn catch_up_snapshot(&mut self, message: &str) -> bool {
+        self.commit_snapshot(message, None, self.config.author_time_from_mtime)
+    }
+
+    /// Stage and commit all changes, or `only` the given ones (relative to the working directory),
+    /// authored when the files were last modified if `backdate` is set.
+    fn commit_snapshot(&mut self, message: &str, only: Option<&[PathBuf]>, backdate: bool) -> bool {
+        match only.map_or_else(|| self.repo.has_changes(), |only| Ok(!only.is_empty())) {
+            Ok(false) => {
+                log::debug!("no changes, skipping {}", message);
+                return false;
+            }
+            Ok(true) => {}
+            Err(err) => log::warn!("failed to check for changes: {}", err.message()),
+        }
+        if self.comes_from_git(self.config.delay, None)
+            || self.holds_secrets(None)
+            || self.holds_conflict_markers(None)
+        {
+            return false;
+        }
+        let message = format!("{} @ {}", message, self.timestamp());
+        if self.confirmation.is_some() {
+            let paths = only.map_or_else(
+                || self.repo.changed_paths().unwrap_or_default(),
+                <[PathBuf]>::to_vec,
+            );
+            if !self.confirm(&paths, &message) {
+                return false;
+            }
+        }
+        let author_time = if backdate {
+            self.repo.last_modified().unwrap_or_else(|err| {
+                log::warn!(
+                    "failed to find when the files were modified: {}",
+                    err.message()
+                );
+                None
+            })
+        } else {
+            None
+        };
+        let result = self.stage_changes(only).and_then(|staged| {
+            match (staged, author_time) {
+                (false, _) => {}
+                (true, Some(author_time)) => self
+                    .repo
+                    .commit_authored_at(&self.full_message(&message), author_time)?,
+                (true, None) => self.repo.commit(&self.full_message(&message))?,
+            }
+            Ok(staged)
+        });
+        match result {
+            Ok(false) => {
+                log::debug!("the script skipped every change, skipping {}", message);
+                false
+            }
+            Ok(true) => {
+                log::info!("commit with message: {}", message);
+                self.note_commit("snapshot", None);
+                let workdir = self.workdir.clone();
+                let mut changed_at = None;
+                self.changed_at.retain(|changed, at| {
+                    let committed = only
+                        .is_none_or(|only| only.iter().any(|path| workdir.join(path) == *changed));
+                    if committed {
+                        changed_at =
+                            Some(changed_at.map_or(*at, |changed_at: Instant| changed_at.min(*at)));
+                    }
+                    !committed
+                });
+                self.uncommitted.retain(|changed| {
+                    only.is_some_and(|only| !only.iter().any(|path| workdir.join(path) == *changed))
+                });
+                self.broadcast_commit(message, changed_at);
+                true
+            }
+            Err(err) => {
+                log::error!("failed to commit snapshot: {}", err.message());
+                false
+            }
+        }
+    }
+
+    /// Broadcast the commit, along with its latency since the oldest change it includes, if known.
+    fn broadcast_commit(&mut self, message: String, changed_at: Option<Instant>) {
+        self.oldest_unpushed.get_or_insert_with(Instant::now);
+        self.activity.broadcast(Activity::Commit {
+            message,
+            latency_ms: changed_at.map(elapsed_ms),
+        });
+    }
+
+    /// Stage the changes, or `only` the given ones, and only the ones accepted by the script's
+    /// `should_commit` if any.
+    /// Returns whether anything was staged.
+    fn stage_changes(&self, only: Option<&[PathBuf]>) -> Result<bool, git2::Error> {
+        let paths = match (only, &self.script) {
+            (None, None) => return self.repo.stage_all().map(|_| true),
+            (Some(only), _) => only.to_vec(),
+            (None, Some(_)) => self.repo.changed_paths()?,
+        };
+        let mut staged = false;
+        for path in paths {
+            if self
+                .script
+                .as_ref()
+                .is_none_or(|script| script.should_commit(&path))
+            {
+                self.repo.stage(self.workdir.join(&path))?;
+                staged = true;
+            }
+        }
+        Ok(staged)
+    }
+
+    /// Push if the push schedule is due, or if a previously skipped push should be retried.
+    fn run_scheduled_push(&mut self) {
+        if matches!(self.retry_push_at, Some(retry_push_at) if retry_push_at <= Instant::now()) {
+            self.retry_push_at = None;
+            self.try_push("Retrying skipped push.");
+        }
+        let due = matches!(self.next_push, Some(next_push) if next_push <= Local::now());
+        if !due {
+            return;
+        }
+        self.next_push = self.push_schedule.as_ref().and_then(next_occurrence);
+        self.try_push("Scheduled push.");
+    }
+
+    /// Push if the number of unpushed commits, or their size, reached the configured threshold.
+    fn run_threshold_push(&mut self) {
+        if let Some(threshold) = self.config.push_every_n_commits {
+            if self.unpushed_commits >= threshold {
+                self.try_push(&format!(
+                    "{} commits waiting to be pushed, pushing.",
+                    threshold
+                ));
+                return;
+            }
+        }
+        let threshold = match self.config.push_every_n_megabytes {
+            Some(threshold) if self.unpushed_commits > 0 => threshold,
+            _ => return,
+        };
+        match self.repo.unpushed_bytes() {
+            Ok(bytes) if bytes >= threshold * BYTES_PER_MEGABYTE => {
+                self.try_push(&format!(
+                    "{} MB waiting to be pushed, pushing.",
+                    bytes / BYTES_PER_MEGABYTE
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("failed to measure the unpushed commits: {}", err),
+        }
+    }
+
+    /// Push if the push preconditions are met, otherwise retry later.
+    /// Returns whether the commits were pushed.
+    fn try_push(&mut self, reason: &str) -> bool {
+        if self.authentication_method.is_none() && self.config.remote.s3.is_none() {
+            return false;
+        }
+        if self.retry_push_at.is_some() {
+            // A retry is already scheduled
+            return false;
+        }
+        if let Some(error) = &self.push_rejected {
+            log::debug!("Skipping push, the remote rejects it: {}", error);
+            return false;
+        }
+        if let Some(skip_reason) = check_push_preconditions(&self.config) {
+            log::info!(
+                "Skipping push ({}), retrying in {}s.",
+                skip_reason,
+                PUSH_RETRY_INTERVAL
+            );
+            self.retry_push_at = Some(Instant::now() + Duration::from_secs(PUSH_RETRY_INTERVAL));
+            return false;
+        }
+        log::info!("{}", reason);
+        let outcome = push(
+            &self.repo,
+            self.authentication_method.as_ref(),
+            &self.config,
+        );
+        let mut latency_ms = None;
+        if outcome.is_pushed() {
+            self.unpushed_commits = 0;
+            latency_ms = self.oldest_unpushed.take().map(elapsed_ms);
+        }
+        if let PushOutcome::Failed {
+            error,
+            permanent: true,
+            ..
+        } = &outcome
+        {
+            log::error!(
+                "The remote rejects the pushes ({}), they are stopped until the configuration is reloaded. Push to a branch of your own instead, with `push_mode = \"device\"` or `push_refspecs`.",
+                error
+            );
+            self.push_rejected = Some(error.clone());
+        }
+        self.push_alerts.record(&outcome, &mut self.notifier);
+        if let Some(script) = &self.script {
+            script.on_push_result(&outcome);
+        }
+        let pushed = outcome.is_pushed();
+        self.activity.broadcast(Activity::Push {
+            outcome,
+            latency_ms,
+        });
+        pushed
+    }
+
+    /// Handle an event sent by the watcher.
+    /// Returns whether a commit was made.
+    fn handle_event(&mut self, event: &DebouncedEvent) -> bool {
+        log::debug!("received event: {:?}", event);
+        // TODO: better commit messages (e.g. short title, descriptive body)
+        let (path, from, action) = match event {
+            DebouncedEvent::Create(path) => {
+                if path.is_dir() {
+                    return false;
+                }
+                (path, None, "created")
+            }
+            DebouncedEvent::Write(path) => (path, None, "written"),
+            DebouncedEvent::Chmod(_) if self.ignore_chmod => return false,
+            DebouncedEvent::Chmod(path) => (path, None, "chmod"),
+            DebouncedEvent::Remove(path) => (path, None, "deleted"),
+            DebouncedEvent::Rename(old, new) => (new, Some(old), "renamed"),
+            // Handled by the reconciliation pass
+            DebouncedEvent::Rescan => return false,
+            DebouncedEvent::Error(err, path) => {
+                match path {
+                    Some(path) => {
+                        log::warn!("skipping {}, watching it failed: {}", path.display(), err)
+                    }
+                    None => log::warn!("the file watcher failed: {}", err),
+                }
+                return false;
+            }
+            DebouncedEvent::NoticeRemove(_) | DebouncedEvent::NoticeWrite(_) => {
+                return false;
+            }
+        };
+
+        let settings = self.overrides.settings(self.relative(path));
+        if !settings.watch {
+            log::debug!("{} is not watched, skipping the event", path.display());
+            return false;
+        }
+        if !matches!(self.repo.is_checked_out(path), Ok(true)) {
+            log::debug!(
+                "{} is outside of the sparse checkout, skipping the event",
+                path.display()
+            );
+            return false;
+        }
+        if matches!(self.repo.is_excluded(path), Ok(true)) {
+            log::debug!(
+                "{} holds secrets, it is kept out of the commits",
+                path.display()
+            );
+            return false;
+        }
+        // A renamed file is tracked if it was before
+        if !self.config.track_new_files
+            && !matches!(self.repo.is_tracked(from.unwrap_or(path)), Ok(true))
+        {
+            log::debug!("{} is not tracked, skipping the event", path.display());
+            return false;
+        }
+        if let Some(script) = &self.script {
+            if !script.should_commit(self.relative(path)) {
+                log::debug!("the script skipped {}", path.display());
+                return false;
+            }
+        }
+        let delay = settings.delay.unwrap_or(self.config.delay);
+        if self.comes_from_git(delay, Some(path)) {
+            return false;
+        }
+        // Only the mode changes git records are committed, e.g. not the read permissions
+        let modes = if action == "chmod" {
+            match self.repo.mode_change(path) {
+                Ok(Some(modes)) => Some(modes),
+                Ok(None) => {
+                    log::debug!("git does not see the mode of {} change", path.display());
+                    return false;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "failed to check the mode of {}: {}",
+                        path.display(),
+                        err.message()
+                    );
+                    return false;
+                }
+            }
+        } else {
+            None
+        };
+        let catalog = self.config.catalog();
+        let template = settings
+            .commit_message
+            .or_else(|| self.config.commit_message.clone())
+            .unwrap_or_else(|| match (from, modes) {
+                (Some(_), _) => catalog.rename_commit_message.to_string(),
+                (None, Some(_)) => catalog.chmod_commit_message.to_string(),
+                (None, None) => catalog.commit_message.to_string(),
+            });
+        let rendered_from = from.map(|from| self.message_path(from)).unwrap_or_default();
+        let (old_mode, new_mode) = modes
+            .map(|(old, new)| (format!("{:06o}", old), format!("{:06o}", new)))
+            .unwrap_or_default();
+        let mut message = render(
+            &template,
+            &[
+                ("action", catalog.action(action)),
+                ("path", &self.message_path(path)),
+                ("from", &rendered_from),
+                ("old_mode", &old_mode),
+                ("new_mode", &new_mode),
+                ("time", &self.timestamp()),
+            ],
+        );
+        if let Some(script) = &self.script {
+            let event = ScriptEvent {
+                action,
+                path: self.relative(path),
+                from: from.map(|from| self.relative(from)),
+                message: &message,
+            };
+            if let Some(script_message) = script.commit_message(&event) {
+                message = script_message;
+            }
+        }
+        // e.g. the change was committed by a snapshot, possibly by a previous session
+        let committed = |path| matches!(self.repo.is_committed(path), Ok(true));
+        if committed(path) && from.is_none_or(committed) {
+            log::debug!("{} is already committed", path.display());
+            return false;
+        }
+        let from = rendered_from;
+
+        let now = Instant::now();
+        let content = content_hash(path);
+        if from.is_empty() && self.is_duplicate(path, content, Duration::from_secs(delay)) {
+            log::debug!("{} did not change since its last commit", path.display());
+            return false;
+        }
+        let decision = self.strategy.on_change(path, now);
+        if decision == Decision::Defer {
+            log::debug!("{} is left to the next snapshot", path.display());
+            return false;
+        }
+
+        if self.holds_secrets(Some(path)) || self.holds_conflict_markers(Some(path)) {
+            return false;
+        }
+
+        let paths = match event {
+            DebouncedEvent::Rename(old, new) => vec![old, new],
+            _ => vec![path],
+        };
+        if !self.confirm(&paths, &message) {
+            return false;
+        }
+
+        let amend = decision == Decision::Amend && self.can_amend();
+        // A rename removes the old path
+        let result = paths
+            .iter()
+            .try_for_each(|path| self.repo.stage(path))
+            .and_then(|_| {
+                if amend {
+                    self.repo.amend(&self.full_message(&message))
+                } else {
+                    self.repo.commit(&self.full_message(&message))
+                }
+            });
+        if let Err(err) = result {
+            // The change is left to the next snapshot
+            log::error!("failed to commit {}: {}", path.display(), err.message());
+            return false;
+        }
+        if amend {
+            log::info!("amended the last commit with message: {}", message);
+        } else {
+            log::info!("commit with message: {}", message);
+        }
+        self.note_commit(action, Some(path));
+        if let Ok(metadata) = std::fs::metadata(path) {
+            self.observe(path, |observations, path| {
+                observations.record_commit(path, metadata.len(), Local::now())
+            });
+        }
+        let changed_at = paths
+            .iter()
+            .filter_map(|path| self.changed_at.remove(*path))
+            .min();
+        for path in &paths {
+            self.uncommitted.remove(*path);
+        }
+        self.broadcast_commit(message, changed_at);
+        self.strategy.on_commit(path, amend, now);
+        self.last_event_commit = self.repo.head().ok().flatten();
+        self.last_committed = Some((path.clone(), content, now));
+        !amend
+    }
+
+    /// The commit message, naming the author first in a shared vault, following the repository's conventions
+    /// if enabled by `[conventions]` and ending with the trailers telling it is nabu's,
+    /// identifying the session if enabled by `session_trailer`.
+    fn full_message(&self, message: &str) -> String {
+        let message = match self.repo.author_name() {
+            Ok(author) if self.config.shared_vault => format!("[{}] {}", author, message),
+            _ => message.to_string(),
+        };
+        let message = match &self.config.conventions {
+            Some(settings) => self.conventions.apply(settings, &message),
+            None => message,
+        };
+        let session = self.config.session_trailer.then_some(self.session.as_str());
+        with_trailers(&message, session)
+    }
+
+    /// Note the metadata of the commit `HEAD` points to, if enabled by `commit_notes`,
+    /// along with the path of the event and when it was changed.
+    fn note_commit(&self, event: &str, path: Option<&Path>) {
+        if !self.config.commit_notes {
+            return;
+        }
+        let head = match self.repo.head() {
+            Ok(Some(head)) => head,
+            _ => return,
+        };
+        // Removed files have no modification time
+        let event_time = path
+            .and_then(|path| {
+                path.symlink_metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+            .map(|modified| DateTime::<Local>::from(modified).to_rfc3339());
+        let metadata = CommitMetadata {
+            event: event.to_string(),
+            path: path.map(|path| match self.config.redact_paths {
+                PathRedaction::None => self.relative(path).to_path_buf(),
+                redaction => PathBuf::from(redaction.apply(self.relative(path))),
+            }),
+            event_time,
+            device: device_name(self.config.device_name.as_deref()),
+            session: self.session.clone(),
+        };
+        if let Err(err) = self.repo.add_note(head, &metadata) {
+            log::warn!("failed to note the commit: {}", err.message());
+        }
+    }
+
+    /// Whether the event repeats the last committed one, i.e. same path and content,
+    /// within the debounce horizon.
+    fn is_duplicate(&self, path: &Path, content: Option<git2::Oid>, horizon: Duration) -> bool {
+        match &self.last_committed {
+            Some((last_path, last_content, at)) => {
+                last_path == path && *last_content == content && at.elapsed() <= horizon
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the changes to the path, or all changes, come from a git operation
+    /// (e.g. a checkout or a merge), in which case they are not committed.
+    /// Their events arrive up to the watcher `delay` (in seconds) after `HEAD` moved.
+    fn comes_from_git(&self, delay: u64, path: Option<&Path>) -> bool {
+        match self
+            .repo
+            .git_operation(Duration::from_secs(2 * delay + 1), path)
+        {
+            Some(operation) => {
+                log::info!("Skipping the changes made by git: {}.", operation);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the changes to the path, or all changes, look like they contain secrets,
+    /// in which case they are not committed, see `secret_scan`.
+    fn holds_secrets(&mut self, path: Option<&Path>) -> bool {
+        if !self.config.secret_scan {
+            return false;
+        }
+        let secrets = match self.repo.find_secrets(path) {
+            Ok(secrets) => secrets,
+            Err(err) => {
+                log::warn!("failed to scan for secrets: {}", err.message());
+                return false;
+            }
+        };
+        for secret in &secrets {
+            log::warn!(
+                "possible secret ({}) at {}:{}, not committing it",
+                secret.kind,
+                secret.path.display(),
+                secret.line
+            );
+        }
+        let paths: HashSet<_> = secrets.iter().map(|secret| &secret.path).collect();
+        for path in paths {
+            self.observe(path, |observations, path| {
+                observations.record_secret(path, Local::now())
+            });
+        }
+        !secrets.is_empty()
+    }
+
+    /// Whether the file at the path, or any changed file, holds unresolved conflict markers,
+    /// in which case the changes are not committed until they are resolved, see `allow_conflict_markers`.
+    fn holds_conflict_markers(&mut self, path: Option<&Path>) -> bool {
+        if self.config.allow_conflict_markers {
+            return false;
+        }
+        let paths = match path {
+            Some(path) => vec![path.to_path_buf()],
+            None => match self.repo.changed_paths() {
+                Ok(paths) => paths.iter().map(|path| self.workdir.join(path)).collect(),
+                Err(err) => {
+                    log::warn!("failed to look for conflict markers: {}", err.message());
+                    return false;
+                }
+            },
+        };
+        let conflicted = paths
+            .iter()
+            .filter(|path| std::fs::read(path).is_ok_and(|content| has_conflict_markers(&content)))
+            .map(|path| self.relative(path).display().to_string())
+            .collect::<Vec<_>>();
+        if conflicted.is_empty() {
+            return false;
+        }
+        self.notifier.warn(
+            "conflict-markers",
+            &format!(
+                "unresolved conflict markers in {}, not committing until they are resolved",
+                conflicted.join(", ")
+            ),
+        );
+        true
+    }
+
+    /// Whether the commit made for the last event can be amended,
+    /// i.e. nothing else was committed since (e.g. a snapshot) and it was not pushed.
+    fn can_amend(&self) -> bool {
+        match self.last_event_commit {
+            Some(commit) => {
+                matches!(self.repo.head(), Ok(Some(head)) if head == commit)
+                    && matches!(self.repo.is_pushed(commit), Ok(false))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Hash of the file's content as a git blob, `None` if it cannot be read (e.g. it was removed).
+fn content_hash(path: &Path) -> Option<git2::Oid> {
+    git2::Oid::hash_file(git2::ObjectType::Blob, path).ok()
+}
+
+/// Keep track of the paths with a pending debounced event,
+/// the watcher notifies writes and removals before debouncing them.
+/// The directories, from and to, renamed along with the file
+/// (e.g. `notes/a.md` to `archive/a.md`, once `notes` is gone), if it moved with its directory.
+fn renamed_directory(from: &Path, path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let (mut from_directory, mut directory) = (from, path);
+    // Strip the trailing components they share, e.g. `a.md`
+    while from_directory.file_name().is_some()
+        && from_directory.file_name() == directory.file_name()
+    {
+        from_directory = from_directory.parent()?;
+        directory = directory.parent()?;
+    }
+    if from_directory == from || from_directory.exists() || !directory.is_dir() {
+        return None;
+    }
+    Some((from_directory.to_path_buf(), directory.to_path_buf()))
+}
+
+/// The paths changed by the event.
+/// The file created, written, removed or renamed to by the event.
+fn changed_file(event: &DebouncedEvent) -> Option<&PathBuf> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path)
+        | DebouncedEvent::Rename(_, path) => Some(path),
+        _ => None,
+    }
+}
+
+fn changed_paths(event: &DebouncedEvent) -> Vec<&PathBuf> {
+    match event {
+        DebouncedEvent::NoticeWrite(path)
+        | DebouncedEvent::NoticeRemove(path)
+        | DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(old, new) => vec![old, new],
+        DebouncedEvent::Rescan | DebouncedEvent::Error(_, _) => Vec::new(),
+    }
+}
+
+fn track_in_flight(in_flight: &mut HashSet<PathBuf>, event: &DebouncedEvent) {
+    match event {
+        DebouncedEvent::NoticeWrite(path) | DebouncedEvent::NoticeRemove(path) => {
+            in_flight.insert(path.clone());
+        }
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path) => {
+            in_flight.remove(path);
+        }
+        DebouncedEvent::Rename(old, new) => {
+            in_flight.remove(old);
+            in_flight.remove(new);
+        }
+        DebouncedEvent::Rescan | DebouncedEvent::Error(_, _) => {}
+    }
+}
+
+/// Forward the watchers' events to a channel the watch loop can select on,
+/// until the watchers are dropped.
+/// Past `max_pending` events waiting to be processed, the events are dropped and counted in `dropped`.
+fn forward_events(
+    event_rcv: Receiver<DebouncedEvent>,
+    max_pending: Option<usize>,
+    dropped: Arc<AtomicUsize>,
+) -> crossbeam_channel::Receiver<DebouncedEvent> {
+    let (forward_snd, forward_rcv) = match max_pending {
+        Some(max_pending) => bounded(max_pending),
+        None => unbounded(),
+    };
+    thread::spawn(move || {
+        for event in event_rcv {
+            match forward_snd.try_send(event) {
+                Ok(()) => {}
+                Err(TrySendError::Full(event)) => {
+                    log::debug!("too many pending events, dropping event: {:?}", event);
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => break,
+            }
+        }
+    });
+    forward_rcv
+}
+
+/// One watcher per delay, all of them sending their events to the same channel.
+pub(crate) struct Watchers {
+    event_snd: Sender<DebouncedEvent>,
+    watchers: HashMap<u64, RecommendedWatcher>,
+    /// The watched directories, along with their delay.
+    watchlist: HashMap<PathBuf, u64>,
+    /// The directories which could not be watched (e.g. permissions, vanished), along with their delay.
+    skipped: HashMap<PathBuf, u64>,
+    /// The directories polled rather than watched, past `max_watches` or the OS's limit, along with their delay.
+    polled: HashMap<PathBuf, u64>,
+    max_watches: Option<usize>,
+    /// Where the next watchdog sample starts in the watchlist.
+    sample_start: usize,
+}
+
+impl Watchers {
+    pub(crate) fn new(event_snd: Sender<DebouncedEvent>, max_watches: Option<usize>) -> Self {
+        Self {
+            event_snd,
+            watchers: HashMap::new(),
+            watchlist: HashMap::new(),
+            skipped: HashMap::new(),
+            polled: HashMap::new(),
+            max_watches,
+            sample_start: 0,
+        }
+    }
+
+    /// The longest delay of the watchers, in seconds.
+    fn max_delay(&self) -> u64 {
+        self.watchers.keys().copied().max().unwrap_or_default()
+    }
+
+    /// Recreate the watchers, watching the same directories.
+    fn restart(&mut self) {
+        // Dropping the watchers stops them
+        self.watchers.clear();
+        let skipped = std::mem::take(&mut self.skipped);
+        let polled = std::mem::take(&mut self.polled);
+        for (dir, delay) in std::mem::take(&mut self.watchlist)
+            .into_iter()
+            .chain(skipped)
+            .chain(polled)
+        {
+            self.watch(dir, delay);
+        }
+    }
+
+    /// Update the delay of each directory, recreating the watchers if any changed.
+    /// Returns whether the watchers were recreated.
+    fn set_delays<F>(&mut self, delay: F) -> bool
+    where
+        F: Fn(&Path) -> u64,
+    {
+        let mut changed = false;
+        for (dir, dir_delay) in self
+            .watchlist
+            .iter_mut()
+            .chain(self.skipped.iter_mut())
+            .chain(self.polled.iter_mut())
+        {
+            let new_delay = delay(dir);
+            changed |= new_delay != *dir_delay;
+            *dir_delay = new_delay;
+        }
+        if changed {
+            log::info!("Watcher delays changed, restarting the watchers.");
+            self.restart();
+        }
+        changed
+    }
+
+    /// Stop watching the directory and its subdirectories, e.g. once it vanished.
+    fn unwatch_tree(&mut self, root: &Path) {
+        let watchers = &mut self.watchers;
+        self.watchlist.retain(|dir, delay| {
+            if !dir.starts_with(root) {
+                return true;
+            }
+            if let Some(watcher) = watchers.get_mut(delay) {
+                // Fails for the directories which are already gone
+                let _ = watcher.unwatch(dir);
+            }
+            false
+        });
+        self.skipped.retain(|dir, _| !dir.starts_with(root));
+        self.polled.retain(|dir, _| !dir.starts_with(root));
+    }
+
+    /// Try to watch the skipped directories again, forgetting the ones which no longer exist.
+    /// Returns the directories which are now watched.
+    fn retry_skipped(&mut self) -> Vec<PathBuf> {
+        self.skipped.retain(|dir, _| dir.is_dir());
+        let mut watched = vec![];
+        for (dir, delay) in self.skipped.clone() {
+            self.watch(dir.clone(), delay);
+            if self.watchlist.contains_key(&dir) {
+                watched.push(dir);
+            }
+        }
+        watched
+    }
+
+    /// The directories which could not be watched, sorted.
+    fn skipped_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.skipped.keys().cloned().collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    /// The polled directories modified, or with an entry modified, between `since` and `until`,
+    /// forgetting the ones which no longer exist.
+    fn polled_modified(
+        &mut self,
+        since: SystemTime,
+        until: SystemTime,
+        discovery: &Discovery,
+    ) -> Vec<PathBuf> {
+        self.polled.retain(|dir, _| dir.is_dir());
+        self.polled
+            .keys()
+            .filter(|dir| modified_between(dir, since, until, discovery).is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Poll the directory rather than watching it, `reason` being logged for the first one.
+    fn poll(&mut self, dir: PathBuf, delay: u64, reason: &str) {
+        if self.polled.is_empty() {
+            log::warn!(
+                "{}, polling {} and the directories watched after it every {} seconds.",
+                reason,
+                dir.display(),
+                POLL_INTERVAL.as_secs()
+            );
+        }
+        log::debug!("polling {}", dir.display());
+        self.skipped.remove(&dir);
+        self.polled.insert(dir, delay);
+    }
+
+    /// A file or directory modified between `since` and `until` in the next sample of the watched directories,
+    /// the samples going through all of them in turn.
+    fn sample_modified(
+        &mut self,
+        since: SystemTime,
+        until: SystemTime,
+        discovery: &Discovery,
+    ) -> Option<PathBuf> {
+        if self.watchlist.is_empty() {
+            return None;
+        }
+        let sample = self
+            .watchlist
+            .keys()
+            .cycle()
+            .skip(self.sample_start % self.watchlist.len())
+            .take(WATCHDOG_SAMPLE_SIZE.min(self.watchlist.len()))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.sample_start = (self.sample_start + sample.len()) % self.watchlist.len();
+        sample
+            .iter()
+            .find_map(|dir| modified_between(dir, since, until, discovery))
+    }
+
+    /// Add the directory to the watcher with the given delay (in seconds), unless it is already watched.
+    pub(crate) fn watch(&mut self, dir: PathBuf, delay: u64) {
+        if self.watchlist.contains_key(&dir) || self.polled.contains_key(&dir) {
+            return;
+        }
+        if let Some(max_watches) = self
+            .max_watches
+            .filter(|max_watches| self.watchlist.len() >= *max_watches)
+        {
+            let reason = format!("Watching max_watches ({}) directories", max_watches);
+            self.poll(dir, delay, &reason);
+            return;
+        }
+        log::debug!("adding {} to watcher (delay {}s)", dir.display(), delay);
+        let watcher = match self.watchers.entry(delay) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                match watcher(self.event_snd.clone(), Duration::from_secs(delay)) {
+                    Ok(watcher) => entry.insert(watcher),
+                    Err(err) => {
+                        log::warn!("failed to create a watcher: {}", err);
+                        self.skipped.insert(dir, delay);
+                        return;
+                    }
+                }
+            }
+        };
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                self.skipped.remove(&dir);
+                self.watchlist.insert(dir, delay);
+            }
+            Err(err) if out_of_watches(&err) => {
+                let reason = format!("Out of file watches ({})", err);
+                self.poll(dir, delay, &reason);
+            }
+            // Only warn the first time, the skipped directories are retried periodically
+            Err(err) if self.skipped.contains_key(&dir) => {
+                log::debug!("still failing to watch {}: {}", dir.display(), err);
+            }
+            Err(err) => {
+                log::warn!("skipping {}, failed to watch it: {}", dir.display(), err);
+                self.skipped.insert(dir, delay);
+            }
+        }
+    }
+}
+
+/// Whether watching failed for lack of watches (e.g. past `fs.inotify.max_user_watches`) or file descriptors.
+fn out_of_watches(err: &notify::Error) -> bool {
+    #[cfg(unix)]
+    let exhausted = [libc::ENOSPC, libc::EMFILE, libc::ENFILE];
+    #[cfg(not(unix))]
+    let exhausted: [i32; 0] = [];
+    match err {
+        notify::Error::Io(err) => err
+            .raw_os_error()
+            .is_some_and(|code| exhausted.contains(&code)),
+        _ => false,
+    }
+}
+
+/// The directory, or one of its files, if modified between `since` and `until`,
+/// git's own metadata aside (e.g. the `.git` directory modified by each commit).
+/// The subdirectories are left out: the directory's (non-recursive) watch does not report the changes inside them,
+/// which are checked along with them if they are watched, and are of no interest if not (e.g. ignored).
+fn modified_between(
+    dir: &Path,
+    since: SystemTime,
+    until: SystemTime,
+    discovery: &Discovery,
+) -> Option<PathBuf> {
+    let modified = |path: &Path| {
+        path.symlink_metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| since < modified && modified <= until)
+    };
+    if modified(dir) {
+        return Some(dir.to_path_buf());
+    }
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+        .find(|path| !discovery.is_git_internal(path) && modified(path))
+}
+
+/// Describe a watcher event for humans, `None` for the events which are not acted upon.
+pub(crate) fn describe_event(event: &DebouncedEvent) -> Option<String> {
+    match event {
+        DebouncedEvent::Create(path) => Some(format!("created {}", path.display())),
+        DebouncedEvent::Write(path) => Some(format!("written {}", path.display())),
+        DebouncedEvent::Chmod(path) => Some(format!("chmod {}", path.display())),
+        DebouncedEvent::Remove(path) => Some(format!("deleted {}", path.display())),
+        DebouncedEvent::Rename(old, new) => {
+            Some(format!("renamed {} to {}", old.display(), new.display()))
+        }
+        DebouncedEvent::Rescan => Some(String::from("rescan")),
+        DebouncedEvent::Error(err, _) => Some(format!("error: {}", err)),
+        DebouncedEvent::NoticeRemove(_) | DebouncedEvent::NoticeWrite(_) => None,
+    }
+}
+
+/// The adaptive delay, if enabled by the configuration.
+fn adaptive_delay(config: &Config) -> Option<AdaptiveDelay> {
+    config
+        .adaptive_delay
+        .map(|max| AdaptiveDelay::new(Duration::from_secs(max)))
+}
+
+/// The notifier running the configured notification command, for the watched directory.
+fn notifier(config: &Config, root: &Path) -> Notifier {
+    Notifier::new(
+        config.notify_command.clone(),
+        Duration::from_secs(config.notify_cooldown * 60),
+    )
+    .with_payload(config.notify_payload.clone(), root.display().to_string())
+    .with_services(config.notify.clone())
+}
+
+/// Load the script set in the configuration, relative to the watched directory.
+fn load_script(config: &Config, root: &Path) -> Result<Option<Script>> {
+    config
+        .script
+        .as_deref()
+        .map(|script| Script::load(&root.join(script)))
+        .transpose()
+}
+
+/// Commit pointers to the large files, kept in the `[attachments]` store, through a git filter running nabu,
+/// or stop filtering them once the section is removed.
+fn configure_attachments(
+    repo: &WatchedRepository,
+    directory: &Path,
+    attachments: Option<&AttachmentsConfig>,
+) -> Result<()> {
+    if let Some(attachments) = attachments {
+        let store = attachments
+            .store
+            .canonicalize()
+            .unwrap_or_else(|_| attachments.store.clone());
+        if store.starts_with(directory) {
+            return Err(eyre!(
+                "the attachments store {} must be outside of the watched directory",
+                attachments.store.display()
+            ))
+            .wrap_err(Failure::Config);
+        }
+    }
+    configure_filter(repo.git_dir(), &std::env::current_exe()?, attachments)
+}
+
+/// Log what the watcher resolved: the root and its directories, the commit and push settings,
+/// the remote and the authentication method, without any secret.
+/// The directories listed one per line, e.g. by `fd` or `find`, skipping the blank lines.
+fn read_directories(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    let mut directories = vec![];
+    for line in reader.lines() {
+        let line = line.wrap_err("failed to read the directories from the standard input")?;
+        if !line.trim().is_empty() {
+            directories.push(PathBuf::from(line));
+        }
+    }
+    log::info!("Read {} directories to watch", directories.len());
+    Ok(directories)
+}
+
+fn log_summary(
+    directory: &Path,
+    repo: &WatchedRepository,
+    discovery: &Discovery,
+    config: &Config,
+    authentication_method: Option<&AuthenticationMethod>,
+) {
+    // The directories of the last run, the discovery of the current ones runs in the background
+    let directories = discovery
+        .clone()
+        .with_cache(repo.git_dir().join(WATCHLIST_CACHE_FILE_NAME))
+        .cached()
+        .len();
+    if directories == 0 {
+        log::info!("Watching {}", directory.display());
+    } else {
+        log::info!(
+            "Watching {} ({} directories on the last run)",
+            directory.display(),
+            directories
+        );
+    }
+
+    let delay = match config.adaptive_delay {
+        Some(adaptive_delay) => format!("{}s, adaptive up to {}s", config.delay, adaptive_delay),
+        None => format!("{}s", config.delay),
+    };
+    let commit_strategy = match config.commit_strategy() {
+        CommitStrategyKind::PerEvent => "per-event",
+        CommitStrategyKind::PerFileAmend => "per-file-amend",
+        CommitStrategyKind::Batched => "batched",
+        CommitStrategyKind::Session => "session",
+    };
+    let grouping = match config.group_by_folder {
+        Some(depth) => format!(", grouped by folder {} levels deep", depth),
+        None => String::new(),
+    };
+    log::info!(
+        "Delay: {}, commit strategy: {}{}",
+        delay,
+        commit_strategy,
+        grouping
+    );
+
+    let conventions = Conventions::read(repo.workdir(), repo.commit_template().as_deref());
+    if !conventions.is_empty() {
+        match config.conventions {
+            Some(_) => log::info!("Commit messages: following {}", conventions.describe()),
+            None => log::info!(
+                "Commit messages: the repository has conventions ({}), set the [conventions] section to follow them",
+                conventions.describe()
+            ),
+        }
+    }
+
+    let mut pushes = vec![];
+    if let Some(schedule) = &config.push_schedule {
+        pushes.push(format!("on the schedule \"{}\"", schedule));
+    }
+    if let Some(commits) = config.push_every_n_commits {
+        pushes.push(format!("every {} commits", commits));
+    }
+    if let Some(megabytes) = config.push_every_n_megabytes {
+        pushes.push(format!("every {} MB", megabytes));
+    }
+    if let Some(sync_interval) = config.sync_interval {
+        pushes.push(format!("synced every {} minutes", sync_interval));
+    }
+    if config.push_on_exit {
+        pushes.push(String::from("on exit"));
+    }
+    if config.pull_only {
+        log::info!("Push: disabled, pulling only");
+    } else if pushes.is_empty() {
+        log::info!("Push: disabled");
+    } else if config.syncs() {
+        log::info!(
+            "Push: {}, rebasing onto the remote first",
+            pushes.join(", ")
+        );
+    } else {
+        log::info!("Push: {}", pushes.join(", "));
+    }
+
+    let url = repo.origin_url().ok().flatten();
+    let (branch, upstream) = repo.branches();
+    let remote = match (&config.remote.s3, &url) {
+        (Some(s3), _) => s3.location(),
+        (None, Some(url)) => format!("origin {}", redact_url(url)),
+        (None, None) => String::from("none"),
+    };
+    log::info!(
+        "Remote: {}, branch: {}, upstream: {}",
+        remote,
+        branch.as_deref().unwrap_or("detached HEAD"),
+        upstream.as_deref().unwrap_or("none")
+    );
+
+    let authentication = match authentication_method {
+        Some(AuthenticationMethod::Token) => match url.as_deref().and_then(token_for_url) {
+            Some((variable, _, _)) => format!("access token from {}", variable),
+            None => AuthenticationMethod::Token.describe(),
+        },
+        Some(authentication_method) => authentication_method.describe(),
+        None => String::from("none"),
+    };
+    log::info!("Authentication: {}", authentication);
+}
+
+/// The commit message conventions of the repository holding the directory, none if it cannot be opened.
+fn read_conventions(directory: &Path) -> Conventions {
+    match WatchedRepository::discover(directory) {
+        Ok(repo) => Conventions::read(repo.workdir(), repo.commit_template().as_deref()),
+        Err(err) => {
+            log::debug!(
+                "failed to read the commit message conventions: {}",
+                err.message()
+            );
+            Conventions::default()
+        }
+    }
+}
+
+/// Start the plugins and send them the activity until the watcher hangs up,
+/// plugins which fail to start are skipped.
+fn spawn_plugins(
+    config: &Config,
+    directory: &Path,
+    suppress_file: &Path,
+    activity: Receiver<Activity>,
+) -> Option<JoinHandle<()>> {
+    if config.plugins.is_empty() {
+        return None;
+    }
+    let mut plugins = config
+        .plugins
+        .iter()
+        .filter_map(
+            |(name, plugin)| match Plugin::spawn(name, plugin, directory, suppress_file) {
+                Ok(plugin) => {
+                    log::info!("Started plugin {}.", name);
+                    Some(plugin)
+                }
+                Err(err) => {
+                    log::warn!("failed to start plugin {}: {}", name, err);
+                    None
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    Some(thread::spawn(move || {
+        for activity in activity {
+            for plugin in &mut plugins {
+                plugin.send(&activity);
+            }
+        }
+        for plugin in plugins {
+            plugin.stop();
+        }
+    }))
+}
+
+/// Request termination once the deadline passes, as `--for` and `--until` do,
+/// the watchers then committing and pushing as on exit.
+fn stop_at(deadline: DateTime<Local>, signals: &Signals) {
+    log::info!("Watching until {}.", deadline.format("%Y-%m-%d %H:%M:%S"));
+    let signals = signals.clone();
+    thread::spawn(move || {
+        // Checked against the clock, the monotonic sleep not counting the time suspended
+        while let Ok(remaining) = (deadline - Local::now()).to_std() {
+            if !signals.is_running() {
+                return;
+            }
+            thread::sleep(remaining.min(DEADLINE_CHECK_INTERVAL));
+        }
+        if signals.is_running() {
+            log::info!("The watch session ended.");
+            signals.request_termination();
+        }
+    });
+}
+
+/// Run the repository maintenance, logging how long it took or why it failed.
+fn run_maintenance<R>(repo: &R)
+where
+    R: Repository,
+{
+    log::info!("Running the repository maintenance.");
+    let start = Instant::now();
+    match repo.run_maintenance() {
+        Ok(()) => log::debug!("maintenance done in {:?}", start.elapsed()),
+        Err(err) => log::warn!("maintenance failed: {}", err.message()),
+    }
+}
+
+/// Wait for the maintenance run after the push on exit, until it is done or skipped.
+fn wait_for_maintenance(signals: &Signals, done: &Receiver<PushOutcome>) {
+    loop {
+        match done.recv_timeout(Duration::from_millis(100)) {
+            Err(mpsc::RecvTimeoutError::Timeout) if signals.skip_push() => {
+                log::warn!("Maintenance on exit skipped.");
+                return;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            _ => return,
+        }
+    }
+}
+
+/// Measure the repository of the directory unless the last size check is less than a day old,
+/// keeping the sizes as the last check. Returns the growth of `.git` since the last check, if any.
+fn measure_growth(root: &Path) -> Option<Growth> {
+    let (repo, _) = open_repository(root, true).ok()?;
+    let (workdir, git_dir) = (repo.workdir().unwrap_or(repo.path()), repo.path());
+    let previous = SizeCheck::read(git_dir);
+    if previous
+        .as_ref()
+        .and_then(SizeCheck::age)
+        .is_some_and(|age| age < chrono::Duration::days(1))
+    {
+        return None;
+    }
+    let check = match SizeCheck::measure(workdir, git_dir) {
+        Ok(check) => check,
+        Err(err) => {
+            log::warn!("failed to measure the repository: {}", err);
+            return None;
+        }
+    };
+    if let Err(err) = check.write(git_dir) {
+        log::warn!("failed to keep the size check: {}", err);
+    }
+    let growth = previous.and_then(|previous| check.growth_since(&previous))?;
+    log::debug!("the .git directory grew by {} bytes", growth.git);
+    Some(growth)
+}
+
+/// Keep the state file up to date with the watcher's activity, until the watcher stops.
+fn spawn_state_writer(directory: &Path, activity: Receiver<Activity>) -> Result<JoinHandle<()>> {
+    let repo = WatchedRepository::discover(directory)?.with_scope(directory);
+    let path = state_path(repo.git_dir());
+    let mut state = State::default();
+    let previous = State::load(&path).unwrap_or_else(|err| {
+        // Rebuilt rather than failing, lest a bad state file keeps the watcher from starting
+        match quarantine(&path) {
+            Ok(corrupt) => log::warn!(
+                "The state file is corrupt ({}), moved it to {} and rebuilding it.",
+                err,
+                corrupt.display()
+            ),
+            Err(quarantine_err) => log::warn!(
+                "The state file {} is corrupt ({}) and cannot be moved aside: {}",
+                path.display(),
+                err,
+                quarantine_err
+            ),
+        }
+        if let Some(journal) = read_journal(repo.git_dir()) {
+            state.rebuild(&journal);
+        }
+        None
+    });
+    if let Some(previous) = previous {
+        if !previous.stopped() {
+            log::warn!(
+                "The previous watcher (pid {}, started {}) did not stop cleanly, its last event was at {}.",
+                previous.pid,
+                previous.started,
+                previous.last_event.as_deref().unwrap_or("unknown")
+            );
+        }
+        state.resume(previous);
+    }
+    let write = move |state: &mut State| {
+        if let Ok(status) = repo.status() {
+            state.refresh(&status);
+        }
+        if let Err(err) = state.write(&path) {
+            log::warn!("Failed to write the state file {}: {}", path.display(), err);
+        }
+    };
+    write(&mut state);
+    Ok(thread::spawn(move || {
+        for activity in activity {
+            state.apply(activity);
+            write(&mut state);
+        }
+        state.state = WatcherState::Stopped;
+        // A clean stop acknowledges the crash of an earlier session
+        state.last_crash = None;
+        write(&mut state);
+    }))
+}
+
+/// Record the activity of the watcher session in the journal until the watcher hangs up.
+fn spawn_journal_writer(
+    directory: &Path,
+    session: String,
+    retention_days: Option<u64>,
+    activity: Receiver<Activity>,
+) -> Result<JoinHandle<()>> {
+    let repo = WatchedRepository::discover(directory)?;
+    let journal = Journal::recover(&journal_path(repo.git_dir())).wrap_err(Failure::Config)?;
+    let compact = move |journal: &Journal| match journal.compact(retention_days) {
+        Ok(0) => {}
+        Ok(removed) => log::info!("Removed {} old rows from the journal.", removed),
+        Err(err) => log::warn!("Failed to compact the journal: {}", err),
+    };
+    compact(&journal);
+    Ok(thread::spawn(move || {
+        let mut compacted = Instant::now();
+        for activity in activity {
+            if let Err(err) = journal.record(&session, &activity) {
+                log::warn!("Failed to record the activity in the journal: {}", err);
+            }
+            if compacted.elapsed() >= JOURNAL_COMPACTION_INTERVAL {
+                compact(&journal);
+                compacted = Instant::now();
+            }
+        }
+    }))
 }
@@ -0,0 +1,228 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::{Duration as ChronoDuration, Local};
+use color_eyre::{eyre::eyre, Result};
+use crossbeam_channel::Sender;
+use nabu::{
+    activity::{Activity, Control},
+    git::{CommitSummary, RepositoryStatus, WatchedRepository},
+    stats::DayActivity,
+    sync::PushOutcome,
+};
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How often the server checks whether the watcher stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How many recent commits are listed.
+const RECENT_COMMITS: usize = 20;
+/// How many days the activity chart covers.
+const ACTIVITY_DAYS: i64 = 30;
+/// How long the activity chart is reused for, unless a commit changes it.
+const ACTIVITY_CACHE_DURATION: Duration = Duration::from_secs(60);
+/// Header carrying the token the requests changing anything must hold.
+const TOKEN_HEADER: &str = "X-Nabu-Token";
+
+/// The dashboard page, which polls `/api/status`.
+const DASHBOARD_PAGE: &str = include_str!("web.html");
+
+/// Start serving the dashboard on `address` in a separate thread, on the loopback interface
+/// when it is only a port (e.g. `8080`).
+///
+/// The server stops once `running` is cleared, the only control it offers is pushing now,
+/// which needs the token of the dashboard's URL, as logged.
+pub(crate) fn spawn(
+    address: &str,
+    directory: PathBuf,
+    activity: Receiver<Activity>,
+    control: Sender<Control>,
+    running: Arc<AtomicBool>,
+) -> Result<JoinHandle<Result<()>>> {
+    let address = match address.trim_start_matches(':').parse::<u16>() {
+        Ok(port) => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port).to_string(),
+        Err(_) => address.to_string(),
+    };
+    let server = Server::http(&address)
+        .map_err(|err| eyre!("failed to serve the dashboard on {}: {}", address, err))?;
+    let token = token();
+    let served = server.server_addr();
+    if served
+        .clone()
+        .to_ip()
+        .is_some_and(|served| !served.ip().is_loopback())
+    {
+        log::warn!(
+            "The dashboard is reachable from other machines on {}, anyone who can reach it sees the repository's status",
+            served
+        );
+    }
+    log::info!(
+        "Serving the dashboard on http://{}/?token={}",
+        served,
+        token
+    );
+    let mut dashboard = WebDashboard {
+        directory,
+        activity,
+        control,
+        token,
+        paused: false,
+        last_push: None,
+        days: None,
+    };
+    Ok(thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            dashboard.receive_activity();
+            if let Some(request) = server.recv_timeout(POLL_INTERVAL)? {
+                dashboard.handle(request);
+            }
+        }
+        Ok(())
+    }))
+}
+
+/// What `/api/status` returns.
+#[derive(Serialize)]
+struct DashboardStatus {
+    directory: PathBuf,
+    paused: bool,
+    /// Outcome of the last push, if any since the watcher started.
+    last_push: Option<String>,
+    repository: RepositoryStatus,
+    /// Latest commits, newest first.
+    commits: Vec<CommitSummary>,
+    /// Activity over the last `ACTIVITY_DAYS` days, oldest first.
+    days: Vec<DayActivity>,
+}
+
+struct WebDashboard {
+    directory: PathBuf,
+    activity: Receiver<Activity>,
+    control: Sender<Control>,
+    /// Token of the requests changing anything, lest any page the browser visits pushes.
+    token: String,
+    paused: bool,
+    last_push: Option<String>,
+    /// The activity chart and when it was computed, reused for [`ACTIVITY_CACHE_DURATION`].
+    days: Option<(Instant, Vec<DayActivity>)>,
+}
+
+impl WebDashboard {
+    fn receive_activity(&mut self) {
+        while let Ok(activity) = self.activity.try_recv() {
+            match activity {
+                Activity::Push { outcome, .. } => {
+                    let outcome = match outcome {
+                        PushOutcome::Pushed => String::from("pushed"),
+                        PushOutcome::Paused { conflicts } => {
+                            format!("paused, {} conflicted files", conflicts.len())
+                        }
+                        PushOutcome::Failed { error, .. } => format!("failed: {}", error),
+                        PushOutcome::Bundled { path, .. } => {
+                            format!("bundled into {}", path.display())
+                        }
+                    };
+                    self.last_push = Some(format!("{} {}", Local::now().to_rfc3339(), outcome));
+                }
+                Activity::Paused => self.paused = true,
+                Activity::Resumed => self.paused = false,
+                Activity::Commit { .. } => self.days = None,
+                Activity::Event { .. } | Activity::Unwatched { .. } | Activity::Usage { .. } => {}
+            }
+        }
+    }
+
+    fn handle(&mut self, request: Request) {
+        let path = request.url().split('?').next().unwrap_or_default();
+        let response = match (request.method(), path) {
+            (Method::Get, "/") => html(DASHBOARD_PAGE.to_string()),
+            (Method::Get, "/api/status") => match self.status() {
+                Ok(status) => json(&status),
+                Err(err) => text(500, err.to_string()),
+            },
+            (Method::Post, "/api/push") if !self.authorized(&request) => text(
+                403,
+                String::from("open the dashboard from the URL nabu logged"),
+            ),
+            (Method::Post, "/api/push") => match self.control.send(Control::PushNow) {
+                Ok(()) => text(202, String::from("push requested")),
+                Err(_) => text(503, String::from("the watcher stopped")),
+            },
+            _ => text(404, String::from("not found")),
+        };
+        if let Err(err) = request.respond(response) {
+            log::debug!("failed to respond to a dashboard request: {}", err);
+        }
+    }
+
+    /// Whether the request holds the dashboard's token, which a custom header only lets the page send.
+    fn authorized(&self, request: &Request) -> bool {
+        request
+            .headers()
+            .iter()
+            .any(|header| header.field.equiv(TOKEN_HEADER) && header.value.as_str() == self.token)
+    }
+
+    fn status(&mut self) -> Result<DashboardStatus> {
+        let repo = WatchedRepository::discover(&self.directory)?.with_scope(&self.directory);
+        let days = match &self.days {
+            Some((computed, days)) if computed.elapsed() < ACTIVITY_CACHE_DURATION => days.clone(),
+            _ => {
+                let since = (Local::now() - ChronoDuration::days(ACTIVITY_DAYS))
+                    .date()
+                    .naive_local();
+                let days = repo.stats(Some(since), false)?.days;
+                self.days = Some((Instant::now(), days.clone()));
+                days
+            }
+        };
+        Ok(DashboardStatus {
+            directory: self.directory.clone(),
+            paused: self.paused,
+            last_push: self.last_push.clone(),
+            repository: repo.status()?,
+            commits: repo.log(RECENT_COMMITS)?,
+            days,
+        })
+    }
+}
+
+/// A random token, from the randomly seeded hasher of the standard library.
+fn token() -> String {
+    let random = || RandomState::new().hash_one(Instant::now());
+    format!("{:016x}{:016x}", random(), random())
+}
+
+fn html(body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body).with_header(content_type("text/html; charset=utf-8"))
+}
+
+fn json<T>(value: &T) -> Response<std::io::Cursor<Vec<u8>>>
+where
+    T: Serialize,
+{
+    match serde_json::to_string(value) {
+        Ok(body) => Response::from_string(body).with_header(content_type("application/json")),
+        Err(err) => text(500, err.to_string()),
+    }
+}
+
+fn text(status: u16, body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body).with_status_code(status)
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("the header is valid")
+}
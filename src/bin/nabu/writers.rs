@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use color_eyre::{eyre::WrapErr, Result};
+use nabu::{
+    config::Config,
+    git::WatchedRepository,
+    writers::{writers_path, WriterProfile},
+};
+
+use crate::{
+    exit::Failure,
+    output::{print_json, OutputFormat},
+};
+
+/// `nabu`'s `writers` command arguments structure.
+#[derive(Args)]
+pub(crate) struct WritersArgs {
+    /// The repository directory.
+    #[clap(parse(from_os_str), default_value = ".")]
+    directory: PathBuf,
+
+    /// Path to the configuration file.
+    #[clap(short, long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+}
+
+impl WritersArgs {
+    /// Execute the `writers` command, listing the files the watcher saw changing
+    /// more than `changes_per_hour` times within an hour of the learning window.
+    pub fn run(self) -> Result<()> {
+        let config =
+            Config::load(self.config.as_deref(), &self.directory).wrap_err(Failure::Config)?;
+        let repo = WatchedRepository::discover(&self.directory)?;
+        let learning = match config.learning {
+            Some(learning) => learning,
+            None => {
+                eprintln!("The learning mode is off, enable it with the [learning] section.");
+                return Ok(());
+            }
+        };
+        let mut profile = WriterProfile::read(&writers_path(repo.git_dir()));
+        profile.prune(chrono::Local::now(), learning.window);
+        let writers = profile.writers(&learning);
+
+        if self.output == OutputFormat::Json {
+            print_json(&writers)?;
+        } else if writers.is_empty() {
+            println!(
+                "No file changed more than {} times within an hour of the last {} hours.",
+                learning.changes_per_hour, learning.window
+            );
+        } else {
+            for writer in &writers {
+                println!(
+                    "{}  ({} changes within an hour, {} over the last {} hours)",
+                    writer.path.display(),
+                    writer.peak_per_hour,
+                    writer.changes,
+                    learning.window
+                );
+            }
+            if !learning.demote {
+                println!(
+                    "\nIgnore them, or set `demote = true` to commit them every `commit_batch_interval` minutes."
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,32 +1,695 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
-use color_eyre::Result;
+use color_eyre::{
+    eyre::{eyre, Report},
+    Result,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    activity::ACTIVITY_KINDS,
+    attachments::AttachmentsConfig,
+    conventions::ConventionsConfig,
+    fleet::AuthConfig,
+    fs::{ForceInclude, IgnoreRules, MissingRoot, UnicodeNormalization},
+    git::{ConflictStrategy, ForcePush, GitBackend, PushMode, PushSettings},
+    interpolate::expand_all,
+    limits::LimitsConfig,
+    locale::{Catalog, Locale},
+    message::{is_valid_timestamp_format, PathRedaction, Timezone, DEFAULT_TIMESTAMP_FORMAT},
+    migration::{config_version, migrate, CONFIG_VERSION},
+    mirror::MirrorConfig,
+    notes::device_name,
+    notification::{DEFAULT_NOTIFY_COOLDOWN, DEFAULT_PUSH_FAILURE_ESCALATION},
+    notify_service::NotifyServices,
+    overrides::{DirectoryConfig, Overrides},
+    plugin::PluginConfig,
+    precondition::DEFAULT_PUSH_VALIDATION_TIMEOUT,
+    quiet::QuietHours,
+    rolling::{is_valid_prefix, RollingPeriod, DEFAULT_ROLLING_BRANCH_PREFIX},
+    s3::S3Config,
+    schedule::parse_cron,
+    storm::DEFAULT_STORM_THRESHOLD,
+    strategy::{CommitStrategyKind, DEFAULT_COMMIT_BATCH_INTERVAL, DEFAULT_SESSION_GAP},
+    suppress::DEFAULT_SUPPRESS_GRACE_PERIOD,
+    writers::LearningConfig,
+};
 
 /// Default watcher delay (in seconds).
 pub const DEFAULT_DELAY: u64 = 30;
 
+/// Default interval (in seconds) between the watchdog's checks.
+pub const DEFAULT_WATCHDOG_INTERVAL: u64 = 60;
+
+/// Default interval (in minutes) between the pulls of the pull-only mode.
+pub const DEFAULT_PULL_INTERVAL: u64 = 5;
+
 #[inline(always)]
 fn default_delay() -> u64 {
     DEFAULT_DELAY
 }
 
-pub fn global_config_path() -> PathBuf {
-    let path = std::env::var("HOME").unwrap() + "/.config/nabu.toml";
-    PathBuf::from(path)
+#[inline(always)]
+fn default_push_validation_timeout() -> u64 {
+    DEFAULT_PUSH_VALIDATION_TIMEOUT
+}
+
+#[inline(always)]
+fn default_commit_batch_interval() -> u64 {
+    DEFAULT_COMMIT_BATCH_INTERVAL
+}
+
+#[inline(always)]
+fn default_session_gap() -> u64 {
+    DEFAULT_SESSION_GAP
+}
+
+#[inline(always)]
+fn default_storm_threshold() -> usize {
+    DEFAULT_STORM_THRESHOLD
+}
+
+#[inline(always)]
+fn default_suppress_grace_period() -> u64 {
+    DEFAULT_SUPPRESS_GRACE_PERIOD
+}
+
+#[inline(always)]
+fn default_notify_cooldown() -> u64 {
+    DEFAULT_NOTIFY_COOLDOWN
+}
+
+#[inline(always)]
+fn default_push_failure_escalation() -> usize {
+    DEFAULT_PUSH_FAILURE_ESCALATION
+}
+
+#[inline(always)]
+fn default_watchdog_interval() -> u64 {
+    DEFAULT_WATCHDOG_INTERVAL
+}
+
+#[inline(always)]
+fn default_track_new_files() -> bool {
+    true
+}
+
+#[inline(always)]
+fn default_throttle_app_state() -> bool {
+    true
+}
+
+#[inline(always)]
+fn default_rolling_branch_prefix() -> String {
+    DEFAULT_ROLLING_BRANCH_PREFIX.to_string()
+}
+
+#[inline(always)]
+fn default_timestamp_format() -> String {
+    DEFAULT_TIMESTAMP_FORMAT.to_string()
+}
+
+/// Name of the configuration file, without the extension.
+const CONFIG_FILE_STEM: &str = "nabu";
+
+/// Name of the configuration file used by `seshat`, nabu's former name.
+const LEGACY_CONFIG_FILE_NAME: &str = "seshat.toml";
+
+/// Path of the configuration committed in the repository, relative to the watched directory,
+/// which shares the repository's recommended settings with every collaborator.
+pub const REPOSITORY_CONFIG_PATH: &str = ".nabu/config.toml";
+
+/// Keys the repository configuration can set: only the settings shaping the history,
+/// neither the secrets nor the pushes nor anything running a command.
+const REPOSITORY_SETTINGS: [&str; 23] = [
+    "delay",
+    "adaptive_delay",
+    "ignore",
+    "dir",
+    "commit_strategy",
+    "amend_window",
+    "commit_batch_interval",
+    "session_gap",
+    "group_by_folder",
+    "storm_threshold",
+    "commit_message",
+    "conventions",
+    "timestamp_format",
+    "timezone",
+    "locale",
+    "commit_notes",
+    "session_trailer",
+    "daily_tags",
+    "rolling_branch_prefix",
+    "unicode_normalization",
+    "track_new_files",
+    "throttle_app_state",
+    "shared_vault",
+];
+
+/// Protections the repository configuration can only tighten, see [`RepositoryProtections`].
+const REPOSITORY_PROTECTIONS: [&str; 3] = ["secret_scan", "allow_conflict_markers", "redact_paths"];
+
+/// Protections set by the repository configuration, which apply over each collaborator's own settings
+/// but can only make them stricter (e.g. `secret_scan = false` is ignored).
+#[derive(Debug, Default, Deserialize)]
+struct RepositoryProtections {
+    secret_scan: Option<bool>,
+    allow_conflict_markers: Option<bool>,
+    redact_paths: Option<PathRedaction>,
+}
+
+impl RepositoryProtections {
+    /// Tighten the configuration's protections.
+    fn apply(&self, config: &mut Config) {
+        if self.secret_scan == Some(true) {
+            config.secret_scan = true;
+        }
+        if self.allow_conflict_markers == Some(false) {
+            config.allow_conflict_markers = false;
+        }
+        if let Some(redact_paths) = self.redact_paths {
+            config.redact_paths = config.redact_paths.stricter(redact_paths);
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Format of a configuration file, all formats share the same schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// All the formats, in the order configuration files are looked up.
+    pub const ALL: [Self; 3] = [Self::Toml, Self::Yaml, Self::Json];
+
+    /// The format matching the path's extension, TOML for unknown extensions.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Toml => "toml",
+            Self::Yaml => "yaml",
+            Self::Json => "json",
+        }
+    }
+
+    /// Name of the configuration file in this format (e.g. `nabu.toml`).
+    pub fn file_name(self) -> String {
+        format!("{}.{}", CONFIG_FILE_STEM, self.extension())
+    }
+
+    /// Parse the configuration, suggesting the closest key for unknown ones.
+    pub fn parse(self, bytes: &[u8]) -> Result<Config> {
+        let result = match self {
+            Self::Toml => toml::from_slice(bytes).map_err(|err| err.to_string()),
+            Self::Yaml => serde_yaml::from_slice(bytes).map_err(|err| err.to_string()),
+            Self::Json => serde_json::from_slice(bytes).map_err(|err| err.to_string()),
+        };
+        result.map_err(schema_error)
+    }
+
+    /// Parse the configuration without checking its schema.
+    pub fn parse_value(self, bytes: &[u8]) -> Result<Value> {
+        Ok(match self {
+            Self::Toml => toml::from_slice(bytes)?,
+            Self::Yaml => serde_yaml::from_slice(bytes)?,
+            Self::Json => serde_json::from_slice(bytes)?,
+        })
+    }
+
+    pub fn serialize(self, config: &Config) -> Result<String> {
+        Ok(match self {
+            // As for `serialize_value`, lest a table field ahead of the plain values breaks `init` and `adopt`
+            Self::Toml => toml::to_string_pretty(&toml::Value::try_from(config)?)?,
+            Self::Yaml => serde_yaml::to_string(config)?,
+            Self::Json => serde_json::to_string_pretty(config)?,
+        })
+    }
+
+    /// Serialize a parsed configuration file, only holding the keys it sets.
+    pub fn serialize_value(self, config: &Value) -> Result<String> {
+        Ok(match self {
+            // Tables must come after the plain values, which `toml::Value` takes care of
+            Self::Toml => toml::to_string_pretty(&toml::Value::try_from(config)?)?,
+            Self::Yaml => serde_yaml::to_string(config)?,
+            Self::Json => serde_json::to_string_pretty(config)?,
+        })
+    }
+}
+
+/// Error for a configuration not matching the schema, suggesting the closest key for unknown ones.
+pub(crate) fn schema_error(message: String) -> Report {
+    match suggest_field(&message) {
+        Some(field) => eyre!("{} (did you mean `{}`?)", message, field),
+        None => eyre!(message),
+    }
+}
+
+/// The expected field closest to the unknown field in a serde error message.
+fn suggest_field(message: &str) -> Option<&str> {
+    // e.g. "unknown field `delya`, expected one of `delay`, `ignore`"
+    let (_, rest) = message.split_once("unknown field `")?;
+    let (unknown, rest) = rest.split_once('`')?;
+    let (_, expected) = rest.split_once("expected ")?;
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|field| (edit_distance(unknown, field), field))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, field)| field)
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Path of the global configuration file in the given format.
+pub fn global_config_path(format: ConfigFormat) -> PathBuf {
+    global_config_dir().join(format.file_name())
+}
+
+/// Directory of the global configuration files.
+fn global_config_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap() + "/.config")
+}
+
+/// Storages the pushes go to instead of a git server, declared as `[remote.<kind>]` sections.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteConfig {
+    #[serde(default)]
+    pub s3: Option<S3Config>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Version of the configuration schema, see [`CONFIG_VERSION`].
+    #[serde(default)]
+    pub version: u32,
+
+    /// Seconds to wait after the last change to a file before committing it,
+    /// each file has its own timer so busy files do not hold up the others.
     #[serde(default = "default_delay")]
     pub delay: u64,
 
+    /// Longest delay (in seconds) of the adaptive mode, in which the delay grows
+    /// with the commits of the last minutes, falling back to `delay` once editing quiets down.
+    #[serde(default)]
+    pub adaptive_delay: Option<u64>,
+
+    /// Glob patterns of the directories not to watch, see [`IgnoreRules`].
     #[serde(default = "Vec::new")]
     pub ignore: Vec<String>,
 
     // https://github.com/serde-rs/serde/issues/1030
     #[serde(default = "bool::default")]
     pub push_on_exit: bool,
+
+    /// Fetch and rebase onto the remote before pushing.
+    #[serde(default = "bool::default")]
+    pub sync: bool,
+
+    /// Etiquette for a vault shared by a team: fetch and fast-forward to the remote before each burst of commits,
+    /// name the author first in the commit messages and hold the pushes while files hold conflict markers.
+    /// Implies `sync`.
+    #[serde(default = "bool::default")]
+    pub shared_vault: bool,
+
+    /// Interval (in minutes) between syncs with the remote: the pending changes are committed,
+    /// the remote is fetched, the local commits are rebased onto it and pushed.
+    #[serde(default)]
+    pub sync_interval: Option<u64>,
+
+    /// Only fast-forward the branch to the remote, every `fetch_interval` minutes,
+    /// without committing anything (for read-mostly replicas).
+    #[serde(default = "bool::default")]
+    pub pull_only: bool,
+
+    /// How to handle conflicts when syncing.
+    #[serde(default)]
+    pub on_conflict: ConflictStrategy,
+
+    /// Whether to force pushes (`never` or `with-lease`).
+    #[serde(default)]
+    pub force_push: ForcePush,
+
+    /// Which references to push (`branch`, `mirror` or `device`).
+    #[serde(default)]
+    pub push_mode: PushMode,
+
+    /// Refspecs pushed verbatim instead of the current branch (e.g. `HEAD:refs/for/main`).
+    #[serde(default)]
+    pub push_refspecs: Vec<String>,
+
+    /// Squash the unpushed commits into one summary commit before each push,
+    /// keeping the remote history readable while the local commits stay fine-grained until then.
+    #[serde(default = "bool::default")]
+    pub squash_before_push: bool,
+
+    /// Git bundle written instead when the remote is unreachable (e.g. `/mnt/usb/vault.bundle`).
+    #[serde(default)]
+    pub backup_path: Option<PathBuf>,
+
+    /// Tag the last commit of each day (e.g. `nabu/2024-05-01`) and push the tags.
+    #[serde(default = "bool::default")]
+    pub daily_tags: bool,
+
+    /// Move the commits onto a new branch every `month` or `quarter` (e.g. `nabu/2024-Q2`),
+    /// each starting from a single commit of the files, so the old ones can be archived or deleted.
+    #[serde(default)]
+    pub rolling_branch: Option<RollingPeriod>,
+
+    /// Prefix of the rolling branches.
+    #[serde(default = "default_rolling_branch_prefix")]
+    pub rolling_branch_prefix: String,
+
+    /// Cron expression for scheduled pushes (e.g. `0 */2 * * *`).
+    #[serde(default)]
+    pub push_schedule: Option<String>,
+
+    /// Push once this many commits are waiting to be pushed.
+    #[serde(default)]
+    pub push_every_n_commits: Option<usize>,
+
+    /// Push once the commits waiting to be pushed add this many megabytes of file contents.
+    #[serde(default)]
+    pub push_every_n_megabytes: Option<u64>,
+
+    /// Number of threads packing the pushed objects, one per CPU by default.
+    #[serde(default)]
+    pub pack_threads: Option<u32>,
+
+    /// zlib compression level (0-9) of the pushed packs,
+    /// lower levels push already compressed files (e.g. images) faster.
+    /// Pushes go through `git` when set.
+    #[serde(default)]
+    pub pack_compression: Option<u32>,
+
+    /// Command that must succeed for a push to happen.
+    #[serde(default)]
+    pub push_precondition_command: Option<String>,
+
+    /// Command validating the outgoing commits before every push, which fails the push unless it succeeds,
+    /// see [`crate::precondition::validate_push`].
+    #[serde(default)]
+    pub push_validation_command: Option<String>,
+
+    /// Time (in seconds) the validation command may run for before it is killed and the push fails.
+    #[serde(default = "default_push_validation_timeout")]
+    pub push_validation_timeout: u64,
+
+    /// Skip pushes while running on battery.
+    #[serde(default = "bool::default")]
+    pub push_require_ac_power: bool,
+
+    /// Skip pushes while on a metered connection.
+    #[serde(default = "bool::default")]
+    pub push_avoid_metered: bool,
+
+    /// Warn once this many commits are waiting to be pushed.
+    #[serde(default)]
+    pub unpushed_warning_threshold: Option<usize>,
+
+    /// Warn once the `.git` directory grows faster than this many megabytes per day, see `nabu size`.
+    #[serde(default)]
+    pub growth_warning_megabytes_per_day: Option<u64>,
+
+    /// Command run on warnings (e.g. the remote diverged), with the `NABU_NOTIFICATION_KIND`
+    /// and `NABU_NOTIFICATION_MESSAGE` environment variables.
+    #[serde(default)]
+    pub notify_command: Option<String>,
+
+    /// Minutes during which `notify_command` is not run again for the same kind of notification.
+    #[serde(default = "default_notify_cooldown")]
+    pub notify_cooldown: u64,
+
+    /// Template of the payload given to `notify_command` on its standard input and in `NABU_NOTIFICATION_PAYLOAD`,
+    /// e.g. the JSON body a chat webhook expects, see [`crate::notification::render_payload`].
+    #[serde(default)]
+    pub notify_payload: Option<String>,
+
+    /// Consecutive push failures after which they are escalated (`push-failing`), never if 0.
+    #[serde(default = "default_push_failure_escalation")]
+    pub push_failure_escalation: usize,
+
+    /// DSN of a Sentry (or GlitchTip) project to report panics and repeated push failures to,
+    /// requires the `error-reports` feature.
+    #[serde(default)]
+    pub error_reports_dsn: Option<String>,
+
+    /// Record every event, commit and push in an SQLite journal, `.git/nabu-journal.sqlite`,
+    /// read by `status`, `stats` and `export`; requires the `journal` feature.
+    #[serde(default = "bool::default")]
+    pub journal: bool,
+
+    /// Days of activity kept in the journal, the older rows are removed when it is compacted
+    /// (on start and then daily); kept forever if unset.
+    #[serde(default)]
+    pub journal_retention_days: Option<u64>,
+
+    /// Interval (in hours) between snapshots taken regardless of events.
+    #[serde(default)]
+    pub snapshot_interval: Option<u64>,
+
+    /// Interval (in minutes) between reconciliation passes,
+    /// which commit any changes the watcher missed.
+    #[serde(default)]
+    pub reconcile_interval: Option<u64>,
+
+    /// Interval (in seconds) between the checks of a sample of the watched directories
+    /// for changes the file watchers missed, restarting them if so, 0 disables it.
+    #[serde(default = "default_watchdog_interval")]
+    pub watchdog_interval: u64,
+
+    /// What happens when the watched directory vanishes (e.g. its drive is unmounted).
+    #[serde(default)]
+    pub missing_root: MissingRoot,
+
+    /// How the changes are grouped into commits.
+    #[serde(default)]
+    pub commit_strategy: CommitStrategyKind,
+
+    /// Window (in minutes) within which successive changes to a file amend
+    /// the commit made for its first change, as long as it was not pushed.
+    /// Setting it selects the `per-file-amend` strategy.
+    #[serde(default)]
+    pub amend_window: Option<u64>,
+
+    /// Interval (in minutes) between the snapshots of the `batched` strategy.
+    #[serde(default = "default_commit_batch_interval")]
+    pub commit_batch_interval: u64,
+
+    /// Inactivity (in minutes) ending a session of the `session` strategy.
+    #[serde(default = "default_session_gap")]
+    pub session_gap: u64,
+
+    /// Split the snapshots of the `batched` and `session` strategies into a commit per folder,
+    /// this many levels deep (1 for the top-level folders).
+    #[serde(default)]
+    pub group_by_folder: Option<usize>,
+
+    /// Seconds, on top of the delay, during which the changes to the paths the hooks list in `.git/nabu-suppress`
+    /// (see `NABU_SUPPRESS`) are not committed, so that committing them does not trigger the hooks again.
+    #[serde(default = "default_suppress_grace_period")]
+    pub suppress_grace_period: u64,
+
+    /// Events per second (e.g. from a `git checkout`) above which the events are committed
+    /// in bulk once they settle, instead of one by one, 0 disables it.
+    #[serde(default = "default_storm_threshold")]
+    pub storm_threshold: usize,
+
+    /// Interval (in minutes) between fetches from the remote, pruning the deleted branches,
+    /// which keeps the ahead/behind counts up to date.
+    #[serde(default)]
+    pub fetch_interval: Option<u64>,
+
+    /// Cron expression for the repository maintenance (garbage collection and commit-graph).
+    #[serde(default)]
+    pub maintenance_schedule: Option<String>,
+
+    /// Run the repository maintenance on exit, after the push on exit if any.
+    #[serde(default = "bool::default")]
+    pub maintenance_on_exit: bool,
+
+    /// Seconds the shutdown may take to snapshot and push (e.g. within systemd's `TimeoutStopSec`),
+    /// only the changes the watcher saw are committed, what is left is finished by the next run.
+    #[serde(default)]
+    pub shutdown_budget: Option<u64>,
+
+    /// Ignore chmod events, by default they are ignored on filesystems
+    /// known to report spurious ones (e.g. FAT, exFAT or Dropbox folders).
+    #[serde(default)]
+    pub ignore_chmod: Option<bool>,
+
+    /// Watch the directories symbolic links point to, as long as they are inside the watched directory.
+    #[serde(default = "bool::default")]
+    pub follow_symlinks: bool,
+
+    /// Unicode normalization applied to paths before staging them.
+    #[serde(default)]
+    pub unicode_normalization: UnicodeNormalization,
+
+    /// Commit new files, otherwise only the changes to the files already tracked are committed.
+    #[serde(default = "default_track_new_files")]
+    pub track_new_files: bool,
+
+    /// Glob patterns of the paths committed even though git ignores them (e.g. `.obsidian/snippets`),
+    /// see [`ForceInclude`].
+    #[serde(default = "Vec::new")]
+    pub force_include: Vec<String>,
+
+    /// Author the catch-up (`--once`) and reconciliation snapshots when their files were last modified,
+    /// rather than now, so that the history tells when the edits were made even if nabu was not running.
+    #[serde(default = "bool::default")]
+    pub author_time_from_mtime: bool,
+
+    /// Commit the application state files rewritten on every interaction (e.g. Obsidian's `workspace.json`)
+    /// at most once per hour, rather than on each of their events.
+    #[serde(default = "default_throttle_app_state")]
+    pub throttle_app_state: bool,
+
+    /// Library staging and committing the changes (`libgit2` or `gitoxide`),
+    /// `gitoxide` requires the `gitoxide` feature.
+    #[serde(default)]
+    pub git_backend: GitBackend,
+
+    /// Hold the commits whose changes look like they contain secrets (e.g. private keys).
+    #[serde(default = "bool::default")]
+    pub secret_scan: bool,
+
+    /// Append the ignore patterns `nabu suggest-ignores` would suggest to `.gitignore` as soon as they are observed.
+    #[serde(default = "bool::default")]
+    pub auto_ignore: bool,
+
+    /// Commit the files holding unresolved conflict markers (`<<<<<<<`),
+    /// they are otherwise held back until the conflict is resolved.
+    #[serde(default = "bool::default")]
+    pub allow_conflict_markers: bool,
+
+    /// Commit the configuration file even when it holds secrets (e.g. tokens),
+    /// it is otherwise kept out of the commits if it lives in the repository.
+    #[serde(default = "bool::default")]
+    pub commit_config_secrets: bool,
+
+    /// Template of the commit messages made on each event,
+    /// with the `{action}`, `{path}`, `{from}` (when renaming) and `{time}` placeholders.
+    #[serde(default)]
+    pub commit_message: Option<String>,
+
+    /// Redact the paths in the commit messages and notes (`hash` or `truncate`),
+    /// for remotes which should not see the file names, the files are committed as usual.
+    #[serde(default)]
+    pub redact_paths: PathRedaction,
+
+    /// Attach the metadata of each commit (event, device, session and time of the change)
+    /// as a git note under `refs/notes/nabu`.
+    #[serde(default = "bool::default")]
+    pub commit_notes: bool,
+
+    /// End each commit message with a `Nabu-Session` trailer identifying the watcher session,
+    /// which, unlike the notes, is pushed along with the commits.
+    #[serde(default = "bool::default")]
+    pub session_trailer: bool,
+
+    /// Name of this device in the commit notes, the hostname by default.
+    #[serde(default)]
+    pub device_name: Option<String>,
+
+    /// Rhai script deciding which changes are committed and with which message,
+    /// relative to the watched directory, requires the `scripting` feature.
+    #[serde(default)]
+    pub script: Option<PathBuf>,
+
+    /// Time zone of the commit message timestamps and of the daily tags (`utc` or `local`).
+    #[serde(default)]
+    pub timezone: Timezone,
+
+    /// `strftime`-style format of the commit message timestamps (e.g. `%Y-%m-%d %H:%M`).
+    #[serde(default = "default_timestamp_format")]
+    pub timestamp_format: String,
+
+    /// Language of the commit messages and of the commands' output (`en` or `pt`),
+    /// taken from `LC_ALL`, `LC_MESSAGES` or `LANG` if unset.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+
+    // The tables come last, toml cannot write values after them
+    /// Time windows during which auto-commits are paused or batched.
+    #[serde(default = "Vec::new")]
+    pub quiet_hours: Vec<QuietHours>,
+
+    /// Keep the large files in a store outside of the repository, committing pointers to them instead,
+    /// as the `[attachments]` section.
+    #[serde(default)]
+    pub attachments: Option<AttachmentsConfig>,
+
+    /// Directories outside of the repository mirrored into it (e.g. `[[mirrors]]`).
+    #[serde(default = "Vec::new")]
+    pub mirrors: Vec<MirrorConfig>,
+
+    /// Overrides for parts of the tree, keyed by glob (e.g. `[dir."journal/**"]`).
+    #[serde(default, rename = "dir")]
+    pub directories: BTreeMap<String, DirectoryConfig>,
+
+    /// Plugins receiving the watcher's activity, keyed by name (e.g. `[plugins.slack]` runs `nabu-slack`).
+    #[serde(default)]
+    pub plugins: BTreeMap<String, PluginConfig>,
+
+    /// Push notification services (ntfy, Gotify) the notifications are sent to,
+    /// as the `[notify.ntfy]` and `[notify.gotify]` sections.
+    #[serde(default)]
+    pub notify: NotifyServices,
+
+    /// Bounds on the watches, queued events and libgit2's memory, as the `[limits]` section.
+    #[serde(default)]
+    pub limits: LimitsConfig,
+
+    /// Learning mode finding the files changing too often, see `nabu writers`, as the `[learning]` section.
+    #[serde(default)]
+    pub learning: Option<LearningConfig>,
+
+    /// How to authenticate with the remote when no authentication flag is given, as the `[auth]` section.
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+
+    /// Storage the pushes go to instead of a git server, e.g. the `[remote.s3]` section.
+    #[serde(default)]
+    pub remote: RemoteConfig,
+
+    /// Adapt the commit messages to the repository's `commit.template` and commitlint configuration,
+    /// as the `[conventions]` section.
+    #[serde(default)]
+    pub conventions: Option<ConventionsConfig>,
 }
 
 impl Config {
@@ -34,18 +697,496 @@ impl Config {
     where
         P: AsRef<Path>,
     {
-        log::info!("attempting to read config from {}", path.as_ref().display());
+        let mut config = Self::read_value(path.as_ref())?;
+        expand_all(&mut config)?;
+        Config::from_value(config)
+    }
+
+    /// Read the configuration file at `path`, migrated to the current version.
+    fn read_value(path: &Path) -> Result<Value> {
+        log::info!("attempting to read config from {}", path.display());
         let bytes = std::fs::read(path)?;
-        Ok(toml::from_slice::<Config>(bytes.as_slice())?)
+        let format = ConfigFormat::from_path(path);
+        let mut config = format.parse_value(&bytes)?;
+        if config_version(&config)? == CONFIG_VERSION {
+            // Check the file itself, for the errors to point to the offending line
+            format.parse(&bytes)?;
+        } else {
+            let version = migrate(&mut config)?;
+            log::warn!(
+                "{} is for version {} of the configuration, run `nabu config migrate` to upgrade it.",
+                path.display(),
+                version
+            );
+        }
+        Ok(config)
+    }
+
+    /// Read a configuration parsed by [`ConfigFormat::parse_value`].
+    pub fn from_value(config: Value) -> Result<Self> {
+        serde_json::from_value(config).map_err(|err| schema_error(err.to_string()))
+    }
+
+    /// Load the configuration from the given path, if any,
+    /// otherwise from the configuration file found for the directory.
+    /// If no configuration file exists, the default configuration is used.
+    /// Either way, the settings of the directory's [`REPOSITORY_CONFIG_PATH`] apply below it.
+    pub fn load<P>(path: Option<&Path>, directory: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| find_config_path(&directory));
+        let (repository_config, protections) = match read_repository_config(directory.as_ref())? {
+            Some(repository_config) => repository_config,
+            None => {
+                return match path {
+                    Some(path) => Self::from_path(path),
+                    None => Ok(Self::default()),
+                }
+            }
+        };
+        let mut config = match path {
+            Some(path) => {
+                let mut config = Self::read_value(&path)?;
+                merge_below(&mut config, repository_config);
+                config
+            }
+            // The repository settings take precedence over the defaults only
+            None => {
+                let mut config = repository_config;
+                merge_below(&mut config, serde_json::to_value(Self::default())?);
+                config
+            }
+        };
+        expand_all(&mut config)?;
+        let mut config = Config::from_value(config)?;
+        protections.apply(&mut config);
+        Ok(config)
+    }
+
+    /// How to push to the remote.
+    pub fn push_settings(&self) -> PushSettings {
+        PushSettings {
+            force: self.force_push,
+            mode: self.push_mode,
+            refspecs: self.push_refspecs.clone(),
+            device: device_name(self.device_name.as_deref()),
+            rolling_branch: self.rolling_branch.is_some(),
+            daily_tags: self.daily_tags,
+            pack_threads: self.pack_threads,
+            pack_compression: self.pack_compression,
+        }
+    }
+
+    /// Apply the `[auth.tls]` settings to the HTTPS remotes, if any.
+    pub fn apply_tls(&self) -> Result<()> {
+        match self.auth.as_ref().and_then(|auth| auth.tls.as_ref()) {
+            Some(tls) => tls.apply(),
+            None => Ok(()),
+        }
+    }
+
+    /// The messages in the configured language, or the environment's.
+    pub fn catalog(&self) -> &'static Catalog {
+        Locale::resolve(self.locale).catalog()
+    }
+
+    /// The commit strategy, `amend_window` alone selects `per-file-amend`.
+    pub fn commit_strategy(&self) -> CommitStrategyKind {
+        match self.commit_strategy {
+            CommitStrategyKind::PerEvent if self.amend_window.is_some() => {
+                CommitStrategyKind::PerFileAmend
+            }
+            commit_strategy => commit_strategy,
+        }
+    }
+
+    /// Whether any kind of push is enabled.
+    pub fn push_enabled(&self) -> bool {
+        self.push_on_exit
+            || self.push_schedule.is_some()
+            || self.push_every_n_commits.is_some()
+            || self.push_every_n_megabytes.is_some()
+            || self.sync_interval.is_some()
+    }
+
+    /// Disable every kind of push and sync, for repositories which cannot be pushed.
+    pub fn disable_pushes(&mut self) {
+        self.push_on_exit = false;
+        self.push_schedule = None;
+        self.push_every_n_commits = None;
+        self.push_every_n_megabytes = None;
+        self.sync = false;
+        self.sync_interval = None;
+    }
+
+    /// Whether the watcher pushes on its own, on a schedule, a threshold, an interval or on exit.
+    pub fn pushes(&self) -> bool {
+        self.push_schedule.is_some()
+            || self.push_every_n_commits.is_some()
+            || self.push_every_n_megabytes.is_some()
+            || self.sync_interval.is_some()
+            || self.push_on_exit
+    }
+
+    /// Whether to sync with the remote before pushing.
+    pub fn syncs(&self) -> bool {
+        self.sync || self.shared_vault || self.sync_interval.is_some()
+    }
+
+    /// Check the values which cannot be validated when deserializing.
+    pub fn validate(&self) -> Result<()> {
+        IgnoreRules::new(&self.ignore)?;
+        ForceInclude::new(&self.force_include)?;
+        if self.group_by_folder == Some(0) {
+            return Err(eyre!("group_by_folder must be at least 1"));
+        }
+        if self.journal_retention_days == Some(0) {
+            return Err(eyre!("journal_retention_days must be at least 1"));
+        }
+        if let Some(adaptive_delay) = self.adaptive_delay {
+            if adaptive_delay < self.delay {
+                return Err(eyre!(
+                    "adaptive_delay ({}) must not be shorter than delay ({})",
+                    adaptive_delay,
+                    self.delay
+                ));
+            }
+        }
+        if self.pull_only && (self.push_enabled() || self.syncs()) {
+            return Err(eyre!("pull_only cannot be combined with pushes or sync"));
+        }
+        if let Some(push_schedule) = &self.push_schedule {
+            parse_cron(push_schedule)?;
+        }
+        if let Some(maintenance_schedule) = &self.maintenance_schedule {
+            parse_cron(maintenance_schedule)?;
+        }
+        if !is_valid_prefix(&self.rolling_branch_prefix) {
+            return Err(eyre!(
+                "invalid rolling_branch_prefix \"{}\"",
+                self.rolling_branch_prefix
+            ));
+        }
+        if self.push_mode != PushMode::Branch && !self.push_refspecs.is_empty() {
+            return Err(eyre!(
+                "push_refspecs can only be combined with push_mode = \"branch\""
+            ));
+        }
+        if self
+            .auth
+            .as_ref()
+            .is_some_and(|auth| auth.ssh_agent && !auth.ssh_key.is_empty())
+        {
+            return Err(eyre!("the [auth] section sets both ssh_agent and ssh_key"));
+        }
+        if let Some(tls) = self.auth.as_ref().and_then(|auth| auth.tls.as_ref()) {
+            tls.validate()?;
+        }
+        if let Some(conventions) = &self.conventions {
+            conventions.validate()?;
+        }
+        // The device references are never the upstream, every push would squash the previous ones again
+        if self.squash_before_push && self.push_mode == PushMode::Device {
+            return Err(eyre!(
+                "squash_before_push cannot be combined with push_mode = \"device\""
+            ));
+        }
+        if let Some(pack_compression) = self.pack_compression.filter(|level| *level > 9) {
+            return Err(eyre!(
+                "pack_compression ({}) must be between 0 and 9",
+                pack_compression
+            ));
+        }
+        if !is_valid_timestamp_format(&self.timestamp_format) {
+            return Err(eyre!("invalid timestamp format: {}", self.timestamp_format));
+        }
+        for quiet_hours in &self.quiet_hours {
+            quiet_hours.validate()?;
+        }
+        if self.git_backend == GitBackend::Gitoxide && !cfg!(feature = "gitoxide") {
+            return Err(eyre!(
+                "git_backend is gitoxide but nabu was built without the gitoxide feature"
+            ));
+        }
+        self.limits.validate()?;
+        Overrides::new(&self.directories)?;
+        for mirror in &self.mirrors {
+            mirror.validate()?;
+        }
+        if let Some(attachments) = &self.attachments {
+            attachments.validate()?;
+        }
+        if let Some(s3) = &self.remote.s3 {
+            if !cfg!(feature = "s3") {
+                return Err(eyre!(
+                    "[remote.s3] is set but nabu was built without the s3 feature"
+                ));
+            }
+            s3.validate()?;
+        }
+        self.notify.validate()?;
+        if let Some(learning) = &self.learning {
+            learning.validate()?;
+        }
+        for (name, plugin) in &self.plugins {
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return Err(eyre!("invalid plugin name: {:?}", name));
+            }
+            if let Some(kind) = plugin
+                .events
+                .iter()
+                .find(|kind| !ACTIVITY_KINDS.contains(&kind.as_str()))
+            {
+                return Err(eyre!(
+                    "invalid event kind {:?} for plugin {}, expected one of {}",
+                    kind,
+                    name,
+                    ACTIVITY_KINDS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the configuration file for a directory,
+/// its `nabu.toml` (or `nabu.yaml`, `nabu.json`) if present, otherwise its legacy `seshat.toml`,
+/// falling back to the global configuration file and then to the global legacy one.
+pub fn find_config_path<P>(directory: P) -> Option<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    // A directory's own settings, legacy or not, win over the global ones
+    let (directory, global_directory) = (directory.as_ref(), global_config_dir());
+    let (path, legacy) = [directory, &global_directory]
+        .into_iter()
+        .flat_map(|directory| {
+            ConfigFormat::ALL
+                .iter()
+                .map(|format| (directory.join(format.file_name()), false))
+                .chain([(directory.join(LEGACY_CONFIG_FILE_NAME), true)])
+        })
+        .find(|(path, _)| path.exists())?;
+    if legacy {
+        log::warn!(
+            "reading the legacy {}, rename it to {}",
+            path.display(),
+            ConfigFormat::Toml.file_name()
+        );
+    }
+    Some(path)
+}
+
+/// Read the directory's [`REPOSITORY_CONFIG_PATH`], if any, migrated to the current version,
+/// splitting the [`REPOSITORY_SETTINGS`] from the [`REPOSITORY_PROTECTIONS`] and dropping the other keys.
+fn read_repository_config(directory: &Path) -> Result<Option<(Value, RepositoryProtections)>> {
+    let path = directory.join(REPOSITORY_CONFIG_PATH);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    log::info!("reading the repository settings from {}", path.display());
+    let mut config = ConfigFormat::Toml
+        .parse_value(&bytes)
+        .map_err(|err| eyre!("{}: {}", path.display(), err))?;
+    migrate(&mut config).map_err(|err| eyre!("{}: {}", path.display(), err))?;
+    let settings = config
+        .as_object_mut()
+        .ok_or_else(|| eyre!("{} is not a table", path.display()))?;
+    let mut protections = serde_json::Map::new();
+    settings.retain(|key, value| {
+        if REPOSITORY_PROTECTIONS.contains(&key.as_str()) {
+            protections.insert(key.clone(), value.take());
+            return false;
+        }
+        let shared = REPOSITORY_SETTINGS.contains(&key.as_str());
+        if !shared && key != "version" {
+            log::warn!(
+                "{} cannot set `{}`, set it in your own configuration file instead",
+                path.display(),
+                key
+            );
+        }
+        shared
+    });
+    // Report the invalid settings against the repository configuration
+    let mut checked = settings.clone();
+    checked.extend(protections.clone());
+    Config::from_value(Value::Object(checked))
+        .map_err(|err| eyre!("{}: {}", path.display(), err))?;
+    let protections: RepositoryProtections = serde_json::from_value(Value::Object(protections))?;
+    if protections.secret_scan == Some(false) {
+        log::warn!(
+            "{} cannot disable `secret_scan`, only enable it",
+            path.display()
+        );
+    }
+    if protections.allow_conflict_markers == Some(true) {
+        log::warn!(
+            "{} cannot enable `allow_conflict_markers`, only disable it",
+            path.display()
+        );
+    }
+    Ok(Some((config, protections)))
+}
+
+/// Add the keys of `below` missing from `config`, merging the tables they both hold.
+fn merge_below(config: &mut Value, below: Value) {
+    // Values other than tables are replaced as a whole (e.g. the `ignore` patterns)
+    if let (Value::Object(config), Value::Object(below)) = (config, below) {
+        for (key, value) in below {
+            match config.get_mut(&key) {
+                Some(config) => merge_below(config, value),
+                None => {
+                    config.insert(key, value);
+                }
+            }
+        }
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             delay: DEFAULT_DELAY,
+            adaptive_delay: None,
             ignore: vec![String::from(".git")],
             push_on_exit: false,
+            sync: false,
+            shared_vault: false,
+            sync_interval: None,
+            pull_only: false,
+            on_conflict: ConflictStrategy::default(),
+            force_push: ForcePush::default(),
+            push_mode: PushMode::default(),
+            push_refspecs: Vec::new(),
+            squash_before_push: false,
+            auth: None,
+            remote: RemoteConfig::default(),
+            backup_path: None,
+            daily_tags: false,
+            rolling_branch: None,
+            rolling_branch_prefix: default_rolling_branch_prefix(),
+            push_schedule: None,
+            push_every_n_commits: None,
+            push_every_n_megabytes: None,
+            pack_threads: None,
+            pack_compression: None,
+            push_precondition_command: None,
+            push_validation_command: None,
+            push_validation_timeout: DEFAULT_PUSH_VALIDATION_TIMEOUT,
+            push_require_ac_power: false,
+            push_avoid_metered: false,
+            unpushed_warning_threshold: None,
+            growth_warning_megabytes_per_day: None,
+            learning: None,
+            notify_command: None,
+            notify_cooldown: DEFAULT_NOTIFY_COOLDOWN,
+            notify_payload: None,
+            notify: NotifyServices::default(),
+            push_failure_escalation: DEFAULT_PUSH_FAILURE_ESCALATION,
+            error_reports_dsn: None,
+            journal: false,
+            journal_retention_days: None,
+            snapshot_interval: None,
+            reconcile_interval: None,
+            watchdog_interval: DEFAULT_WATCHDOG_INTERVAL,
+            missing_root: MissingRoot::default(),
+            limits: LimitsConfig::default(),
+            commit_strategy: CommitStrategyKind::default(),
+            amend_window: None,
+            commit_batch_interval: DEFAULT_COMMIT_BATCH_INTERVAL,
+            session_gap: DEFAULT_SESSION_GAP,
+            group_by_folder: None,
+            suppress_grace_period: DEFAULT_SUPPRESS_GRACE_PERIOD,
+            storm_threshold: DEFAULT_STORM_THRESHOLD,
+            fetch_interval: None,
+            maintenance_schedule: None,
+            maintenance_on_exit: false,
+            shutdown_budget: None,
+            ignore_chmod: None,
+            follow_symlinks: false,
+            unicode_normalization: UnicodeNormalization::default(),
+            track_new_files: true,
+            force_include: vec![],
+            author_time_from_mtime: false,
+            throttle_app_state: true,
+            git_backend: GitBackend::default(),
+            secret_scan: false,
+            auto_ignore: false,
+            allow_conflict_markers: false,
+            commit_config_secrets: false,
+            commit_message: None,
+            conventions: None,
+            redact_paths: PathRedaction::default(),
+            commit_notes: false,
+            session_trailer: false,
+            device_name: None,
+            script: None,
+            timezone: Timezone::default(),
+            timestamp_format: DEFAULT_TIMESTAMP_FORMAT.to_string(),
+            locale: None,
+            quiet_hours: Vec::new(),
+            attachments: None,
+            mirrors: Vec::new(),
+            directories: BTreeMap::new(),
+            plugins: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_round_trips_through_toml() {
+        let config = Config::default();
+        let serialized = toml::to_string(&config).expect("the default configuration serializes");
+        let deserialized: Config =
+            toml::from_str(&serialized).expect("the serialized configuration deserializes");
+        assert_eq!(
+            toml::to_string(&deserialized).unwrap(),
+            serialized,
+            "the configuration changed through the round trip"
+        );
+    }
+
+    #[test]
+    fn default_config_serializes_in_every_format() {
+        for format in ConfigFormat::ALL {
+            let serialized = format
+                .serialize(&Config::default())
+                .unwrap_or_else(|err| panic!("{:?} serialization failed: {}", format, err));
+            format
+                .parse(serialized.as_bytes())
+                .unwrap_or_else(|err| panic!("{:?} parsing failed: {}", format, err));
         }
     }
+
+    #[test]
+    fn local_legacy_config_is_found_before_the_global_config() {
+        let root = std::env::temp_dir().join(format!("nabu-config-paths-{}", std::process::id()));
+        let (home, directory) = (root.join("home"), root.join("notes"));
+        std::fs::create_dir_all(home.join(".config")).unwrap();
+        std::fs::create_dir_all(&directory).unwrap();
+        // No other test reads the global configuration
+        std::env::set_var("HOME", &home);
+        let global = global_config_path(ConfigFormat::Toml);
+        std::fs::write(&global, "").unwrap();
+        let legacy = directory.join(LEGACY_CONFIG_FILE_NAME);
+        std::fs::write(&legacy, "").unwrap();
+
+        assert_eq!(find_config_path(&directory), Some(legacy));
+        let local = directory.join(ConfigFormat::Toml.file_name());
+        std::fs::write(&local, "").unwrap();
+        assert_eq!(find_config_path(&directory), Some(local));
+        assert_eq!(find_config_path(&root), Some(global));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
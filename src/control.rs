@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::activity::Activity;
+
+/// Name of the watcher's control socket, kept inside the repository's `.git` directory.
+pub const CONTROL_SOCKET_FILE_NAME: &str = "nabu.sock";
+
+/// Request streaming the watcher's activity, as the first line sent to the control socket.
+pub const TAIL_REQUEST: &str = "tail";
+
+/// Path of the control socket for the repository with the given `.git` directory.
+pub fn control_socket_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(CONTROL_SOCKET_FILE_NAME)
+}
+
+/// An activity of the watcher and when it happened (RFC 3339),
+/// streamed as a JSON line to the tailing clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TailedActivity {
+    pub time: String,
+    #[serde(flatten)]
+    pub activity: Activity,
+}
+
+#[cfg(unix)]
+pub use unix::{serve, tail};
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::Path,
+        sync::mpsc::{Receiver, RecvTimeoutError},
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+
+    use chrono::Local;
+
+    use super::{TailedActivity, TAIL_REQUEST};
+    use crate::activity::Activity;
+
+    /// How often the server accepts the pending connections while no activity is received.
+    const ACCEPT_INTERVAL: Duration = Duration::from_millis(250);
+    /// How long a client may take to send its request, or to read an activity, before it is dropped.
+    const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Serve the control socket at `path` in a separate thread, streaming the activity to the tailing clients,
+    /// until the watcher hangs up; the socket is removed then.
+    ///
+    /// A leftover socket of a watcher which did not stop cleanly is replaced,
+    /// the one of a watcher still running is an error.
+    pub fn serve(path: &Path, activity: Receiver<Activity>) -> io::Result<JoinHandle<()>> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("another watcher listens on {}", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        let path = path.to_path_buf();
+        Ok(thread::spawn(move || {
+            let mut clients = Vec::new();
+            loop {
+                accept(&listener, &mut clients);
+                match activity.recv_timeout(ACCEPT_INTERVAL) {
+                    Ok(activity) => {
+                        let mut line = match serde_json::to_string(&TailedActivity {
+                            time: Local::now().to_rfc3339(),
+                            activity,
+                        }) {
+                            Ok(line) => line,
+                            Err(err) => {
+                                log::debug!("failed to serialize the activity: {}", err);
+                                continue;
+                            }
+                        };
+                        line.push('\n');
+                        // Clients which hung up, or fell behind, are dropped
+                        clients.retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            if let Err(err) = fs::remove_file(&path) {
+                log::debug!("failed to remove the control socket: {}", err);
+            }
+        }))
+    }
+
+    /// Accept the pending connections, keeping the clients requesting the activity.
+    fn accept(listener: &UnixListener, clients: &mut Vec<UnixStream>) {
+        loop {
+            let client = match listener.accept() {
+                Ok((client, _)) => client,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    log::debug!("failed to accept a control connection: {}", err);
+                    return;
+                }
+            };
+            match read_request(&client) {
+                Ok(request) if request == TAIL_REQUEST => clients.push(client),
+                Ok(request) => {
+                    let _ = writeln!(&client, "unknown request {:?}", request);
+                }
+                Err(err) => log::debug!("failed to read a control request: {}", err),
+            }
+        }
+    }
+
+    fn read_request(client: &UnixStream) -> io::Result<String> {
+        // Accepted connections inherit the listener's mode on some platforms
+        client.set_nonblocking(false)?;
+        client.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        client.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut request = String::new();
+        BufReader::new(client).read_line(&mut request)?;
+        Ok(request.trim().to_string())
+    }
+
+    /// Connect to the control socket at `path` and stream the watcher's activity, as JSON lines,
+    /// until the watcher stops.
+    pub fn tail(path: &Path) -> io::Result<impl Iterator<Item = io::Result<String>> + Send> {
+        let mut stream = UnixStream::connect(path)?;
+        writeln!(stream, "{}", TAIL_REQUEST)?;
+        Ok(BufReader::new(stream).lines())
+    }
+}
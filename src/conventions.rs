@@ -0,0 +1,304 @@
+use std::{fs, path::Path};
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Files holding the commitlint configuration, by precedence;
+/// the JavaScript ones cannot be read, `package.json` holds it under its `commitlint` key.
+const COMMITLINT_FILES: [&str; 5] = [
+    ".commitlintrc",
+    ".commitlintrc.json",
+    ".commitlintrc.yaml",
+    ".commitlintrc.yml",
+    "package.json",
+];
+
+/// The shared commitlint configuration enforcing the conventional commits.
+const CONVENTIONAL_PRESET: &str = "@commitlint/config-conventional";
+
+/// Types allowed by [`CONVENTIONAL_PRESET`].
+const CONVENTIONAL_TYPES: [&str; 11] = [
+    "build", "chore", "ci", "docs", "feat", "fix", "perf", "refactor", "revert", "style", "test",
+];
+
+/// Maximum length of the headers allowed by [`CONVENTIONAL_PRESET`].
+const CONVENTIONAL_HEADER_MAX_LENGTH: usize = 100;
+
+fn default_type() -> String {
+    String::from("chore")
+}
+
+fn default_scope() -> String {
+    String::from("nabu")
+}
+
+/// Adapt the commit messages to the repository's conventions, declared as the `[conventions]` section,
+/// see [`Conventions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConventionsConfig {
+    /// Type of the conventional commits, replaced by the first allowed one if the repository does not allow it.
+    #[serde(rename = "type", default = "default_type")]
+    pub kind: String,
+
+    /// Scope of the conventional commits, left out when empty unless the repository requires one.
+    #[serde(default = "default_scope")]
+    pub scope: String,
+}
+
+impl Default for ConventionsConfig {
+    fn default() -> Self {
+        Self {
+            kind: default_type(),
+            scope: default_scope(),
+        }
+    }
+}
+
+impl ConventionsConfig {
+    /// Check that the type and scope fit in a conventional header.
+    pub fn validate(&self) -> Result<()> {
+        let valid = |name: &str| {
+            !name.contains(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ':' | '!'))
+        };
+        if self.kind.is_empty() || !valid(&self.kind) {
+            return Err(eyre!("invalid conventional commit type {:?}", self.kind));
+        }
+        if !valid(&self.scope) {
+            return Err(eyre!("invalid conventional commit scope {:?}", self.scope));
+        }
+        Ok(())
+    }
+}
+
+/// The commit message conventions of a repository, as read from its `commit.template`
+/// and its commitlint configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conventions {
+    /// Prefix of the subjects, the first line of the `commit.template` when it ends with `:` or `]`
+    /// (e.g. `docs:` or `[notes]`), other templates describe the message rather than start it.
+    pub prefix: Option<String>,
+    /// Whether the headers must be conventional commits, `type(scope): subject`.
+    pub conventional: bool,
+    /// The allowed types, any if empty.
+    pub types: Vec<String>,
+    /// The allowed scopes, any if empty.
+    pub scopes: Vec<String>,
+    /// Whether the headers need a scope.
+    pub scope_required: bool,
+    /// Maximum number of characters of the headers.
+    pub header_max_length: Option<usize>,
+}
+
+impl Conventions {
+    /// Read the conventions of the repository with the given working directory and `commit.template`, if any.
+    /// The configuration files which cannot be parsed are skipped with a warning.
+    pub fn read(workdir: &Path, template: Option<&Path>) -> Self {
+        let mut conventions = Self {
+            prefix: template.and_then(template_prefix),
+            ..Self::default()
+        };
+        if let Some(commitlint) = read_commitlint(workdir) {
+            conventions.apply_commitlint(&commitlint);
+        }
+        conventions
+    }
+
+    /// Whether the repository has any convention.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Describe the conventions for humans, e.g. `conventional commits, headers up to 72 characters`.
+    pub fn describe(&self) -> String {
+        let mut descriptions = Vec::new();
+        if let Some(prefix) = &self.prefix {
+            descriptions.push(format!("subjects starting with {:?}", prefix));
+        }
+        if self.conventional {
+            descriptions.push(String::from("conventional commits"));
+        }
+        if !self.types.is_empty() {
+            descriptions.push(format!("types {}", self.types.join(", ")));
+        }
+        if !self.scopes.is_empty() {
+            descriptions.push(format!("scopes {}", self.scopes.join(", ")));
+        } else if self.scope_required {
+            descriptions.push(String::from("a required scope"));
+        }
+        if let Some(length) = self.header_max_length {
+            descriptions.push(format!("headers up to {} characters", length));
+        }
+        descriptions.join(", ")
+    }
+
+    /// The message with its header adapted to the conventions, the rest of the message is kept as it is.
+    pub fn apply(&self, config: &ConventionsConfig, message: &str) -> String {
+        let (subject, rest) = match message.split_once('\n') {
+            Some((subject, rest)) => (subject, Some(rest)),
+            None => (message, None),
+        };
+        let mut header = String::new();
+        if self.conventional {
+            let kind = match self.types.first() {
+                Some(first) if !self.types.contains(&config.kind) => first,
+                _ => &config.kind,
+            };
+            let scope = match self.scopes.first() {
+                Some(first) if !self.scopes.contains(&config.scope) => first.clone(),
+                _ if config.scope.is_empty() && self.scope_required => default_scope(),
+                _ => config.scope.clone(),
+            };
+            header.push_str(kind);
+            if !scope.is_empty() {
+                header.push_str(&format!("({})", scope));
+            }
+            header.push_str(": ");
+        }
+        if let Some(prefix) = &self.prefix {
+            header.push_str(prefix);
+            header.push(' ');
+        }
+        header.push_str(subject);
+        if let Some(length) = self.header_max_length {
+            header = shorten(&header, length);
+        }
+        match rest {
+            Some(rest) => format!("{}\n{}", header, rest),
+            None => header,
+        }
+    }
+
+    /// Apply the rules of the commitlint configuration this crate knows of, the others are ignored.
+    fn apply_commitlint(&mut self, commitlint: &Value) {
+        let extends = match commitlint.get("extends") {
+            Some(Value::String(preset)) => vec![preset.as_str()],
+            Some(Value::Array(presets)) => presets.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if extends
+            .iter()
+            .any(|preset| preset.contains(CONVENTIONAL_PRESET))
+        {
+            self.conventional = true;
+            self.types = CONVENTIONAL_TYPES
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect();
+            self.header_max_length = Some(CONVENTIONAL_HEADER_MAX_LENGTH);
+        }
+        let rules = match commitlint.get("rules").and_then(Value::as_object) {
+            Some(rules) => rules,
+            None => return,
+        };
+        for (name, rule) in rules {
+            // Rules are `[level, "always" | "never", value]`, level 0 disabling them
+            let rule = match rule.as_array() {
+                Some(rule) if rule.first().and_then(Value::as_u64).unwrap_or(0) > 0 => rule,
+                _ => continue,
+            };
+            let always = rule.get(1).and_then(Value::as_str) != Some("never");
+            let value = rule.get(2);
+            let names = || {
+                value
+                    .and_then(Value::as_array)
+                    .map(|names| {
+                        names
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .map(String::from)
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            match name.as_str() {
+                "type-enum" if always => {
+                    self.conventional = true;
+                    self.types = names();
+                }
+                "type-empty" if !always => self.conventional = true,
+                "scope-enum" if always => {
+                    self.conventional = true;
+                    self.scopes = names();
+                }
+                "scope-empty" if !always => {
+                    self.conventional = true;
+                    self.scope_required = true;
+                }
+                "header-max-length" if always => {
+                    self.header_max_length =
+                        value.and_then(Value::as_u64).map(|length| length as usize);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The header shortened to `length` characters by eliding the middle of the subject (usually the path)
+/// rather than its end, so the trailing ` @ {time}` of nabu's messages is kept.
+fn shorten(header: &str, length: usize) -> String {
+    let count = header.chars().count();
+    if count <= length {
+        return header.to_string();
+    }
+    let (front, suffix) = match header.rsplit_once(" @ ") {
+        Some((front, time)) if time.chars().count() + 4 < length => (front, format!(" @ {}", time)),
+        _ => (header, String::new()),
+    };
+    // The front keeps its start (e.g. the type and the action) and its end (e.g. the file name)
+    let kept = length.saturating_sub(suffix.chars().count() + 1);
+    let end = kept / 2;
+    let start = kept - end;
+    let front = front.chars().collect::<Vec<_>>();
+    let mut shortened = front[..start].iter().collect::<String>();
+    shortened.push('…');
+    shortened.extend(&front[front.len() - end..]);
+    shortened.push_str(&suffix);
+    shortened
+}
+
+/// The prefix set by the first line of the commit template, if it is one.
+fn template_prefix(template: &Path) -> Option<String> {
+    let contents = match fs::read_to_string(template) {
+        Ok(contents) => contents,
+        Err(err) => {
+            log::warn!(
+                "failed to read the commit template {}: {}",
+                template.display(),
+                err
+            );
+            return None;
+        }
+    };
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+    (line.ends_with(':') || line.ends_with(']')).then(|| line.to_string())
+}
+
+/// The first commitlint configuration of the working directory, if any.
+fn read_commitlint(workdir: &Path) -> Option<Value> {
+    for name in COMMITLINT_FILES {
+        let path = workdir.join(name);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+        // YAML being a superset of JSON, it reads every format
+        let config = match serde_yaml::from_slice::<Value>(&bytes) {
+            Ok(config) => config,
+            Err(err) => {
+                log::warn!("failed to parse {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        if name == "package.json" {
+            return config.get("commitlint").cloned();
+        }
+        return Some(config);
+    }
+    None
+}
@@ -0,0 +1,363 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    config::{find_config_path, Config},
+    fs::{list_subdirs, IgnoreRules},
+    git::{AuthenticationMethod, Repository, WatchedRepository, TOKEN_VARIABLES},
+    git_env::open_repository,
+    overrides::Overrides,
+    ssh_key::{public_key_path, SshKeyInfo},
+    state::{state_path, State},
+};
+
+const MAX_USER_WATCHES_PATH: &str = "/proc/sys/fs/inotify/max_user_watches";
+
+/// The outcome of a diagnostic check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// The result of a single diagnostic check.
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    /// What was checked.
+    pub check: &'static str,
+    pub status: Status,
+    /// What was found.
+    pub message: String,
+    /// How to fix the problem, if there is one.
+    pub fix: Option<String>,
+}
+
+impl Diagnostic {
+    fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: Status::Warn,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: Status::Fail,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run all diagnostic checks over the given directory.
+pub fn diagnose(
+    directory: &Path,
+    config_path: Option<&Path>,
+    authentication_method: Option<&AuthenticationMethod>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    let config = check_config(directory, config_path, &mut diagnostics);
+
+    let (repo, _overrides) = match open_repository(directory, true) {
+        Ok(opened) => {
+            diagnostics.push(Diagnostic::ok(
+                "repository",
+                format!("opened {}", directory.display()),
+            ));
+            opened
+        }
+        Err(err) => {
+            diagnostics.push(Diagnostic::fail(
+                "repository",
+                err.message(),
+                "run `git init` or point nabu to a directory inside a git repository",
+            ));
+            return diagnostics;
+        }
+    };
+
+    diagnostics.push(check_head(&repo));
+    diagnostics.extend(check_identity(&repo));
+    diagnostics.push(check_authentication(authentication_method));
+    if let Some(remote) = check_remote(&repo, directory, authentication_method) {
+        diagnostics.push(remote);
+    }
+    if let Some(watches) = check_watches(directory, &config) {
+        diagnostics.push(watches);
+    }
+    if let Some(crash) = check_crash(&repo) {
+        diagnostics.push(crash);
+    }
+
+    diagnostics
+}
+
+fn check_config(
+    directory: &Path,
+    config_path: Option<&Path>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Config {
+    let path = match config_path
+        .map(Path::to_path_buf)
+        .or_else(|| find_config_path(directory))
+    {
+        Some(path) => path,
+        None => {
+            diagnostics.push(Diagnostic::ok(
+                "config",
+                "no configuration file found, using the defaults",
+            ));
+            return Config::default();
+        }
+    };
+    match Config::from_path(&path).and_then(|config| config.validate().map(|_| config)) {
+        Ok(config) => {
+            diagnostics.push(Diagnostic::ok(
+                "config",
+                format!("loaded {}", path.display()),
+            ));
+            config
+        }
+        Err(err) => {
+            diagnostics.push(Diagnostic::fail(
+                "config",
+                format!("{}: {}", path.display(), err),
+                "fix the configuration file or regenerate it with `nabu init`",
+            ));
+            Config::default()
+        }
+    }
+}
+
+fn check_head(repo: &git2::Repository) -> Diagnostic {
+    if repo.state() != git2::RepositoryState::Clean {
+        return Diagnostic::fail(
+            "head",
+            format!("repository is in the middle of a {:?}", repo.state()),
+            "finish or abort the ongoing operation",
+        );
+    }
+    match repo.head() {
+        Ok(head) if head.is_branch() => Diagnostic::ok(
+            "head",
+            format!("on branch {}", head.shorthand().unwrap_or_default()),
+        ),
+        Ok(_) => Diagnostic::warn(
+            "head",
+            "HEAD is detached",
+            "checkout a branch with `git switch <branch>`",
+        ),
+        Err(err) if err.code() == git2::ErrorCode::UnbornBranch => Diagnostic::fail(
+            "head",
+            "the current branch has no commits",
+            "create an initial commit with `git commit --allow-empty -m \"initial commit\"`",
+        ),
+        Err(err) => Diagnostic::fail(
+            "head",
+            err.message(),
+            "check the repository with `git status`",
+        ),
+    }
+}
+
+fn check_identity(repo: &git2::Repository) -> Vec<Diagnostic> {
+    let config = match repo.config() {
+        Ok(config) => config,
+        Err(err) => {
+            return vec![Diagnostic::fail(
+                "identity",
+                err.message(),
+                "check your git configuration files",
+            )]
+        }
+    };
+    ["user.name", "user.email"]
+        .into_iter()
+        .map(|key| match config.get_string(key) {
+            Ok(value) => Diagnostic::ok("identity", format!("{} is {}", key, value)),
+            Err(_) => Diagnostic::fail(
+                "identity",
+                format!("{} is not set", key),
+                format!("run `git config --global {} <value>`", key),
+            ),
+        })
+        .collect()
+}
+
+/// Check that the authentication method is usable.
+pub fn check_authentication(authentication_method: Option<&AuthenticationMethod>) -> Diagnostic {
+    match authentication_method {
+        None => Diagnostic::warn(
+            "authentication",
+            "no authentication method provided, pushing is disabled",
+            "declare `--ssh-agent` or `--ssh-key <path>`, or set a token (e.g. `GITHUB_TOKEN`)",
+        ),
+        Some(AuthenticationMethod::Token) => {
+            let variables = TOKEN_VARIABLES
+                .iter()
+                .map(|(variable, _, _)| *variable)
+                .filter(|variable| std::env::var_os(variable).is_some())
+                .collect::<BTreeSet<_>>();
+            Diagnostic::ok(
+                "authentication",
+                format!(
+                    "using the token from {}",
+                    variables.into_iter().collect::<Vec<_>>().join(", ")
+                ),
+            )
+        }
+        Some(AuthenticationMethod::SshAgent) => match std::env::var_os("SSH_AUTH_SOCK") {
+            Some(socket) if Path::new(&socket).exists() => {
+                Diagnostic::ok("authentication", "ssh-agent is reachable")
+            }
+            _ => Diagnostic::fail(
+                "authentication",
+                "ssh-agent is not reachable",
+                "start the agent with `eval $(ssh-agent)` and add your key with `ssh-add`",
+            ),
+        },
+        Some(AuthenticationMethod::SshKey { paths, passphrase }) => {
+            for path in paths {
+                if let Err(err) = fs::read(path) {
+                    return Diagnostic::fail(
+                        "authentication",
+                        format!("cannot read {}: {}", path.display(), err),
+                        "check the key path and its permissions",
+                    );
+                }
+                let public_key = public_key_path(path);
+                if !public_key.exists() {
+                    return Diagnostic::warn(
+                        "authentication",
+                        format!("public key {} is missing", public_key.display()),
+                        format!(
+                            "generate it with `ssh-keygen -y -f {} > {}`",
+                            path.display(),
+                            public_key.display()
+                        ),
+                    );
+                }
+                let info = SshKeyInfo::read(path).unwrap_or_default();
+                if info.security_key && !ssh_installed() {
+                    return Diagnostic::fail(
+                        "authentication",
+                        format!(
+                            "{} is held by a security key, which needs the OpenSSH client",
+                            path.display()
+                        ),
+                        "install OpenSSH 8.2 or later, git uses it to authenticate with the key",
+                    );
+                }
+                if info.encrypted && !info.security_key && passphrase.is_empty() {
+                    return Diagnostic::warn(
+                        "authentication",
+                        format!("{} is protected by a passphrase", path.display()),
+                        "give it with --ssh-passphrase, or load the key into the ssh-agent and use --ssh-agent",
+                    );
+                }
+            }
+            let paths = paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+            let verb = if paths.len() == 1 { "is" } else { "are" };
+            Diagnostic::ok(
+                "authentication",
+                format!("{} {} readable", paths.join(", "), verb),
+            )
+        }
+    }
+}
+
+/// Whether the OpenSSH client can be run.
+fn ssh_installed() -> bool {
+    std::process::Command::new("ssh")
+        .arg("-V")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn check_remote(
+    repo: &git2::Repository,
+    directory: &Path,
+    authentication_method: Option<&AuthenticationMethod>,
+) -> Option<Diagnostic> {
+    if let Err(err) = repo.find_remote("origin") {
+        return Some(Diagnostic::fail(
+            "remote",
+            err.message(),
+            "add a remote with `git remote add origin <url>`",
+        ));
+    }
+    let result = WatchedRepository::discover(directory).and_then(|watched| {
+        watched.check_remote(authentication_method)?;
+        watched.push_url()
+    });
+    Some(match result {
+        Ok(url) => Diagnostic::ok("remote", format!("origin ({}) is reachable", url)),
+        Err(err) => Diagnostic::fail(
+            "remote",
+            format!("origin is not reachable: {}", err.message()),
+            "check the remote URL with `git remote -v` and your authentication method",
+        ),
+    })
+}
+
+fn check_watches(directory: &Path, config: &Config) -> Option<Diagnostic> {
+    let max_user_watches = fs::read_to_string(MAX_USER_WATCHES_PATH)
+        .ok()?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    let ignore = IgnoreRules::new(&config.ignore).ok()?;
+    let overrides = Overrides::new(&config.directories).ok()?;
+    let directories = list_subdirs(directory, ignore, overrides, config.follow_symlinks).len();
+    let fix = format!(
+        "raise the limit with `sysctl fs.inotify.max_user_watches={}`",
+        directories * 2
+    );
+    let message = format!(
+        "{} directories to watch, the limit is {}",
+        directories, max_user_watches
+    );
+    Some(if directories > max_user_watches {
+        Diagnostic::fail("watches", message, fix)
+    } else if directories * 10 > max_user_watches * 8 {
+        Diagnostic::warn("watches", message, fix)
+    } else {
+        Diagnostic::ok("watches", message)
+    })
+}
+
+/// Surface the last panic of the watcher, recorded in the state file by its panic hook.
+fn check_crash(repo: &git2::Repository) -> Option<Diagnostic> {
+    let crash = State::read(&state_path(repo.path()))?.last_crash?;
+    let mut message = format!("the watcher crashed at {}: {}", crash.time, crash.message);
+    if let Some(location) = &crash.location {
+        message.push_str(&format!(" ({})", location));
+    }
+    let fix = match &crash.snapshot {
+        Some(snapshot) => format!(
+            "the changes pending at the time were committed as {}, check the log from then and report the crash",
+            snapshot
+        ),
+        None => String::from("check the log from then and report the crash"),
+    };
+    Some(Diagnostic::warn("crash", message, fix))
+}
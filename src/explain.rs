@@ -0,0 +1,259 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::{eyre::eyre, Result};
+use serde::Serialize;
+
+use crate::{
+    app_state::is_app_state,
+    attachments::FILTER_DRIVER,
+    config::Config,
+    fs::{ForceInclude, IgnoreRules},
+    git::{has_conflict_markers, Repository, WatchedRepository},
+    overrides::Overrides,
+    script::Script,
+};
+
+const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+/// How a finding weighs on the commit of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// Keeps the file out of the commits.
+    Skip,
+    /// Changes how or when the file is committed.
+    Note,
+}
+
+/// A rule or setting applying to the file.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// What was checked.
+    pub check: &'static str,
+    pub verdict: Verdict,
+    /// What was found, e.g. the matching pattern.
+    pub message: String,
+}
+
+impl Finding {
+    fn skip(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            verdict: Verdict::Skip,
+            message: message.into(),
+        }
+    }
+
+    fn note(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            verdict: Verdict::Note,
+            message: message.into(),
+        }
+    }
+}
+
+/// Whether the watcher commits a file, and why.
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    /// The file, relative to the watched directory.
+    pub path: PathBuf,
+    /// Whether the changes to the file are committed as they happen.
+    pub committed: bool,
+    pub findings: Vec<Finding>,
+}
+
+/// Explain whether the watcher of `directory` commits the changes to the file at `path`, and why,
+/// going through the checks it makes in the same order: the ignore patterns, the `[dir]` sections,
+/// the gitignore rules, the sparse checkout, new files, the configuration secrets, the script,
+/// the secret scan and the conflict markers, then the settings changing how the file is committed.
+pub fn explain(
+    directory: &Path,
+    repo: &WatchedRepository,
+    config: &Config,
+    path: &Path,
+) -> Result<Explanation> {
+    let root = directory.canonicalize()?;
+    let path = resolve(path)?;
+    let relative = path
+        .strip_prefix(&root)
+        .map_err(|_| {
+            eyre!(
+                "{} is outside of the watched directory {}",
+                path.display(),
+                root.display()
+            )
+        })?
+        .to_path_buf();
+    let mut findings = vec![];
+
+    let ignore = IgnoreRules::new(&config.ignore)?;
+    let overrides = Overrides::new(&config.directories)?;
+    let directories = relative
+        .parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .collect::<Vec<_>>();
+    // From the top, the watcher does not look into the ignored directories
+    for directory in directories.into_iter().rev() {
+        if let Some(pattern) = ignore.matching_pattern(directory) {
+            findings.push(Finding::skip(
+                "ignore",
+                format!(
+                    "{} matches the ignore pattern \"{}\", only the snapshots commit its changes",
+                    directory.display(),
+                    pattern
+                ),
+            ));
+            break;
+        }
+        if let Some((glob, pattern)) = overrides.unwatched_by(directory) {
+            let reason = match pattern {
+                Some(pattern) => format!("the ignore pattern \"{}\"", pattern),
+                None => String::from("watch = false"),
+            };
+            findings.push(Finding::skip(
+                "dir",
+                format!(
+                    "{} is not watched, by {} of [dir.\"{}\"], only the snapshots commit its changes",
+                    directory.display(),
+                    reason,
+                    glob
+                ),
+            ));
+            break;
+        }
+    }
+    let settings = overrides.settings(&relative);
+    if !settings.watch && findings.is_empty() {
+        findings.push(Finding::skip(
+            "dir",
+            "a [dir] section sets watch = false, only the snapshots commit its changes",
+        ));
+    }
+
+    if let Some(rule) = repo.ignore_rule(&path)? {
+        let force_include = ForceInclude::new(&config.force_include)?;
+        let in_workdir = path.strip_prefix(repo.workdir()).unwrap_or(&path);
+        if force_include.is_included(in_workdir) {
+            findings.push(Finding::note(
+                "force include",
+                format!("ignored by {}, but force_include commits it anyway", rule),
+            ));
+        } else {
+            findings.push(Finding::skip("gitignore", format!("ignored by {}", rule)));
+        }
+    }
+    if !repo.is_checked_out(&path)? {
+        findings.push(Finding::skip(
+            "sparse checkout",
+            "outside of the sparse checkout",
+        ));
+    }
+    if !config.track_new_files && !repo.is_tracked(&path)? {
+        findings.push(Finding::skip(
+            "new files",
+            "not tracked, and track_new_files is off",
+        ));
+    }
+    if repo.is_excluded(&path)? {
+        findings.push(Finding::skip(
+            "configuration secrets",
+            "the configuration file holds secrets, set commit_config_secrets = true to commit it anyway",
+        ));
+    }
+    if let Some(script) = &config.script {
+        match Script::load(&root.join(script)) {
+            Ok(script) if !script.should_commit(&relative) => findings.push(Finding::skip(
+                "script",
+                "should_commit of the script rejects it",
+            )),
+            Ok(_) => {}
+            Err(err) => findings.push(Finding::note(
+                "script",
+                format!("the script cannot be loaded: {}", err),
+            )),
+        }
+    }
+    if config.secret_scan {
+        for secret in repo.find_secrets(Some(&path))? {
+            findings.push(Finding::skip(
+                "secret scan",
+                format!(
+                    "possible {} on line {}, held until it is removed",
+                    secret.kind, secret.line
+                ),
+            ));
+        }
+    }
+    if !config.allow_conflict_markers
+        && std::fs::read(&path).is_ok_and(|content| has_conflict_markers(&content))
+    {
+        findings.push(Finding::skip(
+            "conflict markers",
+            "holds unresolved conflict markers, held until they are resolved",
+        ));
+    }
+
+    let skipped = findings
+        .iter()
+        .any(|finding| finding.verdict == Verdict::Skip);
+    if !skipped {
+        let delay = match settings.delay {
+            Some(delay) => format!(
+                "{} seconds after its last change, set by its [dir] section",
+                delay
+            ),
+            None => format!("{} seconds after its last change", config.delay),
+        };
+        findings.push(Finding::note("delay", format!("committed {}", delay)));
+    }
+    if config.throttle_app_state && is_app_state(&relative) {
+        findings.push(Finding::note(
+            "application state",
+            "an application state file, committed at most once per hour",
+        ));
+    }
+    if let Some(attachments) = &config.attachments {
+        let size = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if size > attachments.threshold * BYTES_PER_MEGABYTE
+            && repo.attribute(&path, "filter")?.as_deref() == Some(FILTER_DRIVER)
+        {
+            findings.push(Finding::note(
+                "attachments",
+                format!(
+                    "larger than {} MB, committed as a pointer to {}",
+                    attachments.threshold,
+                    attachments.store.display()
+                ),
+            ));
+        }
+    }
+    if !path.exists() && !repo.is_tracked(&path)? {
+        findings.push(Finding::note("status", "does not exist"));
+    } else if repo.is_committed(&path)? {
+        findings.push(Finding::note("status", "no uncommitted changes"));
+    }
+
+    Ok(Explanation {
+        committed: !skipped,
+        path: relative,
+        findings,
+    })
+}
+
+/// The canonical path, which may not exist (e.g. a deleted file) as long as its directory does.
+fn resolve(path: &Path) -> Result<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Ok(path);
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| eyre!("{} is not a file", path.display()))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(parent.canonicalize()?.join(name))
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::Serialize;
+
+use crate::{journal::JournalDay, stats::FileActivity};
+
+/// Activity over a single day, as exported.
+#[derive(Debug, Clone, Serialize)]
+pub struct DayReport {
+    /// The day, formatted as `YYYY-MM-DD`.
+    pub date: String,
+    pub commits: usize,
+    /// Words added to Markdown files.
+    pub words_added: usize,
+    /// Words removed from Markdown files.
+    pub words_removed: usize,
+    /// Files changed that day, most edited first.
+    pub files: Vec<FileActivity>,
+    /// The watcher's events and pushes that day, from its journal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watcher: Option<JournalDay>,
+}
+
+/// Report of the activity over a period, one entry per day with commits.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// First day of the period, formatted as `YYYY-MM-DD`.
+    pub since: Option<String>,
+    /// Last day of the period, formatted as `YYYY-MM-DD`.
+    pub until: Option<String>,
+    pub commits: usize,
+    /// Number of distinct files changed over the period.
+    pub files_changed: usize,
+    pub words_added: usize,
+    pub words_removed: usize,
+    /// Days with commits, oldest first.
+    pub days: Vec<DayReport>,
+}
+
+#[derive(Default)]
+struct DayBuilder {
+    commits: usize,
+    words_added: usize,
+    words_removed: usize,
+    files: HashMap<PathBuf, usize>,
+}
+
+/// Accumulates commits into a [`Report`].
+#[derive(Default)]
+pub struct ReportBuilder {
+    days: BTreeMap<NaiveDate, DayBuilder>,
+}
+
+impl ReportBuilder {
+    /// Record a commit made at `time`, editing `paths`,
+    /// adding `words_added` words to Markdown files and removing `words_removed` from them.
+    pub fn record(
+        &mut self,
+        time: DateTime<Local>,
+        paths: &[PathBuf],
+        words_added: usize,
+        words_removed: usize,
+    ) {
+        let day = self.days.entry(time.date().naive_local()).or_default();
+        day.commits += 1;
+        day.words_added += words_added;
+        day.words_removed += words_removed;
+        for path in paths {
+            *day.files.entry(path.clone()).or_default() += 1;
+        }
+    }
+
+    pub fn build(self, since: Option<NaiveDate>, until: Option<NaiveDate>) -> Report {
+        let mut files = HashMap::<PathBuf, usize>::new();
+        let days = self
+            .days
+            .into_iter()
+            .map(|(date, day)| {
+                for path in day.files.keys() {
+                    *files.entry(path.clone()).or_default() += 1;
+                }
+                let mut day_files = day
+                    .files
+                    .into_iter()
+                    .map(|(path, edits)| FileActivity { path, edits })
+                    .collect::<Vec<_>>();
+                day_files.sort_by(|a, b| b.edits.cmp(&a.edits).then_with(|| a.path.cmp(&b.path)));
+                DayReport {
+                    date: date.format("%Y-%m-%d").to_string(),
+                    commits: day.commits,
+                    words_added: day.words_added,
+                    words_removed: day.words_removed,
+                    files: day_files,
+                    watcher: None,
+                }
+            })
+            .collect::<Vec<_>>();
+        Report {
+            since: since.map(|since| since.format("%Y-%m-%d").to_string()),
+            until: until.map(|until| until.format("%Y-%m-%d").to_string()),
+            commits: days.iter().map(|day| day.commits).sum(),
+            files_changed: files.len(),
+            words_added: days.iter().map(|day| day.words_added).sum(),
+            words_removed: days.iter().map(|day| day.words_removed).sum(),
+            days,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    config::{schema_error, ConfigFormat},
+    interpolate::expand_all,
+    schedule::parse_cron,
+    tls::TlsConfig,
+};
+
+/// Path of the fleet configuration file, listing the repositories `nabu watch --all` watches.
+pub const FLEET_CONFIG_PATH: &str = "/etc/nabu/config.toml";
+
+/// The repositories of a system-wide deployment, watched by a single `nabu watch --all`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    /// When running as root, watch each repository from a process running as the owner of its directory,
+    /// so that the commits and the files nabu writes belong to them and the hooks do not run as root.
+    #[serde(default)]
+    pub drop_privileges: bool,
+
+    /// The repositories, declared as `[[repositories]]` sections.
+    #[serde(default = "Vec::new")]
+    pub repositories: Vec<RepositoryConfig>,
+}
+
+/// A repository of the fleet, its other settings are read from its own configuration file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    /// The watched directory.
+    pub path: PathBuf,
+
+    /// URL of the "origin" remote, set before watching when it differs.
+    #[serde(default)]
+    pub remote: Option<String>,
+
+    /// Cron expression for the scheduled pushes, overriding `push_schedule`.
+    #[serde(default)]
+    pub schedule: Option<String>,
+
+    /// How to authenticate with the remote, the one given on the command line if not set.
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+
+    /// Configuration file of the repository, the one found for the directory if not set.
+    #[serde(default)]
+    pub config: Option<PathBuf>,
+}
+
+/// Authentication method of a repository, as the `--ssh-agent` and `--ssh-key` flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Use the ssh-agent.
+    #[serde(default)]
+    pub ssh_agent: bool,
+
+    /// Use the SSH key at this path, or the keys of a list, tried in order.
+    #[serde(
+        default,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub ssh_key: Vec<PathBuf>,
+
+    /// Passphrase of the SSH keys.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub ssh_passphrase: String,
+
+    /// TLS settings of the HTTPS remotes, as the `[auth.tls]` section.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+}
+
+impl FleetConfig {
+    /// Read the fleet configuration file, in any of the configuration formats.
+    pub fn from_path<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        log::info!(
+            "attempting to read the fleet config from {}",
+            path.display()
+        );
+        let bytes = std::fs::read(path)?;
+        let mut config = ConfigFormat::from_path(path).parse_value(&bytes)?;
+        expand_all(&mut config)?;
+        serde_json::from_value(config).map_err(|err| schema_error(err.to_string()))
+    }
+
+    /// Check that there are repositories, each listed once with an absolute path,
+    /// a valid schedule and a single authentication method.
+    pub fn validate(&self) -> Result<()> {
+        if self.repositories.is_empty() {
+            return Err(eyre!("the fleet configuration lists no repositories"));
+        }
+        if self.drop_privileges && !cfg!(unix) {
+            return Err(eyre!("drop_privileges is only supported on Unix"));
+        }
+        let mut paths = HashSet::new();
+        for repository in &self.repositories {
+            let path = &repository.path;
+            if !path.is_absolute() {
+                return Err(eyre!(
+                    "the repository path {} must be absolute",
+                    path.display()
+                ));
+            }
+            if !paths.insert(path) {
+                return Err(eyre!("the repository {} is listed twice", path.display()));
+            }
+            if let Some(schedule) = &repository.schedule {
+                parse_cron(schedule)?;
+            }
+            if let Some(auth) = &repository.auth {
+                if auth.ssh_agent && !auth.ssh_key.is_empty() {
+                    return Err(eyre!(
+                        "the repository {} sets both ssh_agent and ssh_key",
+                        path.display()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single value, or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+/// Deserialize a single value as a list of it.
+fn one_or_many<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
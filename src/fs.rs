@@ -3,23 +3,1080 @@ use std::{
     env::current_dir,
     ffi::OsStr,
     path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use walkdir::WalkDir;
+use crate::{
+    git_env::{open_repository, ConfigOverrides},
+    latency::elapsed_ms,
+    overrides::Overrides,
+    sparse::SparseCheckout,
+};
+use globset::{GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization as _;
+
+/// Name of the cache of watched directories, kept inside the repository's `.git` directory.
+pub const WATCHLIST_CACHE_FILE_NAME: &str = "nabu-watchlist.json";
+
+/// Directories modified this close to the walk may have changed again within their recorded time,
+/// given the precision of the modification times on some filesystems (e.g. FAT).
+const RACY_MODIFICATION_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Most threads walking the tree, listing directories is bound by the filesystem rather than the processors.
+const WALK_THREADS: usize = 8;
+
+/// Mount table listing the filesystem type of each mount point.
+const MOUNTS_PATH: &str = "/proc/self/mounts";
+
+/// Filesystems which report metadata changes on every read.
+const SPURIOUS_CHMOD_FILESYSTEMS: &[&str] = &["vfat", "msdos", "exfat", "fuseblk", "ntfs", "ntfs3"];
+
+/// Directory names of synchronization clients which touch file metadata.
+const SPURIOUS_CHMOD_FOLDERS: &[&str] = &["Dropbox", "CloudStorage"];
+
+/// Unicode normalization form applied to paths before staging them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeNormalization {
+    /// Follow the repository's `core.precomposeUnicode` setting.
+    #[default]
+    Auto,
+    /// Precomposed characters (e.g. `é`), as stored by git on macOS.
+    Nfc,
+    /// Decomposed characters (e.g. `e` followed by a combining acute accent).
+    Nfd,
+    /// Keep paths as reported by the filesystem.
+    None,
+}
+
+/// What the watcher does when the watched directory vanishes (e.g. its drive is unmounted).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingRoot {
+    /// Pause until it is back, then commit the changes made in the meantime.
+    #[default]
+    Wait,
+    /// Exit with an error, leaving it to the service manager.
+    Exit,
+}
+
+/// Normalize the path to the given form, paths which are not valid UTF-8 are kept as they are.
+pub fn normalize_path(path: &Path, form: UnicodeNormalization) -> PathBuf {
+    let normalized = match (path.to_str(), form) {
+        (Some(path), UnicodeNormalization::Nfc) => path.nfc().collect::<String>(),
+        (Some(path), UnicodeNormalization::Nfd) => path.nfd().collect::<String>(),
+        _ => return path.to_path_buf(),
+    };
+    PathBuf::from(normalized)
+}
+
+/// Rules for the directories not to watch.
+///
+/// Patterns without a slash (e.g. `build` or `*.cache`) match directory names anywhere in the tree,
+/// patterns with a slash (e.g. `docs/build` or `**/target`) match paths relative to the watched directory.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    names: GlobSet,
+    paths: GlobSet,
+    patterns: Vec<String>,
+    /// The index in `patterns` of each glob of `names` and `paths`.
+    name_patterns: Vec<usize>,
+    path_patterns: Vec<usize>,
+}
+
+impl IgnoreRules {
+    /// Build the rules from glob patterns.
+    pub fn new<S>(patterns: &[S]) -> Result<Self, globset::Error>
+    where
+        S: AsRef<str>,
+    {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        let mut name_patterns = vec![];
+        let mut path_patterns = vec![];
+        for (index, pattern) in patterns.iter().enumerate() {
+            let pattern = pattern.as_ref().trim_end_matches('/');
+            let glob = |pattern| GlobBuilder::new(pattern).literal_separator(true).build();
+            match pattern.strip_prefix('/') {
+                Some(anchored) => {
+                    paths.add(glob(anchored)?);
+                    path_patterns.push(index);
+                }
+                None if pattern.contains('/') => {
+                    paths.add(glob(pattern)?);
+                    path_patterns.push(index);
+                }
+                None => {
+                    names.add(glob(pattern)?);
+                    name_patterns.push(index);
+                }
+            };
+        }
+        Ok(Self {
+            names: names.build()?,
+            paths: paths.build()?,
+            patterns: patterns
+                .iter()
+                .map(|pattern| pattern.as_ref().to_string())
+                .collect(),
+            name_patterns,
+            path_patterns,
+        })
+    }
+
+    /// The glob patterns the rules were built from.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether the directory, relative to the watched directory, is ignored.
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        relative
+            .file_name()
+            .is_some_and(|name| self.names.is_match(name))
+            || self.paths.is_match(relative)
+    }
+
+    /// The first pattern ignoring the directory, relative to the watched directory, if any.
+    pub fn matching_pattern(&self, relative: &Path) -> Option<&str> {
+        let name = relative
+            .file_name()
+            .and_then(|name| self.names.matches(name).first().copied())
+            .map(|index| self.name_patterns[index]);
+        let path = self
+            .paths
+            .matches(relative)
+            .first()
+            .map(|index| self.path_patterns[*index]);
+        let index = match (name, path) {
+            (Some(name), Some(path)) => name.min(path),
+            (name, path) => name.or(path)?,
+        };
+        Some(&self.patterns[index])
+    }
+}
+
+/// Patterns of the paths committed even though git ignores them (`force_include`),
+/// with the syntax of [`IgnoreRules`] but relative to the repository's working directory.
+/// Everything under a matching directory is included.
+#[derive(Debug, Clone)]
+pub struct ForceInclude {
+    rules: IgnoreRules,
+    /// The components of the patterns with a slash, `None` for `**`,
+    /// telling which ignored directories lead to the included paths.
+    components: Vec<Vec<Option<GlobMatcher>>>,
+    /// Whether a pattern without a slash may match inside any directory.
+    anywhere: bool,
+}
+
+impl ForceInclude {
+    /// Build the patterns from globs.
+    pub fn new<S>(patterns: &[S]) -> Result<Self, globset::Error>
+    where
+        S: AsRef<str>,
+    {
+        let mut components = vec![];
+        let mut anywhere = false;
+        for pattern in patterns {
+            let pattern = pattern.as_ref().trim_end_matches('/');
+            if !pattern.contains('/') {
+                anywhere = true;
+                continue;
+            }
+            let globs = pattern
+                .trim_start_matches('/')
+                .split('/')
+                .map(|component| match component {
+                    "**" => Ok(None),
+                    component => GlobBuilder::new(component)
+                        .literal_separator(true)
+                        .build()
+                        .map(|glob| Some(glob.compile_matcher())),
+                })
+                .collect::<Result<_, _>>()?;
+            components.push(globs);
+        }
+        Ok(Self {
+            rules: IgnoreRules::new(patterns)?,
+            components,
+            anywhere,
+        })
+    }
+
+    /// Whether there are no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.rules.patterns().is_empty()
+    }
+
+    /// Whether the path, relative to the working directory, or one of its parents matches a pattern.
+    pub fn is_included(&self, relative: &Path) -> bool {
+        relative
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.rules.is_ignored(ancestor))
+    }
+
+    /// Whether the directory, relative to the working directory, may hold included paths,
+    /// i.e. whether an ignored directory is walked nonetheless.
+    pub fn may_contain(&self, relative: &Path) -> bool {
+        if self.anywhere || self.is_included(relative) {
+            return true;
+        }
+        let directory = relative.components().collect::<Vec<_>>();
+        self.components.iter().any(|pattern| {
+            for (index, glob) in pattern.iter().enumerate() {
+                match (glob, directory.get(index)) {
+                    (None, _) => return true,
+                    // The pattern goes deeper than the directory
+                    (Some(_), None) => return true,
+                    (Some(glob), Some(component)) if glob.is_match(component.as_os_str()) => {}
+                    (Some(_), Some(_)) => return false,
+                }
+            }
+            false
+        })
+    }
+}
+
+impl Default for ForceInclude {
+    fn default() -> Self {
+        Self::new::<&str>(&[]).expect("no patterns cannot be invalid")
+    }
+}
+
+/// The directories found by the last walk, along with when they were last modified.
+#[derive(Debug, Serialize, Deserialize)]
+struct WatchlistCache {
+    /// The ignore rules, overrides and symbolic links setting of the walk,
+    /// the cache is stale once they change.
+    settings: String,
+    /// When the walk started.
+    walked: SystemTime,
+    directories: Vec<(PathBuf, SystemTime)>,
+}
+
+/// Finds the directories to watch, in the background, caching them between runs.
+///
+/// With a cache, only the directories modified since the last walk are listed again,
+/// since adding, removing or renaming a subdirectory modifies its parent.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    root: PathBuf,
+    ignore: IgnoreRules,
+    overrides: Overrides,
+    follow_symlinks: bool,
+    force_include: ForceInclude,
+    cache: Option<PathBuf>,
+    /// The directories to watch instead of walking the tree, see [`Self::with_directories`].
+    listed: Option<Vec<PathBuf>>,
+    /// The repository's git directories, never watched whatever the configuration, see [`git_directories`].
+    git_dirs: Vec<PathBuf>,
+}
+
+impl Discovery {
+    /// Discover the subdirectories of `root`, see [`list_subdirs`].
+    pub fn new(root: PathBuf, ignore: IgnoreRules, follow_symlinks: bool) -> Self {
+        let git_dirs = open_repository(&root, true)
+            .map(|(repo, _)| git_directories(&repo))
+            .unwrap_or_default();
+        Self {
+            root,
+            ignore,
+            overrides: Overrides::default(),
+            follow_symlinks,
+            force_include: ForceInclude::default(),
+            cache: None,
+            listed: None,
+            git_dirs,
+        }
+    }
+
+    /// Apply the per-directory overrides (i.e. `watch` and `ignore`).
+    pub fn with_overrides(mut self, overrides: Overrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Walk the directories git ignores which may hold paths of `force_include`.
+    pub fn with_force_include(mut self, force_include: ForceInclude) -> Self {
+        self.force_include = force_include;
+        self
+    }
+
+    /// Keep the list of discovered directories in `cache`.
+    pub fn with_cache(mut self, cache: PathBuf) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Watch these directories rather than walking the tree, e.g. the output of `fd` or `find`.
+    /// Neither the ignore rules nor the overrides apply to them, the ones outside of the root
+    /// or inside of a git directory are left out.
+    pub fn with_directories(mut self, directories: Vec<PathBuf>) -> Self {
+        let mut seen = HashSet::new();
+        let listed = directories
+            .into_iter()
+            .filter_map(|directory| match directory.canonicalize() {
+                Ok(canonical) if canonical.is_dir() => Some(canonical),
+                Ok(_) => {
+                    log::warn!("skipping {}: not a directory", directory.display());
+                    None
+                }
+                Err(err) => {
+                    log::warn!("skipping {}: {}", directory.display(), err);
+                    None
+                }
+            })
+            .filter(|directory| match directory.strip_prefix(&self.root) {
+                Ok(_) => !self.is_git_internal(directory),
+                Err(_) => {
+                    log::warn!(
+                        "skipping {}: outside of {}",
+                        directory.display(),
+                        self.root.display()
+                    );
+                    false
+                }
+            })
+            .filter(|directory| seen.insert(directory.clone()))
+            .collect();
+        self.listed = Some(listed);
+        self
+    }
+
+    /// The directory being watched.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The git directory the watched directory is inside of, if any (e.g. `.git` was given to watch).
+    pub fn inside_git_dir(&self) -> Option<&Path> {
+        self.git_dirs
+            .iter()
+            .find(|git_dir| self.root.starts_with(git_dir))
+            .map(PathBuf::as_path)
+    }
+
+    /// Whether the path is part of git's own metadata, see [`is_git_internal`].
+    pub fn is_git_internal(&self, path: &Path) -> bool {
+        is_git_internal(&self.root, path, &self.git_dirs)
+    }
+
+    /// The directories discovered on the previous run which still exist.
+    pub fn cached(&self) -> Vec<PathBuf> {
+        if self.listed.is_some() {
+            return vec![];
+        }
+        self.read_cache()
+            .map(|cache| {
+                self.still_watched(cache.directories)
+                    .into_iter()
+                    .map(|(directory, _)| directory)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Settings the walk depends on, telling whether the cache is stale.
+    fn settings(&self) -> String {
+        format!(
+            "{:?} {:?} {} {:?}",
+            self.ignore.patterns(),
+            self.overrides.patterns(),
+            self.follow_symlinks,
+            self.force_include.rules.patterns()
+        )
+    }
+
+    /// Read the cache, `None` if there is none or it was written with different settings.
+    /// A corrupt cache is moved aside, to be rebuilt by the walk.
+    fn read_cache(&self) -> Option<WatchlistCache> {
+        let path = self.cache.as_ref()?;
+        let content = std::fs::read(path).ok()?;
+        let cache: WatchlistCache = match serde_json::from_slice(&content) {
+            Ok(cache) => cache,
+            Err(err) => {
+                match quarantine(path) {
+                    Ok(corrupt) => log::warn!(
+                        "the watch list cache is corrupt ({}), moved it to {} and walking the tree instead",
+                        err,
+                        corrupt.display()
+                    ),
+                    Err(quarantine_err) => log::warn!(
+                        "the watch list cache {} is corrupt ({}) and cannot be moved aside: {}",
+                        path.display(),
+                        err,
+                        quarantine_err
+                    ),
+                }
+                return None;
+            }
+        };
+        (cache.settings == self.settings()).then_some(cache)
+    }
+
+    /// Write the directories to the cache, along with their modification times.
+    fn write_cache(&self, walked: SystemTime, directories: Vec<PathBuf>) {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return,
+        };
+        let directories = directories
+            .into_iter()
+            .filter_map(|directory| {
+                let modified = std::fs::metadata(&directory)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()?;
+                Some((directory, modified))
+            })
+            .collect();
+        let watchlist = WatchlistCache {
+            settings: self.settings(),
+            walked,
+            directories,
+        };
+        let result = serde_json::to_vec(&watchlist)
+            .map_err(std::io::Error::from)
+            .and_then(|watchlist| write_atomically(cache, &watchlist));
+        if let Err(err) = result {
+            log::warn!("failed to write {}: {}", cache.display(), err);
+        }
+    }
+
+    /// The cached directories which still exist and are neither ignored nor left out.
+    fn still_watched(&self, directories: Vec<(PathBuf, SystemTime)>) -> Vec<(PathBuf, SystemTime)> {
+        // The ignore files and the sparse checkout may have changed since
+        let (repo, _overrides) = open_repository(&self.root, true).ok().unzip();
+        let sparse = repo.as_ref().and_then(SparseCheckout::read);
+        directories
+            .into_iter()
+            .filter(|(directory, _)| directory.starts_with(&self.root) && directory.is_dir())
+            .filter(|(directory, _)| !self.is_git_internal(directory))
+            .filter(|(directory, _)| match directory.strip_prefix(&self.root) {
+                Ok(relative) => {
+                    !self.ignore.is_ignored(relative) && !self.overrides.is_ignored(relative)
+                }
+                Err(_) => false,
+            })
+            .filter(|(directory, _)| {
+                !repo
+                    .as_ref()
+                    .is_some_and(|repo| is_git_ignored(repo, directory, &self.force_include))
+            })
+            .filter(|(directory, _)| is_checked_out(repo.as_ref(), sparse.as_ref(), directory))
+            .collect()
+    }
+
+    /// Walk the directory tree in a separate thread, sending each directory as it is found,
+    /// or only the new ones when the cache is up to date, see [`Self::cached`].
+    /// The cache is updated once the walk is over.
+    /// The listed directories are sent as they are, see [`Self::with_directories`].
+    pub fn spawn(&self) -> Receiver<PathBuf> {
+        let (directory_snd, directory_rcv) = channel();
+        let discovery = self.clone();
+        thread::spawn(move || {
+            if let Some(listed) = discovery.listed {
+                for directory in listed {
+                    if directory_snd.send(directory).is_err() {
+                        return;
+                    }
+                }
+                return;
+            }
+            let walked = SystemTime::now();
+            let started = Instant::now();
+            let directories = match discovery.read_cache() {
+                Some(cache) => discovery.verify(cache, &directory_snd),
+                None => discovery.walk(&discovery.root, true, &directory_snd),
+            };
+            // `None` once the watcher stopped
+            if let Some(directories) = directories {
+                log::debug!(
+                    "discovered {} directories in {}ms",
+                    directories.len(),
+                    elapsed_ms(started)
+                );
+                discovery.write_cache(walked, directories);
+            }
+        });
+        directory_rcv
+    }
+
+    /// The directory and its subdirectories, e.g. once a directory which could not be read can be.
+    /// Only the listed ones, if the directories were listed.
+    pub fn subdirs(&self, start: &Path) -> Vec<PathBuf> {
+        if let Some(listed) = &self.listed {
+            return listed
+                .iter()
+                .filter(|directory| directory.starts_with(start))
+                .cloned()
+                .collect();
+        }
+        walk_subdirs(
+            &self.root,
+            start,
+            self.ignore.clone(),
+            self.overrides.clone(),
+            self.follow_symlinks,
+            self.force_include.clone(),
+            false,
+        )
+        .collect()
+    }
+
+    /// Walk the tree from `start`, sending each directory as it is found,
+    /// in `parallel` for the full walks only.
+    /// Returns the directories, `None` if the receiver is gone.
+    fn walk(
+        &self,
+        start: &Path,
+        parallel: bool,
+        directory_snd: &Sender<PathBuf>,
+    ) -> Option<Vec<PathBuf>> {
+        let walk = walk_subdirs(
+            &self.root,
+            start,
+            self.ignore.clone(),
+            self.overrides.clone(),
+            self.follow_symlinks,
+            self.force_include.clone(),
+            parallel,
+        );
+        let mut directories = vec![];
+        for directory in walk {
+            directory_snd.send(directory.clone()).ok()?;
+            directories.push(directory);
+        }
+        Some(directories)
+    }
+
+    /// Check the cached directories against their modification times,
+    /// listing the modified ones again and walking their new subdirectories.
+    /// Returns all the directories, `None` if the receiver is gone.
+    fn verify(
+        &self,
+        cache: WatchlistCache,
+        directory_snd: &Sender<PathBuf>,
+    ) -> Option<Vec<PathBuf>> {
+        let known = cache
+            .directories
+            .iter()
+            .map(|(directory, _)| directory.clone())
+            .collect::<HashSet<_>>();
+        let mut directories = HashSet::new();
+        let mut modified_directories = 0;
+        for (directory, cached_modified) in self.still_watched(cache.directories) {
+            let modified =
+                match std::fs::metadata(&directory).and_then(|metadata| metadata.modified()) {
+                    Ok(modified) => modified,
+                    Err(_) => continue,
+                };
+            let racy = cached_modified + RACY_MODIFICATION_INTERVAL >= cache.walked;
+            if modified == cached_modified && !racy {
+                directories.insert(directory);
+                continue;
+            }
+            modified_directories += 1;
+            for subdirectory in subdirectories(&directory, self.follow_symlinks) {
+                if !known.contains(&subdirectory) {
+                    directories.extend(self.walk(&subdirectory, false, directory_snd)?);
+                }
+            }
+            directories.insert(directory);
+        }
+        log::debug!(
+            "verified {} cached directories, {} were modified",
+            known.len(),
+            modified_directories
+        );
+        Some(directories.into_iter().collect())
+    }
+}
 
-pub fn list_subdirs<P>(directory: P, ignored: HashSet<&OsStr>) -> Vec<PathBuf>
+/// The subdirectories directly inside the directory,
+/// including the symbolic links to directories if they are followed.
+fn subdirectories(directory: &Path, follow_symlinks: bool) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::warn!("cannot list {}: {}", directory.display(), err);
+            return vec![];
+        }
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => follow_symlinks && entry.path().is_dir(),
+            Ok(file_type) => file_type.is_dir(),
+            Err(_) => false,
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// List the directory and its subdirectories, skipping the `ignored` ones.
+///
+/// Symbolic links are only followed if `follow_symlinks` is set,
+/// in which case loops and links resolving outside of `directory` are skipped.
+pub fn list_subdirs<P>(
+    directory: P,
+    ignore: IgnoreRules,
+    overrides: Overrides,
+    follow_symlinks: bool,
+) -> Vec<PathBuf>
 where
     P: AsRef<Path>,
 {
-    WalkDir::new(directory)
-        .into_iter()
-        .filter_entry(|entry| entry.file_type().is_dir() && !ignored.contains(entry.file_name()))
-        .filter_map(|r| r.ok())
-        // TODO: handle the error gracefully
-        .map(|entry| entry.into_path().canonicalize().unwrap())
-        .collect()
+    let directory = match directory.as_ref().canonicalize() {
+        Ok(directory) => directory,
+        Err(err) => {
+            log::error!("cannot resolve {}: {}", directory.as_ref().display(), err);
+            return vec![];
+        }
+    };
+    let started = Instant::now();
+    let directories = walk_subdirs(
+        &directory,
+        &directory,
+        ignore,
+        overrides,
+        follow_symlinks,
+        ForceInclude::default(),
+        true,
+    )
+    .collect::<Vec<_>>();
+    log::debug!(
+        "listed {} directories of {} in {}ms",
+        directories.len(),
+        directory.display(),
+        elapsed_ms(started)
+    );
+    directories
+}
+
+/// Lazy version of [`list_subdirs`], walking from `start` inside of the canonical `root`.
+///
+/// The tree is walked by a thread, or by several ones in `parallel` (for the full walks),
+/// the directories are yielded as they are found, in no particular order.
+fn walk_subdirs(
+    root: &Path,
+    start: &Path,
+    ignore: IgnoreRules,
+    overrides: Overrides,
+    follow_symlinks: bool,
+    force_include: ForceInclude,
+    parallel: bool,
+) -> Box<dyn Iterator<Item = PathBuf>> {
+    let (directory_snd, directory_rcv) = channel();
+    if !start.is_dir() {
+        return Box::new(directory_rcv.into_iter());
+    }
+    let mut opened = open_repository(root, true).ok();
+    let sparse = opened
+        .as_ref()
+        .and_then(|(repo, _)| SparseCheckout::read(repo));
+    // Never watched, whatever the ignore rules
+    let git_dirs = opened
+        .as_ref()
+        .map(|(repo, _)| git_directories(repo))
+        .unwrap_or_default();
+    // Walking from a canonical root without following links only yields canonical paths,
+    // only the start and the links need to be resolved otherwise
+    let canonical = if follow_symlinks {
+        start.canonicalize().unwrap_or_else(|_| start.to_path_buf())
+    } else {
+        start.to_path_buf()
+    };
+    let walk = Arc::new(Walk {
+        root: root.to_path_buf(),
+        ignore,
+        overrides,
+        force_include,
+        follow_symlinks,
+        sparse,
+        git_dirs,
+        seen: Mutex::new(HashSet::new()),
+        queue: Mutex::new(WalkQueue {
+            pending: vec![WalkEntry {
+                path: start.to_path_buf(),
+                canonical,
+                is_symlink: false,
+            }],
+            listing: 0,
+            stopped: false,
+        }),
+        changed: Condvar::new(),
+    });
+    let threads = match parallel {
+        true => thread::available_parallelism()
+            .map_or(1, usize::from)
+            .min(WALK_THREADS),
+        false => 1,
+    };
+    // The first thread reuses the repository, the others open their own
+    for _ in 0..threads {
+        let walk = walk.clone();
+        let directory_snd = directory_snd.clone();
+        let opened = opened.take();
+        thread::spawn(move || walk.run(opened, &directory_snd));
+    }
+    Box::new(directory_rcv.into_iter())
+}
+
+/// A directory found by a walk of the tree, not yet checked against the ignore rules.
+struct WalkEntry {
+    /// The path it was found at, which the ignore rules are checked against.
+    path: PathBuf,
+    /// Its canonical path, only resolved for the symbolic links when they are followed.
+    canonical: PathBuf,
+    is_symlink: bool,
+}
+
+/// The directories left to list by the threads walking the tree.
+struct WalkQueue {
+    pending: Vec<WalkEntry>,
+    /// Number of directories being listed, whose subdirectories are not pending yet.
+    listing: usize,
+    /// Whether the receiver of the directories is gone.
+    stopped: bool,
+}
+
+/// A walk of the tree shared by its threads, see [`walk_subdirs`].
+struct Walk {
+    root: PathBuf,
+    ignore: IgnoreRules,
+    overrides: Overrides,
+    force_include: ForceInclude,
+    follow_symlinks: bool,
+    sparse: Option<SparseCheckout>,
+    git_dirs: Vec<PathBuf>,
+    /// The canonical directories found so far, several links may point to the same directory.
+    seen: Mutex<HashSet<PathBuf>>,
+    queue: Mutex<WalkQueue>,
+    /// Notified when directories are pending, or once the walk is over.
+    changed: Condvar,
+}
+
+/// A directory being listed by a walking thread, see [`WalkQueue::listing`].
+///
+/// Its subdirectories are queued once it is dropped, even if the thread panicked listing it,
+/// for the other threads not to wait for them forever.
+struct Listing<'a> {
+    walk: &'a Walk,
+    subdirectories: Vec<WalkEntry>,
+}
+
+impl Drop for Listing<'_> {
+    fn drop(&mut self) {
+        let mut queue = self.walk.queue();
+        queue.listing -= 1;
+        queue.pending.append(&mut self.subdirectories);
+        self.walk.changed.notify_all();
+    }
+}
+
+impl Walk {
+    /// List the pending directories until there are none left, sending the ones to watch.
+    ///
+    /// The thread opens its own repository unless it is `opened`, a repository cannot be shared between threads.
+    fn run(
+        &self,
+        opened: Option<(git2::Repository, Option<ConfigOverrides>)>,
+        directory_snd: &Sender<PathBuf>,
+    ) {
+        // Directories ignored by git (e.g. `node_modules`) hold nothing to commit
+        let opened = opened.or_else(|| open_repository(&self.root, true).ok());
+        let repo = opened.as_ref().map(|(repo, _)| repo);
+        while let Some(entry) = self.next() {
+            let mut listing = Listing {
+                walk: self,
+                subdirectories: vec![],
+            };
+            match self.accept(repo, entry) {
+                Some(directory) if directory_snd.send(directory.canonical.clone()).is_ok() => {
+                    listing.subdirectories = self.subdirectories(&directory);
+                }
+                Some(_) => self.queue().stopped = true,
+                None => {}
+            }
+        }
+    }
+
+    /// The queue, even if a thread panicked holding it.
+    fn queue(&self) -> MutexGuard<'_, WalkQueue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The next directory to list, `None` once the walk is over.
+    fn next(&self) -> Option<WalkEntry> {
+        let mut queue = self.queue();
+        loop {
+            if queue.stopped {
+                return None;
+            }
+            if let Some(entry) = queue.pending.pop() {
+                queue.listing += 1;
+                return Some(entry);
+            }
+            // The directories being listed may still hold subdirectories
+            if queue.listing == 0 {
+                return None;
+            }
+            queue = self
+                .changed
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// The directory with its canonical path resolved, if it is to be watched.
+    fn accept(&self, repo: Option<&git2::Repository>, mut entry: WalkEntry) -> Option<WalkEntry> {
+        let path = &entry.path;
+        if is_git_internal(&self.root, path, &self.git_dirs)
+            || path.strip_prefix(&self.root).is_ok_and(|relative| {
+                self.ignore.is_ignored(relative) || self.overrides.is_ignored(relative)
+            })
+            || repo.is_some_and(|repo| is_git_ignored(repo, path, &self.force_include))
+            || !is_checked_out(repo, self.sparse.as_ref(), path)
+        {
+            return None;
+        }
+        if entry.is_symlink {
+            let target = resolve_inside(path, &self.root)?;
+            if entry
+                .canonical
+                .parent()
+                .is_some_and(|parent| parent.starts_with(&target))
+            {
+                log::warn!(
+                    "skipping {}, it links to its ancestor {}",
+                    path.display(),
+                    target.display()
+                );
+                return None;
+            }
+            entry.canonical = target;
+        }
+        if self.follow_symlinks
+            && !self
+                .seen
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(entry.canonical.clone())
+        {
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// The subdirectories directly inside the directory, see [`subdirectories`].
+    fn subdirectories(&self, directory: &WalkEntry) -> Vec<WalkEntry> {
+        let entries = match std::fs::read_dir(&directory.path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::warn!("skipping directory {}: {}", directory.path.display(), err);
+                return vec![];
+            }
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let is_symlink = match entry.file_type() {
+                    Ok(file_type) if file_type.is_symlink() => {
+                        if !self.follow_symlinks || !entry.path().is_dir() {
+                            return None;
+                        }
+                        true
+                    }
+                    Ok(file_type) if file_type.is_dir() => false,
+                    _ => return None,
+                };
+                Some(WalkEntry {
+                    path: entry.path(),
+                    canonical: directory.canonical.join(entry.file_name()),
+                    is_symlink,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The repository's git directories as libgit2 resolves them, canonical:
+/// its own and, for a linked worktree, the main repository's one holding the objects.
+pub fn git_directories(repo: &git2::Repository) -> Vec<PathBuf> {
+    // The linked worktrees name the main repository's git directory in `commondir`
+    let common = std::fs::read_to_string(repo.path().join("commondir"))
+        .ok()
+        .map(|common| repo.path().join(common.trim()));
+    let mut git_dirs = vec![];
+    for git_dir in std::iter::once(repo.path()).chain(common.as_deref()) {
+        let git_dir = git_dir
+            .canonicalize()
+            .unwrap_or_else(|_| git_dir.to_path_buf());
+        if !git_dirs.contains(&git_dir) {
+            git_dirs.push(git_dir);
+        }
+    }
+    git_dirs
+}
+
+/// Whether the path is part of git's own metadata: inside one of the `git_dirs`,
+/// or of any `.git` directory under `root` (e.g. of a nested repository).
+pub fn is_git_internal(root: &Path, path: &Path, git_dirs: &[PathBuf]) -> bool {
+    git_dirs.iter().any(|git_dir| path.starts_with(git_dir))
+        || path.strip_prefix(root).is_ok_and(|relative| {
+            relative
+                .components()
+                .any(|component| component.as_os_str() == OsStr::new(".git"))
+        })
+}
+
+/// Whether the path is ignored by `.gitignore`, `.git/info/exclude` or `core.excludesFile`,
+/// and may not hold paths of `force_include`.
+fn is_git_ignored(repo: &git2::Repository, path: &Path, force_include: &ForceInclude) -> bool {
+    let relative = match repo
+        .workdir()
+        .and_then(|workdir| path.strip_prefix(workdir).ok())
+    {
+        // The root of the repository cannot be ignored
+        Some(relative) if relative.as_os_str().is_empty() => return false,
+        Some(relative) => relative,
+        None => return false,
+    };
+    repo.is_path_ignored(relative).unwrap_or(false) && !force_include.may_contain(relative)
+}
+
+/// Whether the directory holds part of the repository's sparse checkout,
+/// always the case without a sparse checkout.
+fn is_checked_out(
+    repo: Option<&git2::Repository>,
+    sparse: Option<&SparseCheckout>,
+    directory: &Path,
+) -> bool {
+    let (repo, sparse) = match (repo, sparse) {
+        (Some(repo), Some(sparse)) => (repo, sparse),
+        _ => return true,
+    };
+    match repo
+        .workdir()
+        .and_then(|workdir| directory.strip_prefix(workdir).ok())
+    {
+        Some(relative) => sparse.contains_dir(relative),
+        None => true,
+    }
+}
+
+/// The path the symbolic link resolves to, if it is inside of `root`.
+fn resolve_inside(link: &Path, root: &Path) -> Option<PathBuf> {
+    match link.canonicalize() {
+        Ok(path) if path.starts_with(root) => Some(path),
+        Ok(path) => {
+            log::warn!(
+                "skipping {}, it resolves to {} which is outside of {}",
+                link.display(),
+                path.display(),
+                root.display()
+            );
+            None
+        }
+        Err(err) => {
+            log::warn!("skipping {}: {}", link.display(), err);
+            None
+        }
+    }
+}
+
+/// Write `contents` to `path` through a temporary file renamed over it,
+/// so that a crash leaves either the previous file or the new one, never a partial one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = std::fs::File::create(&temporary)?;
+    std::io::Write::write_all(&mut file, contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Read the JSON file at `path`, the default value if there is none or it cannot be parsed.
+pub fn read_json<T>(path: &Path) -> T
+where
+    T: DeserializeOwned + Default,
+{
+    std::fs::read(path)
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Write the value to `path` as pretty JSON, replacing it atomically, see [`write_atomically`].
+pub fn write_json<T>(path: &Path, value: &T) -> std::io::Result<()>
+where
+    T: Serialize,
+{
+    write_atomically(path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// Move a corrupt file aside, next to it with the `.corrupt` suffix (`.corrupt.<n>` if an earlier one is there),
+/// so that it is rebuilt while being kept for inspection; returns where it was moved.
+pub fn quarantine(path: &Path) -> std::io::Result<PathBuf> {
+    let corrupt = (0..)
+        .map(|n| {
+            let mut corrupt = path.as_os_str().to_os_string();
+            corrupt.push(".corrupt");
+            if n > 0 {
+                corrupt.push(format!(".{}", n));
+            }
+            PathBuf::from(corrupt)
+        })
+        .find(|corrupt| !corrupt.exists())
+        .expect("there is always a free name");
+    std::fs::rename(path, &corrupt)?;
+    Ok(corrupt)
 }
 
 pub fn current_dir_string() -> String {
     String::from(current_dir().unwrap().to_str().unwrap())
 }
+
+/// Whether the directory lives on a filesystem, or in a synchronized folder,
+/// known to produce chmod events which do not correspond to actual changes.
+pub fn reports_spurious_chmod<P>(directory: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    let directory = directory.as_ref();
+    let synchronized = directory.components().any(|component| {
+        SPURIOUS_CHMOD_FOLDERS
+            .iter()
+            .any(|folder| component.as_os_str() == OsStr::new(folder))
+    });
+    synchronized
+        || filesystem_type(directory)
+            .is_some_and(|filesystem| SPURIOUS_CHMOD_FILESYSTEMS.contains(&filesystem.as_str()))
+}
+
+/// Type of the filesystem holding the directory, from the mount point closest to it.
+fn filesystem_type(directory: &Path) -> Option<String> {
+    let mounts = std::fs::read_to_string(MOUNTS_PATH).ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?;
+            let filesystem = fields.next()?;
+            // Spaces in mount points are escaped as `\040`
+            let mount_point = PathBuf::from(mount_point.replace("\\040", " "));
+            directory
+                .starts_with(&mount_point)
+                .then(|| (mount_point, filesystem.to_string()))
+        })
+        .max_by_key(|(mount_point, _)| mount_point.components().count())
+        .map(|(_, filesystem)| filesystem)
+}
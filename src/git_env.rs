@@ -0,0 +1,300 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Once,
+    },
+};
+
+use git2::ConfigLevel;
+
+use crate::state::process_exists;
+
+/// File standing for "no file", e.g. `GIT_CONFIG_GLOBAL=/dev/null`.
+#[cfg(unix)]
+const NULL_DEVICE: &str = "/dev/null";
+#[cfg(not(unix))]
+const NULL_DEVICE: &str = "nul";
+
+/// Distinguishes the configuration overrides files of a process.
+static OVERRIDES_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Start of the name of the configuration overrides files, followed by `<pid>-<n>`.
+const OVERRIDES_FILE_PREFIX: &str = "nabu-git-config-";
+
+/// Open the repository at the path, or containing it if `search` is set, honoring git's environment variables
+/// the way the git CLI does: `GIT_DIR` takes precedence over the path, `GIT_WORK_TREE` over the working directory
+/// (the current directory when only `GIT_DIR` is set), `GIT_CEILING_DIRECTORIES` stops the search,
+/// and the `GIT_CONFIG_*` variables are applied to the repository's configuration, see [`configure`].
+///
+/// The configuration set with `GIT_CONFIG_COUNT` or `GIT_CONFIG_PARAMETERS` is read from the returned file,
+/// which must be kept along with the repository.
+pub fn open_repository(
+    path: &Path,
+    search: bool,
+) -> Result<(git2::Repository, Option<ConfigOverrides>), git2::Error> {
+    set_search_paths();
+    let git_dir = env::var_os("GIT_DIR").filter(|git_dir| !git_dir.is_empty());
+    let repo = match &git_dir {
+        // Relative to the current directory, as for git
+        Some(git_dir) => git2::Repository::open(git_dir)?,
+        None if search => git2::Repository::open_ext(
+            path,
+            git2::RepositoryOpenFlags::empty(),
+            env::var_os("GIT_CEILING_DIRECTORIES")
+                .map(|directories| env::split_paths(&directories).collect::<Vec<_>>())
+                .unwrap_or_default(),
+        )?,
+        None => git2::Repository::open(path)?,
+    };
+    // libgit2 does not implement `GIT_WORK_TREE`
+    if let Some(work_tree) = env::var_os("GIT_WORK_TREE").filter(|work_tree| !work_tree.is_empty())
+    {
+        repo.set_workdir(Path::new(&work_tree), false)?;
+    } else if git_dir.is_some()
+        && !repo.is_bare()
+        && repo.config()?.get_path("core.worktree").is_err()
+    {
+        let current_dir = env::current_dir().map_err(|err| {
+            git2::Error::from_str(&format!("cannot read the current directory: {}", err))
+        })?;
+        repo.set_workdir(&current_dir, false)?;
+    }
+    let overrides = configure(&repo)?;
+    Ok((repo, overrides))
+}
+
+/// Apply the `GIT_CONFIG_*` variables to the configuration of the repository, e.g. one just initialized.
+pub fn configure(repo: &git2::Repository) -> Result<Option<ConfigOverrides>, git2::Error> {
+    // The repository's configuration is shared by every `config()` call, so the files added stay
+    let mut config = repo.config()?;
+    for (variable, level) in [
+        ("GIT_CONFIG_SYSTEM", ConfigLevel::System),
+        ("GIT_CONFIG_GLOBAL", ConfigLevel::Global),
+    ] {
+        if level == ConfigLevel::System && no_system() {
+            continue;
+        }
+        if let Some(path) = env::var_os(variable).filter(|path| !is_null_file(path)) {
+            config.add_file(Path::new(&path), level, true)?;
+        }
+    }
+    let overrides = ConfigOverrides::from_env(repo.path())?;
+    if let Some(overrides) = &overrides {
+        config.add_file(&overrides.0, ConfigLevel::App, true)?;
+    }
+    Ok(overrides)
+}
+
+/// Replace the files libgit2 looks for the system and global configurations in,
+/// when `GIT_CONFIG_NOSYSTEM`, `GIT_CONFIG_SYSTEM` or `GIT_CONFIG_GLOBAL` replace them, see [`configure`].
+fn set_search_paths() {
+    static SEARCH_PATHS: Once = Once::new();
+    SEARCH_PATHS.call_once(|| {
+        let mut levels = vec![];
+        if no_system() || env::var_os("GIT_CONFIG_SYSTEM").is_some() {
+            levels.extend([ConfigLevel::System, ConfigLevel::ProgramData]);
+        }
+        if env::var_os("GIT_CONFIG_GLOBAL").is_some() {
+            levels.extend([ConfigLevel::Global, ConfigLevel::XDG]);
+        }
+        for level in levels {
+            // An empty search path finds no file; no repository is open yet to be affected by the change
+            if let Err(err) = unsafe { git2::opts::set_search_path(level, "") } {
+                log::warn!("failed to set the git configuration search path: {}", err);
+            }
+        }
+    });
+}
+
+/// The configuration set by `GIT_CONFIG_COUNT` (along with `GIT_CONFIG_KEY_<n>` and `GIT_CONFIG_VALUE_<n>`)
+/// and `GIT_CONFIG_PARAMETERS` (`git -c`), written to a private file in the git directory libgit2 can read,
+/// removed when dropped (or by a later process, should this one crash).
+#[derive(Debug)]
+pub struct ConfigOverrides(PathBuf);
+
+impl ConfigOverrides {
+    /// Write the configuration set by the environment to a new file in `git_dir`, `None` if it sets none.
+    fn from_env(git_dir: &Path) -> Result<Option<Self>, git2::Error> {
+        let mut entries = match env::var("GIT_CONFIG_PARAMETERS") {
+            Ok(parameters) => parse_parameters(&parameters)?,
+            Err(_) => vec![],
+        };
+        entries.extend(count_entries()?);
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        remove_stale_overrides(git_dir);
+        let error = |err: io::Error| {
+            git2::Error::from_str(&format!(
+                "cannot write the git configuration set by the environment: {}",
+                err
+            ))
+        };
+        let mut options = fs::OpenOptions::new();
+        // Never through a file (or a symlink) already there, the values may hold credentials (e.g. `http.extraHeader`)
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let (overrides, mut file) = loop {
+            let path = git_dir.join(format!(
+                "{}{}-{}",
+                OVERRIDES_FILE_PREFIX,
+                std::process::id(),
+                OVERRIDES_FILES.fetch_add(1, Ordering::Relaxed)
+            ));
+            match options.open(&path) {
+                Ok(file) => break (Self(path), file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(error(err)),
+            }
+        };
+        file.write_all(config_file(&entries).as_bytes())
+            .map_err(error)?;
+        Ok(Some(overrides))
+    }
+}
+
+/// Remove the configuration overrides files left in `git_dir` by the processes which are gone.
+fn remove_stale_overrides(git_dir: &Path) {
+    let entries = match fs::read_dir(git_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let pid = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(OVERRIDES_FILE_PREFIX))
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(pid, _)| pid.parse::<u32>().ok());
+        if pid.is_some_and(|pid| !process_exists(pid)) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+impl Drop for ConfigOverrides {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The entries of `GIT_CONFIG_KEY_<n>` and `GIT_CONFIG_VALUE_<n>`, for `n` up to `GIT_CONFIG_COUNT`.
+fn count_entries() -> Result<Vec<(String, Option<String>)>, git2::Error> {
+    let count = match env::var("GIT_CONFIG_COUNT") {
+        Ok(count) if !count.is_empty() => count
+            .parse::<usize>()
+            .map_err(|_| git2::Error::from_str(&format!("bogus GIT_CONFIG_COUNT: {}", count)))?,
+        _ => return Ok(vec![]),
+    };
+    (0..count)
+        .map(|index| {
+            let key = env::var(format!("GIT_CONFIG_KEY_{}", index)).map_err(|_| {
+                git2::Error::from_str(&format!("missing config key GIT_CONFIG_KEY_{}", index))
+            })?;
+            let value = env::var(format!("GIT_CONFIG_VALUE_{}", index)).map_err(|_| {
+                git2::Error::from_str(&format!("missing config value GIT_CONFIG_VALUE_{}", index))
+            })?;
+            Ok((key, Some(value)))
+        })
+        .collect()
+}
+
+/// Parse `GIT_CONFIG_PARAMETERS`, the shell-quoted `'key'='value'` (or older `'key=value'`) entries
+/// `git -c` passes down; a key without a value is a boolean set to true.
+fn parse_parameters(parameters: &str) -> Result<Vec<(String, Option<String>)>, git2::Error> {
+    let bogus = || git2::Error::from_str("bogus format in GIT_CONFIG_PARAMETERS");
+    let mut characters = parameters.chars().peekable();
+    let mut entries = vec![];
+    loop {
+        while characters.next_if(|c| c.is_whitespace()).is_some() {}
+        if characters.peek().is_none() {
+            return Ok(entries);
+        }
+        let key = dequote(&mut characters).ok_or_else(bogus)?;
+        if characters.next_if_eq(&'=').is_some() {
+            let value = dequote(&mut characters).ok_or_else(bogus)?;
+            entries.push((key, Some(value)));
+        } else {
+            match key.split_once('=') {
+                Some((key, value)) => entries.push((key.to_string(), Some(value.to_string()))),
+                None => entries.push((key, None)),
+            }
+        }
+    }
+}
+
+/// Read a single-quoted word, where `'\''` stands for a quote (e.g. `'it'\''s'`), as git's `sq_dequote`.
+fn dequote(characters: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    if characters.next()? != '\'' {
+        return None;
+    }
+    let mut word = String::new();
+    loop {
+        match characters.next()? {
+            '\'' => {
+                // Either the end of the word or an escaped character between two quoted parts
+                if characters.next_if_eq(&'\\').is_none() {
+                    return Some(word);
+                }
+                word.push(characters.next()?);
+                if characters.next()? != '\'' {
+                    return None;
+                }
+            }
+            c => word.push(c),
+        }
+    }
+}
+
+/// The entries in git's configuration file syntax, e.g. `url.<base>.insteadOf` as `[url "<base>"]`.
+fn config_file(entries: &[(String, Option<String>)]) -> String {
+    let mut content = String::new();
+    for (key, value) in entries {
+        let (section, name) = match key.rsplit_once('.') {
+            Some(split) => split,
+            None => continue,
+        };
+        match section.split_once('.') {
+            Some((section, subsection)) => content.push_str(&format!(
+                "[{} \"{}\"]\n",
+                section,
+                subsection.replace('\\', "\\\\").replace('"', "\\\"")
+            )),
+            None => content.push_str(&format!("[{}]\n", section)),
+        }
+        match value {
+            Some(value) => content.push_str(&format!(
+                "\t{} = \"{}\"\n",
+                name,
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\t', "\\t")
+            )),
+            None => content.push_str(&format!("\t{}\n", name)),
+        }
+    }
+    content
+}
+
+fn is_null_file(path: &OsString) -> bool {
+    path.is_empty() || path == NULL_DEVICE
+}
+
+/// Whether `GIT_CONFIG_NOSYSTEM` is set to true, skipping the system configuration.
+fn no_system() -> bool {
+    env::var("GIT_CONFIG_NOSYSTEM").is_ok_and(|value| {
+        matches!(
+            value.to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
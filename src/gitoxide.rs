@@ -0,0 +1,443 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local};
+use gix::{
+    bstr::{BStr, BString},
+    date::parse::TimeBuf,
+    index::entry::{Flags, Mode, Stage, Stat},
+    refs::{
+        transaction::{Change, LogChange, PreviousValue, RefEdit, RefLog},
+        Target,
+    },
+};
+
+use crate::{
+    conventions::ConventionsConfig,
+    git::{
+        AuthenticationMethod, ConflictStrategy, FastForwardOutcome, PushSettings, RebaseOutcome,
+        Repository, Result, WatchedRepository,
+    },
+    message::{PathRedaction, Timezone},
+    notes::CommitMetadata,
+    overrides::Overrides,
+    secrets::Secret,
+};
+
+/// Repository staging and committing with gitoxide.
+///
+/// The backend is partial: only staging, committing and amending go through gitoxide.
+/// gitoxide cannot push yet, so pushing, fetching, rebasing, reading the status and everything else
+/// go through the libgit2 repository it falls back to,
+/// as does staging files with clean filters or line ending conversions.
+pub struct GitoxideRepository {
+    repo: gix::Repository,
+    fallback: WatchedRepository,
+}
+
+impl GitoxideRepository {
+    /// Open the repository of `fallback` with gitoxide, honoring git's environment variables as libgit2 does.
+    pub fn new(fallback: WatchedRepository) -> Result<Self> {
+        let repo = gix::open_with_environment_overrides(fallback.git_dir()).map_err(error)?;
+        Ok(Self { repo, fallback })
+    }
+
+    fn workdir(&self) -> Result<&Path> {
+        self.repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("the repository has no working directory"))
+    }
+
+    /// Whether the file at the path, relative to the working directory, goes through filters
+    /// (clean filters or line ending conversions) gitoxide does not apply, so libgit2 stages it.
+    /// Removals are not filtered.
+    fn needs_filters(&self, path: &Path) -> Result<bool> {
+        Ok(self.workdir()?.join(path).exists()
+            && (self.fallback.has_clean_filter(path) || self.fallback.converts_line_endings(path)))
+    }
+
+    /// Update the index entry of a path, relative to the working directory,
+    /// to the file's current content, removing it if the file is gone.
+    fn update_entry(&self, index: &mut gix::index::File, path: &Path) -> Result<()> {
+        let full_path = self.workdir()?.join(path);
+        let path = index_path(path);
+        // The cached trees are not updated along with the entries
+        index.remove_tree();
+        let metadata = match gix::index::fs::Metadata::from_path_no_follow(&full_path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                index.remove_entries(|_, entry_path, _| entry_path == path.as_slice());
+                return Ok(());
+            }
+            Err(err) => return Err(error(err)),
+        };
+        let (mode, content) = if metadata.is_symlink() {
+            let target = std::fs::read_link(&full_path).map_err(error)?;
+            (
+                Mode::SYMLINK,
+                gix::path::into_bstr(target).into_owned().into(),
+            )
+        } else if metadata.is_file() {
+            let mode = if metadata.is_executable() {
+                Mode::FILE_EXECUTABLE
+            } else {
+                Mode::FILE
+            };
+            (mode, std::fs::read(&full_path).map_err(error)?)
+        } else {
+            return Err(git2::Error::from_str(&format!(
+                "cannot stage {}, it is not a file",
+                full_path.display()
+            )));
+        };
+        let id = self.repo.write_blob(content).map_err(error)?.detach();
+        let stat = Stat::from_fs(&metadata).map_err(error)?;
+        let path: &BStr = path.as_ref();
+        match index.entry_mut_by_path_and_stage(path, Stage::Unconflicted) {
+            Some(entry) => {
+                entry.stat = stat;
+                entry.id = id;
+                entry.mode = mode;
+            }
+            None => {
+                index.dangerously_push_entry(stat, id, Flags::empty(), mode, path);
+                index.sort_entries();
+            }
+        }
+        Ok(())
+    }
+
+    /// The configured committer, signing now rather than when the repository was opened.
+    fn signature(&self) -> Result<gix::actor::Signature> {
+        let committer = self
+            .repo
+            .committer()
+            .ok_or_else(|| git2::Error::from_str("no committer is configured"))?
+            .map_err(error)?;
+        Ok(gix::actor::Signature {
+            name: committer.name.to_owned(),
+            email: committer.email.to_owned(),
+            time: gix::date::Time::now_local_or_utc(),
+        })
+    }
+
+    /// The commit `HEAD` points to, the parent of the next commit, `None` if `HEAD` is unborn (e.g. a new repository).
+    fn head_parent(&self) -> Result<Option<gix::ObjectId>> {
+        Ok(self.repo.head().map_err(error)?.id().map(|id| id.detach()))
+    }
+
+    /// Write the tree of the index, like `git write-tree`.
+    fn write_tree(&self) -> Result<gix::ObjectId> {
+        let index = self.repo.open_index().map_err(error)?;
+        let empty_tree = gix::ObjectId::empty_tree(self.repo.object_hash());
+        let mut editor = self.repo.edit_tree(empty_tree).map_err(error)?;
+        for entry in index.entries() {
+            if entry.stage() != Stage::Unconflicted {
+                return Err(git2::Error::from_str(
+                    "cannot commit with unresolved conflicts",
+                ));
+            }
+            let mode = entry.mode.to_tree_entry_mode().ok_or_else(|| {
+                git2::Error::from_str(&format!("invalid mode of {}", entry.path(&index)))
+            })?;
+            editor
+                .upsert(entry.path(&index).to_owned(), mode.kind(), entry.id)
+                .map_err(error)?;
+        }
+        Ok(editor.write().map_err(error)?.detach())
+    }
+}
+
+impl Repository for GitoxideRepository {
+    fn stage<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = self.fallback.indexed_path(path.as_ref())?;
+        self.fallback.check_stageable(&path)?;
+        if self.needs_filters(&path)? {
+            return self.fallback.stage(self.workdir()?.join(path));
+        }
+        let mut index = self.repo.open_index().map_err(error)?;
+        self.update_entry(&mut index, &path)?;
+        index.write(Default::default()).map_err(error)
+    }
+
+    /// Stage the changed paths, which only include new files if they are tracked.
+    fn stage_all(&self) -> Result<()> {
+        if self.fallback.has_filter_drivers() {
+            return self.fallback.stage_all();
+        }
+        let mut index = self.repo.open_index().map_err(error)?;
+        let mut filtered = vec![];
+        for path in self.fallback.changed_paths()? {
+            if self.needs_filters(&path)? {
+                filtered.push(path);
+            } else {
+                self.update_entry(&mut index, &path)?;
+            }
+        }
+        index.write(Default::default()).map_err(error)?;
+        for path in filtered {
+            self.fallback.stage(self.workdir()?.join(path))?;
+        }
+        Ok(())
+    }
+
+    fn stage_directory<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback.stage_directory(path)
+    }
+
+    fn find_secrets(&self, path: Option<&Path>) -> Result<Vec<Secret>> {
+        self.fallback.find_secrets(path)
+    }
+
+    fn git_operation(&self, recent: Duration, path: Option<&Path>) -> Option<String> {
+        self.fallback.git_operation(recent, path)
+    }
+
+    fn ahead_behind(&self) -> Result<Option<(usize, usize)>> {
+        self.fallback.ahead_behind()
+    }
+
+    fn is_tracked<P>(&self, path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let path = index_path(&self.fallback.indexed_path(path.as_ref())?);
+        let index = self.repo.open_index().map_err(error)?;
+        Ok(index
+            .entry_by_path_and_stage(path.as_ref(), Stage::Unconflicted)
+            .is_some())
+    }
+
+    fn is_checked_out<P>(&self, path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback.is_checked_out(path)
+    }
+
+    fn is_excluded<P>(&self, path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback.is_excluded(path)
+    }
+
+    fn is_committed<P>(&self, path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback.is_committed(path)
+    }
+
+    fn mode_change<P>(&self, path: P) -> Result<Option<(u32, u32)>>
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback.mode_change(path)
+    }
+
+    fn committed_blob<P>(&self, path: P) -> Result<Option<git2::Oid>>
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback.committed_blob(path)
+    }
+
+    fn commit(&self, message: &str) -> Result<()> {
+        let tree = self.write_tree()?;
+        let parent = self.head_parent()?;
+        let signature = self.signature()?;
+        let mut time = TimeBuf::default();
+        let signature = signature.to_ref(&mut time);
+        self.repo
+            .commit_as(signature, signature, "HEAD", message, tree, parent)
+            .map_err(error)?;
+        Ok(())
+    }
+
+    fn commit_authored_at(&self, message: &str, author_time: SystemTime) -> Result<()> {
+        let tree = self.write_tree()?;
+        let parent = self.head_parent()?;
+        let committer = self.signature()?;
+        let author_time = DateTime::<Local>::from(author_time);
+        let author = gix::actor::Signature {
+            time: gix::date::Time::new(
+                author_time.timestamp(),
+                author_time.offset().local_minus_utc(),
+            ),
+            ..committer.clone()
+        };
+        let (mut committer_time, mut author_buf) = (TimeBuf::default(), TimeBuf::default());
+        self.repo
+            .commit_as(
+                committer.to_ref(&mut committer_time),
+                author.to_ref(&mut author_buf),
+                "HEAD",
+                message,
+                tree,
+                parent,
+            )
+            .map_err(error)?;
+        Ok(())
+    }
+
+    fn amend(&self, message: &str) -> Result<()> {
+        let tree = self.write_tree()?;
+        let head = self.repo.head_commit().map_err(error)?;
+        let committer = self.signature()?;
+        let mut time = TimeBuf::default();
+        // Keep the original author time
+        let author = head.author().map_err(error)?;
+        let commit = self
+            .repo
+            .new_commit_as(
+                committer.to_ref(&mut time),
+                author,
+                message,
+                tree,
+                head.parent_ids(),
+            )
+            .map_err(error)?;
+        self.repo
+            .edit_reference(RefEdit {
+                change: Change::Update {
+                    log: LogChange {
+                        mode: RefLog::AndReference,
+                        force_create_reflog: false,
+                        message: format!("commit (amend): {}", summary(message)).into(),
+                    },
+                    expected: PreviousValue::MustExistAndMatch(Target::Object(head.id)),
+                    new: Target::Object(commit.id),
+                },
+                name: "HEAD".try_into().map_err(error)?,
+                deref: true,
+            })
+            .map_err(error)?;
+        Ok(())
+    }
+
+    fn head(&self) -> Result<Option<git2::Oid>> {
+        match self.repo.head().map_err(error)?.id() {
+            Some(id) => Ok(Some(git2::Oid::from_bytes(id.as_bytes())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn add_note(&self, commit: git2::Oid, metadata: &CommitMetadata) -> Result<()> {
+        self.fallback.add_note(commit, metadata)
+    }
+
+    fn upstream_head(&self) -> Result<Option<git2::Oid>> {
+        self.fallback.upstream_head()
+    }
+
+    fn root(&self) -> &Path {
+        self.fallback.workdir()
+    }
+
+    fn is_pushed(&self, commit: git2::Oid) -> Result<bool> {
+        self.fallback.is_pushed(commit)
+    }
+
+    fn squash_unpushed(
+        &self,
+        redaction: PathRedaction,
+        conventions: Option<&ConventionsConfig>,
+    ) -> Result<Option<usize>> {
+        self.fallback.squash_unpushed(redaction, conventions)
+    }
+
+    fn unpushed_bytes(&self) -> Result<u64> {
+        self.fallback.unpushed_bytes()
+    }
+
+    fn changed_paths(&self) -> Result<Vec<PathBuf>> {
+        self.fallback.changed_paths()
+    }
+
+    fn last_modified(&self) -> Result<Option<SystemTime>> {
+        self.fallback.last_modified()
+    }
+
+    fn conflict_marked_paths(&self) -> Result<Vec<PathBuf>> {
+        self.fallback.conflict_marked_paths()
+    }
+
+    fn author_name(&self) -> Result<String> {
+        self.fallback.author_name()
+    }
+
+    fn check_remote(&self, authentication_method: Option<&AuthenticationMethod>) -> Result<()> {
+        self.fallback.check_remote(authentication_method)
+    }
+
+    fn fetch(&self, authentication_method: &AuthenticationMethod, prune: bool) -> Result<()> {
+        self.fallback.fetch(authentication_method, prune)
+    }
+
+    fn rebase_onto_upstream(
+        &self,
+        conflict_strategy: ConflictStrategy,
+        merge_favors: &Overrides,
+    ) -> Result<RebaseOutcome> {
+        self.fallback
+            .rebase_onto_upstream(conflict_strategy, merge_favors)
+    }
+
+    fn fast_forward_to_upstream(&self) -> Result<FastForwardOutcome> {
+        self.fallback.fast_forward_to_upstream()
+    }
+
+    fn tag_past_days(&self, timezone: Timezone) -> Result<Vec<String>> {
+        self.fallback.tag_past_days(timezone)
+    }
+
+    fn branch(&self) -> Option<String> {
+        self.fallback.branch()
+    }
+
+    fn switch_to_rolling_branch(&self, name: &str, message: &str) -> Result<bool> {
+        self.fallback.switch_to_rolling_branch(name, message)
+    }
+
+    fn run_maintenance(&self) -> Result<()> {
+        self.fallback.run_maintenance()
+    }
+
+    fn write_bundle(&self, path: &Path) -> Result<()> {
+        self.fallback.write_bundle(path)
+    }
+
+    fn push(
+        &self,
+        authentication_method: &AuthenticationMethod,
+        settings: PushSettings,
+    ) -> Result<()> {
+        self.fallback.push(authentication_method, settings)
+    }
+}
+
+/// Path as stored in the index, with `/` separators.
+fn index_path(path: &Path) -> BString {
+    gix::path::to_unix_separators_on_windows(gix::path::into_bstr(path)).into_owned()
+}
+
+/// First line of a commit message, as shown in the reflog.
+fn summary(message: &str) -> &str {
+    message.lines().next().unwrap_or_default()
+}
+
+fn error<E>(err: E) -> git2::Error
+where
+    E: std::fmt::Display,
+{
+    git2::Error::from_str(&err.to_string())
+}
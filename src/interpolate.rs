@@ -0,0 +1,62 @@
+use std::env;
+
+use color_eyre::{eyre::eyre, Result};
+use serde_json::Value;
+
+/// Expand a leading `~` to the home directory and `${NAME}` to the value of the environment variable.
+/// `$${` is kept as a literal `${`.
+pub fn expand(value: &str) -> Result<String> {
+    let value = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = env::var("HOME").map_err(|_| eyre!("cannot expand `~`, HOME is not set"))?;
+            home + rest
+        }
+        _ => value.to_string(),
+    };
+
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value.as_str();
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| eyre!("unterminated `${{` in \"{}\"", value))?;
+        let name = &rest[start + 2..start + end];
+        let variable = env::var(name).map_err(|_| {
+            eyre!(
+                "environment variable {} is not set (in \"{}\")",
+                name,
+                value
+            )
+        })?;
+        expanded.push_str(&variable);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// [`expand`] every string in the value, table keys are kept as they are.
+pub fn expand_all(value: &mut Value) -> Result<()> {
+    match value {
+        Value::String(string) => *string = expand(string)?,
+        Value::Array(values) => {
+            for value in values {
+                expand_all(value)?;
+            }
+        }
+        Value::Object(table) => {
+            for value in table.values_mut() {
+                expand_all(value)?;
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
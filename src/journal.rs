@@ -0,0 +1,390 @@
+#[cfg(feature = "journal")]
+use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "journal")]
+use chrono::Local;
+use chrono::NaiveDate;
+use color_eyre::{eyre::eyre, Result};
+use serde::Serialize;
+
+use crate::{activity::Activity, latency::Latencies};
+#[cfg(feature = "journal")]
+use crate::{fs::quarantine, sync::PushOutcome};
+
+/// Name of the journal, kept inside the repository's `.git` directory.
+pub const JOURNAL_FILE_NAME: &str = "nabu-journal.sqlite";
+
+/// Time a connection waits for the lock another one holds before failing.
+#[cfg(feature = "journal")]
+const JOURNAL_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Path of the journal for the repository with the given `.git` directory.
+pub fn journal_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(JOURNAL_FILE_NAME)
+}
+
+/// Open the journal of the repository with the given `.git` directory for reading,
+/// `None` if the watcher keeps none or it cannot be opened.
+pub fn read_journal(git_dir: &Path) -> Option<Journal> {
+    let path = journal_path(git_dir);
+    if !path.exists() {
+        return None;
+    }
+    match Journal::open_read_only(&path) {
+        Ok(journal) => Some(journal),
+        Err(err) => {
+            log::warn!("{}", err);
+            None
+        }
+    }
+}
+
+/// A commit or push recorded in the journal.
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    /// When it happened (RFC 3339).
+    pub time: String,
+    /// The commit message, or the push outcome (e.g. `pushed`).
+    pub summary: String,
+    /// The error of a failed push.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The latest activity recorded in the journal.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JournalStatus {
+    pub last_commit: Option<JournalEntry>,
+    pub last_push: Option<JournalEntry>,
+    /// Pushes which failed since the last successful one.
+    pub failed_pushes: usize,
+}
+
+/// The watcher's activity over a day.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct JournalDay {
+    pub events: usize,
+    pub commits: usize,
+    pub pushes: usize,
+    pub failed_pushes: usize,
+}
+
+/// Every event, commit and push of the watcher, with its time and outcome,
+/// recorded in an SQLite database as they happen so that a crash loses none of them.
+#[cfg(feature = "journal")]
+pub struct Journal(rusqlite::Connection);
+
+#[cfg(feature = "journal")]
+impl Journal {
+    /// Open the journal at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::connect(path)
+            .map(Self)
+            .map_err(|err| eyre!("cannot open the journal {}: {}", path.display(), err))
+    }
+
+    /// Open the existing journal at `path` for reading (e.g. for `nabu status`),
+    /// without creating its tables nor changing its settings.
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        let flags =
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        rusqlite::Connection::open_with_flags(path, flags)
+            .and_then(|connection| {
+                connection.busy_timeout(JOURNAL_BUSY_TIMEOUT)?;
+                Ok(connection)
+            })
+            .map(Self)
+            .map_err(|err| eyre!("cannot open the journal {}: {}", path.display(), err))
+    }
+
+    fn connect(path: &Path) -> rusqlite::Result<rusqlite::Connection> {
+        let connection = rusqlite::Connection::open(path)?;
+        // Another connection setting the journal up (e.g. `nabu observe` creating the tables) only holds it briefly
+        connection.busy_timeout(JOURNAL_BUSY_TIMEOUT)?;
+        // Readers (e.g. `nabu status`) do not block the watcher, and a crash keeps every committed row
+        connection.pragma_update(None, "journal_mode", "wal")?;
+        connection.pragma_update(None, "synchronous", "normal")?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS activity (
+                id INTEGER PRIMARY KEY,
+                time TEXT NOT NULL,
+                day TEXT NOT NULL,
+                session TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                summary TEXT,
+                error TEXT,
+                latency_ms INTEGER,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS activity_day ON activity (day);
+            CREATE INDEX IF NOT EXISTS activity_kind ON activity (kind);",
+        )?;
+        Ok(connection)
+    }
+
+    /// Open the journal at `path` for the watcher, checking its integrity first:
+    /// a corrupt journal is moved aside, along with its write-ahead log, and a new one is started.
+    /// Any other failure (e.g. the journal being locked, or the disk full) is an error, the journal is left as is.
+    pub fn recover(path: &Path) -> Result<Self> {
+        let error = match Self::connect(path) {
+            Ok(connection) => match check(&connection) {
+                Ok(None) => return Ok(Self(connection)),
+                Ok(Some(result)) => format!("integrity check failed: {}", result),
+                Err(err) if is_corrupt(&err) => err.to_string(),
+                Err(err) => {
+                    return Err(eyre!(
+                        "cannot check the journal {}: {}",
+                        path.display(),
+                        err
+                    ))
+                }
+            },
+            Err(err) if is_corrupt(&err) => err.to_string(),
+            Err(err) => return Err(eyre!("cannot open the journal {}: {}", path.display(), err)),
+        };
+        let corrupt = quarantine(path)
+            .map_err(|err| eyre!("cannot move the journal {} aside: {}", path.display(), err))?;
+        for suffix in ["-wal", "-shm"] {
+            let mut side = path.as_os_str().to_os_string();
+            side.push(suffix);
+            let mut side_corrupt = corrupt.as_os_str().to_os_string();
+            side_corrupt.push(suffix);
+            // Left behind, they would be replayed into the new journal; next to the corrupt one, they are read with it
+            let _ = std::fs::rename(&side, &side_corrupt);
+        }
+        log::warn!(
+            "The journal is corrupt ({}), moved it to {} and started a new one.",
+            error,
+            corrupt.display()
+        );
+        Self::open(path)
+    }
+
+    /// Forget the activity older than `retention_days`, if set, and shrink the database:
+    /// the write-ahead log is folded back, and the freed pages are released.
+    /// Returns the number of rows removed.
+    pub fn compact(&self, retention_days: Option<u64>) -> Result<usize> {
+        let removed = match retention_days {
+            Some(days) => {
+                let oldest = (Local::now() - chrono::Duration::days(days as i64))
+                    .format("%Y-%m-%d")
+                    .to_string();
+                self.0
+                    .execute("DELETE FROM activity WHERE day < ?1", [oldest])?
+            }
+            None => 0,
+        };
+        if removed > 0 {
+            self.0.execute_batch("VACUUM")?;
+        }
+        self.0
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(removed)
+    }
+
+    /// Record the activity of the watcher session.
+    pub fn record(&self, session: &str, activity: &Activity) -> Result<()> {
+        let now = Local::now();
+        let (summary, error, latency_ms) = match activity {
+            Activity::Event { description } => (Some(description.clone()), None, None),
+            Activity::Commit {
+                message,
+                latency_ms,
+            } => (Some(message.clone()), None, *latency_ms),
+            Activity::Push {
+                outcome,
+                latency_ms,
+            } => {
+                let error = match outcome {
+                    PushOutcome::Failed { error, .. } | PushOutcome::Bundled { error, .. } => {
+                        Some(error.clone())
+                    }
+                    PushOutcome::Paused { conflicts } => Some(format!(
+                        "conflicts on {}",
+                        conflicts
+                            .iter()
+                            .map(|path| path.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )),
+                    PushOutcome::Pushed => None,
+                };
+                (Some(outcome_name(outcome).to_string()), error, *latency_ms)
+            }
+            Activity::Paused | Activity::Resumed | Activity::Unwatched { .. } => (None, None, None),
+            // Reported by the state file, the journal keeps the history
+            Activity::Usage { .. } => return Ok(()),
+        };
+        self.0.execute(
+            "INSERT INTO activity (time, day, session, kind, summary, error, latency_ms, data)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                now.to_rfc3339(),
+                now.format("%Y-%m-%d").to_string(),
+                session,
+                activity.kind(),
+                summary,
+                error,
+                latency_ms,
+                serde_json::to_string(activity)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The last commit and push, and the pushes failing since the last successful one.
+    pub fn status(&self) -> Result<JournalStatus> {
+        let last = |kind: &str| -> Result<Option<JournalEntry>> {
+            let mut statement = self.0.prepare(
+                "SELECT time, summary, error FROM activity WHERE kind = ?1 ORDER BY id DESC LIMIT 1",
+            )?;
+            let mut rows = statement.query([kind])?;
+            Ok(match rows.next()? {
+                Some(row) => Some(JournalEntry {
+                    time: row.get(0)?,
+                    summary: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    error: row.get(2)?,
+                }),
+                None => None,
+            })
+        };
+        let failed_pushes: usize = self.0.query_row(
+            "SELECT COUNT(*) FROM activity WHERE kind = 'push' AND summary != 'pushed'
+            AND id > (SELECT COALESCE(MAX(id), 0) FROM activity WHERE kind = 'push' AND summary = 'pushed')",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(JournalStatus {
+            last_commit: last("commit")?,
+            last_push: last("push")?,
+            failed_pushes,
+        })
+    }
+
+    /// The latencies of every commit and successful push recorded.
+    pub fn latencies(&self) -> Result<Latencies> {
+        let mut latencies = Latencies::default();
+        let mut statement = self.0.prepare(
+            "SELECT kind, latency_ms FROM activity WHERE latency_ms IS NOT NULL ORDER BY id",
+        )?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(0)?;
+            let latency_ms: u64 = row.get(1)?;
+            match kind.as_str() {
+                "commit" => latencies.event_to_commit.record(latency_ms),
+                _ => latencies.commit_to_push.record(latency_ms),
+            };
+        }
+        Ok(latencies)
+    }
+
+    /// The activity per day (formatted as `YYYY-MM-DD`), between `since` and `until` included.
+    pub fn days(
+        &self,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> Result<BTreeMap<String, JournalDay>> {
+        let since = since.map_or_else(String::new, |since| since.to_string());
+        let until = until.map_or_else(|| String::from("9999"), |until| until.to_string());
+        let mut statement = self.0.prepare(
+            "SELECT day, kind, summary, COUNT(*) FROM activity
+            WHERE day >= ?1 AND day <= ?2 AND kind IN ('event', 'commit', 'push')
+            GROUP BY day, kind, summary = 'pushed'",
+        )?;
+        let mut rows = statement.query([since, until])?;
+        let mut days = BTreeMap::<String, JournalDay>::new();
+        while let Some(row) = rows.next()? {
+            let day = days.entry(row.get(0)?).or_default();
+            let kind: String = row.get(1)?;
+            let summary: Option<String> = row.get(2)?;
+            let count: usize = row.get(3)?;
+            match kind.as_str() {
+                "event" => day.events += count,
+                "commit" => day.commits += count,
+                _ if summary.as_deref() == Some("pushed") => day.pushes += count,
+                _ => day.failed_pushes += count,
+            }
+        }
+        Ok(days)
+    }
+}
+
+/// Check the integrity of the database, returning what is wrong with it, `None` if nothing.
+#[cfg(feature = "journal")]
+fn check(connection: &rusqlite::Connection) -> rusqlite::Result<Option<String>> {
+    let result: String = connection.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    Ok((result != "ok").then_some(result))
+}
+
+/// Whether SQLite failed since the database is corrupt, rather than locked or unreadable.
+#[cfg(feature = "journal")]
+fn is_corrupt(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
+/// Name of the push outcome, as serialized.
+#[cfg(feature = "journal")]
+fn outcome_name(outcome: &PushOutcome) -> &'static str {
+    match outcome {
+        PushOutcome::Pushed => "pushed",
+        PushOutcome::Paused { .. } => "paused",
+        PushOutcome::Failed { .. } => "failed",
+        PushOutcome::Bundled { .. } => "bundled",
+    }
+}
+
+/// Without the `journal` feature there is no journal to open.
+#[cfg(not(feature = "journal"))]
+pub struct Journal {
+    _private: (),
+}
+
+#[cfg(not(feature = "journal"))]
+impl Journal {
+    pub fn open(path: &Path) -> Result<Self> {
+        Err(eyre!(
+            "cannot open the journal {}, nabu was built without the journal feature",
+            path.display()
+        ))
+    }
+
+    pub fn open_read_only(path: &Path) -> Result<Self> {
+        Self::open(path)
+    }
+
+    pub fn recover(path: &Path) -> Result<Self> {
+        Self::open(path)
+    }
+
+    pub fn compact(&self, _retention_days: Option<u64>) -> Result<usize> {
+        Ok(0)
+    }
+
+    pub fn record(&self, _session: &str, _activity: &Activity) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn status(&self) -> Result<JournalStatus> {
+        Ok(JournalStatus::default())
+    }
+
+    pub fn latencies(&self) -> Result<Latencies> {
+        Ok(Latencies::default())
+    }
+
+    pub fn days(
+        &self,
+        _since: Option<NaiveDate>,
+        _until: Option<NaiveDate>,
+    ) -> Result<BTreeMap<String, JournalDay>> {
+        Ok(BTreeMap::new())
+    }
+}
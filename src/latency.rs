@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Upper bounds (in seconds) of the latency buckets, the last bucket holds the longer latencies.
+pub const LATENCY_BUCKETS: &[u64] = &[1, 5, 10, 30, 60, 120, 300, 600, 1800, 3600];
+
+/// Latencies recorded before the slow ones are told apart.
+const MIN_SAMPLES: u64 = 10;
+
+/// How many times the mean latency a latency takes to be logged as slow.
+const SLOW_FACTOR: u64 = 4;
+
+/// Distribution of a latency, bucketed by [`LATENCY_BUCKETS`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    pub max_ms: u64,
+    /// Latencies per bucket, the last one above the last bound.
+    pub buckets: Vec<u64>,
+}
+
+impl LatencyHistogram {
+    /// Record a latency, returning whether it is an outlier, much slower than the previous ones.
+    pub fn record(&mut self, latency_ms: u64) -> bool {
+        let slow = self
+            .mean_ms()
+            .is_some_and(|mean| self.count >= MIN_SAMPLES && latency_ms > SLOW_FACTOR * mean);
+        self.buckets.resize(LATENCY_BUCKETS.len() + 1, 0);
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency_ms <= bound * 1000)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += latency_ms;
+        self.max_ms = self.max_ms.max(latency_ms);
+        slow
+    }
+
+    pub fn mean_ms(&self) -> Option<u64> {
+        (self.count > 0).then(|| self.sum_ms / self.count)
+    }
+
+    /// Upper bound of the `percentile` (e.g. 90) of the latencies,
+    /// the bound of its bucket or the longest latency if shorter.
+    pub fn percentile_ms(&self, percentile: u64) -> Option<u64> {
+        let rank = (self.count * percentile).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = LATENCY_BUCKETS
+                    .get(bucket)
+                    .map_or(self.max_ms, |bound| bound * 1000);
+                return Some(bound.min(self.max_ms));
+            }
+        }
+        None
+    }
+}
+
+/// The latencies of the watcher, for tuning the `delay` and the push settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Latencies {
+    /// From a filesystem change to the commit including it.
+    pub event_to_commit: LatencyHistogram,
+    /// From a commit to the push sending it to the remote.
+    pub commit_to_push: LatencyHistogram,
+}
+
+impl Latencies {
+    /// Record the latency of a commit, logging it if slow.
+    pub fn record_commit(&mut self, latency_ms: u64) {
+        if self.event_to_commit.record(latency_ms) {
+            log::warn!(
+                "Slow commit, {} after the change (mean {}).",
+                format_ms(latency_ms),
+                format_ms(self.event_to_commit.mean_ms().unwrap_or_default())
+            );
+        }
+    }
+
+    /// Record the latency of a push, logging it if slow.
+    pub fn record_push(&mut self, latency_ms: u64) {
+        if self.commit_to_push.record(latency_ms) {
+            log::warn!(
+                "Slow push, {} after the commit (mean {}).",
+                format_ms(latency_ms),
+                format_ms(self.commit_to_push.mean_ms().unwrap_or_default())
+            );
+        }
+    }
+}
+
+/// Milliseconds elapsed since `instant`.
+pub fn elapsed_ms(instant: Instant) -> u64 {
+    instant.elapsed().as_millis() as u64
+}
+
+/// Format a latency in seconds, e.g. `31.2s`.
+pub fn format_ms(latency_ms: u64) -> String {
+    format!("{:.1}s", latency_ms as f64 / 1000.0)
+}
@@ -1,3 +1,54 @@
+pub mod activity;
+pub mod adaptive;
+pub mod adopt;
+pub mod app_state;
+pub mod attachments;
 pub mod config;
+pub mod control;
+pub mod conventions;
+pub mod doctor;
+pub mod explain;
+pub mod export;
+pub mod fleet;
 pub mod fs;
 pub mod git;
+pub mod git_env;
+#[cfg(feature = "gitoxide")]
+pub mod gitoxide;
+pub mod interpolate;
+pub mod journal;
+pub mod latency;
+pub mod limits;
+pub mod locale;
+pub mod message;
+pub mod migration;
+pub mod mirror;
+pub mod notes;
+pub mod notification;
+pub mod notify_service;
+pub mod overrides;
+pub mod plugin;
+pub mod precondition;
+pub mod quiet;
+pub mod rolling;
+pub mod runtime;
+pub mod s3;
+pub mod schedule;
+pub mod script;
+pub mod secrets;
+pub mod shell;
+pub mod shutdown;
+pub mod size;
+pub mod sparse;
+pub mod ssh_key;
+pub mod state;
+pub mod stats;
+pub mod storm;
+pub mod strategy;
+pub mod suggestions;
+pub mod suppress;
+pub mod sync;
+pub mod tls;
+#[cfg(feature = "rustls")]
+pub mod transport;
+pub mod writers;
@@ -0,0 +1,149 @@
+use std::ffi::c_int;
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+/// Bounds on the resources the watcher uses, as the `[limits]` section, for long-running instances on small devices.
+/// Once reached, the watcher degrades (polling directories, batching changes) rather than failing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Directories watched through the OS's notifications (an inotify watch each on Linux),
+    /// the other ones are polled.
+    #[serde(default)]
+    pub max_watches: Option<usize>,
+
+    /// Filesystem events waiting to be processed, the events received while full are dropped
+    /// and their changes committed together once the queue drains.
+    #[serde(default)]
+    pub max_pending_events: Option<usize>,
+
+    /// Megabytes of objects libgit2 caches, and of pack files it maps into memory.
+    #[serde(default)]
+    pub max_git_memory: Option<u64>,
+
+    /// Pack files libgit2 keeps open.
+    #[serde(default)]
+    pub max_open_packs: Option<usize>,
+}
+
+impl LimitsConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_pending_events == Some(0) {
+            return Err(eyre!("max_pending_events must be at least 1"));
+        }
+        if self.max_open_packs == Some(0) {
+            return Err(eyre!("max_open_packs must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Apply the limits on libgit2's memory and open files, which are shared by every repository of the process.
+    pub fn apply_to_libgit2(&self) -> std::result::Result<(), git2::Error> {
+        libgit2_sys::init();
+        if let Some(megabytes) = self.max_git_memory {
+            let bytes = megabytes.saturating_mul(BYTES_PER_MEGABYTE);
+            check(
+                unsafe {
+                    libgit2_sys::git_libgit2_opts(
+                        libgit2_sys::GIT_OPT_SET_CACHE_MAX_SIZE as c_int,
+                        isize::try_from(bytes).unwrap_or(isize::MAX),
+                    )
+                },
+                "the object cache",
+            )?;
+            check(
+                unsafe {
+                    libgit2_sys::git_libgit2_opts(
+                        libgit2_sys::GIT_OPT_SET_MWINDOW_MAPPED_LIMIT as c_int,
+                        usize::try_from(bytes).unwrap_or(usize::MAX),
+                    )
+                },
+                "the mapped packs",
+            )?;
+        }
+        if let Some(packs) = self.max_open_packs {
+            check(
+                unsafe {
+                    libgit2_sys::git_libgit2_opts(
+                        libgit2_sys::GIT_OPT_SET_MWINDOW_FILE_LIMIT as c_int,
+                        packs,
+                    )
+                },
+                "the open packs",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The error of setting one of libgit2's options, limiting `what`, if it failed.
+fn check(result: c_int, what: &str) -> std::result::Result<(), git2::Error> {
+    if result < 0 {
+        let reason = git2::Error::last_error(result)
+            .map_or_else(String::new, |err| err.message().to_string());
+        return Err(git2::Error::from_str(&format!(
+            "failed to limit {}: {}",
+            what, reason
+        )));
+    }
+    Ok(())
+}
+
+/// Memory used by libgit2's object cache, and the most it may use, in bytes.
+pub fn git_cache_memory() -> Option<(u64, u64)> {
+    let mut current: isize = 0;
+    let mut allowed: isize = 0;
+    libgit2_sys::init();
+    let result = unsafe {
+        libgit2_sys::git_libgit2_opts(
+            libgit2_sys::GIT_OPT_GET_CACHED_MEMORY as c_int,
+            &mut current as *mut isize,
+            &mut allowed as *mut isize,
+        )
+    };
+    (result >= 0).then(|| (current.max(0) as u64, allowed.max(0) as u64))
+}
+
+/// The resources the watcher uses, as reported by `nabu status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Directories watched through the OS's notifications.
+    pub watches: usize,
+    /// Directories polled instead, past `max_watches` or the OS's own limit.
+    pub polled: usize,
+    /// Filesystem events waiting to be processed.
+    pub pending_events: usize,
+    /// Filesystem events dropped since the watcher started, the queue being full.
+    pub dropped_events: usize,
+    /// Bytes used by libgit2's object cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_cache: Option<u64>,
+    /// Bytes libgit2's object cache may use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_cache_limit: Option<u64>,
+}
+
+impl ResourceUsage {
+    /// Describe the usage for humans, e.g. `120 watches (30 polled directories), 0 pending events, 2 of 256 MB of git cache`.
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} watches", self.watches);
+        if self.polled > 0 {
+            description.push_str(&format!(" ({} polled directories)", self.polled));
+        }
+        description.push_str(&format!(", {} pending events", self.pending_events));
+        if self.dropped_events > 0 {
+            description.push_str(&format!(" ({} dropped)", self.dropped_events));
+        }
+        if let (Some(cache), Some(limit)) = (self.git_cache, self.git_cache_limit) {
+            description.push_str(&format!(
+                ", {} of {} MB of git cache",
+                cache / BYTES_PER_MEGABYTE,
+                limit / BYTES_PER_MEGABYTE
+            ));
+        }
+        description
+    }
+}
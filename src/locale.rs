@@ -0,0 +1,212 @@
+use std::env;
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::{
+    DEFAULT_CHMOD_COMMIT_MESSAGE, DEFAULT_COMMIT_MESSAGE, DEFAULT_RENAME_COMMIT_MESSAGE,
+    DEFAULT_RENAME_DIRECTORY_COMMIT_MESSAGE,
+};
+
+/// Environment variables naming the locale of the messages, by precedence.
+const LOCALE_VARIABLES: [&str; 3] = ["LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// Language of the commit messages and of the commands' output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Pt,
+}
+
+impl Locale {
+    /// The locale named by `LC_ALL`, `LC_MESSAGES` or `LANG` (e.g. `pt_PT.UTF-8`),
+    /// English for the languages without a catalog.
+    pub fn from_env() -> Self {
+        let name = LOCALE_VARIABLES
+            .iter()
+            .filter_map(|variable| env::var(variable).ok())
+            .find(|name| !name.is_empty())
+            .unwrap_or_default();
+        let language = name.split(['_', '.', '@']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "pt" => Self::Pt,
+            _ => Self::En,
+        }
+    }
+
+    /// The configured locale, or the one of the environment.
+    pub fn resolve(configured: Option<Self>) -> Self {
+        configured.unwrap_or_else(Self::from_env)
+    }
+
+    /// The messages in this language.
+    pub fn catalog(self) -> &'static Catalog {
+        match self {
+            Self::En => &EN,
+            Self::Pt => &PT,
+        }
+    }
+}
+
+/// The user-facing messages of a language, rendered with [`crate::message::render`]
+/// where they have `{name}` placeholders.
+#[derive(Debug)]
+pub struct Catalog {
+    /// Default template of the commit messages, see [`DEFAULT_COMMIT_MESSAGE`].
+    pub commit_message: &'static str,
+    pub rename_commit_message: &'static str,
+    pub chmod_commit_message: &'static str,
+    pub rename_directory_commit_message: &'static str,
+
+    /// The `{action}` of the commit messages.
+    pub created: &'static str,
+    pub written: &'static str,
+    pub chmod: &'static str,
+    pub deleted: &'static str,
+    pub renamed: &'static str,
+
+    /// Messages of the snapshots.
+    pub exit_snapshot: &'static str,
+    /// The changes pending when the watcher panicked.
+    pub crash_snapshot: &'static str,
+    pub catch_up_snapshot: &'static str,
+    pub adopted_snapshot: &'static str,
+    pub sync_snapshot: &'static str,
+    pub heartbeat_snapshot: &'static str,
+    pub deferred_snapshot: &'static str,
+    pub quiet_hours_snapshot: &'static str,
+    pub bulk_snapshot: &'static str,
+    pub reconciliation_snapshot: &'static str,
+    pub writers_snapshot: &'static str,
+    pub batched_snapshot: &'static str,
+    pub session_snapshot: &'static str,
+    pub long_session_snapshot: &'static str,
+    /// First commit of a rolling branch.
+    pub rolling_branch_snapshot: &'static str,
+    /// A snapshot split with `group_by_folder`.
+    pub top_level_group: &'static str,
+    pub folder_group: &'static str,
+
+    /// Output of `nabu status`.
+    pub on_branch: &'static str,
+    pub detached_head: &'static str,
+    pub no_commits: &'static str,
+    pub ahead_behind: &'static str,
+    pub no_changes: &'static str,
+    pub changes: &'static str,
+    pub unwatched: &'static str,
+    pub usage: &'static str,
+    pub last_commit: &'static str,
+    pub last_push: &'static str,
+    pub failed_pushes: &'static str,
+    pub push_rejected: &'static str,
+    pub push_rejected_hint: &'static str,
+}
+
+impl Catalog {
+    /// The `{action}` of the commit messages for the action as named by the events
+    /// (`created`, `written`, `chmod`, `deleted` or `renamed`).
+    pub fn action<'a>(&'a self, action: &'a str) -> &'a str {
+        match action {
+            "created" => self.created,
+            "written" => self.written,
+            "chmod" => self.chmod,
+            "deleted" => self.deleted,
+            "renamed" => self.renamed,
+            action => action,
+        }
+    }
+}
+
+pub static EN: Catalog = Catalog {
+    commit_message: DEFAULT_COMMIT_MESSAGE,
+    rename_commit_message: DEFAULT_RENAME_COMMIT_MESSAGE,
+    chmod_commit_message: DEFAULT_CHMOD_COMMIT_MESSAGE,
+    rename_directory_commit_message: DEFAULT_RENAME_DIRECTORY_COMMIT_MESSAGE,
+
+    created: "created",
+    written: "written",
+    chmod: "chmod",
+    deleted: "deleted",
+    renamed: "renamed",
+
+    exit_snapshot: "nabu exited snapshot",
+    crash_snapshot: "nabu crash snapshot",
+    catch_up_snapshot: "nabu catch-up snapshot",
+    adopted_snapshot: "nabu adopted snapshot",
+    sync_snapshot: "nabu sync snapshot",
+    heartbeat_snapshot: "heartbeat snapshot",
+    deferred_snapshot: "deferred changes snapshot",
+    quiet_hours_snapshot: "quiet hours batch snapshot",
+    bulk_snapshot: "bulk snapshot of {count} events",
+    reconciliation_snapshot: "reconciliation snapshot of {count} files",
+    writers_snapshot: "snapshot of {count} high-frequency writers",
+    batched_snapshot: "batched snapshot of {count} files",
+    session_snapshot: "session snapshot of {count} files",
+    long_session_snapshot: "session snapshot of {count} files over {minutes} minutes",
+    rolling_branch_snapshot: "rolling branch {branch} snapshot, continuing from {commit}",
+    top_level_group: "{message}, {count} at the top level",
+    folder_group: "{message}, {count} in {folder}",
+
+    on_branch: "On branch {branch}",
+    detached_head: "HEAD detached at {head}",
+    no_commits: "No commits yet",
+    ahead_behind: "{ahead} ahead, {behind} behind {upstream}",
+    no_changes: "No uncommitted changes",
+    changes: "{count} uncommitted changes:",
+    unwatched: "{count} directories cannot be watched:",
+    usage: "Using {usage}",
+    last_commit: "Last commit at {time}: {message}",
+    last_push: "Last push at {time}: {outcome}",
+    failed_pushes: "{count} pushes failed since the last successful one",
+    push_rejected: "Pushes are stopped, the remote rejects them: {error}",
+    push_rejected_hint:
+        "push to a branch of your own with `push_mode = \"device\"` or `push_refspecs`",
+};
+
+pub static PT: Catalog = Catalog {
+    commit_message: "ficheiro {path} {action} @ {time}",
+    rename_commit_message: "ficheiro {from} renomeado para {path} @ {time}",
+    chmod_commit_message: "modo do ficheiro {path} alterado de {old_mode} para {new_mode} @ {time}",
+    rename_directory_commit_message: "pasta {from} renomeada para {path} @ {time}",
+
+    created: "criado",
+    written: "alterado",
+    chmod: "com modo alterado",
+    deleted: "apagado",
+    renamed: "renomeado",
+
+    exit_snapshot: "snapshot à saída do nabu",
+    crash_snapshot: "snapshot da falha do nabu",
+    catch_up_snapshot: "snapshot de recuperação do nabu",
+    adopted_snapshot: "snapshot de adoção do nabu",
+    sync_snapshot: "snapshot de sincronização do nabu",
+    heartbeat_snapshot: "snapshot periódico",
+    deferred_snapshot: "snapshot das alterações adiadas",
+    quiet_hours_snapshot: "snapshot do lote das horas de silêncio",
+    bulk_snapshot: "snapshot em massa de {count} eventos",
+    reconciliation_snapshot: "snapshot de reconciliação de {count} ficheiros",
+    writers_snapshot: "snapshot de {count} ficheiros alterados com frequência",
+    batched_snapshot: "snapshot em lote de {count} ficheiros",
+    session_snapshot: "snapshot da sessão de {count} ficheiros",
+    long_session_snapshot: "snapshot da sessão de {count} ficheiros ao longo de {minutes} minutos",
+    rolling_branch_snapshot: "snapshot do ramo {branch}, a continuar de {commit}",
+    top_level_group: "{message}, {count} no nível de topo",
+    folder_group: "{message}, {count} em {folder}",
+
+    on_branch: "No ramo {branch}",
+    detached_head: "HEAD destacada em {head}",
+    no_commits: "Ainda sem commits",
+    ahead_behind: "{ahead} à frente, {behind} atrás de {upstream}",
+    no_changes: "Sem alterações por registar",
+    changes: "{count} alterações por registar:",
+    unwatched: "{count} pastas não podem ser vigiadas:",
+    usage: "A usar {usage}",
+    last_commit: "Último commit em {time}: {message}",
+    last_push: "Último push em {time}: {outcome}",
+    failed_pushes: "{count} pushes falharam desde o último bem-sucedido",
+    push_rejected: "Os pushes estão parados, o remoto rejeita-os: {error}",
+    push_rejected_hint:
+        "faça push para um ramo seu com `push_mode = \"device\"` ou `push_refspecs`",
+};
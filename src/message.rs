@@ -0,0 +1,126 @@
+use std::path::Path;
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local, NaiveDate, TimeZone, Utc,
+};
+use serde::{Deserialize, Serialize};
+
+/// Default message for the commits made on each event.
+pub const DEFAULT_COMMIT_MESSAGE: &str = "{action} file {path} @ {time}";
+
+/// Default message for the commits made when a file is renamed.
+pub const DEFAULT_RENAME_COMMIT_MESSAGE: &str = "renamed file {from} to {path} @ {time}";
+
+/// Default message for the commits made when the mode of a file changes, e.g. it became executable.
+pub const DEFAULT_CHMOD_COMMIT_MESSAGE: &str =
+    "changed mode of file {path} from {old_mode} to {new_mode} @ {time}";
+
+/// Default message for the commits made when a directory is renamed, along with its files.
+pub const DEFAULT_RENAME_DIRECTORY_COMMIT_MESSAGE: &str =
+    "renamed directory {from} to {path} @ {time}";
+
+/// Replace each `{name}` in the template with its value, unknown names are kept as they are.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// Number of hexadecimal digits of the hashed paths.
+const REDACTED_HASH_LENGTH: usize = 12;
+
+/// How the paths are written in the commit messages, e.g. to keep the file names from the remote's host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathRedaction {
+    /// The paths are written as they are.
+    #[default]
+    None,
+    /// The paths are replaced by a hash of their relative path, the same path always hashing the same.
+    Hash,
+    /// Only the top-level directory of the paths is kept, e.g. `journal/…`.
+    Truncate,
+}
+
+impl PathRedaction {
+    /// The stricter of the two redactions, `hash` hiding even the top-level directories `truncate` keeps.
+    pub fn stricter(self, other: Self) -> Self {
+        match (self, other) {
+            (PathRedaction::Hash, _) | (_, PathRedaction::Hash) => PathRedaction::Hash,
+            (PathRedaction::Truncate, _) | (_, PathRedaction::Truncate) => PathRedaction::Truncate,
+            _ => PathRedaction::None,
+        }
+    }
+
+    /// The path, relative to the watched directory, as written in the commit messages.
+    pub fn apply(self, relative: &Path) -> String {
+        match self {
+            PathRedaction::None => relative.to_string_lossy().into_owned(),
+            PathRedaction::Hash => {
+                let hash = git2::Oid::hash_object(
+                    git2::ObjectType::Blob,
+                    relative.to_string_lossy().as_bytes(),
+                )
+                .map(|oid| oid.to_string())
+                .unwrap_or_default();
+                hash[..REDACTED_HASH_LENGTH.min(hash.len())].to_string()
+            }
+            PathRedaction::Truncate => {
+                let mut components = relative.components();
+                match (components.next(), components.next()) {
+                    (Some(directory), Some(_)) => {
+                        format!("{}/…", directory.as_os_str().to_string_lossy())
+                    }
+                    _ => String::from("…"),
+                }
+            }
+        }
+    }
+}
+
+/// Default format of the `{time}` placeholder and of the snapshot timestamps,
+/// e.g. `2024-05-01 10:31:05.123456789 UTC`.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f %Z";
+
+/// Time zone of the timestamps in the commit messages and of the days the daily tags are for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Timezone {
+    #[default]
+    Utc,
+    Local,
+}
+
+impl Timezone {
+    /// The current time, in the `strftime`-style format (see [`DEFAULT_TIMESTAMP_FORMAT`]).
+    pub fn timestamp(self, format: &str) -> String {
+        match self {
+            Timezone::Utc => Utc::now().format(format).to_string(),
+            Timezone::Local => Local::now().format(format).to_string(),
+        }
+    }
+
+    /// The current day.
+    pub fn today(self) -> NaiveDate {
+        match self {
+            Timezone::Utc => Utc::now().date().naive_utc(),
+            Timezone::Local => Local::now().date().naive_local(),
+        }
+    }
+
+    /// The day of the Unix timestamp (in seconds).
+    pub fn day(self, seconds: i64) -> NaiveDate {
+        match self {
+            Timezone::Utc => Utc.timestamp(seconds, 0).date().naive_utc(),
+            Timezone::Local => Local.timestamp(seconds, 0).date().naive_local(),
+        }
+    }
+}
+
+/// Whether the `strftime`-style format is valid.
+pub fn is_valid_timestamp_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
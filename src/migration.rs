@@ -0,0 +1,42 @@
+use color_eyre::{eyre::eyre, Result};
+use serde_json::{Map, Value};
+
+/// Version of the configuration schema written by this version of `nabu`.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrades of the configuration schema, the n-th one upgrades version n to version n + 1.
+const MIGRATIONS: [fn(&mut Map<String, Value>); CONFIG_VERSION as usize] = [
+    // Version 0 predates the `version` field, the schema is otherwise the same
+    |_| {},
+];
+
+/// Version of a configuration, files without a `version` predate it.
+pub fn config_version(config: &Value) -> Result<u32> {
+    match config.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| eyre!("invalid configuration version: {}", version)),
+    }
+}
+
+/// Upgrade a configuration to the current version, returning the version it was upgraded from.
+pub fn migrate(config: &mut Value) -> Result<u32> {
+    let version = config_version(config)?;
+    if version > CONFIG_VERSION {
+        return Err(eyre!(
+            "the configuration is for version {} but this version of nabu only supports up to version {}",
+            version,
+            CONFIG_VERSION
+        ));
+    }
+    let table = config
+        .as_object_mut()
+        .ok_or_else(|| eyre!("the configuration is not a table"))?;
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(table);
+    }
+    table.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    Ok(version)
+}
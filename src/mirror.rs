@@ -0,0 +1,248 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use notify::DebouncedEvent;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+/// A directory outside of the repository whose files are mirrored into it,
+/// declared as `[[mirrors]]` sections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// The external directory (e.g. `~/Pictures/attachments`).
+    pub source: PathBuf,
+
+    /// Where its files are mirrored, relative to the watched directory (e.g. `attachments`).
+    pub target: PathBuf,
+
+    /// Hard link the files instead of copying them, falling back to copies across filesystems.
+    #[serde(default)]
+    pub hardlink: bool,
+}
+
+impl MirrorConfig {
+    /// Check that the source is absolute and that the target stays inside the watched directory.
+    pub fn validate(&self) -> Result<()> {
+        if !self.source.is_absolute() {
+            return Err(eyre!(
+                "the mirror source {} must be an absolute path",
+                self.source.display()
+            ));
+        }
+        if self.target.as_os_str().is_empty()
+            || !self
+                .target
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(eyre!(
+                "the mirror target {} must be a relative path inside the watched directory",
+                self.target.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The external directories mirrored into the watched directory.
+#[derive(Debug, Clone, Default)]
+pub struct Mirrors {
+    root: PathBuf,
+    mirrors: Vec<MirrorConfig>,
+}
+
+impl Mirrors {
+    /// Mirror the directories into `root`, the watched directory.
+    pub fn new(root: &Path, mirrors: &[MirrorConfig]) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            mirrors: mirrors.to_vec(),
+        }
+    }
+
+    /// The external directories.
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.mirrors.iter().map(|mirror| mirror.source.as_path())
+    }
+
+    /// The mirror of an external path and the path mirroring it, `None` outside of the sources.
+    fn mirrored(&self, path: &Path) -> Option<(&MirrorConfig, PathBuf)> {
+        self.mirrors.iter().find_map(|mirror| {
+            let relative = path.strip_prefix(&mirror.source).ok()?;
+            Some((mirror, self.root.join(&mirror.target).join(relative)))
+        })
+    }
+
+    /// Mirror the files of the sources which are missing from the watched directory or differ,
+    /// and remove the mirrors of the files gone from the sources.
+    /// Returns the events of the paths mirroring them.
+    pub fn sync_all(&self) -> io::Result<Vec<DebouncedEvent>> {
+        let mut mirrored = vec![];
+        for mirror in &self.mirrors {
+            for entry in WalkDir::new(&mirror.source) {
+                let entry = entry?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let relative = entry.path().strip_prefix(&mirror.source).unwrap();
+                let target = self.root.join(&mirror.target).join(relative);
+                if !same_content(entry.path(), &target)? {
+                    mirror_file(entry.path(), &target, mirror.hardlink)?;
+                    mirrored.push(DebouncedEvent::Write(target));
+                }
+            }
+            let targets = self.root.join(&mirror.target);
+            if !targets.is_dir() {
+                continue;
+            }
+            let mut walk = WalkDir::new(&targets).min_depth(1).into_iter();
+            while let Some(entry) = walk.next() {
+                let entry = entry?;
+                let relative = entry.path().strip_prefix(&targets).unwrap();
+                if mirror.source.join(relative).exists() {
+                    continue;
+                }
+                // The removal of each file is committed, rather than of the directory
+                if entry.file_type().is_dir() {
+                    walk.skip_current_dir();
+                    for file in WalkDir::new(entry.path()) {
+                        let file = file?;
+                        if !file.file_type().is_dir() {
+                            mirrored.push(DebouncedEvent::Remove(file.into_path()));
+                        }
+                    }
+                } else {
+                    mirrored.push(DebouncedEvent::Remove(entry.path().to_path_buf()));
+                }
+                remove(entry.path())?;
+            }
+        }
+        Ok(mirrored)
+    }
+
+    /// Apply the event of an external path to its mirror, returning the event of the mirrored path.
+    /// The events of the other paths are returned as they are,
+    /// `None` if the event could not be mirrored.
+    pub fn mirror_event(&self, event: DebouncedEvent) -> Option<DebouncedEvent> {
+        let result = match event {
+            DebouncedEvent::Create(path) => self.mirror_change(path, DebouncedEvent::Create),
+            DebouncedEvent::Write(path) => self.mirror_change(path, DebouncedEvent::Write),
+            DebouncedEvent::Chmod(path) => self.mirror_change(path, DebouncedEvent::Chmod),
+            DebouncedEvent::Remove(path) => self.mirror_removal(path),
+            DebouncedEvent::NoticeWrite(path) => Ok(DebouncedEvent::NoticeWrite(
+                self.mirrored(&path).map_or(path, |(_, target)| target),
+            )),
+            DebouncedEvent::NoticeRemove(path) => Ok(DebouncedEvent::NoticeRemove(
+                self.mirrored(&path).map_or(path, |(_, target)| target),
+            )),
+            DebouncedEvent::Rename(old, new) => self.mirror_rename(old, new),
+            event => Ok(event),
+        };
+        match result {
+            Ok(event) => Some(event),
+            Err(err) => {
+                log::warn!("failed to mirror a change: {}", err);
+                None
+            }
+        }
+    }
+
+    fn mirror_change<F>(&self, path: PathBuf, event: F) -> io::Result<DebouncedEvent>
+    where
+        F: Fn(PathBuf) -> DebouncedEvent,
+    {
+        let (mirror, target) = match self.mirrored(&path) {
+            Some(mirrored) => mirrored,
+            None => return Ok(event(path)),
+        };
+        if path.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            mirror_file(&path, &target, mirror.hardlink)?;
+        }
+        Ok(event(target))
+    }
+
+    fn mirror_removal(&self, path: PathBuf) -> io::Result<DebouncedEvent> {
+        let target = match self.mirrored(&path) {
+            Some((_, target)) => target,
+            None => return Ok(DebouncedEvent::Remove(path)),
+        };
+        remove(&target)?;
+        Ok(DebouncedEvent::Remove(target))
+    }
+
+    fn mirror_rename(&self, old: PathBuf, new: PathBuf) -> io::Result<DebouncedEvent> {
+        match (self.mirrored(&old), self.mirrored(&new)) {
+            (Some((_, old_target)), Some(_)) if !old_target.exists() => {
+                self.mirror_change(new, DebouncedEvent::Create)
+            }
+            (Some((_, old_target)), Some((_, new_target))) => {
+                if let Some(parent) = new_target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(&old_target, &new_target)?;
+                Ok(DebouncedEvent::Rename(old_target, new_target))
+            }
+            // Moved out of the source
+            (Some(_), None) => self.mirror_removal(old),
+            // Moved into the source
+            (None, Some(_)) => self.mirror_change(new, DebouncedEvent::Create),
+            (None, None) => Ok(DebouncedEvent::Rename(old, new)),
+        }
+    }
+}
+
+/// Copy (or hard link) the file to `target`, creating its parent directories.
+/// A copy is given the modification time of the file, see [`same_content`].
+fn mirror_file(source: &Path, target: &Path, hardlink: bool) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if hardlink {
+        remove(target)?;
+        match fs::hard_link(source, target) {
+            Ok(()) => return Ok(()),
+            Err(err) => log::debug!(
+                "failed to hard link {}, copying it: {}",
+                source.display(),
+                err
+            ),
+        }
+    }
+    fs::copy(source, target)?;
+    let modified = fs::metadata(source)?.modified()?;
+    fs::File::options()
+        .write(true)
+        .open(target)?
+        .set_modified(modified)
+}
+
+/// Remove the file or directory, if it exists.
+fn remove(path: &Path) -> io::Result<()> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Whether both files exist with the same content,
+/// judged by their size and modification time, which the mirrored copies keep.
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (a_metadata, b_metadata) = match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a_metadata), Ok(b_metadata)) => (a_metadata, b_metadata),
+        (Err(err), _) => return Err(err),
+        (_, Err(err)) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        (_, Err(err)) => return Err(err),
+    };
+    Ok(a_metadata.len() == b_metadata.len() && a_metadata.modified()? == b_metadata.modified()?)
+}
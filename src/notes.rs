@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+/// Notes reference holding the metadata of the commits, see `git notes --ref nabu show <commit>`.
+pub const NOTES_REF: &str = "refs/notes/nabu";
+
+/// Machine-readable metadata of a commit, attached to it as a JSON note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitMetadata {
+    /// What triggered the commit, the event's action (e.g. `written`) or `snapshot`.
+    pub event: String,
+    /// The file of the event, relative to the working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// When the file was changed (RFC 3339), before the watcher delay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_time: Option<String>,
+    /// The device the commit was made on.
+    pub device: String,
+    /// The watcher session which made the commit.
+    pub session: String,
+}
+
+/// Key of the commit message trailer identifying the watcher session, see [`session_trailer`].
+pub const SESSION_TRAILER: &str = "Nabu-Session";
+
+/// Trailer ending the commit messages written by nabu, which tells its commits from the others,
+/// see [`is_nabu_commit`](crate::stats::is_nabu_commit).
+pub const COMMITTED_BY_TRAILER: &str = "Committed-By: nabu";
+
+/// The message ending with the trailer identifying the session, if any, and [`COMMITTED_BY_TRAILER`].
+pub fn with_trailers(message: &str, session: Option<&str>) -> String {
+    let session = session.map_or_else(String::new, |session| {
+        format!("{}: {}\n", SESSION_TRAILER, session)
+    });
+    format!(
+        "{}\n\n{}{}",
+        message.trim_end(),
+        session,
+        COMMITTED_BY_TRAILER
+    )
+}
+
+/// The session identified by the trailer of the commit message, if any.
+pub fn session_trailer(message: &str) -> Option<&str> {
+    let prefix = format!("{}: ", SESSION_TRAILER);
+    message
+        .lines()
+        .rev()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map(str::trim)
+}
+
+/// Name of this device, its hostname unless set.
+pub fn device_name(name: Option<&str>) -> String {
+    if let Some(name) = name {
+        return name.to_string();
+    }
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|variable| std::env::var(variable).ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Identifier of a new watcher session, from its start time and process ID.
+pub fn session_id() -> String {
+    format!(
+        "{}-{}",
+        Local::now().format("%Y%m%dT%H%M%S"),
+        std::process::id()
+    )
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    process::Stdio,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use log::Level;
+
+use crate::{message::render, notify_service::NotifyServices, shell::shell, sync::PushOutcome};
+
+/// Default minutes during which the notification command is not run again for the same kind of notification.
+pub const DEFAULT_NOTIFY_COOLDOWN: u64 = 15;
+
+/// Default number of consecutive push failures after which they are escalated.
+pub const DEFAULT_PUSH_FAILURE_ESCALATION: usize = 5;
+
+/// Sends the warnings worth more than a log line (e.g. a diverged remote) to the user,
+/// through the `notify_command` and the push notification services,
+/// at most once per kind of notification within the cooldown.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    command: Option<String>,
+    cooldown: Duration,
+    /// Template of the payload written to the command's standard input, see [`Notifier::with_payload`].
+    payload: Option<String>,
+    /// The watched directory, as given to the payload.
+    repository: String,
+    services: NotifyServices,
+    /// When the command last ran, for each kind of notification.
+    last_sent: HashMap<String, Instant>,
+}
+
+impl Notifier {
+    /// Run `command` for each notification, if any, unless it ran for the same kind within `cooldown`.
+    pub fn new(command: Option<String>, cooldown: Duration) -> Self {
+        Self {
+            command,
+            cooldown,
+            payload: None,
+            repository: String::new(),
+            services: NotifyServices::default(),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Render the payload template for each notification, see [`render_payload`],
+    /// and give it to the command on its standard input and in `NABU_NOTIFICATION_PAYLOAD`.
+    pub fn with_payload(mut self, payload: Option<String>, repository: String) -> Self {
+        self.payload = payload;
+        self.repository = repository;
+        self
+    }
+
+    /// Send the notifications of the configured kinds to the push notification services as well.
+    pub fn with_services(mut self, services: NotifyServices) -> Self {
+        self.services = services;
+        self
+    }
+
+    /// Log the warning and run the notification command, see [`Notifier::notify`].
+    pub fn warn(&mut self, kind: &str, message: &str) {
+        self.notify(Level::Warn, kind, message);
+    }
+
+    /// Log the message at `level` and run the notification command in the background,
+    /// with the `NABU_NOTIFICATION_KIND` (e.g. `diverged`) and `NABU_NOTIFICATION_MESSAGE` variables.
+    /// The notifications of the kinds sent to the push notification services are sent there too.
+    pub fn notify(&mut self, level: Level, kind: &str, message: &str) {
+        log::log!(level, "{}", message);
+        let sent_to_services = self.services.sends(kind);
+        if self.command.is_none() && !sent_to_services {
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last_sent) = self.last_sent.get(kind) {
+            if now.duration_since(*last_sent) < self.cooldown {
+                log::debug!("not sending the {} notification again yet", kind);
+                return;
+            }
+        }
+        self.last_sent.insert(kind.to_string(), now);
+        if sent_to_services {
+            let services = self.services.clone();
+            let (kind, message) = (kind.to_string(), message.to_string());
+            thread::spawn(move || {
+                if let Err(err) = services.send(level, &kind, &message) {
+                    log::warn!("failed to send the {} notification: {}", kind, err);
+                }
+            });
+        }
+        let command_line = match &self.command {
+            Some(command_line) => command_line.clone(),
+            None => return,
+        };
+        let mut command = shell(&command_line);
+        command
+            .env("NABU_NOTIFICATION_KIND", kind)
+            .env("NABU_NOTIFICATION_MESSAGE", message);
+        let payload = self
+            .payload
+            .as_ref()
+            .map(|template| render_payload(template, level, kind, message, &self.repository));
+        if let Some(payload) = &payload {
+            command
+                .env("NABU_NOTIFICATION_PAYLOAD", payload)
+                .stdin(Stdio::piped());
+        }
+        thread::spawn(move || match run(command, payload) {
+            Ok(status) if status.success() => {}
+            Ok(status) => log::warn!(
+                "notification command \"{}\" exited with {}",
+                command_line,
+                status
+            ),
+            Err(err) => log::warn!(
+                "notification command \"{}\" failed to run: {}",
+                command_line,
+                err
+            ),
+        });
+    }
+}
+
+/// Run the notification command, writing the payload to its standard input.
+fn run(
+    mut command: std::process::Command,
+    payload: Option<String>,
+) -> std::io::Result<std::process::ExitStatus> {
+    let mut child = command.spawn()?;
+    if let (Some(payload), Some(mut stdin)) = (payload, child.stdin.take()) {
+        // The command may not read its input, its exit status tells whether it failed
+        let _ = stdin.write_all(payload.as_bytes());
+    }
+    child.wait()
+}
+
+/// Render the payload template like the commit messages, with `{kind}`, `{level}` (e.g. `warn`),
+/// `{message}`, `{time}` (RFC 3339) and `{repository}`, and their `_json` variants escaped
+/// for JSON strings (e.g. `{"text": "{message_json}"}`).
+pub fn render_payload(
+    template: &str,
+    level: Level,
+    kind: &str,
+    message: &str,
+    repository: &str,
+) -> String {
+    let level = level.as_str().to_lowercase();
+    let time = Local::now().to_rfc3339();
+    let values = [
+        ("kind", kind),
+        ("level", &level),
+        ("message", message),
+        ("time", &time),
+        ("repository", repository),
+    ];
+    let escaped = values
+        .iter()
+        .map(|(name, value)| (format!("{}_json", name), escape_json(value)))
+        .collect::<Vec<_>>();
+    let values = values
+        .into_iter()
+        .chain(
+            escaped
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        )
+        .collect::<Vec<_>>();
+    render(template, &values)
+}
+
+/// The value escaped for a JSON string, without the quotes.
+fn escape_json(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Notifies the push failures without repeating them on every attempt:
+/// a failure is notified (`push-failed`) when it starts a streak or its error changes,
+/// the streak is escalated once it reaches `escalation` failures (`push-failing`)
+/// and the first successful push after it is notified (`recovered`).
+/// The pushes the remote will keep rejecting are notified at once (`push-rejected`).
+#[derive(Debug, Clone, Default)]
+pub struct PushAlerts {
+    /// Failures in a row after which they are escalated, never if 0.
+    escalation: usize,
+    /// Failures since the last successful push.
+    failures: usize,
+    /// Error of the last failure notified.
+    last_error: Option<String>,
+}
+
+impl PushAlerts {
+    /// Escalate the failures once `escalation` of them happened in a row.
+    pub fn new(escalation: usize) -> Self {
+        Self {
+            escalation,
+            ..Self::default()
+        }
+    }
+
+    /// Change the number of failures in a row after which they are escalated, keeping the current streak.
+    pub fn set_escalation(&mut self, escalation: usize) {
+        self.escalation = escalation;
+    }
+
+    /// Notify the outcome of a push, if worth it.
+    pub fn record(&mut self, outcome: &PushOutcome, notifier: &mut Notifier) {
+        match outcome {
+            PushOutcome::Pushed => {
+                if self.failures > 0 {
+                    notifier.notify(
+                        Level::Info,
+                        "recovered",
+                        &format!("Pushes succeed again, after {} failures.", self.failures),
+                    );
+                }
+                self.failures = 0;
+                self.last_error = None;
+            }
+            // Logged as an error by the sync itself
+            PushOutcome::Paused { .. } => {}
+            // The commits are backed up, the push is retried later
+            PushOutcome::Bundled { .. } => {}
+            // Not retried, so notified right away and only once
+            PushOutcome::Failed {
+                error,
+                permanent: true,
+                ..
+            } => {
+                self.failures += 1;
+                self.last_error = Some(error.clone());
+                notifier.notify(
+                    Level::Error,
+                    "push-rejected",
+                    &format!(
+                        "The remote rejects the pushes, they are stopped until the configuration is reloaded: {}. Push to a branch of your own instead, with push_mode = \"device\" or push_refspecs.",
+                        error
+                    ),
+                );
+            }
+            PushOutcome::Failed { error, .. } => {
+                self.failures += 1;
+                if self.last_error.as_ref() == Some(error) {
+                    log::debug!("push failed again ({} in a row): {}", self.failures, error);
+                } else {
+                    notifier.warn("push-failed", &format!("Push failed: {}", error));
+                    self.last_error = Some(error.clone());
+                }
+                if self.failures == self.escalation {
+                    notifier.notify(
+                        Level::Error,
+                        "push-failing",
+                        &format!("Push failed {} times in a row: {}", self.failures, error),
+                    );
+                }
+            }
+        }
+    }
+}
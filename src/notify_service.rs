@@ -0,0 +1,211 @@
+use color_eyre::{eyre::eyre, Result};
+use log::Level;
+use serde::{Deserialize, Serialize};
+
+/// Notification kinds sent to the services unless `kinds` is set:
+/// the push failure streaks, their end, and the daily snapshots.
+pub const DEFAULT_SERVICE_KINDS: &[&str] = &[
+    "push-failing",
+    "push-rejected",
+    "recovered",
+    "daily-snapshot",
+];
+
+#[inline(always)]
+fn default_ntfy_url() -> String {
+    String::from("https://ntfy.sh")
+}
+
+#[inline(always)]
+fn default_kinds() -> Vec<String> {
+    DEFAULT_SERVICE_KINDS
+        .iter()
+        .map(|kind| kind.to_string())
+        .collect()
+}
+
+/// Push notification services the notifications are sent to, e.g. phones,
+/// declared as the `[notify.<service>]` sections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyServices {
+    #[serde(default)]
+    pub ntfy: Option<NtfyConfig>,
+
+    #[serde(default)]
+    pub gotify: Option<GotifyConfig>,
+
+    /// Kinds of notification sent to the services, see [`DEFAULT_SERVICE_KINDS`].
+    #[serde(default = "default_kinds")]
+    pub kinds: Vec<String>,
+}
+
+impl Default for NotifyServices {
+    fn default() -> Self {
+        Self {
+            ntfy: None,
+            gotify: None,
+            kinds: default_kinds(),
+        }
+    }
+}
+
+/// Topic of an [ntfy](https://ntfy.sh) server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NtfyConfig {
+    /// URL of the server, ntfy.sh by default.
+    #[serde(default = "default_ntfy_url")]
+    pub url: String,
+
+    pub topic: String,
+
+    /// Access token of the topic, if it is protected.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Application of a [Gotify](https://gotify.net) server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GotifyConfig {
+    /// URL of the server.
+    pub url: String,
+
+    /// Token of the application the messages are sent as.
+    pub token: String,
+}
+
+impl NotifyServices {
+    /// Whether no service is declared.
+    pub fn is_empty(&self) -> bool {
+        self.ntfy.is_none() && self.gotify.is_none()
+    }
+
+    /// Check that the services are reachable over HTTP(S) and identified.
+    pub fn validate(&self) -> Result<()> {
+        if !self.is_empty() && !cfg!(feature = "push-notifications") {
+            return Err(eyre!(
+                "[notify.ntfy] or [notify.gotify] is set but nabu was built without the push-notifications feature"
+            ));
+        }
+        if let Some(ntfy) = &self.ntfy {
+            check_url("ntfy", &ntfy.url)?;
+            if ntfy.topic.is_empty() || ntfy.topic.contains('/') {
+                return Err(eyre!("invalid ntfy topic: {:?}", ntfy.topic));
+            }
+        }
+        if let Some(gotify) = &self.gotify {
+            check_url("gotify", &gotify.url)?;
+            if gotify.token.is_empty() {
+                return Err(eyre!("the gotify token cannot be empty"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the notifications of this kind are sent to the services.
+    pub fn sends(&self, kind: &str) -> bool {
+        !self.is_empty() && self.kinds.iter().any(|sent| sent == kind)
+    }
+
+    /// Send the notification to each service, returning the first failure.
+    #[cfg(feature = "push-notifications")]
+    pub fn send(&self, level: Level, kind: &str, message: &str) -> Result<()> {
+        let title = format!("nabu: {}", kind);
+        let mut result = Ok(());
+        if let Some(ntfy) = &self.ntfy {
+            let url = format!("{}/{}", ntfy.url.trim_end_matches('/'), ntfy.topic);
+            let mut request = ureq::post(&url)
+                .set("Title", &title)
+                .set("Priority", ntfy_priority(level))
+                .set("Tags", ntfy_tag(level));
+            if let Some(token) = &ntfy.token {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            result = result.and(response("ntfy", &url, request.send_string(message)));
+        }
+        if let Some(gotify) = &self.gotify {
+            let url = format!("{}/message", gotify.url.trim_end_matches('/'));
+            let request = ureq::post(&url)
+                .set("X-Gotify-Key", &gotify.token)
+                .set("Content-Type", "application/json");
+            let body = serde_json::json!({
+                "title": title,
+                "message": message,
+                "priority": gotify_priority(level),
+            });
+            result = result.and(response(
+                "gotify",
+                &url,
+                request.send_string(&body.to_string()),
+            ));
+        }
+        result
+    }
+
+    #[cfg(not(feature = "push-notifications"))]
+    pub fn send(&self, _level: Level, _kind: &str, _message: &str) -> Result<()> {
+        Err(eyre!(
+            "nabu was built without the push-notifications feature"
+        ))
+    }
+}
+
+fn check_url(service: &str, url: &str) -> Result<()> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(eyre!(
+            "the {} URL {} must start with https:// or http://",
+            service,
+            url
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "push-notifications")]
+fn response(
+    service: &str,
+    url: &str,
+    response: std::result::Result<ureq::Response, ureq::Error>,
+) -> Result<()> {
+    match response {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => Err(eyre!(
+            "{} rejected the notification ({}): {}",
+            service,
+            status,
+            response.into_string().unwrap_or_default().trim()
+        )),
+        Err(err) => Err(eyre!("cannot reach {}: {}", url, err)),
+    }
+}
+
+/// ntfy priorities go from 1 (min) to 5 (max), 3 being the default.
+#[cfg(feature = "push-notifications")]
+fn ntfy_priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "4",
+        _ => "3",
+    }
+}
+
+/// Emoji shortcode shown by the ntfy apps.
+#[cfg(feature = "push-notifications")]
+fn ntfy_tag(level: Level) -> &'static str {
+    match level {
+        Level::Error => "rotating_light",
+        Level::Warn => "warning",
+        _ => "white_check_mark",
+    }
+}
+
+/// Gotify priorities go from 0 to 10, the apps alert from 4 and sound from 8.
+#[cfg(feature = "push-notifications")]
+fn gotify_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 8,
+        Level::Warn => 5,
+        _ => 2,
+    }
+}
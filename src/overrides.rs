@@ -0,0 +1,145 @@
+use std::{collections::BTreeMap, path::Path};
+
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use crate::{fs::IgnoreRules, git::MergeFavor};
+
+/// Settings overriding the configuration for part of the tree,
+/// declared as `[dir."<glob>"]` sections.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectoryConfig {
+    /// Whether to watch the matching directories at all.
+    #[serde(default)]
+    pub watch: Option<bool>,
+
+    /// Watcher delay (in seconds) for the matching directories.
+    #[serde(default)]
+    pub delay: Option<u64>,
+
+    /// Glob patterns of the directories not to watch inside the matching directories.
+    #[serde(default = "Vec::new")]
+    pub ignore: Vec<String>,
+
+    /// Template of the commit messages for the changes in the matching directories.
+    #[serde(default)]
+    pub commit_message: Option<String>,
+
+    /// Which version wins the conflicts on the matching paths when syncing with the remote.
+    #[serde(default)]
+    pub merge: Option<MergeFavor>,
+}
+
+/// Settings resolved for a path.
+#[derive(Debug, Clone, Default)]
+pub struct DirectorySettings {
+    pub watch: bool,
+    pub delay: Option<u64>,
+    pub commit_message: Option<String>,
+    pub merge: Option<MergeFavor>,
+}
+
+/// Compiled per-directory overrides.
+///
+/// A section applies to a path if its glob matches the path or any of its ancestors,
+/// when several sections apply the most specific one (i.e. longest glob) wins.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides(Vec<(GlobMatcher, IgnoreRules, DirectoryConfig)>);
+
+impl Overrides {
+    pub fn new(directories: &BTreeMap<String, DirectoryConfig>) -> Result<Self, globset::Error> {
+        let mut overrides = directories
+            .iter()
+            .map(|(pattern, config)| {
+                let glob = Glob::new(pattern.trim_matches('/'))?.compile_matcher();
+                Ok((glob, IgnoreRules::new(&config.ignore)?, config.clone()))
+            })
+            .collect::<Result<Vec<_>, globset::Error>>()?;
+        // Least specific first, so the most specific override is applied last
+        overrides.sort_by_key(|(glob, _, _)| glob.glob().glob().len());
+        Ok(Self(overrides))
+    }
+
+    /// The glob and ignore patterns of each section, along with whether it is watched.
+    pub fn patterns(&self) -> Vec<(&str, Option<bool>, &[String])> {
+        self.0
+            .iter()
+            .map(|(glob, ignore, config)| (glob.glob().glob(), config.watch, ignore.patterns()))
+            .collect()
+    }
+
+    /// Resolve the settings for a path relative to the watched directory.
+    pub fn settings(&self, relative: &Path) -> DirectorySettings {
+        let mut settings = DirectorySettings {
+            watch: true,
+            ..Default::default()
+        };
+        for (_, _, config) in self.matching(relative) {
+            if let Some(watch) = config.watch {
+                settings.watch = watch;
+            }
+            if config.delay.is_some() {
+                settings.delay = config.delay;
+            }
+            if config.commit_message.is_some() {
+                settings.commit_message = config.commit_message.clone();
+            }
+            if config.merge.is_some() {
+                settings.merge = config.merge;
+            }
+        }
+        settings
+    }
+
+    /// Whether a directory, relative to the watched directory, should not be watched.
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        if !self.settings(relative).watch {
+            return true;
+        }
+        self.0.iter().any(|(glob, ignore, _)| {
+            // The ignore rules apply below the directories matched by the section
+            ancestors(relative).any(|ancestor| {
+                glob.is_match(ancestor)
+                    && relative
+                        .strip_prefix(ancestor)
+                        .is_ok_and(|inner| ignore.is_ignored(inner))
+            })
+        })
+    }
+
+    /// The glob of the section keeping a directory, relative to the watched directory, from being watched, if any,
+    /// along with its ignore pattern matching the directory, `None` if the section sets `watch = false`.
+    pub fn unwatched_by<'a>(&'a self, relative: &'a Path) -> Option<(&'a str, Option<&'a str>)> {
+        let watch = self
+            .matching(relative)
+            .filter(|(_, _, config)| config.watch.is_some())
+            .last();
+        if let Some((glob, _, config)) = watch {
+            if config.watch == Some(false) {
+                return Some((glob.glob().glob(), None));
+            }
+        }
+        self.0.iter().find_map(|(glob, ignore, _)| {
+            ancestors(relative)
+                .filter(|ancestor| glob.is_match(ancestor))
+                .find_map(|ancestor| ignore.matching_pattern(relative.strip_prefix(ancestor).ok()?))
+                .map(|pattern| (glob.glob().glob(), Some(pattern)))
+        })
+    }
+
+    fn matching<'a>(
+        &'a self,
+        relative: &'a Path,
+    ) -> impl Iterator<Item = &'a (GlobMatcher, IgnoreRules, DirectoryConfig)> {
+        self.0
+            .iter()
+            .filter(move |(glob, _, _)| ancestors(relative).any(|ancestor| glob.is_match(ancestor)))
+    }
+}
+
+/// The path and its ancestors, excluding the empty path.
+fn ancestors(path: &Path) -> impl Iterator<Item = &Path> {
+    path.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+}
@@ -0,0 +1,96 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{activity::Activity, suppress::SUPPRESS_ENV};
+
+/// Prefix of the plugin executables, e.g. `nabu-slack` for the `slack` plugin.
+pub const PLUGIN_PREFIX: &str = "nabu-";
+
+/// Settings of a plugin, declared as `[plugins.<name>]` sections.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Arguments passed to the plugin executable.
+    #[serde(default = "Vec::new")]
+    pub args: Vec<String>,
+
+    /// Kinds of activity sent to the plugin (e.g. `["commit", "push"]`), empty means all.
+    #[serde(default = "Vec::new")]
+    pub events: Vec<String>,
+}
+
+/// The command running the `nabu-<name>` executable, found in the `PATH`.
+pub fn plugin_command(name: &str) -> Command {
+    Command::new(format!("{}{}", PLUGIN_PREFIX, name))
+}
+
+/// A running plugin, which receives the watcher's activity on its standard input,
+/// one JSON object per line (e.g. `{"kind":"commit","message":"..."}`).
+/// Its standard input is closed once the watcher stops.
+pub struct Plugin {
+    name: String,
+    events: Vec<String>,
+    child: Child,
+    /// Gone once the plugin stopped reading.
+    stdin: Option<ChildStdin>,
+}
+
+impl Plugin {
+    /// Start the plugin in the watched directory, which is also set in `NABU_DIRECTORY`,
+    /// along with the file listing the paths it changes in `NABU_SUPPRESS`, see [`crate::suppress`].
+    pub fn spawn(
+        name: &str,
+        config: &PluginConfig,
+        directory: &Path,
+        suppress_file: &Path,
+    ) -> io::Result<Self> {
+        let mut child = plugin_command(name)
+            .args(&config.args)
+            .current_dir(directory)
+            .env("NABU_DIRECTORY", directory)
+            .env(SUPPRESS_ENV, suppress_file)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        Ok(Self {
+            name: name.to_string(),
+            events: config.events.clone(),
+            child,
+            stdin,
+        })
+    }
+
+    /// Send the activity to the plugin, if it is interested in its kind.
+    pub fn send(&mut self, activity: &Activity) {
+        if !self.events.is_empty() && !self.events.iter().any(|kind| kind == activity.kind()) {
+            return;
+        }
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return,
+        };
+        let result = serde_json::to_string(activity)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(stdin, "{}", line))
+            .and_then(|_| stdin.flush());
+        if let Err(err) = result {
+            log::warn!("plugin {} stopped receiving events: {}", self.name, err);
+            self.stdin = None;
+        }
+    }
+
+    /// Close the plugin's standard input and wait for it to exit.
+    pub fn stop(mut self) {
+        self.stdin = None;
+        match self.child.wait() {
+            Ok(status) if status.success() => {}
+            Ok(status) => log::warn!("plugin {} exited with {}", self.name, status),
+            Err(err) => log::warn!("failed to wait for plugin {}: {}", self.name, err),
+        }
+    }
+}
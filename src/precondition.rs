@@ -0,0 +1,189 @@
+use std::{
+    fs,
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{config::Config, shell::shell};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Number of trailing lines of the validation command's output kept in the push failure.
+const VALIDATION_REPORT_LINES: usize = 5;
+
+/// Default time (in seconds) the validation command may run for before it is killed and the push fails.
+pub const DEFAULT_PUSH_VALIDATION_TIMEOUT: u64 = 300;
+
+/// Interval between the checks of whether the validation command exited.
+const VALIDATION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Check whether pushing is currently allowed by the configured preconditions.
+/// Returns the reason why the push should be skipped, if any.
+pub fn check_push_preconditions(config: &Config) -> Option<String> {
+    if config.push_require_ac_power && on_ac_power() == Some(false) {
+        return Some(String::from("running on battery"));
+    }
+    if config.push_avoid_metered && on_metered_connection() == Some(true) {
+        return Some(String::from("on a metered connection"));
+    }
+    if let Some(command_line) = &config.push_precondition_command {
+        match shell(command_line).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                return Some(format!(
+                    "precondition command \"{}\" exited with {}",
+                    command_line, status
+                ))
+            }
+            Err(err) => {
+                return Some(format!(
+                    "precondition command \"{}\" failed to run: {}",
+                    command_line, err
+                ))
+            }
+        }
+    }
+    None
+}
+
+/// Run the validation command of the outgoing commits, from `from` (the upstream branch, if any)
+/// to `to` (`HEAD`), in the repository's working directory, killing it once it runs for longer than `timeout`.
+/// Returns why the push must be aborted, with the end of the command's output, if it fails.
+pub fn validate_push(
+    command_line: &str,
+    directory: &Path,
+    from: Option<git2::Oid>,
+    to: Option<git2::Oid>,
+    timeout: Duration,
+) -> Result<(), String> {
+    let from = from.map(|from| from.to_string()).unwrap_or_default();
+    let to = to.map(|to| to.to_string()).unwrap_or_default();
+    let range = if from.is_empty() {
+        to.clone()
+    } else {
+        format!("{}..{}", from, to)
+    };
+    let failed = |err: std::io::Error| {
+        format!(
+            "push validation command \"{}\" failed to run: {}",
+            command_line, err
+        )
+    };
+    let mut child = shell(command_line)
+        .current_dir(directory)
+        .env("NABU_PUSH_FROM", &from)
+        .env("NABU_PUSH_TO", &to)
+        .env("NABU_PUSH_RANGE", &range)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(failed)?;
+    // Drained while it runs, lest it blocks on a full pipe
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait().map_err(failed)? {
+            Some(status) => break status,
+            None if Instant::now() < deadline => thread::sleep(VALIDATION_POLL_INTERVAL),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "push validation command \"{}\" timed out after {}s",
+                    command_line,
+                    timeout.as_secs()
+                ));
+            }
+        }
+    };
+    if status.success() {
+        return Ok(());
+    }
+    // Scripts report on either stream, stderr is preferred
+    let stderr = stderr.join().unwrap_or_default();
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr);
+    let stdout = String::from_utf8_lossy(&stdout);
+    let report = if stderr.trim().is_empty() {
+        stdout.trim()
+    } else {
+        stderr.trim()
+    };
+    let lines = report.lines().collect::<Vec<_>>();
+    let report = lines[lines.len().saturating_sub(VALIDATION_REPORT_LINES)..].join("; ");
+    let mut error = format!(
+        "push validation command \"{}\" exited with {}",
+        command_line, status
+    );
+    if !report.is_empty() {
+        error.push_str(": ");
+        error.push_str(&report);
+    }
+    Err(error)
+}
+
+/// Read the whole of the child's output stream on a thread of its own.
+fn read_in_background<R>(stream: Option<R>) -> JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut output = vec![];
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Whether the machine is running on AC power.
+/// Returns `None` when it cannot be detected.
+pub fn on_ac_power() -> Option<bool> {
+    if cfg!(target_os = "macos") {
+        let output = Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        return Some(output.contains("AC Power"));
+    }
+
+    let mut found_mains = false;
+    for entry in fs::read_dir(POWER_SUPPLY_PATH).ok()?.flatten() {
+        let path = entry.path();
+        if read_trimmed(&path.join("type")).as_deref() != Some("Mains") {
+            continue;
+        }
+        found_mains = true;
+        if read_trimmed(&path.join("online")).as_deref() == Some("1") {
+            return Some(true);
+        }
+    }
+    // Desktops may not report a mains supply at all
+    found_mains.then_some(false)
+}
+
+/// Whether the active network connection is metered, according to NetworkManager.
+/// Returns `None` when it cannot be detected.
+pub fn on_metered_connection() -> Option<bool> {
+    let output = Command::new("nmcli")
+        .args(["-t", "-f", "GENERAL.METERED", "device", "show"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    Some(output.lines().any(|line| {
+        line.trim_start_matches("GENERAL.METERED:")
+            .starts_with("yes")
+    }))
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// Default interval (in minutes) between batch snapshots during quiet hours.
+pub const DEFAULT_BATCH_INTERVAL: u64 = 60;
+
+#[inline(always)]
+fn default_batch_interval() -> u64 {
+    DEFAULT_BATCH_INTERVAL
+}
+
+/// What happens to the changes made during quiet hours.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuietMode {
+    /// Commit nothing, the changes are snapshotted once the quiet hours end.
+    #[default]
+    Pause,
+    /// Snapshot the changes periodically instead of committing each event.
+    Batch,
+}
+
+/// A time window during which auto-commits are paused or batched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHours {
+    /// Start of the window (e.g. `09:00`).
+    pub start: String,
+
+    /// End of the window (e.g. `17:00`), may be before `start` to span midnight.
+    pub end: String,
+
+    /// Days the window applies to (e.g. `["mon", "tue"]`), empty means every day.
+    #[serde(default = "Vec::new")]
+    pub days: Vec<String>,
+
+    #[serde(default)]
+    pub mode: QuietMode,
+
+    /// Interval (in minutes) between snapshots in batch mode.
+    #[serde(default = "default_batch_interval")]
+    pub batch_interval: u64,
+}
+
+impl QuietHours {
+    /// Check that the window's times and days can be parsed.
+    pub fn validate(&self) -> Result<()> {
+        self.window()?;
+        self.weekdays()?;
+        Ok(())
+    }
+
+    /// Whether the given time falls inside the window.
+    pub fn contains(&self, time: DateTime<Local>) -> bool {
+        let (start, end) = match self.window() {
+            Ok(window) => window,
+            Err(_) => return false,
+        };
+        let days = self.weekdays().unwrap_or_default();
+        if !days.is_empty() && !days.contains(&time.weekday()) {
+            return false;
+        }
+        let time = time.time();
+        if start <= end {
+            start <= time && time < end
+        } else {
+            start <= time || time < end
+        }
+    }
+
+    fn window(&self) -> Result<(NaiveTime, NaiveTime)> {
+        Ok((parse_time(&self.start)?, parse_time(&self.end)?))
+    }
+
+    fn weekdays(&self) -> Result<Vec<Weekday>> {
+        self.days
+            .iter()
+            .map(|day| Weekday::from_str(day).map_err(|_| eyre!("invalid day \"{}\"", day)))
+            .collect()
+    }
+}
+
+/// Find the quiet hours active at the given time, if any.
+pub fn active_quiet_hours(
+    quiet_hours: &[QuietHours],
+    time: DateTime<Local>,
+) -> Option<&QuietHours> {
+    quiet_hours.iter().find(|window| window.contains(time))
+}
+
+pub(crate) fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+        .map_err(|_| eyre!("invalid time \"{}\", expected HH:MM", time))
+}
@@ -0,0 +1,28 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// Default prefix of the rolling branches (e.g. `nabu/2024-Q2`).
+pub const DEFAULT_ROLLING_BRANCH_PREFIX: &str = "nabu";
+
+/// How often the commits move onto a new branch, see [`RollingPeriod::branch_name`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RollingPeriod {
+    Month,
+    Quarter,
+}
+
+impl RollingPeriod {
+    /// Name of the branch of the period holding the day, e.g. `nabu/2024-05` or `nabu/2024-Q2`.
+    pub fn branch_name(self, prefix: &str, day: NaiveDate) -> String {
+        match self {
+            Self::Month => format!("{}/{}-{:02}", prefix, day.year(), day.month()),
+            Self::Quarter => format!("{}/{}-Q{}", prefix, day.year(), day.month0() / 3 + 1),
+        }
+    }
+}
+
+/// Whether the prefix makes valid branch names.
+pub fn is_valid_prefix(prefix: &str) -> bool {
+    git2::Reference::is_valid_name(&format!("refs/heads/{}/2024-Q1", prefix))
+}
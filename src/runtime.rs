@@ -0,0 +1,66 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use serde_json::{Map, Value};
+
+use crate::{config::Config, fs::write_atomically};
+
+/// Name of the file holding the settings changed with `nabu ctl set`, kept inside the repository's `.git` directory.
+pub const RUNTIME_SETTINGS_FILE_NAME: &str = "nabu-runtime.json";
+
+/// Path of the runtime settings file for the repository with the given `.git` directory.
+pub fn runtime_settings_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(RUNTIME_SETTINGS_FILE_NAME)
+}
+
+/// Top-level configuration keys changed while the watcher runs,
+/// applied over the configuration file until the watcher stops.
+#[derive(Debug, Default)]
+pub struct RuntimeSettings(Map<String, Value>);
+
+impl RuntimeSettings {
+    /// Read the settings written to `path`, none if there is no such file.
+    pub fn read(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(settings) => Ok(Self(serde_json::from_slice(&settings)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Set the key, replacing its previous value.
+    pub fn set(&mut self, key: &str, value: Value) {
+        self.0.insert(key.to_string(), value);
+    }
+
+    /// Write the settings to `path`, replacing it atomically so readers never see a partial file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, &serde_json::to_vec_pretty(&self.0)?)
+    }
+
+    /// The configuration with the settings applied over it.
+    pub fn apply(&self, config: Config) -> Result<Config> {
+        if self.0.is_empty() {
+            return Ok(config);
+        }
+        let mut value = serde_json::to_value(config)?;
+        value
+            .as_object_mut()
+            .ok_or_else(|| eyre!("the configuration is not a table"))?
+            .extend(self.0.clone());
+        Config::from_value(value)
+    }
+}
+
+/// Parse the value of a setting as a TOML value (e.g. `10`, `true` or `["a", "b"]`),
+/// falling back to a string so that `main` needs no quotes.
+pub fn parse_setting(value: &str) -> Value {
+    toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .and_then(|value| serde_json::to_value(value).ok())
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
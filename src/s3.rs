@@ -0,0 +1,177 @@
+use std::path::Path;
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// Environment variables holding the S3 credentials, the session token is optional.
+pub const S3_CREDENTIAL_VARIABLES: [&str; 3] = [
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+];
+
+#[inline(always)]
+fn default_region() -> String {
+    String::from("us-east-1")
+}
+
+#[inline(always)]
+fn default_key() -> String {
+    String::from("nabu.bundle")
+}
+
+/// S3-compatible object storage the repository is uploaded to as a git bundle on each push,
+/// instead of pushing to a git server, declared as the `[remote.s3]` section.
+/// The credentials are read from the [`S3_CREDENTIAL_VARIABLES`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Config {
+    pub bucket: String,
+
+    #[serde(default = "default_region")]
+    pub region: String,
+
+    /// Endpoint of the S3-compatible storage (e.g. `https://s3.fr-par.scw.cloud`), AWS by default.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    /// Object key of the bundle.
+    #[serde(default = "default_key")]
+    pub key: String,
+}
+
+impl S3Config {
+    /// Check that the endpoint is an HTTP(S) URL and that the key is set.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(endpoint) = &self.endpoint {
+            if !endpoint.starts_with("https://") && !endpoint.starts_with("http://") {
+                return Err(eyre!(
+                    "the S3 endpoint {} must start with https:// or http://",
+                    endpoint
+                ));
+            }
+        }
+        if self.bucket.is_empty() || self.key.is_empty() {
+            return Err(eyre!("the S3 bucket and key cannot be empty"));
+        }
+        Ok(())
+    }
+
+    /// Where the bundle is uploaded, e.g. `s3://notes/nabu.bundle`.
+    pub fn location(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.key)
+    }
+
+    #[cfg(feature = "s3")]
+    fn endpoint(&self) -> String {
+        self.endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", self.region))
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    /// Upload the file as the bundle object, signed with AWS Signature Version 4.
+    /// The bucket is addressed in the path, which S3-compatible storages support.
+    #[cfg(feature = "s3")]
+    pub fn upload(&self, path: &Path) -> Result<()> {
+        let [access_key, secret_key, session_token] =
+            S3_CREDENTIAL_VARIABLES.map(|variable| std::env::var(variable).ok());
+        let (access_key, secret_key) = access_key.zip(secret_key).ok_or_else(|| {
+            eyre!("set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY to upload to S3")
+        })?;
+        let body = std::fs::read(path)?;
+
+        let endpoint = self.endpoint();
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint.as_str(), |(_, host)| host);
+        let uri = format!("/{}/{}", uri_encode(&self.bucket), uri_encode(&self.key));
+        let now = chrono::Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), self.region);
+        let payload_hash = hex(&hmac_sha256::Hash::hash(&body));
+
+        let mut headers = vec![
+            ("host", host.to_string()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(session_token) = session_token {
+            headers.push(("x-amz-security-token", session_token));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect::<String>();
+        let canonical_request = format!(
+            "PUT\n{}\n\n{}\n{}\n{}",
+            uri, canonical_headers, signed_headers, payload_hash
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&hmac_sha256::Hash::hash(canonical_request.as_bytes()))
+        );
+        let signing_key = [self.region.as_str(), "s3", "aws4_request"].iter().fold(
+            hmac_sha256::HMAC::mac(
+                now.format("%Y%m%d").to_string(),
+                format!("AWS4{}", secret_key),
+            ),
+            |key, part| hmac_sha256::HMAC::mac(part, key),
+        );
+        let signature = hex(&hmac_sha256::HMAC::mac(string_to_sign, signing_key));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            access_key, scope, signed_headers, signature
+        );
+
+        let mut request = ureq::put(&format!("{}{}", endpoint, uri));
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.set(name, value);
+        }
+        match request
+            .set("authorization", &authorization)
+            .send_bytes(&body)
+        {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => Err(eyre!(
+                "{} rejected the upload ({}): {}",
+                self.location(),
+                status,
+                response.into_string().unwrap_or_default().trim()
+            )),
+            Err(err) => Err(eyre!("cannot reach {}: {}", endpoint, err)),
+        }
+    }
+
+    #[cfg(not(feature = "s3"))]
+    pub fn upload(&self, _path: &Path) -> Result<()> {
+        Err(eyre!("nabu was built without the s3 feature"))
+    }
+}
+
+/// Percent-encode a path segment as S3 expects, keeping the `/` separators.
+#[cfg(feature = "s3")]
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(feature = "s3")]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
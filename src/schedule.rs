@@ -0,0 +1,94 @@
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use color_eyre::{eyre::eyre, Result};
+
+use crate::quiet::parse_time;
+
+/// Parse a cron expression.
+/// Besides the `cron` crate format (which includes seconds),
+/// the standard five field format (e.g. `0 */2 * * *`) is accepted.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule> {
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression)
+        .map_err(|err| eyre!("invalid cron expression \"{}\": {}", expression, err))
+}
+
+/// Compute the next time the schedule fires, in local time.
+pub fn next_occurrence(schedule: &cron::Schedule) -> Option<DateTime<Local>> {
+    schedule.upcoming(Local).next()
+}
+
+/// Parse a duration made of amounts of hours, minutes and seconds (e.g. `3h`, `90m` or `1h30m`),
+/// short enough for the time it ends to be representable.
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let invalid = || {
+        eyre!(
+            "invalid duration \"{}\", expected e.g. 3h, 90m or 1h30m",
+            duration
+        )
+    };
+    let mut seconds: u64 = 0;
+    let mut amount = String::new();
+    for character in duration.trim().chars() {
+        if character.is_ascii_digit() {
+            amount.push(character);
+            continue;
+        }
+        let unit = match character {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = std::mem::take(&mut amount).parse().map_err(|_| invalid())?;
+        seconds = value
+            .checked_mul(unit)
+            .and_then(|value| seconds.checked_add(value))
+            .ok_or_else(|| eyre!("the duration \"{}\" is too long", duration))?;
+    }
+    if !amount.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+    let parsed = Duration::from_secs(seconds);
+    chrono::Duration::from_std(parsed)
+        .ok()
+        .and_then(|parsed| Local::now().checked_add_signed(parsed))
+        .ok_or_else(|| eyre!("the duration \"{}\" is too long", duration))?;
+    Ok(parsed)
+}
+
+/// Parse a local wall-clock time, `HH:MM` being the next time the clock shows it
+/// (e.g. `01:00` at night is tomorrow's), or a date and time as `YYYY-MM-DD HH:MM`.
+pub fn parse_deadline(time: &str) -> Result<DateTime<Local>> {
+    let now = Local::now();
+    let date_time = match NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M") {
+        Ok(date_time) => date_time,
+        Err(_) => {
+            let time = parse_time(time).map_err(|_| {
+                eyre!(
+                    "invalid time \"{}\", expected HH:MM or YYYY-MM-DD HH:MM",
+                    time
+                )
+            })?;
+            let today = now.naive_local().date().and_time(time);
+            if today > now.naive_local() {
+                today
+            } else {
+                today + chrono::Duration::days(1)
+            }
+        }
+    };
+    let deadline = Local
+        .from_local_datetime(&date_time)
+        .earliest()
+        .ok_or_else(|| eyre!("{} does not exist in the local time zone", time))?;
+    if deadline <= now {
+        return Err(eyre!("{} is in the past", time));
+    }
+    Ok(deadline)
+}
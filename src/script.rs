@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use color_eyre::{eyre::eyre, Result};
+
+use crate::sync::PushOutcome;
+
+/// A change about to be committed, as passed to the script's `commit_message(event)`.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptEvent<'a> {
+    /// What happened to the file, e.g. "created" or "renamed".
+    pub action: &'a str,
+    pub path: &'a Path,
+    /// The previous path of a renamed file.
+    pub from: Option<&'a Path>,
+    /// The message rendered from the commit message template.
+    pub message: &'a str,
+}
+
+/// A user script customizing the commits, with any of the callbacks:
+///
+/// - `commit_message(event)`, returning the message to commit the event with,
+///   or nothing to keep the default one;
+/// - `should_commit(path)`, returning whether the change to the path is committed;
+/// - `on_push_result(result)`, called after each push.
+///
+/// Errors in the callbacks are logged and the default behaviour is kept.
+#[cfg(feature = "scripting")]
+pub struct Script {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+#[cfg(feature = "scripting")]
+impl Script {
+    /// Compile the Rhai script at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut engine = rhai::Engine::new();
+        engine.on_print(|text| log::info!("script: {}", text));
+        engine.on_debug(|text, _, _| log::debug!("script: {}", text));
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|err| eyre!("failed to load the script {}: {}", path.display(), err))?;
+        Ok(Self { engine, ast })
+    }
+
+    /// The message to commit the event with, `None` to keep the default one.
+    pub fn commit_message(&self, event: &ScriptEvent) -> Option<String> {
+        let mut map = rhai::Map::new();
+        map.insert("action".into(), event.action.into());
+        map.insert(
+            "path".into(),
+            event.path.to_string_lossy().to_string().into(),
+        );
+        let from = event.from.map_or(rhai::Dynamic::UNIT, |from| {
+            from.to_string_lossy().to_string().into()
+        });
+        map.insert("from".into(), from);
+        map.insert("message".into(), event.message.into());
+        let message = self.call("commit_message", map.into())?;
+        if message.is_unit() {
+            return None;
+        }
+        match message.into_string() {
+            Ok(message) => Some(message),
+            Err(kind) => {
+                log::warn!("script: commit_message returned a {}, not a string", kind);
+                None
+            }
+        }
+    }
+
+    /// Whether the change to the path is committed, `true` unless the script says otherwise.
+    pub fn should_commit(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().to_string();
+        match self.call("should_commit", path.into()) {
+            None => true,
+            Some(commit) => commit.as_bool().unwrap_or_else(|kind| {
+                log::warn!("script: should_commit returned a {}, not a bool", kind);
+                true
+            }),
+        }
+    }
+
+    /// Let the script know how a push went.
+    pub fn on_push_result(&self, outcome: &PushOutcome) {
+        match rhai::serde::to_dynamic(outcome) {
+            Ok(result) => {
+                self.call("on_push_result", result);
+            }
+            Err(err) => log::warn!("script: failed to pass the push result: {}", err),
+        }
+    }
+
+    /// Call the callback if the script defines it.
+    fn call(&self, name: &str, argument: rhai::Dynamic) -> Option<rhai::Dynamic> {
+        let defined = self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == 1);
+        if !defined {
+            return None;
+        }
+        // Only the callbacks are run, not the top-level statements
+        let options = rhai::CallFnOptions::new().eval_ast(false);
+        let mut scope = rhai::Scope::new();
+        match self
+            .engine
+            .call_fn_with_options(options, &mut scope, &self.ast, name, (argument,))
+        {
+            Ok(result) => Some(result),
+            Err(err) => {
+                log::warn!("script: {} failed: {}", name, err);
+                None
+            }
+        }
+    }
+}
+
+/// Without the `scripting` feature scripts cannot be loaded.
+#[cfg(not(feature = "scripting"))]
+pub struct Script {
+    _private: (),
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        Err(eyre!(
+            "cannot load the script {}, nabu was built without the scripting feature",
+            path.display()
+        ))
+    }
+
+    pub fn commit_message(&self, _event: &ScriptEvent) -> Option<String> {
+        None
+    }
+
+    pub fn should_commit(&self, _path: &Path) -> bool {
+        true
+    }
+
+    pub fn on_push_result(&self, _outcome: &PushOutcome) {}
+}
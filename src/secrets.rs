@@ -0,0 +1,85 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use regex::RegexSet;
+
+/// Patterns of the obvious secrets, along with what they are.
+const SECRET_PATTERNS: &[(&str, &str)] = &[
+    ("private key", r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY-----"),
+    ("AWS access key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    ("AWS secret key", r"(?i)aws_secret_access_key\s*[=:]\s*\S+"),
+    ("GitHub token", r"\bgh[pousr]_[A-Za-z0-9]{36}\b"),
+    (
+        "secret variable",
+        r"^\s*(?:export\s+)?[A-Z0-9_]*(?:SECRET|PASSWORD|PASSWD|TOKEN|API_KEY|PRIVATE_KEY)[A-Z0-9_]*\s*=\s*\S+",
+    ),
+];
+
+/// Patterns of the secrets written in settings, e.g. in the arguments of a command.
+const SETTING_SECRET_PATTERNS: &[(&str, &str)] = &[
+    (
+        "URL credentials",
+        r"\b[a-z][a-z0-9+.-]*://[^/\s:@]+:[^/\s@]+@",
+    ),
+    ("Sentry DSN", r"\bhttps?://[0-9a-f]{32}@"),
+    ("bearer token", r"(?i)\bbearer\s+[A-Za-z0-9._~+/=-]{8,}"),
+    (
+        "secret argument",
+        r#"(?i)(?:token|password|passwd|passphrase|secret|api[_-]?key)["']?\s*[=:]\s*["']?[^\s"'$]"#,
+    ),
+];
+
+/// A secret found in the changes about to be committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Secret {
+    /// Path of the file, relative to the working directory.
+    pub path: PathBuf,
+    /// Line of the file the secret is on.
+    pub line: u32,
+    /// What the secret looks like, e.g. "private key".
+    pub kind: &'static str,
+}
+
+/// What the line looks like a secret of, if anything.
+pub fn find_secret(line: &str) -> Option<&'static str> {
+    static PATTERNS: OnceLock<RegexSet> = OnceLock::new();
+    first_match(&PATTERNS, SECRET_PATTERNS, line)
+}
+
+/// The secrets written in a settings file (e.g. `nabu.toml`),
+/// which also include the credentials passed to commands and URLs.
+/// Environment variables references (e.g. `${TOKEN}`) are not secrets.
+pub fn find_setting_secrets(path: &Path, content: &str) -> Vec<Secret> {
+    static PATTERNS: OnceLock<RegexSet> = OnceLock::new();
+    content
+        .lines()
+        .zip(1..)
+        .filter_map(|(line, number)| {
+            let kind = find_secret(line)
+                .or_else(|| first_match(&PATTERNS, SETTING_SECRET_PATTERNS, line))?;
+            Some(Secret {
+                path: path.to_path_buf(),
+                line: number,
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// What the first of the patterns matching the line is.
+fn first_match(
+    set: &OnceLock<RegexSet>,
+    patterns: &[(&'static str, &str)],
+    line: &str,
+) -> Option<&'static str> {
+    let set = set.get_or_init(|| {
+        RegexSet::new(patterns.iter().map(|(_, pattern)| pattern))
+            .expect("the secret patterns are valid")
+    });
+    set.matches(line)
+        .iter()
+        .next()
+        .map(|index| patterns[index].0)
+}
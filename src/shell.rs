@@ -0,0 +1,14 @@
+use std::process::Command;
+
+/// Build a `Command` that runs the given command line through the platform's shell.
+pub fn shell(command_line: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", command_line]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", command_line]);
+        command
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::fs::write_atomically;
+
+/// Name of the file recording what the last shutdown left unfinished,
+/// kept inside the repository's `.git` directory.
+pub const SHUTDOWN_FILE_NAME: &str = "nabu-shutdown.json";
+
+/// What a shutdown could not finish within `shutdown_budget`, left to the next run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnfinishedShutdown {
+    /// When the shutdown ran out of time, in RFC 3339 format.
+    pub time: String,
+    /// The changes left uncommitted, relative to the repository's working directory.
+    pub uncommitted: Vec<PathBuf>,
+    /// Whether the push on exit was left undone.
+    pub push: bool,
+}
+
+impl UnfinishedShutdown {
+    pub fn new(uncommitted: Vec<PathBuf>, push: bool) -> Self {
+        Self {
+            time: Local::now().to_rfc3339(),
+            uncommitted,
+            push,
+        }
+    }
+
+    /// Whether anything was left unfinished.
+    pub fn is_unfinished(&self) -> bool {
+        !self.uncommitted.is_empty() || self.push
+    }
+
+    /// Read and remove the record of the repository with the given `.git` directory, if any.
+    pub fn take(git_dir: &Path) -> Option<Self> {
+        let path = shutdown_path(git_dir);
+        let bytes = fs::read(&path).ok()?;
+        if let Err(err) = fs::remove_file(&path) {
+            log::warn!("failed to remove {}: {}", path.display(), err);
+        }
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Write the record for the repository with the given `.git` directory.
+    pub fn write(&self, git_dir: &Path) -> io::Result<()> {
+        write_atomically(&shutdown_path(git_dir), &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Path of the shutdown record for the repository with the given `.git` directory.
+pub fn shutdown_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(SHUTDOWN_FILE_NAME)
+}
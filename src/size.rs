@@ -0,0 +1,153 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::fs::write_atomically;
+
+/// Name of the file keeping the last size check, inside the repository's `.git` directory.
+pub const SIZE_FILE_NAME: &str = "nabu-size.json";
+
+/// Bytes in a megabyte, as counted by `growth_warning_megabytes_per_day`.
+pub const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+/// The sizes of the repository at some point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SizeCheck {
+    /// When the sizes were measured (RFC 3339).
+    pub time: String,
+    /// Bytes of the files in the working tree, outside of `.git`.
+    pub worktree: u64,
+    /// Bytes of the `.git` directory.
+    pub git: u64,
+}
+
+impl SizeCheck {
+    /// Measure the working tree and the `.git` directory.
+    pub fn measure(workdir: &Path, git_dir: &Path) -> io::Result<Self> {
+        Ok(Self {
+            time: Local::now().to_rfc3339(),
+            worktree: directory_size(workdir, Some(git_dir))?,
+            git: directory_size(git_dir, None)?,
+        })
+    }
+
+    /// Read the last check of the repository, `None` if there is none or it cannot be parsed.
+    pub fn read(git_dir: &Path) -> Option<Self> {
+        let check = fs::read(size_path(git_dir)).ok()?;
+        serde_json::from_slice(&check).ok()
+    }
+
+    /// Keep the check as the last one of the repository.
+    pub fn write(&self, git_dir: &Path) -> io::Result<()> {
+        write_atomically(&size_path(git_dir), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Time elapsed since the check, `None` if its time cannot be parsed.
+    pub fn age(&self) -> Option<chrono::Duration> {
+        let time = DateTime::parse_from_rfc3339(&self.time).ok()?;
+        Some(Local::now().signed_duration_since(time))
+    }
+
+    /// How much the repository grew since the `previous` check.
+    pub fn growth_since(&self, previous: &SizeCheck) -> Option<Growth> {
+        let elapsed = DateTime::parse_from_rfc3339(&self.time)
+            .ok()?
+            .signed_duration_since(DateTime::parse_from_rfc3339(&previous.time).ok()?);
+        Some(Growth {
+            since: previous.time.clone(),
+            days: elapsed.num_milliseconds() as f64 / 86_400_000.0,
+            worktree: self.worktree as i64 - previous.worktree as i64,
+            git: self.git as i64 - previous.git as i64,
+        })
+    }
+}
+
+/// The growth of the repository between two checks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Growth {
+    /// When the previous check was made (RFC 3339).
+    pub since: String,
+    /// Days elapsed since the previous check.
+    pub days: f64,
+    /// Bytes gained by the working tree, negative if it shrank.
+    pub worktree: i64,
+    /// Bytes gained by the `.git` directory, negative if it shrank.
+    pub git: i64,
+}
+
+impl Growth {
+    /// Megabytes gained by the `.git` directory per day, `None` if no time elapsed.
+    pub fn git_megabytes_per_day(&self) -> Option<f64> {
+        (self.days > 0.0).then(|| self.git as f64 / BYTES_PER_MEGABYTE as f64 / self.days)
+    }
+
+    /// Whether the `.git` directory grew faster than `threshold` megabytes per day.
+    pub fn exceeds(&self, threshold: u64) -> bool {
+        self.git_megabytes_per_day()
+            .is_some_and(|rate| rate > threshold as f64)
+    }
+}
+
+/// A blob introduced by one of the watcher's commits.
+#[derive(Debug, Clone, Serialize)]
+pub struct LargeBlob {
+    pub path: PathBuf,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+    /// The commit introducing the blob.
+    pub commit: String,
+    /// When the commit was made (RFC 3339).
+    pub time: String,
+}
+
+/// Path of the size file for the repository with the given `.git` directory.
+pub fn size_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(SIZE_FILE_NAME)
+}
+
+/// Bytes of the files under the directory, without following symbolic links, nor entering `skip`.
+pub fn directory_size(directory: &Path, skip: Option<&Path>) -> io::Result<u64> {
+    let mut size = 0;
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let metadata = match entry.path().symlink_metadata() {
+                Ok(metadata) => metadata,
+                // Removed while walking
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            if metadata.is_dir() {
+                if skip != Some(entry.path().as_path()) {
+                    directories.push(entry.path());
+                }
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+/// Format a number of bytes with decimal units, e.g. `12.3 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["kB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1000.0 {
+            break;
+        }
+        value /= 1000.0;
+        unit = next;
+    }
+    format!("{:.1} {}", value, unit)
+}
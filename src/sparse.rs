@@ -0,0 +1,151 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use git2::IndexEntryExtendedFlag;
+
+/// Per-worktree settings, where `git sparse-checkout` writes its own.
+const WORKTREE_CONFIG_FILE_NAME: &str = "config.worktree";
+
+/// Sparse checkout of a repository, i.e. the part of the tree checked out in the working directory.
+///
+/// Paths outside of it are missing from the working directory on purpose,
+/// they must neither be staged as deletions nor watched.
+#[derive(Debug, Clone, Default)]
+pub struct SparseCheckout {
+    /// Index paths left out of the working directory (i.e. with the skip-worktree flag).
+    skipped: HashSet<PathBuf>,
+    /// The cone, `None` if the patterns are not in cone mode.
+    cone: Option<Cone>,
+}
+
+/// Directories of a cone mode sparse checkout, as set by `git sparse-checkout set <dir>...`.
+/// Files at the root are always checked out.
+#[derive(Debug, Clone, Default)]
+struct Cone {
+    /// Directories checked out with all their contents.
+    recursive: Vec<PathBuf>,
+    /// Directories whose files are checked out, but not their subdirectories.
+    parents: Vec<PathBuf>,
+}
+
+impl SparseCheckout {
+    /// Read the sparse checkout of the repository, `None` if `core.sparseCheckout` is not set.
+    pub fn read(repo: &git2::Repository) -> Option<Self> {
+        if !config_bool(repo, "core.sparsecheckout") {
+            return None;
+        }
+        let skipped = repo
+            .index()
+            .map(|index| {
+                index
+                    .iter()
+                    .filter(|entry| {
+                        entry.flags_extended & IndexEntryExtendedFlag::SKIP_WORKTREE.bits() != 0
+                    })
+                    .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).as_ref()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let cone = if config_bool(repo, "core.sparsecheckoutcone") {
+            std::fs::read_to_string(repo.path().join("info").join("sparse-checkout"))
+                .ok()
+                .map(|patterns| Cone::parse(&patterns))
+        } else {
+            None
+        };
+        Some(Self { skipped, cone })
+    }
+
+    /// Whether the file, relative to the working directory, is part of the sparse checkout.
+    pub fn contains(&self, path: &Path) -> bool {
+        !self.skipped.contains(path) && self.cone.as_ref().is_none_or(|cone| cone.contains(path))
+    }
+
+    /// Whether the directory, relative to the working directory, holds part of the sparse checkout.
+    pub fn contains_dir(&self, dir: &Path) -> bool {
+        self.cone.as_ref().is_none_or(|cone| cone.contains_dir(dir))
+    }
+}
+
+impl Cone {
+    /// Parse the cone mode patterns of `.git/info/sparse-checkout`, e.g.
+    ///
+    /// ```text
+    /// /*
+    /// !/*/
+    /// /docs/
+    /// !/docs/*/
+    /// /docs/notes/
+    /// ```
+    ///
+    /// where `docs` is a parent of the recursive `docs/notes`.
+    fn parse(patterns: &str) -> Self {
+        let mut directories = vec![];
+        let mut parents = HashSet::new();
+        for pattern in patterns.lines().map(str::trim) {
+            if let Some(parent) = pattern
+                .strip_prefix("!/")
+                .and_then(|pattern| pattern.strip_suffix("/*/"))
+            {
+                parents.insert(unescape(parent));
+            } else if let Some(directory) = pattern
+                .strip_prefix('/')
+                .and_then(|pattern| pattern.strip_suffix('/'))
+            {
+                directories.push(unescape(directory));
+            }
+        }
+        let (parents, recursive) = directories
+            .into_iter()
+            .partition(|directory| parents.contains(directory));
+        Self { recursive, parents }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        parent.as_os_str().is_empty()
+            || self.parents.iter().any(|dir| dir == parent)
+            || self.recursive.iter().any(|dir| path.starts_with(dir))
+    }
+
+    fn contains_dir(&self, dir: &Path) -> bool {
+        dir.as_os_str().is_empty()
+            || self
+                .recursive
+                .iter()
+                .any(|recursive| dir.starts_with(recursive))
+            || self
+                .recursive
+                .iter()
+                .chain(&self.parents)
+                .any(|cone_dir| cone_dir.starts_with(dir))
+    }
+}
+
+/// A boolean setting of the repository, `false` if unset.
+/// libgit2 does not read the per-worktree settings, so they are looked up first.
+fn config_bool(repo: &git2::Repository, name: &str) -> bool {
+    let worktree_config = repo.path().join(WORKTREE_CONFIG_FILE_NAME);
+    let worktree = worktree_config
+        .is_file()
+        .then(|| git2::Config::open(&worktree_config).and_then(|config| config.get_bool(name)))
+        .and_then(Result::ok);
+    worktree
+        .or_else(|| repo.config().and_then(|config| config.get_bool(name)).ok())
+        .unwrap_or(false)
+}
+
+/// Remove the escaping of the glob characters git adds to the cone directories.
+fn unescape(pattern: &str) -> PathBuf {
+    let mut unescaped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    PathBuf::from(unescaped)
+}
@@ -0,0 +1,249 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    activity::Activity, fs::write_atomically, git::RepositoryStatus, journal::Journal,
+    latency::Latencies, limits::ResourceUsage, sync::PushOutcome,
+};
+
+/// Name of the state file, kept inside the repository's `.git` directory.
+pub const STATE_FILE_NAME: &str = "nabu-state.json";
+
+/// Whether the watcher is committing changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatcherState {
+    Running,
+    Paused,
+    Stopped,
+}
+
+/// The last commit made by the watcher.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastCommit {
+    pub time: String,
+    pub message: String,
+    /// The commit's ID, set when the state is refreshed after it.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// The last push attempted by the watcher.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastPush {
+    pub time: String,
+    #[serde(flatten)]
+    pub outcome: PushOutcome,
+}
+
+/// A panic of the watcher, recorded by its panic hook before the process aborts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Crash {
+    pub time: String,
+    /// The panic message.
+    pub message: String,
+    /// Where it panicked, `file:line:column`.
+    #[serde(default)]
+    pub location: Option<String>,
+    /// The ID of the snapshot committing the changes pending at the time, if one was made.
+    #[serde(default)]
+    pub snapshot: Option<String>,
+}
+
+impl Crash {
+    pub fn new(message: String, location: Option<String>) -> Self {
+        Self {
+            time: now(),
+            message,
+            location,
+            snapshot: None,
+        }
+    }
+}
+
+/// The watcher's health, as written to the state file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct State {
+    pub state: WatcherState,
+    /// Process ID of the watcher.
+    pub pid: u32,
+    /// When the watcher started (RFC 3339), telling its sessions apart.
+    #[serde(default)]
+    pub started: String,
+    /// When the state was last written (RFC 3339).
+    pub updated: String,
+    /// When the last filesystem event was processed (RFC 3339).
+    #[serde(default)]
+    pub last_event: Option<String>,
+    pub last_commit: Option<LastCommit>,
+    pub last_push: Option<LastPush>,
+    /// Number of paths with uncommitted changes.
+    pub pending_changes: usize,
+    /// Number of local commits missing from the upstream.
+    pub unpushed_commits: Option<usize>,
+    /// Latencies of the commits and pushes, over every session.
+    #[serde(default)]
+    pub latency: Latencies,
+    /// Directories which cannot be watched (e.g. unreadable), retried periodically.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unwatched: Vec<PathBuf>,
+    /// The resources used by the watcher, as last reported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResourceUsage>,
+    /// The last panic of the watcher, until a later session stops cleanly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_crash: Option<Crash>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            state: WatcherState::Running,
+            pid: std::process::id(),
+            started: now(),
+            updated: now(),
+            last_event: None,
+            last_commit: None,
+            last_push: None,
+            pending_changes: 0,
+            unpushed_commits: None,
+            latency: Latencies::default(),
+            unwatched: vec![],
+            usage: None,
+            last_crash: None,
+        }
+    }
+}
+
+impl State {
+    /// Read the state written to `path`, `None` if there is none or it cannot be parsed.
+    pub fn read(path: &Path) -> Option<Self> {
+        let state = fs::read(path).ok()?;
+        serde_json::from_slice(&state).ok()
+    }
+
+    /// Read the state written to `path`, `None` if there is none,
+    /// failing with [`io::ErrorKind::InvalidData`] if it is corrupt.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let state = match fs::read(path) {
+            Ok(state) => state,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        Ok(Some(serde_json::from_slice(&state)?))
+    }
+
+    /// Recover the last commit and the latencies from the journal, for a state file found corrupt.
+    pub fn rebuild(&mut self, journal: &Journal) {
+        if let Ok(status) = journal.status() {
+            self.last_commit = status.last_commit.map(|commit| LastCommit {
+                time: commit.time,
+                message: commit.summary,
+                id: None,
+            });
+        }
+        if let Ok(latency) = journal.latencies() {
+            self.latency = latency;
+        }
+    }
+
+    /// Whether the session stopped cleanly, rather than crashing or being killed.
+    pub fn stopped(&self) -> bool {
+        self.state == WatcherState::Stopped
+    }
+
+    /// Whether the watcher stopped without saying so, its process being gone.
+    pub fn crashed(&self) -> bool {
+        !self.stopped() && !process_exists(self.pid)
+    }
+
+    /// Carry over the last activity of the previous session.
+    pub fn resume(&mut self, previous: State) {
+        self.last_event = previous.last_event;
+        self.last_commit = previous.last_commit;
+        self.last_push = previous.last_push;
+        self.latency = previous.latency;
+        self.last_crash = previous.last_crash;
+    }
+
+    /// Update the state with the watcher's activity.
+    pub fn apply(&mut self, activity: Activity) {
+        match activity {
+            Activity::Event { .. } => self.last_event = Some(now()),
+            Activity::Commit {
+                message,
+                latency_ms,
+            } => {
+                if let Some(latency_ms) = latency_ms {
+                    self.latency.record_commit(latency_ms);
+                }
+                self.last_commit = Some(LastCommit {
+                    time: now(),
+                    message,
+                    id: None,
+                })
+            }
+            Activity::Push {
+                outcome,
+                latency_ms,
+            } => {
+                if let Some(latency_ms) = latency_ms {
+                    self.latency.record_push(latency_ms);
+                }
+                self.last_push = Some(LastPush {
+                    time: now(),
+                    outcome,
+                })
+            }
+            Activity::Paused => self.state = WatcherState::Paused,
+            Activity::Resumed => self.state = WatcherState::Running,
+            Activity::Unwatched { paths } => self.unwatched = paths,
+            Activity::Usage { usage } => self.usage = Some(usage),
+        }
+    }
+
+    /// Update the pending changes and unpushed commits from the repository status,
+    /// along with the ID of a commit just made.
+    pub fn refresh(&mut self, status: &RepositoryStatus) {
+        self.pending_changes = status.changes.len();
+        self.unpushed_commits = status.ahead;
+        if let Some(last_commit) = &mut self.last_commit {
+            if last_commit.id.is_none() {
+                last_commit.id = status.head.clone();
+            }
+        }
+    }
+
+    /// Write the state to `path`, replacing it atomically so readers never see a partial file.
+    pub fn write(&mut self, path: &Path) -> io::Result<()> {
+        self.updated = now();
+        write_atomically(path, &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// Path of the state file for the repository with the given `.git` directory.
+pub fn state_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(STATE_FILE_NAME)
+}
+
+/// Whether the process is running, assumed where it cannot be checked.
+#[cfg(unix)]
+pub(crate) fn process_exists(pid: u32) -> bool {
+    // Signal 0 only checks the process, which may belong to another user
+    let signaled = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    signaled || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn process_exists(_pid: u32) -> bool {
+    true
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339()
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate, Timelike};
+use serde::Serialize;
+
+use crate::{latency::Latencies, notes::COMMITTED_BY_TRAILER};
+
+/// Activity over a single day.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DayActivity {
+    /// The day, formatted as `YYYY-MM-DD`.
+    pub date: String,
+    pub commits: usize,
+    /// Words added to Markdown files.
+    pub words_added: usize,
+}
+
+/// How many commits edited a file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileActivity {
+    pub path: PathBuf,
+    pub edits: usize,
+}
+
+/// How many commits were made at a given hour of the day.
+#[derive(Debug, Clone, Serialize)]
+pub struct HourActivity {
+    pub hour: u32,
+    pub commits: usize,
+}
+
+/// Activity aggregated over the repository history.
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub commits: usize,
+    /// Days with commits, oldest first.
+    pub days: Vec<DayActivity>,
+    /// Edited files, most edited first.
+    pub files: Vec<FileActivity>,
+    /// Every hour of the day, in local time.
+    pub hours: Vec<HourActivity>,
+    /// Latencies of the watcher's commits and pushes, from its journal or state file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Latencies>,
+}
+
+/// Accumulates commits into [`Stats`].
+#[derive(Default)]
+pub struct StatsBuilder {
+    commits: usize,
+    days: BTreeMap<NaiveDate, DayActivity>,
+    files: HashMap<PathBuf, usize>,
+    hours: [usize; 24],
+}
+
+impl StatsBuilder {
+    /// Record a commit made at `time`, editing `paths` and adding `words_added` words to Markdown files.
+    pub fn record(&mut self, time: DateTime<Local>, paths: &[PathBuf], words_added: usize) {
+        self.commits += 1;
+        let day = self
+            .days
+            .entry(time.date().naive_local())
+            .or_insert_with(|| DayActivity {
+                date: time.format("%Y-%m-%d").to_string(),
+                ..Default::default()
+            });
+        day.commits += 1;
+        day.words_added += words_added;
+        for path in paths {
+            *self.files.entry(path.clone()).or_default() += 1;
+        }
+        self.hours[time.hour() as usize] += 1;
+    }
+
+    pub fn build(self) -> Stats {
+        let mut files = self
+            .files
+            .into_iter()
+            .map(|(path, edits)| FileActivity { path, edits })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| b.edits.cmp(&a.edits).then_with(|| a.path.cmp(&b.path)));
+        Stats {
+            commits: self.commits,
+            days: self.days.into_values().collect(),
+            files,
+            hours: self
+                .hours
+                .into_iter()
+                .enumerate()
+                .map(|(hour, commits)| HourActivity {
+                    hour: hour as u32,
+                    commits,
+                })
+                .collect(),
+            latency: None,
+        }
+    }
+}
+
+/// Whether the commit message is one written by nabu, ending with [`COMMITTED_BY_TRAILER`].
+/// The summaries of the commits predating the trailer end with ` @ <timestamp>` in the default
+/// timestamp format, i.e. ending with `UTC` or with the local offset (e.g. `+02:00`).
+pub fn is_nabu_commit(message: &str) -> bool {
+    if message
+        .lines()
+        .rev()
+        .any(|line| line.trim_end() == COMMITTED_BY_TRAILER)
+    {
+        return true;
+    }
+    let summary = message.lines().next().unwrap_or_default();
+    summary
+        .rsplit_once(" @ ")
+        .and_then(|(_, timestamp)| timestamp.rsplit_once(' '))
+        .is_some_and(|(_, zone)| zone == "UTC" || is_utc_offset(zone))
+}
+
+/// Whether the time zone is a UTC offset, e.g. `+02:00`.
+fn is_utc_offset(zone: &str) -> bool {
+    let zone = zone.as_bytes();
+    zone.len() == 6
+        && matches!(zone[0], b'+' | b'-')
+        && zone[3] == b':'
+        && [1, 2, 4, 5].iter().all(|i| zone[*i].is_ascii_digit())
+}
+
+/// Whether the path is a Markdown file.
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "md" || extension == "markdown")
+}
+
+/// Count the words in a line of text.
+pub fn count_words(line: &str) -> usize {
+    line.split_whitespace().count()
+}
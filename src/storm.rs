@@ -0,0 +1,86 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Default number of events per second starting an event storm.
+pub const DEFAULT_STORM_THRESHOLD: usize = 20;
+
+/// Window the event rate is measured over.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Time without events after which a storm is over.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Detects event storms (e.g. a `git checkout` or an `rsync` into the tree),
+/// during which the events are not committed one by one but in bulk once the storm settles.
+#[derive(Debug)]
+pub struct StormDetector {
+    /// Events per second starting a storm, 0 disables the detection.
+    threshold: usize,
+    /// When the events of the last `RATE_WINDOW` were received.
+    recent: VecDeque<Instant>,
+    storm: Option<Storm>,
+}
+
+#[derive(Debug)]
+struct Storm {
+    events: usize,
+    last_event: Instant,
+}
+
+impl StormDetector {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            recent: VecDeque::new(),
+            storm: None,
+        }
+    }
+
+    /// Record an event, returns whether it is part of a storm.
+    pub fn on_event(&mut self, now: Instant) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        if let Some(storm) = &mut self.storm {
+            storm.events += 1;
+            storm.last_event = now;
+            return true;
+        }
+        while self
+            .recent
+            .front()
+            .is_some_and(|event| now.duration_since(*event) > RATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        if self.recent.len() < self.threshold {
+            return false;
+        }
+        log::warn!(
+            "Event storm detected ({} events per second), committing in bulk once it settles.",
+            self.recent.len()
+        );
+        self.recent.clear();
+        self.storm = Some(Storm {
+            events: 1,
+            last_event: now,
+        });
+        true
+    }
+
+    /// End the storm if no events were received for a while,
+    /// returns the number of events it held back.
+    pub fn settle(&mut self, now: Instant) -> Option<usize> {
+        match &self.storm {
+            Some(storm) if now.duration_since(storm.last_event) >= SETTLE_TIME => {
+                let events = storm.events;
+                self.storm = None;
+                Some(events)
+            }
+            _ => None,
+        }
+    }
+}
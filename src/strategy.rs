@@ -0,0 +1,201 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, locale::Catalog, message::render};
+
+/// Default window (in minutes) of the `per-file-amend` strategy.
+pub const DEFAULT_AMEND_WINDOW: u64 = 10;
+
+/// Default interval (in minutes) between the snapshots of the `batched` strategy.
+pub const DEFAULT_COMMIT_BATCH_INTERVAL: u64 = 15;
+
+/// Default inactivity (in minutes) ending a session of the `session` strategy.
+pub const DEFAULT_SESSION_GAP: u64 = 10;
+
+/// How the changes are grouped into commits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitStrategyKind {
+    /// One commit per event.
+    #[default]
+    PerEvent,
+    /// One commit per event, successive changes to the same file within `amend_window` amend it.
+    PerFileAmend,
+    /// A snapshot of all the changes every `commit_batch_interval` minutes.
+    Batched,
+    /// A snapshot of all the changes once there were none for `session_gap` minutes.
+    Session,
+}
+
+/// What to do with a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Commit the change on its own.
+    Commit,
+    /// Amend the last commit, falling back to a new commit
+    /// if something else was committed since or if it was pushed.
+    Amend,
+    /// Leave the change to a later snapshot, see [`CommitStrategy::snapshot_due`].
+    Defer,
+}
+
+/// Policy grouping the changes into commits.
+pub trait CommitStrategy: Send {
+    /// Decide what to do with a change to `path`.
+    fn on_change(&mut self, path: &Path, now: Instant) -> Decision;
+
+    /// Record the commit made for a change, `amended` if it amended the last commit.
+    fn on_commit(&mut self, _path: &Path, _amended: bool, _now: Instant) {}
+
+    /// The message of the snapshot of the deferred changes, once it is due.
+    fn snapshot_due(&mut self, _now: Instant) -> Option<String> {
+        None
+    }
+}
+
+/// Build the strategy selected by the configuration.
+pub fn commit_strategy(config: &Config) -> Box<dyn CommitStrategy> {
+    let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+    match config.commit_strategy() {
+        CommitStrategyKind::PerEvent => Box::new(PerEvent),
+        CommitStrategyKind::PerFileAmend => Box::new(PerFileAmend {
+            window: minutes(config.amend_window.unwrap_or(DEFAULT_AMEND_WINDOW)),
+            last: None,
+        }),
+        CommitStrategyKind::Batched => Box::new(Batched {
+            interval: minutes(config.commit_batch_interval),
+            catalog: config.catalog(),
+            pending: Pending::default(),
+        }),
+        CommitStrategyKind::Session => Box::new(Session {
+            gap: minutes(config.session_gap),
+            catalog: config.catalog(),
+            pending: Pending::default(),
+            last_change: None,
+        }),
+    }
+}
+
+struct PerEvent;
+
+impl CommitStrategy for PerEvent {
+    fn on_change(&mut self, _path: &Path, _now: Instant) -> Decision {
+        Decision::Commit
+    }
+}
+
+struct PerFileAmend {
+    window: Duration,
+    /// The path of the last commit and when it was first made.
+    last: Option<(PathBuf, Instant)>,
+}
+
+impl CommitStrategy for PerFileAmend {
+    fn on_change(&mut self, path: &Path, now: Instant) -> Decision {
+        match &self.last {
+            Some((last, since)) if last == path && now.duration_since(*since) < self.window => {
+                Decision::Amend
+            }
+            _ => Decision::Commit,
+        }
+    }
+
+    fn on_commit(&mut self, path: &Path, amended: bool, now: Instant) {
+        if !amended {
+            self.last = Some((path.to_path_buf(), now));
+        }
+    }
+}
+
+/// Group the changed paths by their folder `depth` levels deep (e.g. `projects/alpha` at 2),
+/// the paths of shallower folders by their own folder (the empty path for the top-level files).
+pub fn group_by_folder(paths: Vec<PathBuf>, depth: usize) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut groups = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+    for path in paths {
+        let folder = path
+            .parent()
+            .map(|parent| parent.components().take(depth).collect())
+            .unwrap_or_default();
+        groups.entry(folder).or_default().push(path);
+    }
+    groups
+}
+
+/// Changes deferred to a snapshot.
+#[derive(Default)]
+struct Pending {
+    since: Option<Instant>,
+    paths: HashSet<PathBuf>,
+}
+
+impl Pending {
+    fn add(&mut self, path: &Path, now: Instant) {
+        self.since.get_or_insert(now);
+        self.paths.insert(path.to_path_buf());
+    }
+
+    /// Forget the pending changes, returning their number.
+    fn take(&mut self) -> usize {
+        self.since = None;
+        self.paths.drain().count()
+    }
+}
+
+struct Batched {
+    interval: Duration,
+    catalog: &'static Catalog,
+    pending: Pending,
+}
+
+impl CommitStrategy for Batched {
+    fn on_change(&mut self, path: &Path, now: Instant) -> Decision {
+        self.pending.add(path, now);
+        Decision::Defer
+    }
+
+    fn snapshot_due(&mut self, now: Instant) -> Option<String> {
+        let since = self.pending.since?;
+        if now.duration_since(since) < self.interval {
+            return None;
+        }
+        let count = self.pending.take().to_string();
+        Some(render(self.catalog.batched_snapshot, &[("count", &count)]))
+    }
+}
+
+struct Session {
+    gap: Duration,
+    catalog: &'static Catalog,
+    pending: Pending,
+    last_change: Option<Instant>,
+}
+
+impl CommitStrategy for Session {
+    fn on_change(&mut self, path: &Path, now: Instant) -> Decision {
+        self.pending.add(path, now);
+        self.last_change = Some(now);
+        Decision::Defer
+    }
+
+    fn snapshot_due(&mut self, now: Instant) -> Option<String> {
+        let since = self.pending.since?;
+        let last_change = self.last_change?;
+        if now.duration_since(last_change) < self.gap {
+            return None;
+        }
+        let minutes = last_change.duration_since(since).as_secs() / 60;
+        let count = self.pending.take().to_string();
+        if minutes == 0 {
+            return Some(render(self.catalog.session_snapshot, &[("count", &count)]));
+        }
+        Some(render(
+            self.catalog.long_session_snapshot,
+            &[("count", &count), ("minutes", &minutes.to_string())],
+        ))
+    }
+}
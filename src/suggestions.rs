@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adopt::DEFAULT_MAX_FILE_SIZE,
+    fs::{read_json, write_json},
+    writers::HourlyCounts,
+};
+
+/// Name of the file recording what the watcher observed of each file, kept inside the repository's `.git` directory.
+pub const OBSERVATIONS_FILE_NAME: &str = "nabu-observations.json";
+
+/// Commits of a file within an hour making it a high-churn file for that hour.
+const CHURN_COMMITS_PER_HOUR: usize = 12;
+
+/// Times a file must be observed (held back, committed while large, or churning for an hour)
+/// before it is suggested.
+const SUGGESTION_THRESHOLD: usize = 3;
+
+/// Most files whose observations are kept, the ones observed the longest ago are forgotten beyond.
+const MAX_OBSERVED_PATHS: usize = 1000;
+
+const BYTES_PER_MEGABYTE: u64 = 1_000_000;
+
+const SECONDS_PER_HOUR: i64 = 3600;
+
+/// Comment heading the patterns appended to `.gitignore`.
+const GITIGNORE_COMMENT: &str = "# Suggested by `nabu suggest-ignores`";
+
+/// What the watcher observed of a file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathObservations {
+    /// Changes held back since the file looked like it held a secret.
+    #[serde(default)]
+    pub secrets: usize,
+    /// Commits of the file while larger than [`DEFAULT_MAX_FILE_SIZE`] megabytes.
+    #[serde(default)]
+    pub large_commits: usize,
+    /// Hours during which the file was committed at least [`CHURN_COMMITS_PER_HOUR`] times.
+    #[serde(default)]
+    pub churn_hours: usize,
+    /// The commits of the file during the hour of its last commit.
+    #[serde(default)]
+    commits: HourlyCounts,
+    /// Hour (since the epoch) the file was last observed.
+    #[serde(default)]
+    seen: i64,
+}
+
+impl PathObservations {
+    /// Why the file should be ignored, empty if it should not.
+    pub fn reasons(&self) -> Vec<String> {
+        let mut reasons = vec![];
+        if self.secrets >= SUGGESTION_THRESHOLD {
+            reasons.push(format!(
+                "held back {} times for a possible secret",
+                self.secrets
+            ));
+        }
+        if self.large_commits >= SUGGESTION_THRESHOLD {
+            reasons.push(format!(
+                "committed {} times while larger than {} MB",
+                self.large_commits, DEFAULT_MAX_FILE_SIZE
+            ));
+        }
+        if self.churn_hours >= SUGGESTION_THRESHOLD {
+            reasons.push(format!(
+                "committed at least {} times an hour, over {} hours",
+                CHURN_COMMITS_PER_HOUR, self.churn_hours
+            ));
+        }
+        reasons
+    }
+}
+
+/// A file to ignore, and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    /// The file, relative to the working directory.
+    pub path: PathBuf,
+    /// The `.gitignore` pattern ignoring it, anchored to the working directory.
+    pub pattern: String,
+    pub reasons: Vec<String>,
+}
+
+/// The files the guards keep stopping or the watcher keeps committing, by path relative to the working directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Observations {
+    #[serde(default)]
+    pub paths: BTreeMap<PathBuf, PathObservations>,
+}
+
+impl Observations {
+    /// Read the observations written to `path`, none if there are none or they cannot be parsed.
+    pub fn read(path: &Path) -> Self {
+        read_json(path)
+    }
+
+    /// Write the observations to `path`, replacing them atomically.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(path, self)
+    }
+
+    /// Record that the change to the file was held back since it looked like it held a secret,
+    /// returns whether the file just became a suggestion.
+    pub fn record_secret(&mut self, path: &Path, now: DateTime<Local>) -> bool {
+        self.record(path, now, |observations| observations.secrets += 1)
+    }
+
+    /// Record a commit of the file, `size` bytes large, returns whether the file just became a suggestion.
+    pub fn record_commit(&mut self, path: &Path, size: u64, now: DateTime<Local>) -> bool {
+        self.record(path, now, |observations| {
+            if size > DEFAULT_MAX_FILE_SIZE * BYTES_PER_MEGABYTE {
+                observations.large_commits += 1;
+            }
+            observations.commits.prune(now, 1);
+            // Counted once per hour, as soon as it gets there
+            if observations.commits.record(now) == CHURN_COMMITS_PER_HOUR {
+                observations.churn_hours += 1;
+            }
+        })
+    }
+
+    fn record(
+        &mut self,
+        path: &Path,
+        now: DateTime<Local>,
+        update: impl FnOnce(&mut PathObservations),
+    ) -> bool {
+        let observations = self.paths.entry(path.to_path_buf()).or_default();
+        let suggested = !observations.reasons().is_empty();
+        update(observations);
+        observations.seen = now.timestamp() / SECONDS_PER_HOUR;
+        let suggestion = !suggested && !observations.reasons().is_empty();
+        if self.paths.len() > MAX_OBSERVED_PATHS {
+            let oldest = self
+                .paths
+                .iter()
+                .filter(|(observed, _)| observed.as_path() != path)
+                .min_by_key(|(_, observations)| observations.seen)
+                .map(|(observed, _)| observed.clone());
+            if let Some(oldest) = oldest {
+                self.paths.remove(&oldest);
+            }
+        }
+        suggestion
+    }
+
+    /// Forget what was observed of the file, e.g. once it is ignored.
+    pub fn forget(&mut self, path: &Path) {
+        self.paths.remove(path);
+    }
+
+    /// The files to ignore, in path order.
+    pub fn suggestions(&self) -> Vec<Suggestion> {
+        self.paths
+            .iter()
+            .filter_map(|(path, observations)| {
+                let reasons = observations.reasons();
+                (!reasons.is_empty()).then(|| Suggestion {
+                    path: path.clone(),
+                    pattern: ignore_pattern(path),
+                    reasons,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Path of the observations for the repository with the given `.git` directory.
+pub fn observations_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(OBSERVATIONS_FILE_NAME)
+}
+
+/// The `.gitignore` pattern matching only the file, relative to the working directory.
+pub fn ignore_pattern(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut pattern = String::from("/");
+    for character in path.chars() {
+        if matches!(character, '*' | '?' | '[' | '!' | '#') {
+            pattern.push('\\');
+        }
+        pattern.push(character);
+    }
+    // Trailing spaces are stripped unless escaped
+    if pattern.ends_with(' ') {
+        pattern.pop();
+        pattern.push_str("\\ ");
+    }
+    pattern
+}
+
+/// Append the patterns to the `.gitignore` of the working directory, after a comment the first time.
+pub fn append_to_gitignore(workdir: &Path, patterns: &[String]) -> io::Result<()> {
+    let path = workdir.join(".gitignore");
+    let mut content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    if !content.contains(GITIGNORE_COMMENT) {
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(GITIGNORE_COMMENT);
+        content.push('\n');
+    }
+    for pattern in patterns {
+        content.push_str(pattern);
+        content.push('\n');
+    }
+    fs::write(&path, content)
+}
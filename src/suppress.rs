@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Name of the file the hooks list the paths they are about to change in,
+/// kept inside the repository's `.git` directory.
+pub const SUPPRESS_FILE_NAME: &str = "nabu-suppress";
+
+/// Environment variable holding the path of the suppression file, set for the plugins.
+pub const SUPPRESS_ENV: &str = "NABU_SUPPRESS";
+
+/// Default seconds during which the changes to the listed paths are not committed, on top of the delay.
+pub const DEFAULT_SUPPRESS_GRACE_PERIOD: u64 = 60;
+
+/// Shortest interval between two reads of the suppression file, rather than one per event.
+/// The hooks list the paths before changing them and the events arrive a delay later,
+/// so only a delay shorter than the interval could miss them.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Path of the suppression file for the repository with the given `.git` directory.
+pub fn suppress_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(SUPPRESS_FILE_NAME)
+}
+
+/// The paths the hooks (e.g. a plugin reacting to the commits) declared they change,
+/// whose events are dropped for a while so that committing them does not trigger the hooks again.
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    /// The suppression file, `None` outside of a repository.
+    path: Option<PathBuf>,
+    /// When the suppression of each path ends.
+    until: HashMap<PathBuf, Instant>,
+    /// When the suppression file was last read, see [`REFRESH_INTERVAL`].
+    refreshed_at: Option<Instant>,
+}
+
+impl Suppressions {
+    /// Read the paths listed in the suppression file at `path`, if any.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            until: HashMap::new(),
+            refreshed_at: None,
+        }
+    }
+
+    /// Take the paths listed in the suppression file since the last call, one per line,
+    /// relative to `root` unless absolute, suppressing them for `duration`.
+    /// The file is read at most once per [`REFRESH_INTERVAL`], rather than on every event.
+    pub fn refresh(&mut self, root: &Path, duration: Duration) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if self
+            .refreshed_at
+            .is_some_and(|refreshed_at| refreshed_at.elapsed() < REFRESH_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.refreshed_at = Some(Instant::now());
+        // Moved away first, so that the lines appended meanwhile are kept for the next call
+        let taken = path.with_extension("taken");
+        match fs::rename(path, &taken) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let listed = fs::read_to_string(&taken);
+        fs::remove_file(&taken)?;
+        let until = Instant::now() + duration;
+        for line in listed?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            log::debug!("suppressing the changes to {} for {:?}", line, duration);
+            self.until.insert(root.join(line), until);
+        }
+        Ok(())
+    }
+
+    /// Whether the changes to the path, or to the directory it is in, are suppressed.
+    pub fn is_suppressed(&mut self, path: &Path) -> bool {
+        let now = Instant::now();
+        self.until.retain(|_, until| *until > now);
+        self.until
+            .keys()
+            .any(|suppressed| path.starts_with(suppressed))
+    }
+}
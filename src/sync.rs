@@ -0,0 +1,311 @@
+use std::{path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    git::{self, AuthenticationMethod, ConflictStrategy, PushSettings, RebaseOutcome, Repository},
+    overrides::Overrides,
+    precondition,
+    s3::S3Config,
+};
+
+/// Number of fetch-rebase-push rounds attempted when syncing.
+const SYNC_ATTEMPTS: usize = 3;
+
+/// Parts of the (lowercased) rejection messages of the pushes the remote will keep rejecting,
+/// from GitHub, GitLab, Bitbucket and plain git servers.
+const PERMANENT_REJECTIONS: [&str; 8] = [
+    "protected branch",
+    "gh006",
+    "gh013",
+    "pre-receive hook declined",
+    "not allowed to push",
+    "not allowed to force push",
+    "deny updating",
+    "denied to update",
+];
+
+/// The outcome of pushing to the remote.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum PushOutcome {
+    /// The commits were pushed.
+    Pushed,
+    /// Syncing was paused since rebasing onto the remote conflicts on the given paths.
+    Paused { conflicts: Vec<PathBuf> },
+    /// The push failed, `authentication` is set if the remote rejected the credentials,
+    /// `permanent` if it rejects the push itself (e.g. a protected branch) and retrying cannot help.
+    Failed {
+        error: String,
+        authentication: bool,
+        #[serde(default)]
+        permanent: bool,
+    },
+    /// The remote was unreachable, the commits were written to the `backup_path` bundle instead.
+    Bundled { path: PathBuf, error: String },
+}
+
+impl PushOutcome {
+    pub fn is_pushed(&self) -> bool {
+        matches!(self, Self::Pushed)
+    }
+}
+
+/// Push to the remote, squashing the unpushed commits and syncing with it first if configured to,
+/// or upload the repository to the `[remote.s3]` storage instead, which needs no authentication method.
+pub fn push<R>(
+    repo: &R,
+    authentication_method: Option<&AuthenticationMethod>,
+    config: &Config,
+) -> PushOutcome
+where
+    R: Repository,
+{
+    if config.shared_vault {
+        match repo.conflict_marked_paths() {
+            Ok(paths) if !paths.is_empty() => {
+                let paths = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                return PushOutcome::Failed {
+                    error: format!(
+                        "conflict markers in {}, resolve them before pushing",
+                        paths.join(", ")
+                    ),
+                    authentication: false,
+                    permanent: false,
+                };
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("failed to look for conflict markers: {}", err.message()),
+        }
+    }
+    if config.squash_before_push {
+        match repo.squash_unpushed(config.redact_paths, config.conventions.as_ref()) {
+            Ok(Some(squashed)) => log::info!("Squashed {} unpushed commits.", squashed),
+            Ok(None) => {}
+            // The commits are pushed as they are
+            Err(err) => log::warn!("failed to squash the unpushed commits: {}", err.message()),
+        }
+    }
+    let validation = config
+        .push_validation_command
+        .as_deref()
+        .map(|command_line| {
+            (
+                command_line,
+                Duration::from_secs(config.push_validation_timeout),
+            )
+        });
+    if let Some(s3) = &config.remote.s3 {
+        // After squashing, so the command sees the commits as they are uploaded
+        if let Err(error) = validate(repo, validation) {
+            return validation_failure(error);
+        }
+        return upload(repo, s3);
+    }
+    let authentication_method = match authentication_method {
+        Some(authentication_method) => authentication_method,
+        None => {
+            return PushOutcome::Failed {
+                error: String::from("no authentication method was provided"),
+                authentication: true,
+                permanent: false,
+            }
+        }
+    };
+    let result = if config.syncs() {
+        sync_and_push(
+            repo,
+            authentication_method,
+            config.on_conflict,
+            // Validated along with the configuration
+            &Overrides::new(&config.directories).unwrap_or_default(),
+            config.push_settings(),
+            validation,
+        )
+    } else if let Err(error) = validate(repo, validation) {
+        return validation_failure(error);
+    } else {
+        repo.push(authentication_method, config.push_settings())
+            .map(|_| PushOutcome::Pushed)
+    };
+    match result {
+        Ok(PushOutcome::Pushed) => {
+            log::info!("Successfully pushed to remote.");
+            PushOutcome::Pushed
+        }
+        Ok(outcome) => outcome,
+        Err(err) if is_unreachable(&err) && config.backup_path.is_some() => {
+            let path = config.backup_path.clone().unwrap();
+            match repo.write_bundle(&path) {
+                Ok(()) => {
+                    log::info!(
+                        "Remote unreachable ({}), wrote the bundle {}.",
+                        err.message(),
+                        path.display()
+                    );
+                    PushOutcome::Bundled {
+                        path,
+                        error: err.message().to_string(),
+                    }
+                }
+                Err(bundle_err) => PushOutcome::Failed {
+                    error: format!(
+                        "{}, and writing the bundle {} failed: {}",
+                        err.message(),
+                        path.display(),
+                        bundle_err.message()
+                    ),
+                    authentication: false,
+                    permanent: false,
+                },
+            }
+        }
+        Err(err) => {
+            // Surfaced by the callers, the watcher without repeating the same failure
+            log::debug!("push failed: {}", err.message());
+            PushOutcome::Failed {
+                error: err.message().to_string(),
+                authentication: err.code() == git2::ErrorCode::Auth,
+                permanent: is_permanently_rejected(&err),
+            }
+        }
+    }
+}
+
+/// Run the push validation command, if any, on the commits missing from the upstream branch,
+/// killing it once it runs for longer than the timeout.
+fn validate<R>(repo: &R, validation: Option<(&str, Duration)>) -> Result<(), String>
+where
+    R: Repository,
+{
+    let (command_line, timeout) = match validation {
+        Some(validation) => validation,
+        None => return Ok(()),
+    };
+    let (from, to) = repo
+        .upstream_head()
+        .and_then(|from| Ok((from, repo.head()?)))
+        .map_err(|err| format!("failed to find the outgoing commits: {}", err.message()))?;
+    precondition::validate_push(command_line, repo.root(), from, to, timeout)
+}
+
+fn validation_failure(error: String) -> PushOutcome {
+    PushOutcome::Failed {
+        error,
+        authentication: false,
+        permanent: false,
+    }
+}
+
+/// Write the repository into a bundle and upload it to the S3 storage, replacing the previous one.
+pub fn upload<R>(repo: &R, s3: &S3Config) -> PushOutcome
+where
+    R: Repository,
+{
+    let bundle = std::env::temp_dir().join(format!("nabu-{}.bundle", std::process::id()));
+    let result = repo
+        .write_bundle(&bundle)
+        .map_err(|err| err.message().to_string())
+        .and_then(|_| {
+            // Dry runs only pretend to write the bundle
+            if !bundle.exists() {
+                log::info!("would upload the bundle to {}", s3.location());
+                return Ok(());
+            }
+            let uploaded = s3.upload(&bundle).map_err(|err| err.to_string());
+            let _ = std::fs::remove_file(&bundle);
+            uploaded
+        });
+    match result {
+        Ok(()) => {
+            log::info!("Successfully uploaded the bundle to {}.", s3.location());
+            PushOutcome::Pushed
+        }
+        Err(error) => {
+            log::debug!("upload failed: {}", error);
+            PushOutcome::Failed {
+                error,
+                authentication: false,
+                permanent: false,
+            }
+        }
+    }
+}
+
+/// Whether the push failed since the remote cannot be reached (e.g. offline) or does not exist.
+fn is_unreachable(err: &git2::Error) -> bool {
+    match err.class() {
+        git2::ErrorClass::Net | git2::ErrorClass::Http | git2::ErrorClass::Os => true,
+        git2::ErrorClass::Ssh => err.code() != git2::ErrorCode::Auth,
+        git2::ErrorClass::Config => err.code() == git2::ErrorCode::NotFound,
+        _ => false,
+    }
+}
+
+/// Whether the remote rejected the push for a reason retrying cannot fix,
+/// such as a protected branch or a hook declining it, as opposed to a concurrent push.
+fn is_permanently_rejected(err: &git2::Error) -> bool {
+    let message = err.message().to_lowercase();
+    PERMANENT_REJECTIONS
+        .iter()
+        .any(|rejection| message.contains(rejection))
+}
+
+/// Fetch, rebase the local commits onto the upstream and push, retrying if the push is rejected.
+/// The validation command, if any, runs on the rebased commits before each push.
+pub fn sync_and_push<R>(
+    repo: &R,
+    authentication_method: &AuthenticationMethod,
+    conflict_strategy: ConflictStrategy,
+    merge_favors: &Overrides,
+    push_settings: PushSettings,
+    validation: Option<(&str, Duration)>,
+) -> git::Result<PushOutcome>
+where
+    R: Repository,
+{
+    let mut attempt = 1;
+    loop {
+        repo.fetch(authentication_method, false)?;
+        // A new branch (e.g. a rolling one) has nothing to rebase onto until it is pushed
+        if repo.upstream_head()?.is_none() {
+            if let Err(error) = validate(repo, validation) {
+                return Ok(validation_failure(error));
+            }
+            return repo
+                .push(authentication_method, push_settings)
+                .map(|_| PushOutcome::Pushed);
+        }
+        match repo.rebase_onto_upstream(conflict_strategy, merge_favors)? {
+            RebaseOutcome::Conflicted(conflicts) => {
+                log::error!(
+                    "Sync paused, rebasing onto the remote conflicts on: {:?}. Resolve the conflicts manually and push.",
+                    conflicts
+                );
+                return Ok(PushOutcome::Paused { conflicts });
+            }
+            outcome => log::debug!("rebase outcome: {:?}", outcome),
+        }
+        // After rebasing, so the command sees the commits as they are pushed
+        if let Err(error) = validate(repo, validation) {
+            return Ok(validation_failure(error));
+        }
+        match repo.push(authentication_method, push_settings.clone()) {
+            Ok(()) => return Ok(PushOutcome::Pushed),
+            Err(err) if attempt < SYNC_ATTEMPTS && !is_permanently_rejected(&err) => {
+                log::warn!(
+                    "Push rejected ({}), retrying sync ({}/{}).",
+                    err.message(),
+                    attempt,
+                    SYNC_ATTEMPTS
+                );
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
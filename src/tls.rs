@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// TLS settings of the HTTPS remotes, as the `[auth.tls]` section,
+/// for servers behind an internal certificate authority or requiring client certificates (mTLS).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM bundle of the certificate authorities trusted along with the default ones:
+    /// the system's with libgit2's transport, the bundled Mozilla roots with the `rustls` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// PEM client certificate, followed by its intermediates, presented to the server;
+    /// requires the `rustls` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Check that the client certificate comes with its key,
+    /// and that the transport in use can present it.
+    pub fn validate(&self) -> Result<()> {
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err(eyre!(
+                "the [auth.tls] section must set both client_cert and client_key, or neither"
+            ));
+        }
+        // libgit2's transport has no way to present a client certificate
+        if self.client_cert.is_some() && !cfg!(feature = "rustls") {
+            return Err(eyre!(
+                "client_cert requires nabu to be built with the rustls feature"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Apply the settings to the HTTPS remotes, which are shared by every repository of the process.
+    pub fn apply(&self) -> Result<()> {
+        self.validate()?;
+        #[cfg(feature = "rustls")]
+        crate::transport::configure_tls(self.client_config()?);
+        #[cfg(not(feature = "rustls"))]
+        self.apply_to_libgit2()?;
+        Ok(())
+    }
+
+    /// Trust the certificate authorities of the bundle in libgit2's transport, along with the system's.
+    ///
+    /// The certificate check callback is no substitute: libgit2 does not tell it whether OpenSSL
+    /// validated the certificate, accepting it there would skip the validation altogether.
+    #[cfg(not(feature = "rustls"))]
+    fn apply_to_libgit2(&self) -> Result<()> {
+        let ca_bundle = match &self.ca_bundle {
+            Some(ca_bundle) => ca_bundle,
+            None => return Ok(()),
+        };
+        if !ca_bundle.is_file() {
+            return Err(eyre!(
+                "the CA bundle {} does not exist",
+                ca_bundle.display()
+            ));
+        }
+        let file = std::ffi::CString::new(ca_bundle.to_string_lossy().as_bytes())
+            .map_err(|_| eyre!("invalid CA bundle path {}", ca_bundle.display()))?;
+        libgit2_sys::init();
+        let result = unsafe {
+            libgit2_sys::git_libgit2_opts(
+                libgit2_sys::GIT_OPT_SET_SSL_CERT_LOCATIONS as std::ffi::c_int,
+                file.as_ptr(),
+                std::ptr::null::<std::ffi::c_char>(),
+            )
+        };
+        if result < 0 {
+            let reason = git2::Error::last_error(result)
+                .map_or_else(String::new, |err| err.message().to_string());
+            return Err(eyre!(
+                "failed to trust the CA bundle {}: {}",
+                ca_bundle.display(),
+                reason
+            ));
+        }
+        Ok(())
+    }
+
+    /// The rustls settings of the transport: the bundled roots along with the CA bundle,
+    /// and the client certificate.
+    #[cfg(feature = "rustls")]
+    fn client_config(&self) -> Result<rustls::ClientConfig> {
+        use std::sync::Arc;
+
+        use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+        let mut roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        if let Some(ca_bundle) = &self.ca_bundle {
+            let certificates = CertificateDer::pem_file_iter(ca_bundle)
+                .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+                .map_err(|err| {
+                    eyre!("cannot read the CA bundle {}: {}", ca_bundle.display(), err)
+                })?;
+            let (added, _) = roots.add_parsable_certificates(certificates);
+            if added == 0 {
+                return Err(eyre!(
+                    "the CA bundle {} holds no certificate",
+                    ca_bundle.display()
+                ));
+            }
+        }
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots);
+        let (client_cert, client_key) = match (&self.client_cert, &self.client_key) {
+            (Some(client_cert), Some(client_key)) => (client_cert, client_key),
+            _ => return Ok(builder.with_no_client_auth()),
+        };
+        let chain = CertificateDer::pem_file_iter(client_cert)
+            .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| {
+                eyre!(
+                    "cannot read the client certificate {}: {}",
+                    client_cert.display(),
+                    err
+                )
+            })?;
+        let key = PrivateKeyDer::from_pem_file(client_key).map_err(|err| {
+            eyre!(
+                "cannot read the client key {}: {}",
+                client_key.display(),
+                err
+            )
+        })?;
+        builder
+            .with_client_auth_cert(chain, key)
+            .map_err(|err| eyre!("invalid client certificate: {}", err))
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use git2::transport::{Service, SmartSubtransport, SmartSubtransportStream, Transport};
+
+use crate::git::token_for_url;
+
+/// The TLS settings of the transports created from then on, `ureq`'s own if unset, see `[auth.tls]`.
+static TLS_CONFIG: RwLock<Option<Arc<rustls::ClientConfig>>> = RwLock::new(None);
+
+/// Use these TLS settings (e.g. an internal certificate authority or a client certificate)
+/// for the connections made from then on.
+pub fn configure_tls(config: rustls::ClientConfig) {
+    if let Ok(mut tls_config) = TLS_CONFIG.write() {
+        *tls_config = Some(Arc::new(config));
+    }
+}
+
+/// Replace libgit2's HTTP and HTTPS transports (which need OpenSSL on Linux) with one using rustls,
+/// for the targets without system libraries (e.g. musl or ARM builds).
+/// The tokens of [`token_for_url`] are sent as basic authentication.
+///
+/// Must be called before any remote is used.
+pub fn register() -> Result<(), git2::Error> {
+    for scheme in ["https", "http"] {
+        // Nothing else creates transports yet
+        unsafe {
+            git2::transport::register(scheme, move |remote| {
+                Transport::smart(remote, true, HttpTransport::new())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// The smart HTTP protocol over `ureq`.
+struct HttpTransport {
+    agent: ureq::Agent,
+    /// The URL of the repository once redirected, for the request following the advertisement.
+    base_url: Arc<Mutex<Option<String>>>,
+}
+
+impl HttpTransport {
+    fn new() -> Self {
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(tls_config) = TLS_CONFIG.read().ok().and_then(|config| config.clone()) {
+            agent = agent.tls_config(tls_config);
+        }
+        Self {
+            agent: agent.build(),
+            base_url: Arc::default(),
+        }
+    }
+}
+
+impl SmartSubtransport for HttpTransport {
+    fn action(
+        &self,
+        url: &str,
+        action: Service,
+    ) -> Result<Box<dyn SmartSubtransportStream>, git2::Error> {
+        let (service, advertisement) = match action {
+            Service::UploadPackLs => ("git-upload-pack", true),
+            Service::UploadPack => ("git-upload-pack", false),
+            Service::ReceivePackLs => ("git-receive-pack", true),
+            Service::ReceivePack => ("git-receive-pack", false),
+        };
+        let base_url = if advertisement {
+            url.trim_end_matches('/').to_string()
+        } else {
+            self.base_url
+                .lock()
+                .ok()
+                .and_then(|base_url| base_url.clone())
+                .unwrap_or_else(|| url.trim_end_matches('/').to_string())
+        };
+        Ok(Box::new(HttpStream {
+            agent: self.agent.clone(),
+            base_url,
+            redirected_url: self.base_url.clone(),
+            service,
+            advertisement,
+            request: vec![],
+            response: None,
+        }))
+    }
+
+    fn close(&self) -> Result<(), git2::Error> {
+        Ok(())
+    }
+}
+
+/// A request of the smart HTTP protocol, sent on the first read along with what was written.
+struct HttpStream {
+    agent: ureq::Agent,
+    base_url: String,
+    redirected_url: Arc<Mutex<Option<String>>>,
+    service: &'static str,
+    /// Whether it is the `GET` of the references advertisement, rather than the `POST` of the service.
+    advertisement: bool,
+    request: Vec<u8>,
+    response: Option<Box<dyn Read + Send + Sync>>,
+}
+
+impl HttpStream {
+    fn send(&self) -> io::Result<ureq::Response> {
+        let url = if self.advertisement {
+            format!("{}/info/refs?service={}", self.base_url, self.service)
+        } else {
+            format!("{}/{}", self.base_url, self.service)
+        };
+        let mut request = if self.advertisement {
+            self.agent.get(&url)
+        } else {
+            self.agent
+                .post(&url)
+                .set(
+                    "Content-Type",
+                    &format!("application/x-{}-request", self.service),
+                )
+                .set("Accept", &format!("application/x-{}-result", self.service))
+        };
+        // Credentials in the URL are sent by `ureq` itself
+        let has_credentials = self
+            .base_url
+            .split_once("://")
+            .and_then(|(_, rest)| rest.split('/').next())
+            .is_some_and(|host| host.contains('@'));
+        let token = token_for_url(&self.base_url).filter(|_| !has_credentials);
+        if let Some((_, user, token)) = &token {
+            request = request.set(
+                "Authorization",
+                &format!("Basic {}", STANDARD.encode(format!("{}:{}", user, token))),
+            );
+        }
+        let result = if self.advertisement {
+            request.call()
+        } else {
+            request.send_bytes(&self.request)
+        };
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(401, _)) => {
+                let message = match token {
+                    Some((variable, _, _)) => {
+                        format!(
+                            "the token of {} was rejected by {}",
+                            variable, self.base_url
+                        )
+                    }
+                    None => format!("no token is set for {}", self.base_url),
+                };
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    git2::Error::new(git2::ErrorCode::Auth, git2::ErrorClass::Http, message),
+                ))
+            }
+            Err(ureq::Error::Status(status, _)) => Err(io::Error::other(format!(
+                "{} responded with HTTP status {}",
+                url, status
+            ))),
+            Err(ureq::Error::Transport(err)) => Err(io::Error::other(err.to_string())),
+        }
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.response.is_none() {
+            let response = self.send()?;
+            if self.advertisement {
+                // Later requests go where the advertisement was redirected to
+                let suffix = format!("/info/refs?service={}", self.service);
+                if let Some(base_url) = response.get_url().strip_suffix(&suffix) {
+                    if let Ok(mut redirected_url) = self.redirected_url.lock() {
+                        *redirected_url = Some(base_url.to_string());
+                    }
+                }
+                let expected = format!("application/x-{}-advertisement", self.service);
+                if response.content_type() != expected {
+                    // A dumb HTTP server, which libgit2 would not fall back to either
+                    return Err(io::Error::other(format!(
+                        "{} does not speak the smart HTTP protocol (content type {})",
+                        self.base_url,
+                        response.content_type()
+                    )));
+                }
+            }
+            self.response = Some(response.into_reader());
+        }
+        match &mut self.response {
+            Some(response) => response.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Write for HttpStream {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.response.is_some() {
+            return Err(io::Error::other("the request was already sent"));
+        }
+        self.request.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::fs::{read_json, write_json};
+
+/// Name of the file recording how often each file changes, kept inside the repository's `.git` directory.
+pub const WRITERS_FILE_NAME: &str = "nabu-writers.json";
+
+const SECONDS_PER_HOUR: i64 = 3600;
+
+#[inline(always)]
+fn default_window() -> u64 {
+    24
+}
+
+#[inline(always)]
+fn default_changes_per_hour() -> usize {
+    60
+}
+
+/// Learning mode finding the files changing too often (e.g. an application's state or cache),
+/// declared as the `[learning]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LearningConfig {
+    /// Hours of changes observed, the older ones are forgotten.
+    #[serde(default = "default_window")]
+    pub window: u64,
+
+    /// Changes within an hour making a file a high-frequency writer.
+    #[serde(default = "default_changes_per_hour")]
+    pub changes_per_hour: usize,
+
+    /// Leave the changes to the high-frequency writers to a snapshot every `commit_batch_interval` minutes,
+    /// instead of only reporting them.
+    #[serde(default = "bool::default")]
+    pub demote: bool,
+}
+
+impl Default for LearningConfig {
+    fn default() -> Self {
+        Self {
+            window: default_window(),
+            changes_per_hour: default_changes_per_hour(),
+            demote: false,
+        }
+    }
+}
+
+impl LearningConfig {
+    /// Check that the window and the threshold are not zero.
+    pub fn validate(&self) -> Result<()> {
+        if self.window == 0 || self.changes_per_hour == 0 {
+            return Err(eyre!(
+                "the learning window and changes_per_hour must be at least 1"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A file changing more than `changes_per_hour` times within an hour of the window.
+#[derive(Debug, Clone, Serialize)]
+pub struct Writer {
+    /// The file, relative to the working directory.
+    pub path: PathBuf,
+    /// Most changes within an hour.
+    pub peak_per_hour: usize,
+    /// Changes over the window.
+    pub changes: usize,
+}
+
+/// Changes counted by hour since the epoch, shared with the churn of [`crate::suggestions::Observations`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HourlyCounts(BTreeMap<i64, usize>);
+
+impl HourlyCounts {
+    /// Count a change at `now`, returns the changes counted within its hour.
+    pub fn record(&mut self, now: DateTime<Local>) -> usize {
+        let changes = self
+            .0
+            .entry(now.timestamp() / SECONDS_PER_HOUR)
+            .or_default();
+        *changes += 1;
+        *changes
+    }
+
+    /// Forget the changes older than `window` hours, the current hour only for a window of 1.
+    pub fn prune(&mut self, now: DateTime<Local>, window: u64) {
+        let oldest = now.timestamp() / SECONDS_PER_HOUR - window as i64;
+        self.0.retain(|hour, _| *hour > oldest);
+    }
+
+    /// Whether no change is counted.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Most changes within an hour, `None` if no change is counted.
+    pub fn peak(&self) -> Option<usize> {
+        self.0.values().copied().max()
+    }
+
+    /// Changes over all the hours.
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+}
+
+/// The changes of each file, by hour since the epoch.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WriterProfile {
+    #[serde(default)]
+    pub paths: BTreeMap<PathBuf, HourlyCounts>,
+}
+
+impl WriterProfile {
+    /// Read the profile written to `path`, empty if there is none or it cannot be parsed.
+    pub fn read(path: &Path) -> Self {
+        read_json(path)
+    }
+
+    /// Write the profile to `path`, replacing it atomically.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(path, self)
+    }
+
+    /// Record a change of the file, returns whether it just became a high-frequency writer.
+    pub fn record(&mut self, path: &Path, now: DateTime<Local>, config: &LearningConfig) -> bool {
+        let changes = self
+            .paths
+            .entry(path.to_path_buf())
+            .or_default()
+            .record(now);
+        // Reported once per hour, as soon as it gets there
+        changes == config.changes_per_hour + 1
+    }
+
+    /// Forget the changes older than the window.
+    pub fn prune(&mut self, now: DateTime<Local>, window: u64) {
+        self.paths.retain(|_, hours| {
+            hours.prune(now, window);
+            !hours.is_empty()
+        });
+    }
+
+    /// Whether the file changed more than `changes_per_hour` times within an hour of the window.
+    pub fn is_writer(&self, path: &Path, config: &LearningConfig) -> bool {
+        self.paths
+            .get(path)
+            .and_then(HourlyCounts::peak)
+            .is_some_and(|peak| peak > config.changes_per_hour)
+    }
+
+    /// The high-frequency writers, most changes within an hour first.
+    pub fn writers(&self, config: &LearningConfig) -> Vec<Writer> {
+        let mut writers = self
+            .paths
+            .iter()
+            .filter_map(|(path, hours)| {
+                let peak_per_hour = hours.peak()?;
+                (peak_per_hour > config.changes_per_hour).then(|| Writer {
+                    path: path.clone(),
+                    peak_per_hour,
+                    changes: hours.total(),
+                })
+            })
+            .collect::<Vec<_>>();
+        writers.sort_by_key(|writer| std::cmp::Reverse(writer.peak_per_hour));
+        writers
+    }
+}
+
+/// Path of the profile for the repository with the given `.git` directory.
+pub fn writers_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(WRITERS_FILE_NAME)
+}